@@ -0,0 +1,420 @@
+//! 数値、識別子、演算子、括弧、文字列リテラルへの字句解析
+//!
+//! 各字句は、入力中の位置をバイト単位の範囲Spanとして保持する。
+//! 電卓の中置記法のパーサは識別子や数値、演算子の読み込みに、正規表現のパーサはエスケープの解釈に用い、
+//! いずれのエラーもSpan::diagnosticで同じ形式の診断メッセージとして表示する。
+//!
+//! ```
+//! use helper::lexer::{tokenize, TokenKind};
+//!
+//! let tokens = tokenize("max(x, 1_000) >= 2").unwrap();
+//! let texts: Vec<_> = tokens.iter().map(|t| t.text).collect();
+//! assert_eq!(texts, ["max", "(", "x", ",", "1_000", ")", ">=", "2"]);
+//! assert_eq!(tokens[6].kind, TokenKind::Op);
+//! assert_eq!((tokens[6].span.start, tokens[6].span.end), (14, 16));
+//! ```
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// 演算子
+///
+/// 最長一致で読み込むため、2文字の演算子を先に並べる。
+pub const OPERATORS: [&str; 28] = [
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "..", "+", "-", "*", "/", "%", "^", "&", "|",
+    "<", ">", "!", "~", "=", ",", ".", "[", "]", "$", ";",
+];
+
+/// 入力中の範囲（バイト単位）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// 位置posを指す空の範囲
+    pub fn at(pos: usize) -> Self {
+        Span::new(pos, pos)
+    }
+
+    /// 入力srcの一部であるpartの範囲を返す
+    ///
+    /// partがsrcの一部でない場合は、srcの末尾を指す空の範囲を返す。
+    pub fn of(src: &str, part: &str) -> Self {
+        let start = (part.as_ptr() as usize).wrapping_sub(src.as_ptr() as usize);
+        if start <= src.len() && part.len() <= src.len() - start {
+            Span::new(start, start + part.len())
+        } else {
+            Span::at(src.len())
+        }
+    }
+
+    /// 範囲の先頭の、入力srcにおける桁（0から始まる文字数）を返す
+    pub fn column(&self, src: &str) -> usize {
+        src[..self.start.min(src.len())].chars().count()
+    }
+
+    /// メッセージmsgに、入力srcと範囲を指すキャレットを付けた診断メッセージを返す
+    ///
+    /// 空の範囲は1文字分のキャレットで示す。
+    /// 書式の幅の指定は65535までしか扱えないため、キャレットの前の空白は繰り返して作る。
+    ///
+    /// ```text
+    /// ParseError: expected an operator
+    ///   1 + foo bar
+    ///           ^^^
+    /// ```
+    pub fn diagnostic(&self, src: &str, msg: impl Display) -> String {
+        let end = self.end.clamp(self.start, src.len());
+        let width = src.get(self.start..end).map_or(0, |s| s.chars().count());
+        format!(
+            "{msg}\n  {src}\n  {}{}",
+            " ".repeat(self.column(src)),
+            "^".repeat(width.max(1))
+        )
+    }
+}
+
+/// 字句の種類
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    Number,      // `42`、`3.14`、`1e-3`、`0xFF`、`1_000`のような数値
+    Ident,       // 英字または`_`で始まる識別子
+    Op,          // OPERATORSの演算子
+    LParen,      // `(`
+    RParen,      // `)`
+    Str(String), // エスケープを解釈した文字列リテラルの内容
+}
+
+/// 字句
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str, // 入力中の字句の文字列（文字列リテラルは引用符を含む）
+    pub span: Span,
+}
+
+/// 字句解析エラーの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LexErrorKind {
+    UnexpectedChar(char), // 字句とならない文字
+    UnterminatedString,   // 閉じる引用符がない文字列リテラル
+    InvalidEscape(char),  // 誤ったエスケープシーケンス
+}
+
+/// 字句解析エラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            LexErrorKind::UnexpectedChar(c) => write!(f, "LexError: unexpected character '{c}'"),
+            LexErrorKind::UnterminatedString => write!(f, "LexError: unterminated string literal"),
+            LexErrorKind::InvalidEscape(c) => write!(f, "LexError: invalid escape '\\{c}'"),
+        }
+    }
+}
+
+impl Error for LexError {}
+
+/// 入力を先頭から字句に分割するイテレータ
+///
+/// 字句の間の空白は読み飛ばす。エラーが発生した後は、それ以上字句を返さない。
+#[derive(Debug, Clone)]
+pub struct Lexer<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str) -> Self {
+        Lexer { src, pos: 0 }
+    }
+
+    /// まだ読み込んでいない入力を返す
+    pub fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.rest().trim_start();
+        let offset = self.src.len() - rest.len();
+        let shift = |span: Span| Span::new(span.start + offset, span.end + offset);
+        let result = match scan(rest) {
+            Ok(None) => return None,
+            Ok(Some(token)) => Ok(Token {
+                span: shift(token.span),
+                ..token
+            }),
+            Err(e) => Err(LexError {
+                span: shift(e.span),
+                ..e
+            }),
+        };
+        self.pos = match &result {
+            Ok(token) => token.span.end,
+            Err(_) => self.src.len(),
+        };
+        Some(result)
+    }
+}
+
+/// 入力src全体を字句に分割する
+pub fn tokenize(src: &str) -> Result<Vec<Token<'_>>, LexError> {
+    Lexer::new(src).collect()
+}
+
+/// 入力srcの先頭の字句を読み込む
+///
+/// srcが空の場合や空白で始まる場合はNoneを返す。字句の範囲はsrcの先頭からの位置となる。
+pub fn scan(src: &str) -> Result<Option<Token<'_>>, LexError> {
+    let Some(c) = src.chars().next() else {
+        return Ok(None);
+    };
+    let token = |kind, len| Token {
+        kind,
+        text: &src[..len],
+        span: Span::new(0, len),
+    };
+
+    let len = scan_number(src);
+    if len > 0 {
+        return Ok(Some(token(TokenKind::Number, len)));
+    }
+    let len = scan_ident(src);
+    if len > 0 {
+        return Ok(Some(token(TokenKind::Ident, len)));
+    }
+
+    match c {
+        c if c.is_whitespace() => Ok(None),
+        '(' => Ok(Some(token(TokenKind::LParen, 1))),
+        ')' => Ok(Some(token(TokenKind::RParen, 1))),
+        '"' => scan_string(src).map(|(s, len)| Some(token(TokenKind::Str(s), len))),
+        _ => match OPERATORS.iter().find(|op| src.starts_with(*op)) {
+            Some(op) => Ok(Some(token(TokenKind::Op, op.len()))),
+            None => Err(LexError {
+                kind: LexErrorKind::UnexpectedChar(c),
+                span: Span::new(0, c.len_utf8()),
+            }),
+        },
+    }
+}
+
+/// 入力srcの先頭の数値の長さを返す
+///
+/// 数値でない場合は0を返す。`0x`、`0b`、`0o`の接頭辞の後には、それぞれの基数の数字が1つ以上必要となる。
+/// 10進数は数字で始まり、小数点と指数の後にも数字が必要となる。数字の間には区切りの`_`を含められるが、
+/// その位置が正しいかは検査しない。符号は数値に含めない。
+pub fn scan_number(src: &str) -> usize {
+    let bytes = src.as_bytes();
+    // posから続く、digitを満たす文字と`_`の長さ
+    let run = |pos: usize, digit: fn(&u8) -> bool| {
+        bytes[pos.min(bytes.len())..]
+            .iter()
+            .take_while(|b| digit(b) || **b == b'_')
+            .count()
+    };
+    // posから続く、数字で始まる10進数の数字列の長さ
+    let digits = |pos: usize| match bytes.get(pos) {
+        Some(b) if b.is_ascii_digit() => run(pos, u8::is_ascii_digit),
+        _ => 0,
+    };
+
+    if let Some(prefix) = bytes.get(..2) {
+        let digit: Option<fn(&u8) -> bool> = match prefix {
+            b"0x" | b"0X" => Some(u8::is_ascii_hexdigit),
+            b"0b" | b"0B" => Some(|b| matches!(b, b'0' | b'1')),
+            b"0o" | b"0O" => Some(|b| matches!(b, b'0'..=b'7')),
+            _ => None,
+        };
+        if let Some(n) = digit.map(|digit| run(2, digit)).filter(|&n| n > 0) {
+            return 2 + n;
+        }
+    }
+
+    let mut len = digits(0);
+    if len == 0 {
+        return 0;
+    }
+    if bytes.get(len) == Some(&b'.') {
+        let n = digits(len + 1);
+        if n > 0 {
+            len += 1 + n;
+        }
+    }
+    if matches!(bytes.get(len), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(bytes.get(len + 1), Some(b'+' | b'-')));
+        let n = digits(len + 1 + sign);
+        if n > 0 {
+            len += 1 + sign + n;
+        }
+    }
+    len
+}
+
+/// 入力srcの先頭の識別子の長さを返す
+///
+/// 識別子は英字または`_`で始まり、英数字または`_`が続く。識別子でない場合は0を返す。
+pub fn scan_ident(src: &str) -> usize {
+    let mut chars = src.char_indices();
+    match chars.next() {
+        Some((_, c)) if c.is_alphabetic() || c == '_' => chars
+            .find(|(_, c)| !(c.is_alphanumeric() || *c == '_'))
+            .map_or(src.len(), |(i, _)| i),
+        _ => 0,
+    }
+}
+
+/// `"`で始まる文字列リテラルを読み込み、その内容と長さを返す
+fn scan_string(src: &str) -> Result<(String, usize), LexError> {
+    let mut s = String::new();
+    let mut chars = src.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((s, i + 1)),
+            '\\' => match chars.next() {
+                Some((j, c)) => s.push(unescape(c, "\"'").ok_or(LexError {
+                    kind: LexErrorKind::InvalidEscape(c),
+                    span: Span::new(i, j + c.len_utf8()),
+                })?),
+                None => break,
+            },
+            c => s.push(c),
+        }
+    }
+    Err(LexError {
+        kind: LexErrorKind::UnterminatedString,
+        span: Span::new(0, src.len()),
+    })
+}
+
+/// `\`に続く文字cが表す文字を返す
+///
+/// `\n`、`\t`、`\r`、`\0`、`\\`のほか、literalに含まれる記号はその文字自体を表す。
+/// それ以外の文字の場合はNoneを返す。
+pub fn unescape(c: char, literal: &str) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        '\\' => Some('\\'),
+        c if literal.contains(c) => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(src: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(src)
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.kind, t.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds("f(x_1) != -2.5e3"),
+            [
+                (Ident, "f"),
+                (LParen, "("),
+                (Ident, "x_1"),
+                (RParen, ")"),
+                (Op, "!="),
+                (Op, "-"),
+                (Number, "2.5e3"),
+            ]
+        );
+        assert_eq!(
+            kinds("[1..2] 3km 0xFF 0x"),
+            [
+                (Op, "["),
+                (Number, "1"),
+                (Op, ".."),
+                (Number, "2"),
+                (Op, "]"),
+                (Number, "3"),
+                (Ident, "km"),
+                (Number, "0xFF"),
+                (Number, "0"),
+                (Ident, "x"),
+            ]
+        );
+        assert_eq!(
+            kinds(r#""a\"b\n" "#),
+            [(Str("a\"b\n".to_string()), r#""a\"b\n""#)]
+        );
+        assert_eq!(kinds("  "), []);
+
+        let tokens = tokenize("é + 1").unwrap();
+        assert_eq!(tokens[1].span, Span::new(3, 4));
+    }
+
+    #[test]
+    fn test_scan_number() {
+        assert_eq!(scan_number("1_000+1"), 5);
+        assert_eq!(scan_number("1.x"), 1);
+        assert_eq!(scan_number("2e"), 1);
+        assert_eq!(scan_number("2e-3"), 4);
+        assert_eq!(scan_number("0b102"), 4);
+        assert_eq!(scan_number("_1"), 0);
+        assert_eq!(scan_number("-1"), 0);
+    }
+
+    #[test]
+    fn test_error() {
+        let e = tokenize("1 + @").unwrap_err();
+        assert_eq!(e.kind, LexErrorKind::UnexpectedChar('@'));
+        assert_eq!(e.span, Span::new(4, 5));
+        assert_eq!(e.to_string(), "LexError: unexpected character '@'");
+
+        let e = tokenize(r#"x "a\qb""#).unwrap_err();
+        assert_eq!(e.kind, LexErrorKind::InvalidEscape('q'));
+        assert_eq!(e.span, Span::new(4, 6));
+
+        let e = tokenize(r#"x "ab"#).unwrap_err();
+        assert_eq!(e.kind, LexErrorKind::UnterminatedString);
+        assert_eq!(e.span, Span::new(2, 5));
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let src = "1 + foo bar";
+        assert_eq!(
+            Span::new(8, 11).diagnostic(src, "error"),
+            "error\n  1 + foo bar\n          ^^^"
+        );
+        assert_eq!(
+            Span::at(src.len()).diagnostic(src, "error"),
+            "error\n  1 + foo bar\n             ^"
+        );
+        assert_eq!(Span::of(src, &src[4..7]), Span::new(4, 7));
+        assert_eq!(Span::new(2, 3).column("éé x"), 1);
+
+        // 65535桁を超える位置も示せる
+        let src = format!("{}+", "1 ".repeat(40000));
+        let d = Span::at(80000).diagnostic(&src, "error");
+        assert!(d.ends_with(&format!("\n  {}^", " ".repeat(80000))));
+    }
+}
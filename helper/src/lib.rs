@@ -0,0 +1,114 @@
+//! # 正規表現エンジンと電卓で共通に用いる補助クレート
+//!
+//! 整数の演算でオーバーフローを検査する`SafeArith`と、その結果をエラーに変換する関数を提供する。
+//! `trace`は、`-v`や環境変数RUST_LOGに応じてトレースを表示する。
+//! `lexer`は、電卓と正規表現のパーサが共有する、位置情報付きの字句解析器である。
+//! `rng`は、電卓の乱数関数と正規表現の文字列生成で用いる擬似乱数生成器である。
+//!
+//! ## 利用例
+//!
+//! ```
+//! use helper::{safe_add, SafeArith};
+//!
+//! let mut pc = 0usize;
+//! assert_eq!(safe_add(&mut pc, &1, || "overflow"), Ok(()));
+//! assert_eq!(pc, 1);
+//! assert_eq!(usize::MAX.safe_add(&pc), None);
+//! ```
+pub mod lexer;
+pub mod rng;
+pub mod trace;
+
+/// オーバーフローを検査する算術演算
+///
+/// 結果が型の範囲を超える場合はNoneを返す。
+pub trait SafeArith: Sized {
+    fn safe_add(&self, n: &Self) -> Option<Self>;
+    fn safe_sub(&self, n: &Self) -> Option<Self>;
+    fn safe_mul(&self, n: &Self) -> Option<Self>;
+}
+
+macro_rules! impl_safe_arith {
+    ($($t:ty),*) => {
+        $(
+            impl SafeArith for $t {
+                fn safe_add(&self, n: &Self) -> Option<Self> {
+                    self.checked_add(*n)
+                }
+
+                fn safe_sub(&self, n: &Self) -> Option<Self> {
+                    self.checked_sub(*n)
+                }
+
+                fn safe_mul(&self, n: &Self) -> Option<Self> {
+                    self.checked_mul(*n)
+                }
+            }
+        )*
+    };
+}
+
+impl_safe_arith!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+/// dstにsrcを加算する。オーバーフローする場合はdstを変更せず、f()をエラーとして返す
+pub fn safe_add<T, F, E>(dst: &mut T, src: &T, f: F) -> Result<(), E>
+where
+    T: SafeArith,
+    F: Fn() -> E,
+{
+    apply(dst, dst.safe_add(src), f)
+}
+
+/// dstからsrcを減算する。オーバーフローする場合はdstを変更せず、f()をエラーとして返す
+pub fn safe_sub<T, F, E>(dst: &mut T, src: &T, f: F) -> Result<(), E>
+where
+    T: SafeArith,
+    F: Fn() -> E,
+{
+    apply(dst, dst.safe_sub(src), f)
+}
+
+/// dstにsrcを乗算する。オーバーフローする場合はdstを変更せず、f()をエラーとして返す
+pub fn safe_mul<T, F, E>(dst: &mut T, src: &T, f: F) -> Result<(), E>
+where
+    T: SafeArith,
+    F: Fn() -> E,
+{
+    apply(dst, dst.safe_mul(src), f)
+}
+
+fn apply<T, F, E>(dst: &mut T, result: Option<T>, f: F) -> Result<(), E>
+where
+    F: Fn() -> E,
+{
+    if let Some(n) = result {
+        *dst = n;
+        Ok(())
+    } else {
+        Err(f())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_arith() {
+        let mut n = 10usize;
+        assert_eq!(safe_add(&mut n, &5, || "overflow"), Ok(()));
+        assert_eq!(safe_mul(&mut n, &2, || "overflow"), Ok(()));
+        assert_eq!(safe_sub(&mut n, &1, || "overflow"), Ok(()));
+        assert_eq!(n, 29);
+
+        assert_eq!(safe_sub(&mut n, &30, || "overflow"), Err("overflow"));
+        assert_eq!(
+            safe_mul(&mut n, &usize::MAX, || "overflow"),
+            Err("overflow")
+        );
+        assert_eq!(n, 29);
+
+        assert_eq!(i8::MIN.safe_sub(&1), None);
+        assert_eq!((-3i64).safe_mul(&4), Some(-12));
+    }
+}
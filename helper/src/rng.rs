@@ -0,0 +1,95 @@
+//! 乱数生成器
+//!
+//! 電卓の`rand()`や`rand_int(a, b)`、正規表現の`Regex::sample`で用いる擬似乱数生成器で、
+//! アルゴリズムにはSplitMix64を用いる。
+//! 同じシードからは常に同じ乱数列を生成するため、電卓のREPLの`:seed N`やzerogrepの`--seed`で
+//! シードを設定すると、結果を再現できる。シードを設定しない場合は、現在時刻をシードとする。
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 擬似乱数生成器
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// シードがseedの乱数生成器を返す
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    /// 64ビットの乱数を返す
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// 0以上1未満の浮動小数点数の乱数を返す
+    pub fn next_f64(&mut self) -> f64 {
+        // 上位53ビットを仮数として用いる
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// lo以上hi以下の整数の乱数を返す
+    ///
+    /// loがhiより大きい場合はNone。偏りが生じないよう、範囲外の値は棄却して生成し直す。
+    pub fn range(&mut self, lo: i64, hi: i64) -> Option<i64> {
+        if lo > hi {
+            return None;
+        }
+        // 範囲の幅から1を引いた値（i64の全範囲でもu64に収まる）
+        let span = hi.wrapping_sub(lo) as u64;
+        if span == u64::MAX {
+            return Some(self.next_u64() as i64);
+        }
+        let n = span + 1;
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return Some(lo.wrapping_add((x % n) as i64));
+            }
+        }
+    }
+}
+
+/// 現在時刻をシードとする
+impl Default for Rng {
+    fn default() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Rng::new(seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed() {
+        let (mut a, mut b) = (Rng::new(42), Rng::new(42));
+        for _ in 0..10 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+    }
+
+    #[test]
+    fn test_range() {
+        let mut rng = Rng::new(0);
+        for _ in 0..1000 {
+            let x = rng.range(-3, 3).unwrap();
+            assert!((-3..=3).contains(&x));
+            let f = rng.next_f64();
+            assert!((0.0..1.0).contains(&f));
+        }
+        assert_eq!(rng.range(5, 5), Some(5));
+        assert_eq!(rng.range(2, 1), None);
+        assert!(rng.range(i64::MIN, i64::MAX).is_some());
+    }
+}
@@ -0,0 +1,34 @@
+//! トレースの表示
+//!
+//! 正規表現エンジンと電卓は、パース、コード生成、評価をtracingのスパンで囲む。
+//! コマンドは`-v`の数に応じてinitでトレースの表示を有効にし、各スパンの所要時間を表示する。
+use std::io::{self, IsTerminal};
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+/// トレースを標準エラー出力に表示する
+///
+/// `-v`の数verboseが0の場合は警告のみを、1の場合はdebug、2以上の場合はtraceまでの
+/// targetsのトレースを表示する。環境変数RUST_LOGが設定されている場合は、その指定に従う。
+/// スパンは、終了時に所要時間とともに表示する。
+pub fn init(verbose: u8, targets: &[&str]) {
+    let level = match verbose {
+        0 => "warn",
+        1 => "debug",
+        _ => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        let directives = targets.iter().map(|t| format!(",{t}={level}"));
+        EnvFilter::new(
+            std::iter::once("warn".to_string())
+                .chain(directives)
+                .collect::<String>(),
+        )
+    });
+    // すでに初期化されている場合は、その設定のままとする
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .try_init();
+}
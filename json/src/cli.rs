@@ -0,0 +1,141 @@
+//! zerojsonコマンド
+//!
+//! JSONのファイル（指定がなければ標準入力）を読み込み、フィルタで取り出した値を整形して表示する。
+//! `zerojson`と`zero json`は、ともにmainを呼び出す。
+use crate::{parser, Filter, Value};
+use clap::{ArgAction, Parser};
+use std::{
+    error::Error,
+    ffi::OsString,
+    fmt::{self, Display},
+    fs,
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// zerojsonのエラー
+#[derive(Debug)]
+enum JsonError {
+    Json(Option<PathBuf>, crate::Error), // JSONやフィルタの誤りと、読み込んでいたファイル
+    Io(Option<PathBuf>, io::Error), // 入出力の失敗と、読み込んでいたファイル（標準入出力はNone）
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::Json(Some(path), e) => write!(f, "{}: {e}", path.display()),
+            JsonError::Json(None, e) => write!(f, "{e}"),
+            JsonError::Io(Some(path), e) => write!(f, "{}: {e}", path.display()),
+            JsonError::Io(None, e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for JsonError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            JsonError::Json(_, e) => Some(e),
+            JsonError::Io(_, e) => Some(e),
+        }
+    }
+}
+
+/// 出力の失敗
+impl From<io::Error> for JsonError {
+    fn from(e: io::Error) -> Self {
+        JsonError::Io(None, e)
+    }
+}
+
+/// Parse JSON and print the values selected by a jq-like filter
+#[derive(Parser, Debug)]
+#[command(name = "zerojson", version)]
+struct Args {
+    /// Filter selecting the values to print (e.g. `.items[].name | length`)
+    #[arg(default_value = ".")]
+    filter: String,
+
+    /// JSON files to read (standard input if none)
+    files: Vec<PathBuf>,
+
+    /// Print each value on a single line
+    #[arg(short, long)]
+    compact: bool,
+
+    /// Number of spaces per indentation level
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    indent: usize,
+
+    /// Print strings without quotes
+    #[arg(short, long)]
+    raw_output: bool,
+
+    /// Maximum nesting depth of arrays and objects
+    #[arg(long, value_name = "N", default_value_t = parser::DEFAULT_MAX_DEPTH)]
+    max_depth: usize,
+
+    /// Trace parsing and filtering with timings on stderr (-vv for more detail; RUST_LOG
+    /// overrides)
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+/// コマンドライン引数argsに従って実行する
+///
+/// argsの最初の要素はコマンド名とする。成功した場合は終了ステータス0を、エラーの場合は1を返す。
+pub fn main<I, T>(args: I) -> ExitCode
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    match run(Args::parse_from(args)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("zerojson: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// コマンドライン引数に従って実行する
+fn run(args: Args) -> Result<(), JsonError> {
+    helper::trace::init(args.verbose, &["json"]);
+    let filter = Filter::parse(&args.filter).map_err(|e| JsonError::Json(None, e.into()))?;
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    if args.files.is_empty() {
+        let mut src = String::new();
+        io::stdin().read_to_string(&mut src)?;
+        process(&src, None, &filter, &args, &mut out)?;
+    }
+    for path in &args.files {
+        let src = fs::read_to_string(path).map_err(|e| JsonError::Io(Some(path.clone()), e))?;
+        process(&src, Some(path), &filter, &args, &mut out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// JSONのテキストsrcをパースし、フィルタを適用した結果をoutに出力する
+///
+/// pathは、エラーメッセージに表示するファイル名。
+fn process<W: Write>(
+    src: &str,
+    path: Option<&Path>,
+    filter: &Filter,
+    args: &Args,
+    out: &mut W,
+) -> Result<(), JsonError> {
+    let error = |e: crate::Error| JsonError::Json(path.map(Path::to_path_buf), e);
+    let v = parser::parse_with_depth(src, args.max_depth).map_err(|e| error(e.into()))?;
+    for v in filter.apply(&v).map_err(|e| error(e.into()))? {
+        match v {
+            Value::String(s) if args.raw_output => writeln!(out, "{s}")?,
+            v if args.compact => writeln!(out, "{v}")?,
+            v => writeln!(out, "{}", v.pretty(args.indent))?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! JSONの処理のエラー
+//!
+//! パースとフィルタの各段階のエラーを1つの型にまとめる。
+use crate::{ParseError, QueryError};
+use std::{
+    error,
+    fmt::{self, Display},
+};
+
+/// JSONの処理のエラー
+#[derive(Debug)]
+pub enum Error {
+    Parse(ParseError), // JSONのテキストの誤り
+    Query(QueryError), // フィルタの誤りや、適用の失敗
+}
+
+impl Error {
+    /// エラーの段階を表すコード
+    ///
+    /// パースエラーは`parse`、フィルタのエラーは`query`となる。
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Parse(_) => "parse",
+            Error::Query(_) => "query",
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(e) => write!(f, "{e}"),
+            Error::Query(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Parse(e) => Some(e),
+            Error::Query(e) => Some(e),
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Error::Parse(e)
+    }
+}
+
+impl From<QueryError> for Error {
+    fn from(e: QueryError) -> Self {
+        Error::Query(e)
+    }
+}
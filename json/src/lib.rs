@@ -0,0 +1,26 @@
+//! # JSONを扱うクレート
+//!
+//! JSONのテキストをnomでパースして値（Value）に変換し、整形して表示する。
+//! `zerojson`コマンド（`cli`モジュール）は、jqのようなフィルタ（Filter）で値の一部を取り出して表示する。
+//!
+//! ## 利用例
+//!
+//! ```
+//! use json::{Filter, Value};
+//!
+//! let v = json::parse(r#"{"name": "zero", "tags": ["regex", "rpn"]}"#).unwrap();
+//! assert_eq!(v.to_string(), r#"{"name":"zero","tags":["regex","rpn"]}"#);
+//!
+//! let filter = Filter::parse(".tags[1]").unwrap();
+//! assert_eq!(filter.apply(&v).unwrap(), vec![Value::String("rpn".to_string())]);
+//! ```
+pub mod cli;
+mod error;
+pub mod parser;
+pub mod query;
+pub mod value;
+
+pub use error::Error;
+pub use parser::{parse, ParseError};
+pub use query::{Filter, QueryError};
+pub use value::Value;
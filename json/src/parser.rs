@@ -0,0 +1,448 @@
+//! JSONのテキストをパースし、値に変換
+//!
+//! RFC 8259に従い、値の前後の空白を除いてテキスト全体が1つの値である必要がある。
+//! 配列とオブジェクトのネストの深さには上限があり、深すぎる入力でスタックを使い果たすことはない。
+use crate::Value;
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_while},
+    character::complete::{char, digit0, digit1, one_of},
+    combinator::{map, opt, recognize, value},
+    error::ErrorKind,
+    sequence::{pair, preceded, tuple},
+    IResult,
+};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+use tracing::instrument;
+
+/// 配列とオブジェクトのネストの深さの上限のデフォルト値
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// パース結果を表すための型
+type PResult<'a, T> = IResult<&'a str, T, RawError<'a>>;
+
+/// パースエラーの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    ExpectedValue,           // 値がない
+    ExpectedChar(char),      // `:`などの文字がない
+    ExpectedKey,             // オブジェクトのキーの文字列がない
+    ExpectedSeparator(char), // `,`もしくは閉じ括弧がない
+    InvalidNumber,           // 数値の形式が誤っている
+    OutOfRange,              // 数値が範囲外
+    InvalidEscape,           // 文字列中のエスケープシーケンスが誤っている
+    ControlCharacter,        // 文字列中にエスケープされていない制御文字がある
+    UnterminatedString,      // 文字列が閉じられていない
+    TrailingInput,           // 値の後に余分な入力がある
+    TooDeep,                 // ネストが深すぎる
+    Nom(ErrorKind),          // その他のnomのエラー
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::ExpectedValue => write!(f, "expected a value"),
+            ParseErrorKind::ExpectedChar(c) => write!(f, "expected '{c}'"),
+            ParseErrorKind::ExpectedKey => write!(f, "expected a string key"),
+            ParseErrorKind::ExpectedSeparator(c) => write!(f, "expected ',' or '{c}'"),
+            ParseErrorKind::InvalidNumber => write!(f, "invalid number"),
+            ParseErrorKind::OutOfRange => write!(f, "number out of range"),
+            ParseErrorKind::InvalidEscape => write!(f, "invalid escape sequence"),
+            ParseErrorKind::ControlCharacter => {
+                write!(f, "unescaped control character in string")
+            }
+            ParseErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            ParseErrorKind::TrailingInput => write!(f, "unexpected input after the value"),
+            ParseErrorKind::TooDeep => write!(f, "value nested too deeply"),
+            ParseErrorKind::Nom(kind) => write!(f, "unexpected input ({})", kind.description()),
+        }
+    }
+}
+
+/// パースエラーを表すための型
+///
+/// line、columnは、エラーが発生した位置の1から始まる行番号と桁番号（文字単位）。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    /// テキストsrc中の、残りの入力がinputとなる位置で発生したエラーを生成
+    fn new(src: &str, input: &str, kind: ParseErrorKind) -> Self {
+        let consumed = &src[..src.len() - input.len()];
+        let line_start = consumed.rfind('\n').map_or(0, |i| i + 1);
+        ParseError {
+            line: consumed.matches('\n').count() + 1,
+            column: consumed[line_start..].chars().count() + 1,
+            kind,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ParseError: {} at line {}, column {}",
+            self.kind, self.line, self.column
+        )
+    }
+}
+
+impl Error for ParseError {}
+
+/// パース中のエラー
+///
+/// inputはエラーが発生した位置以降の入力。パースの終了後に行番号と桁番号に変換する。
+#[derive(Debug)]
+struct RawError<'a> {
+    input: &'a str,
+    kind: ParseErrorKind,
+}
+
+/// nomのパーサで利用するための実装
+impl<'a> nom::error::ParseError<&'a str> for RawError<'a> {
+    fn from_error_kind(input: &'a str, kind: ErrorKind) -> Self {
+        RawError {
+            input,
+            kind: ParseErrorKind::Nom(kind),
+        }
+    }
+
+    fn append(_input: &'a str, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    fn from_char(input: &'a str, c: char) -> Self {
+        RawError {
+            input,
+            kind: ParseErrorKind::ExpectedChar(c),
+        }
+    }
+
+    /// altで全ての候補が失敗した場合は、最も先まで読み進めた候補のエラーを採用する
+    fn or(self, other: Self) -> Self {
+        if other.input.len() < self.input.len() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// 回復不能なパースエラーを生成
+fn fail(c: &str, kind: ParseErrorKind) -> nom::Err<RawError<'_>> {
+    nom::Err::Failure(RawError { input: c, kind })
+}
+
+/// JSONのテキストをパースする
+///
+/// ネストの深さの上限はDEFAULT_MAX_DEPTHとなる。
+pub fn parse(src: &str) -> Result<Value, ParseError> {
+    parse_with_depth(src, DEFAULT_MAX_DEPTH)
+}
+
+/// 配列とオブジェクトのネストの深さの上限をmax_depthとして、JSONのテキストをパースする
+#[instrument(level = "debug", skip_all, fields(bytes = src.len()))]
+pub fn parse_with_depth(src: &str, max_depth: usize) -> Result<Value, ParseError> {
+    match parse_value(src.trim_start_matches(is_ws), max_depth) {
+        Ok((rest, v)) => {
+            let rest = rest.trim_start_matches(is_ws);
+            if rest.is_empty() {
+                Ok(v)
+            } else {
+                Err(ParseError::new(src, rest, ParseErrorKind::TrailingInput))
+            }
+        }
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+            Err(ParseError::new(src, e.input, e.kind))
+        }
+        Err(nom::Err::Incomplete(_)) => {
+            Err(ParseError::new(src, "", ParseErrorKind::ExpectedValue))
+        }
+    }
+}
+
+/// JSONの空白文字かを判定
+fn is_ws(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+/// 0文字以上の空白を読み飛ばす
+fn ws(c: &str) -> PResult<'_, &str> {
+    take_while(is_ws)(c)
+}
+
+/// 値をパースする
+///
+/// depthは、この値の中にネストできる配列とオブジェクトの残りの深さ。
+fn parse_value(c: &str, depth: usize) -> PResult<'_, Value> {
+    let result = alt((
+        value(Value::Null, tag("null")),
+        value(Value::Bool(true), tag("true")),
+        value(Value::Bool(false), tag("false")),
+        map(parse_number, Value::Number),
+        map(parse_string, Value::String),
+        |c| parse_array(c, depth),
+        |c| parse_object(c, depth),
+    ))(c);
+    match result {
+        // どの候補も先頭から読み進められなかった場合は、値がないものとする
+        Err(nom::Err::Error(e)) if e.input.len() == c.len() => {
+            Err(fail(c, ParseErrorKind::ExpectedValue))
+        }
+        Err(nom::Err::Error(e)) => Err(nom::Err::Failure(e)),
+        result => result,
+    }
+}
+
+/// 数値をパースする
+///
+/// `-`の後や、小数点と指数の後に数字がない場合は、回復不能なエラーとする。
+fn parse_number(c: &str) -> PResult<'_, f64> {
+    let integer = || alt((tag("0"), recognize(pair(one_of("123456789"), digit0))));
+    let (rest, s) = recognize(tuple((
+        alt((preceded(char('-'), required(integer())), integer())),
+        opt(pair(char('.'), required(digit1))),
+        opt(tuple((one_of("eE"), opt(one_of("+-")), required(digit1)))),
+    )))(c)?;
+    let n: f64 = s
+        .parse()
+        .map_err(|_| fail(c, ParseErrorKind::InvalidNumber))?;
+    if n.is_finite() {
+        Ok((rest, n))
+    } else {
+        Err(fail(c, ParseErrorKind::OutOfRange))
+    }
+}
+
+/// 数字が必要な位置で、パーサpが失敗した場合は回復不能なInvalidNumberのエラーとする
+fn required<'a, O>(
+    mut p: impl FnMut(&'a str) -> PResult<'a, O>,
+) -> impl FnMut(&'a str) -> PResult<'a, O> {
+    move |c| p(c).map_err(|_| fail(c, ParseErrorKind::InvalidNumber))
+}
+
+/// 文字列をパースし、エスケープシーケンスを展開した文字列を返す
+fn parse_string(c: &str) -> PResult<'_, String> {
+    let (mut rest, _) = char('"')(c)?;
+    let mut s = String::new();
+    loop {
+        let mut chars = rest.chars();
+        match chars.next() {
+            None => return Err(fail(c, ParseErrorKind::UnterminatedString)),
+            Some('"') => return Ok((chars.as_str(), s)),
+            Some('\\') => {
+                let (r, ch) = parse_escape(rest)?;
+                s.push(ch);
+                rest = r;
+            }
+            Some(ch) if ch < ' ' => return Err(fail(rest, ParseErrorKind::ControlCharacter)),
+            Some(ch) => {
+                s.push(ch);
+                rest = chars.as_str();
+            }
+        }
+    }
+}
+
+/// 先頭のJSONの文字列をパースし、残りの入力と文字列を返す
+///
+/// フィルタ中の`."key"`のようなキーのパースに用いる。
+pub(crate) fn parse_string_prefix(c: &str) -> Option<(&str, String)> {
+    parse_string(c).ok()
+}
+
+/// `\`から始まるエスケープシーケンスをパースする
+///
+/// `\uXXXX`のサロゲートペアは1文字に結合し、対になっていないサロゲートはエラーとする。
+fn parse_escape(c: &str) -> PResult<'_, char> {
+    let invalid = || fail(c, ParseErrorKind::InvalidEscape);
+    let mut chars = c[1..].chars();
+    let ch = match chars.next() {
+        Some('"') => '"',
+        Some('\\') => '\\',
+        Some('/') => '/',
+        Some('b') => '\u{8}',
+        Some('f') => '\u{c}',
+        Some('n') => '\n',
+        Some('r') => '\r',
+        Some('t') => '\t',
+        Some('u') => {
+            let (rest, hi) = hex4(chars.as_str()).ok_or_else(invalid)?;
+            if !(0xD800..0xDC00).contains(&hi) {
+                let ch = char::from_u32(hi).ok_or_else(invalid)?;
+                return Ok((rest, ch));
+            }
+            let (rest, lo) = rest
+                .strip_prefix("\\u")
+                .and_then(hex4)
+                .filter(|(_, lo)| (0xDC00..0xE000).contains(lo))
+                .ok_or_else(invalid)?;
+            let ch = char::from_u32(0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00));
+            return Ok((rest, ch.ok_or_else(invalid)?));
+        }
+        _ => return Err(invalid()),
+    };
+    Ok((chars.as_str(), ch))
+}
+
+/// 先頭の4桁の16進数を読み、残りの入力と値を返す
+fn hex4(c: &str) -> Option<(&str, u32)> {
+    let digits = c.get(..4)?;
+    if !digits.chars().all(|d| d.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some((&c[4..], u32::from_str_radix(digits, 16).ok()?))
+}
+
+/// 配列をパースする
+fn parse_array(c: &str, depth: usize) -> PResult<'_, Value> {
+    let (c, _) = char('[')(c)?;
+    let depth = depth
+        .checked_sub(1)
+        .ok_or_else(|| fail(c, ParseErrorKind::TooDeep))?;
+    let (mut c, _) = ws(c)?;
+    let mut values = Vec::new();
+    if let Some(rest) = c.strip_prefix(']') {
+        return Ok((rest, Value::Array(values)));
+    }
+
+    loop {
+        let (rest, v) = parse_value(c, depth).map_err(failure)?;
+        values.push(v);
+        let (rest, _) = ws(rest)?;
+        if let Some(rest) = rest.strip_prefix(',') {
+            c = ws(rest)?.0;
+        } else if let Some(rest) = rest.strip_prefix(']') {
+            return Ok((rest, Value::Array(values)));
+        } else {
+            return Err(fail(rest, ParseErrorKind::ExpectedSeparator(']')));
+        }
+    }
+}
+
+/// オブジェクトをパースする
+fn parse_object(c: &str, depth: usize) -> PResult<'_, Value> {
+    let (c, _) = char('{')(c)?;
+    let depth = depth
+        .checked_sub(1)
+        .ok_or_else(|| fail(c, ParseErrorKind::TooDeep))?;
+    let (mut c, _) = ws(c)?;
+    let mut members = Vec::new();
+    if let Some(rest) = c.strip_prefix('}') {
+        return Ok((rest, Value::Object(members)));
+    }
+
+    loop {
+        let (rest, key) = parse_string(c).map_err(|e| match e {
+            nom::Err::Error(_) => fail(c, ParseErrorKind::ExpectedKey),
+            e => e,
+        })?;
+        let (rest, _) = ws(rest)?;
+        let rest = rest
+            .strip_prefix(':')
+            .ok_or_else(|| fail(rest, ParseErrorKind::ExpectedChar(':')))?;
+        let (rest, _) = ws(rest)?;
+        let (rest, v) = parse_value(rest, depth).map_err(failure)?;
+        members.push((key, v));
+        let (rest, _) = ws(rest)?;
+        if let Some(rest) = rest.strip_prefix(',') {
+            c = ws(rest)?.0;
+        } else if let Some(rest) = rest.strip_prefix('}') {
+            return Ok((rest, Value::Object(members)));
+        } else {
+            return Err(fail(rest, ParseErrorKind::ExpectedSeparator('}')));
+        }
+    }
+}
+
+/// 回復可能なエラーを回復不能なエラーに変換する
+fn failure(e: nom::Err<RawError<'_>>) -> nom::Err<RawError<'_>> {
+    match e {
+        nom::Err::Error(e) => nom::Err::Failure(e),
+        e => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(" null "), Ok(Value::Null));
+        assert_eq!(parse("true"), Ok(Value::Bool(true)));
+        assert_eq!(parse("-0"), Ok(Value::Number(-0.0)));
+        assert_eq!(parse("12.5e-1"), Ok(Value::Number(1.25)));
+        assert_eq!(parse("1E2"), Ok(Value::Number(100.0)));
+        assert_eq!(parse(r#""a\"\\\/\né""#), Ok(s("a\"\\/\né")));
+        assert_eq!(parse(r#""😀""#), Ok(s("😀")));
+        assert_eq!(parse("[ ]"), Ok(Value::Array(Vec::new())));
+        assert_eq!(
+            parse("[1, [true], {}]"),
+            Ok(Value::Array(vec![
+                Value::Number(1.0),
+                Value::Array(vec![Value::Bool(true)]),
+                Value::Object(Vec::new()),
+            ]))
+        );
+        assert_eq!(
+            parse("{\n  \"a\": null,\n  \"b\": \"x\"\n}"),
+            Ok(Value::Object(vec![
+                ("a".to_string(), Value::Null),
+                ("b".to_string(), s("x")),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let err = |src: &str| {
+            let e = parse(src).unwrap_err();
+            (e.kind, e.line, e.column)
+        };
+        assert_eq!(err(""), (ParseErrorKind::ExpectedValue, 1, 1));
+        assert_eq!(err("nul"), (ParseErrorKind::ExpectedValue, 1, 1));
+        assert_eq!(err("[1,]"), (ParseErrorKind::ExpectedValue, 1, 4));
+        assert_eq!(err("[1 2]"), (ParseErrorKind::ExpectedSeparator(']'), 1, 4));
+        assert_eq!(
+            err("{\n\"a\" 1}"),
+            (ParseErrorKind::ExpectedChar(':'), 2, 5)
+        );
+        assert_eq!(err("{1: 2}"), (ParseErrorKind::ExpectedKey, 1, 2));
+        assert_eq!(err("01"), (ParseErrorKind::TrailingInput, 1, 2));
+        assert_eq!(err("-"), (ParseErrorKind::InvalidNumber, 1, 2));
+        assert_eq!(err("1."), (ParseErrorKind::InvalidNumber, 1, 3));
+        assert_eq!(err("1e999"), (ParseErrorKind::OutOfRange, 1, 1));
+        assert_eq!(err(r#""ab"#), (ParseErrorKind::UnterminatedString, 1, 1));
+        assert_eq!(err(r#""\x""#), (ParseErrorKind::InvalidEscape, 1, 2));
+        assert_eq!(err(r#""\ud83d""#), (ParseErrorKind::InvalidEscape, 1, 2));
+        assert_eq!(err("\"a\tb\""), (ParseErrorKind::ControlCharacter, 1, 3));
+        assert_eq!(
+            parse("[1,]").unwrap_err().to_string(),
+            "ParseError: expected a value at line 1, column 4"
+        );
+    }
+
+    #[test]
+    fn test_max_depth() {
+        assert!(parse_with_depth("[[1]]", 2).is_ok());
+        assert_eq!(
+            parse_with_depth("[{\"a\": 1}]", 1).unwrap_err().kind,
+            ParseErrorKind::TooDeep
+        );
+        let deep = "[".repeat(100_000);
+        assert_eq!(parse(&deep).unwrap_err().kind, ParseErrorKind::TooDeep);
+    }
+}
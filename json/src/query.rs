@@ -0,0 +1,317 @@
+//! jqのようなフィルタで、値の一部を取り出す
+//!
+//! フィルタは、以下の項を`|`でつないだものである。各項は入力の値1つから0個以上の値を生成し、
+//! 生成された値のそれぞれが次の項の入力となる。
+//!
+//! - `.`: 入力の値そのもの
+//! - `.key`、`."key"`、`.["key"]`: オブジェクトのメンバの値（メンバがない場合はnull）
+//! - `.[n]`: 配列のn番目の要素（負の数は末尾から数える。範囲外の場合はnull）
+//! - `.[]`: 配列の全要素、もしくはオブジェクトの全メンバの値
+//! - `keys`: オブジェクトのキーの配列（ソート済み）、もしくは配列の添字の配列
+//! - `length`: 文字列の文字数、配列とオブジェクトの要素数、数値の絶対値（nullは0）
+//! - `type`: 値の種類の名前
+//!
+//! `.a.b[0]`、`.items[].name`のように、パスの要素は続けて記述できる。
+use crate::{parser, Value};
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_while},
+    character::complete::{char, digit1, multispace0, satisfy},
+    combinator::{map, map_res, not, opt, recognize, success, value},
+    error::ErrorKind,
+    multi::{many0, separated_list1},
+    sequence::{delimited, pair, preceded, terminated},
+    IResult,
+};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+use tracing::instrument;
+
+/// フィルタのエラーを表す型
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    Syntax(usize),                        // 構文の誤りと、その位置（1から始まる桁番号）
+    Index(&'static str, String),          // 添字を適用できない値の種類と、添字
+    Iterate(&'static str),                // 要素を列挙できない値の種類
+    Function(&'static str, &'static str), // 関数を適用できない値の種類と、関数名
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::Syntax(column) => {
+                write!(f, "QueryError: syntax error at column {column}")
+            }
+            QueryError::Index(kind, index) => {
+                write!(f, "QueryError: cannot index {kind} with {index}")
+            }
+            QueryError::Iterate(kind) => write!(f, "QueryError: cannot iterate over {kind}"),
+            QueryError::Function(kind, name) => write!(f, "QueryError: {kind} has no {name}"),
+        }
+    }
+}
+
+impl Error for QueryError {}
+
+/// パスの要素
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Field(String), // .key
+    Index(i64),    // .[n]
+    Iterate,       // .[]
+}
+
+/// `|`で区切られたフィルタの項
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Path(Vec<Step>),
+    Keys,
+    Length,
+    Type,
+}
+
+/// パース済みのフィルタ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    terms: Vec<Term>,
+}
+
+/// パース結果を表すための型
+type QResult<'a, T> = IResult<&'a str, T>;
+
+impl Filter {
+    /// フィルタをパースする
+    pub fn parse(src: &str) -> Result<Filter, QueryError> {
+        let syntax_error =
+            |rest: &str| QueryError::Syntax(src[..src.len() - rest.len()].chars().count() + 1);
+        let pipe = delimited(multispace0, char('|'), multispace0);
+        match delimited(multispace0, separated_list1(pipe, parse_term), multispace0)(src) {
+            Ok(("", terms)) => Ok(Filter { terms }),
+            Ok((rest, _)) => Err(syntax_error(rest)),
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(syntax_error(e.input)),
+            Err(nom::Err::Incomplete(_)) => Err(syntax_error("")),
+        }
+    }
+
+    /// 値vにフィルタを適用し、生成された値を返す
+    #[instrument(level = "debug", skip_all, fields(terms = self.terms.len()))]
+    pub fn apply(&self, v: &Value) -> Result<Vec<Value>, QueryError> {
+        let mut values = vec![v.clone()];
+        for term in &self.terms {
+            let mut next = Vec::new();
+            for v in &values {
+                term.apply(v, &mut next)?;
+            }
+            values = next;
+        }
+        Ok(values)
+    }
+}
+
+impl Term {
+    /// 値vに項を適用し、生成された値をoutに追加する
+    fn apply(&self, v: &Value, out: &mut Vec<Value>) -> Result<(), QueryError> {
+        match self {
+            Term::Path(steps) => {
+                let mut values = vec![v.clone()];
+                for step in steps {
+                    let mut next = Vec::new();
+                    for v in &values {
+                        step.apply(v, &mut next)?;
+                    }
+                    values = next;
+                }
+                out.extend(values);
+            }
+            Term::Keys => match v {
+                Value::Object(members) => {
+                    let mut keys: Vec<&String> = members.iter().map(|(k, _)| k).collect();
+                    keys.sort();
+                    keys.dedup();
+                    out.push(Value::Array(
+                        keys.into_iter().map(|k| Value::String(k.clone())).collect(),
+                    ));
+                }
+                Value::Array(values) => out.push(Value::Array(
+                    (0..values.len()).map(|i| Value::Number(i as f64)).collect(),
+                )),
+                v => return Err(QueryError::Function(v.type_name(), "keys")),
+            },
+            Term::Length => {
+                let n = match v {
+                    Value::Null => 0.0,
+                    Value::Number(n) => n.abs(),
+                    Value::String(s) => s.chars().count() as f64,
+                    Value::Array(values) => values.len() as f64,
+                    Value::Object(members) => members.len() as f64,
+                    v => return Err(QueryError::Function(v.type_name(), "length")),
+                };
+                out.push(Value::Number(n));
+            }
+            Term::Type => out.push(Value::String(v.type_name().to_string())),
+        }
+        Ok(())
+    }
+}
+
+impl Step {
+    /// 値vにパスの要素を適用し、生成された値をoutに追加する
+    fn apply(&self, v: &Value, out: &mut Vec<Value>) -> Result<(), QueryError> {
+        match (self, v) {
+            (Step::Field(_) | Step::Index(_), Value::Null) => out.push(Value::Null),
+            (Step::Field(key), Value::Object(_)) => {
+                out.push(v.get(key).cloned().unwrap_or(Value::Null))
+            }
+            (Step::Field(key), v) => {
+                let key = Value::String(key.clone()).to_string();
+                return Err(QueryError::Index(v.type_name(), key));
+            }
+            (Step::Index(i), Value::Array(values)) => {
+                let index = if *i < 0 {
+                    values.len().checked_sub(i.unsigned_abs() as usize)
+                } else {
+                    Some(*i as usize)
+                };
+                out.push(
+                    index
+                        .and_then(|i| values.get(i))
+                        .cloned()
+                        .unwrap_or(Value::Null),
+                );
+            }
+            (Step::Index(i), v) => return Err(QueryError::Index(v.type_name(), i.to_string())),
+            (Step::Iterate, Value::Array(values)) => out.extend(values.iter().cloned()),
+            (Step::Iterate, Value::Object(members)) => {
+                out.extend(members.iter().map(|(_, v)| v.clone()))
+            }
+            (Step::Iterate, v) => return Err(QueryError::Iterate(v.type_name())),
+        }
+        Ok(())
+    }
+}
+
+/// 項をパースする
+fn parse_term(c: &str) -> QResult<'_, Term> {
+    alt((
+        value(Term::Keys, keyword("keys")),
+        value(Term::Length, keyword("length")),
+        value(Term::Type, keyword("type")),
+        map(parse_path, Term::Path),
+    ))(c)
+}
+
+/// 識別子の一部とならないキーワードkwをパースする
+fn keyword<'a>(kw: &'static str) -> impl FnMut(&'a str) -> QResult<'a, &'a str> {
+    terminated(tag(kw), not(satisfy(is_ident_char)))
+}
+
+/// `.`から始まるパスをパースする
+fn parse_path(c: &str) -> QResult<'_, Vec<Step>> {
+    let (c, first) = preceded(char('.'), opt(alt((parse_name, parse_bracket))))(c)?;
+    let (c, rest) = many0(alt((preceded(char('.'), parse_name), parse_bracket)))(c)?;
+    Ok((c, first.into_iter().chain(rest).collect()))
+}
+
+/// `.`の後のキーをパースする
+fn parse_name(c: &str) -> QResult<'_, Step> {
+    let ident = recognize(pair(
+        satisfy(|c| c.is_ascii_alphabetic() || c == '_'),
+        take_while(is_ident_char),
+    ));
+    alt((
+        map(ident, |s: &str| Step::Field(s.to_string())),
+        map(parse_key, Step::Field),
+    ))(c)
+}
+
+/// `[...]`をパースする
+fn parse_bracket(c: &str) -> QResult<'_, Step> {
+    let index = map_res(recognize(pair(opt(char('-')), digit1)), str::parse);
+    delimited(
+        pair(char('['), multispace0),
+        alt((
+            map(parse_key, Step::Field),
+            map(index, Step::Index),
+            success(Step::Iterate),
+        )),
+        pair(multispace0, char(']')),
+    )(c)
+}
+
+/// JSONの文字列として記述されたキーをパースする
+fn parse_key(c: &str) -> QResult<'_, String> {
+    parser::parse_string_prefix(c)
+        .ok_or_else(|| nom::Err::Error(nom::error::Error::new(c, ErrorKind::Char)))
+}
+
+/// 識別子に使える文字かを判定
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(filter: &str, input: &str) -> Result<String, QueryError> {
+        let values = Filter::parse(filter)?.apply(&crate::parse(input).unwrap())?;
+        Ok(values
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+
+    #[test]
+    fn test_apply() {
+        let input = r#"{"a": {"b": [1, 2, 3]}, "c d": "x", "items": [{"n": 1}, {"n": 2}]}"#;
+        assert_eq!(run(".", "[1]").unwrap(), "[1]");
+        assert_eq!(run(".a.b", input).unwrap(), "[1,2,3]");
+        assert_eq!(run(".a.b[0]", input).unwrap(), "1");
+        assert_eq!(run(".a.b[-1]", input).unwrap(), "3");
+        assert_eq!(run(".a.b[5]", input).unwrap(), "null");
+        assert_eq!(run(r#"."c d""#, input).unwrap(), r#""x""#);
+        assert_eq!(run(r#".[ "c d" ]"#, input).unwrap(), r#""x""#);
+        assert_eq!(run(".items[].n", input).unwrap(), "1 2");
+        assert_eq!(run(".items[] | .n", input).unwrap(), "1 2");
+        assert_eq!(run(".missing.x", input).unwrap(), "null");
+        assert_eq!(run("keys", input).unwrap(), r#"["a","c d","items"]"#);
+        assert_eq!(run(".a.b | keys", input).unwrap(), "[0,1,2]");
+        assert_eq!(
+            run(".[] | length", r#"["aé", [1], {}, null, -3]"#).unwrap(),
+            "2 1 0 0 3"
+        );
+        assert_eq!(
+            run(".[] | type", "[true, {}]").unwrap(),
+            r#""boolean" "object""#
+        );
+    }
+
+    #[test]
+    fn test_error() {
+        assert_eq!(
+            run(".a", "1"),
+            Err(QueryError::Index("number", "\"a\"".to_string()))
+        );
+        assert_eq!(
+            run(".[0]", "{}"),
+            Err(QueryError::Index("object", "0".to_string()))
+        );
+        assert_eq!(run(".[]", "\"s\""), Err(QueryError::Iterate("string")));
+        assert_eq!(
+            run("length", "true"),
+            Err(QueryError::Function("boolean", "length"))
+        );
+        assert_eq!(
+            run(".a", "1").unwrap_err().to_string(),
+            "QueryError: cannot index number with \"a\""
+        );
+
+        assert_eq!(Filter::parse(""), Err(QueryError::Syntax(1)));
+        assert_eq!(Filter::parse(".a |"), Err(QueryError::Syntax(4)));
+        assert_eq!(Filter::parse(".a[0"), Err(QueryError::Syntax(3)));
+        assert_eq!(Filter::parse("keysx"), Err(QueryError::Syntax(1)));
+    }
+}
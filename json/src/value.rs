@@ -0,0 +1,190 @@
+//! JSONの値
+//!
+//! Displayは空白を含まない1行の表現を、`{:#}`は2文字ずつインデントした複数行の表現を出力する。
+//! インデント幅を指定する場合はprettyを用いる。
+use std::fmt::{self, Display, Write};
+
+/// JSONの値を表す型
+///
+/// オブジェクトは、テキスト中に現れた順にメンバを保持する。
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// オブジェクトのメンバkeyの値を返す
+    ///
+    /// 同じキーが複数ある場合は、最後のメンバの値を返す。
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// 値の種類の名前を返す
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }
+    }
+
+    /// 1段階あたりindent文字の空白でインデントした、複数行の表現を返す
+    ///
+    /// 空の配列とオブジェクトは、`[]`、`{}`と1行で表示する。
+    pub fn pretty(&self, indent: usize) -> String {
+        let mut s = String::new();
+        self.write_pretty(&mut s, indent, 0)
+            .expect("writing to a String never fails");
+        s
+    }
+
+    /// 深さdepthの値として、インデントした表現をwに出力する
+    fn write_pretty<W: Write>(&self, w: &mut W, indent: usize, depth: usize) -> fmt::Result {
+        let pad = |w: &mut W, depth: usize| write!(w, "{:width$}", "", width = indent * depth);
+        match self {
+            Value::Array(values) if !values.is_empty() => {
+                writeln!(w, "[")?;
+                for (i, v) in values.iter().enumerate() {
+                    pad(w, depth + 1)?;
+                    v.write_pretty(w, indent, depth + 1)?;
+                    writeln!(w, "{}", if i + 1 < values.len() { "," } else { "" })?;
+                }
+                pad(w, depth)?;
+                write!(w, "]")
+            }
+            Value::Object(members) if !members.is_empty() => {
+                writeln!(w, "{{")?;
+                for (i, (k, v)) in members.iter().enumerate() {
+                    pad(w, depth + 1)?;
+                    write_string(w, k)?;
+                    write!(w, ": ")?;
+                    v.write_pretty(w, indent, depth + 1)?;
+                    writeln!(w, "{}", if i + 1 < members.len() { "," } else { "" })?;
+                }
+                pad(w, depth)?;
+                write!(w, "}}")
+            }
+            v => write!(w, "{v}"),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return self.write_pretty(f, 2, 0);
+        }
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::String(s) => write_string(f, s),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{v}")?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, k)?;
+                    write!(f, ":{v}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// 文字列sを、必要な文字をエスケープしたJSONの文字列としてwに出力する
+fn write_string<W: Write>(w: &mut W, s: &str) -> fmt::Result {
+    w.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\r' => w.write_str("\\r")?,
+            '\t' => w.write_str("\\t")?,
+            '\u{8}' => w.write_str("\\b")?,
+            '\u{c}' => w.write_str("\\f")?,
+            c if c < ' ' => write!(w, "\\u{:04x}", c as u32)?,
+            c => w.write_char(c)?,
+        }
+    }
+    w.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Value {
+        Value::Object(vec![
+            ("a".to_string(), Value::Number(1.0)),
+            (
+                "b".to_string(),
+                Value::Array(vec![Value::Bool(true), Value::Null, Value::Number(-2.5)]),
+            ),
+            ("c".to_string(), Value::Object(Vec::new())),
+            ("d\n".to_string(), Value::String("\"x\"\u{1}".to_string())),
+        ])
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            sample().to_string(),
+            r#"{"a":1,"b":[true,null,-2.5],"c":{},"d\n":"\"x\"\u0001"}"#
+        );
+        assert_eq!(Value::Array(Vec::new()).to_string(), "[]");
+    }
+
+    #[test]
+    fn test_pretty() {
+        let expected = r#"{
+  "a": 1,
+  "b": [
+    true,
+    null,
+    -2.5
+  ],
+  "c": {},
+  "d\n": "\"x\"\u0001"
+}"#;
+        assert_eq!(format!("{:#}", sample()), expected);
+        assert_eq!(sample().pretty(2), expected);
+        assert_eq!(
+            Value::Array(vec![Value::Number(1.0)]).pretty(4),
+            "[\n    1\n]"
+        );
+    }
+
+    #[test]
+    fn test_get() {
+        let v = sample();
+        assert_eq!(v.get("a"), Some(&Value::Number(1.0)));
+        assert_eq!(v.get("x"), None);
+        assert_eq!(Value::Null.get("a"), None);
+    }
+}
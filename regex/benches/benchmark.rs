@@ -3,7 +3,7 @@
 //! ## 計測方法
 //! a?^n a^nという正規表現を、a^nという文字列にマッチさせる。
 //! ただし、a?^nとa^nは、a?とaのn回の繰り返し。
-//! 計測は幅優先と深さ優先で行う。
+//! 計測は幅優先、深さ優先、DFAで行う。
 //!
 //! ## n = 3の場合の例
 //!
@@ -22,7 +22,7 @@
 //! 実行後は、target/criterion/reports/index.htmlというファイルが生成されるため、
 //! それをWebブラウザで閲覧する。
 use criterion::{criterion_group, criterion_main, Criterion};
-use regex::do_matching;
+use regex::{do_matching_with, Backend};
 use std::time::Duration;
 
 /// (計測のid、a?^n a^nという正規表現、文字列)というタプル
@@ -65,21 +65,32 @@ fn depth_first(c: &mut Criterion) {
 
     for i in INPUTS {
         g.bench_with_input(i.0, &(i.1, i.2), |b, args| {
-            b.iter(|| do_matching(args.0, args.1))
+            b.iter(|| do_matching_with(args.0, args.1, true, Backend::DepthFirst))
         });
     }
 }
 
-// fn width_first(c: &mut Criterion) {
-//     let mut g = c.benchmark_group("Width First");
-//     g.measurement_time(Duration::from_secs(12));
+fn width_first(c: &mut Criterion) {
+    let mut g = c.benchmark_group("Width First");
+    g.measurement_time(Duration::from_secs(12));
+
+    for i in INPUTS {
+        g.bench_with_input(i.0, &(i.1, i.2), |b, args| {
+            b.iter(|| do_matching_with(args.0, args.1, true, Backend::BreadthFirst))
+        });
+    }
+}
 
-//     for i in INPUTS {
-//         g.bench_with_input(i.0, &(i.1, i.2), |b, args| {
-//             b.iter(|| do_matching(args.0, args.1))
-//         });
-//     }
-// }
+fn dfa(c: &mut Criterion) {
+    let mut g = c.benchmark_group("DFA");
+    g.measurement_time(Duration::from_secs(12));
+
+    for i in INPUTS {
+        g.bench_with_input(i.0, &(i.1, i.2), |b, args| {
+            b.iter(|| do_matching_with(args.0, args.1, true, Backend::Dfa))
+        });
+    }
+}
 
-criterion_group!(benches, depth_first);
+criterion_group!(benches, depth_first, width_first, dfa);
 criterion_main!(benches);
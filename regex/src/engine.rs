@@ -1,15 +1,19 @@
 //! 正規表現エンジン
 mod codegen;
-mod evaluator;
-mod parser;
+pub(crate) mod dfa;
+pub(crate) mod evaluator;
+pub(crate) mod parser;
+mod sampler;
 
-// use crate::helper::DynError;
+use crate::Error;
+use helper::rng::Rng;
 use std::fmt::{self, Display};
+use tracing::instrument;
 
-use crate::helper::DynError;
+pub use self::{codegen::CodeGenError, evaluator::EvalError, parser::ParseError};
 
 /// 命令列
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     Char(char),
     Match,
@@ -18,6 +22,15 @@ pub enum Instruction {
     AnyChar,
     HeadOfLine,
     EndOfLine,
+    Save(usize),
+    Class(Vec<(char, char)>, bool), // 文字の範囲の一覧と、否定するか（globの`[...]`などに用いる）
+}
+
+impl Instruction {
+    /// 文字cが、Class命令の文字の範囲rangesにマッチするかを判定
+    fn class_matches(ranges: &[(char, char)], negated: bool, c: char) -> bool {
+        ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != negated
+    }
 }
 
 impl Display for Instruction {
@@ -30,6 +43,18 @@ impl Display for Instruction {
             Instruction::AnyChar => write!(f, "anychar"),
             Instruction::HeadOfLine => write!(f, "headofline"),
             Instruction::EndOfLine => write!(f, "endofline"),
+            Instruction::Save(slot) => write!(f, "save {}", slot),
+            Instruction::Class(ranges, negated) => {
+                write!(f, "class [{}", if *negated { "^" } else { "" })?;
+                for (lo, hi) in ranges {
+                    if lo == hi {
+                        write!(f, "{lo}")?;
+                    } else {
+                        write!(f, "{lo}-{hi}")?;
+                    }
+                }
+                write!(f, "]")
+            }
         }
     }
 }
@@ -43,12 +68,12 @@ impl Display for Instruction {
 /// use regex;
 /// regex::print("abc|(de|cd)+");
 /// ```
-pub fn print(expr: &str) -> Result<(), DynError> {
+pub fn print(expr: &str) -> Result<(), Error> {
     println!("expr: {expr}");
     let ast = parser::parse(expr)?;
     println!("AST: {:?}", ast);
 
-    println!("");
+    println!();
     println!("code:");
     let code = codegen::gen_code(&ast)?;
     for (n, c) in code.iter().enumerate() {
@@ -58,6 +83,29 @@ pub fn print(expr: &str) -> Result<(), DynError> {
     Ok(())
 }
 
+/// マッチングに用いる評価器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    DepthFirst,   // 深さ優先探索（バックトラック）
+    BreadthFirst, // 幅優先探索
+    Dfa,          // 遅延評価のDFA
+}
+
+impl Backend {
+    /// 利用可能なすべての評価器
+    pub const ALL: [Backend; 3] = [Backend::DepthFirst, Backend::BreadthFirst, Backend::Dfa];
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::DepthFirst => write!(f, "dfs"),
+            Backend::BreadthFirst => write!(f, "bfs"),
+            Backend::Dfa => write!(f, "dfa"),
+        }
+    }
+}
+
 /// 正規表現と文字列をマッチング
 ///
 /// # 利用例
@@ -70,7 +118,8 @@ pub fn print(expr: &str) -> Result<(), DynError> {
 /// # 引数
 ///
 /// exprに正規表現、lineにマッチ対象とする文字列を与える。
-/// is_depthがtrueの場合は深さ優先探索を、falseの場合は幅優先探索を利用。
+/// include_head_of_lineがtrueの場合は、lineの先頭を行頭とみなす。
+/// 評価には深さ優先探索を利用。
 ///
 /// # 返り値
 ///
@@ -78,9 +127,363 @@ pub fn print(expr: &str) -> Result<(), DynError> {
 /// エラーなく実行でき、かつマッチングに**失敗**した場合はOk(false)を返す。
 ///
 /// 入力された正規表現にエラーがあったり、内部的な実装エラーがある場合はErrを返す。
-pub fn do_matching(expr: &str, line: &str, include_head_of_line: bool) -> Result<bool, DynError> {
-    let ast = parser::parse(expr)?;
-    let code = codegen::gen_code(&ast)?;
+pub fn do_matching(expr: &str, line: &str, include_head_of_line: bool) -> Result<bool, Error> {
+    do_matching_with(expr, line, include_head_of_line, Backend::DepthFirst)
+}
+
+/// 評価器を指定して、正規表現と文字列をマッチング
+///
+/// # 利用例
+///
+/// ```
+/// use regex::{self, Backend};
+/// regex::do_matching_with("abc|(de|cd)+", "decddede", true, Backend::BreadthFirst);
+/// ```
+pub fn do_matching_with(
+    expr: &str,
+    line: &str,
+    include_head_of_line: bool,
+    backend: Backend,
+) -> Result<bool, Error> {
+    let code = compile(expr)?;
     let line = line.chars().collect::<Vec<_>>();
-    Ok(evaluator::eval(&code, &line, include_head_of_line)?)
+    match backend {
+        Backend::DepthFirst => Ok(evaluator::eval(&code, &line, include_head_of_line)?),
+        Backend::BreadthFirst => Ok(evaluator::eval_width(&code, &line, include_head_of_line)?),
+        Backend::Dfa => Ok(dfa::Dfa::new(&code).is_match(&line, include_head_of_line)?),
+    }
+}
+
+/// 正規表現をパースし、キャプチャ位置を保存しない命令列を生成する
+pub(crate) fn compile(expr: &str) -> Result<Vec<Instruction>, Error> {
+    let ast = parser::parse(expr)?;
+    Ok(codegen::gen_code(&ast)?)
+}
+
+/// コンパイル済みの正規表現
+///
+/// 同じ正規表現で何度もマッチングを行う場合に、パースとコード生成を1度で済ませるために利用する。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::Regex;
+/// let re = Regex::new("(a+)b").unwrap();
+/// assert_eq!(re.replace_all("xaab aab", "<$1>").unwrap(), "x<aa> <aa>");
+/// ```
+#[derive(Debug)]
+pub struct Regex {
+    code: Vec<Instruction>,
+    slots: usize,
+}
+
+impl Regex {
+    /// 正規表現をパースしてコード生成する
+    #[instrument(level = "debug")]
+    pub fn new(expr: &str) -> Result<Regex, Error> {
+        let ast = parser::parse(expr)?;
+        let (code, slots) = codegen::gen_code_with_captures(&ast)?;
+        Ok(Regex { code, slots })
+    }
+
+    /// 正規表現に行全体がマッチする、max_len文字以下の文字列を乱数rngで生成する
+    ///
+    /// テストデータの作成や、正規表現が意図した文字列を表すかの確認に利用する。
+    /// `.`にはASCIIの表示可能な文字を生成する。そのような文字列がない場合はNone。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex::{Regex, Rng};
+    /// let re = Regex::new("a(b|c)+").unwrap();
+    /// let s = re.sample(&mut Rng::new(42), 8).unwrap();
+    /// assert!(s.starts_with('a') && s.len() <= 8);
+    /// assert_eq!(Regex::new("abc").unwrap().sample(&mut Rng::new(42), 2), None);
+    /// ```
+    pub fn sample(&self, rng: &mut Rng, max_len: usize) -> Option<String> {
+        sampler::sample(&self.code, rng, max_len)
+    }
+
+    /// lineの中で正規表現にマッチする箇所を、行頭から順に列挙するイテレータを返す
+    ///
+    /// マッチした箇所は互いに重ならない。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex::Regex;
+    /// let re = Regex::new("ab+").unwrap();
+    /// let found = re
+    ///     .find_iter("ab abbb a")
+    ///     .map(|m| m.unwrap().as_str())
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(found, vec!["ab", "abbb"]);
+    /// ```
+    pub fn find_iter<'r, 't>(&'r self, line: &'t str) -> Matches<'r, 't> {
+        // 文字単位の位置からバイト単位の位置への対応表
+        let mut offsets = line.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
+        offsets.push(line.len());
+
+        Matches {
+            inner: CaptureMatches::new(self, line),
+            line,
+            offsets,
+        }
+    }
+
+    /// lineにマッチするすべての箇所を、templateで置き換えた文字列を返す
+    ///
+    /// マッチは行頭から順に、互いに重ならないように探索する。
+    /// templateでは`$0`がマッチ全体に、`$1`, `$2`, ...がキャプチャグループに置き換えられ、
+    /// `$$`は`$`そのものを表す。マッチしなかったグループは空文字列となる。
+    pub fn replace_all(&self, line: &str, template: &str) -> Result<String, Error> {
+        let mut it = CaptureMatches::new(self, line);
+        let mut result = String::new();
+        let mut last = 0; // 置き換え済みの位置
+
+        while let Some(caps) = it.next() {
+            let caps = caps?;
+            let (start, end) = match_range(&caps);
+            result.extend(&it.chars[last..start]);
+            expand(template, &caps, &it.chars, &mut result);
+            last = end;
+        }
+
+        result.extend(&it.chars[last..]);
+        Ok(result)
+    }
+}
+
+/// 正規表現にマッチした箇所
+#[derive(Debug, PartialEq)]
+pub struct Match<'t> {
+    line: &'t str,
+    start: usize,
+    end: usize,
+}
+
+impl<'t> Match<'t> {
+    /// マッチした箇所の開始位置（バイト単位）
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// マッチした箇所の終了位置（バイト単位）
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// マッチした文字列
+    pub fn as_str(&self) -> &'t str {
+        &self.line[self.start..self.end]
+    }
+}
+
+/// Regex::find_iterが返すイテレータ
+#[derive(Debug)]
+pub struct Matches<'r, 't> {
+    inner: CaptureMatches<'r>,
+    line: &'t str,
+    offsets: Vec<usize>,
+}
+
+impl<'t> Iterator for Matches<'_, 't> {
+    type Item = Result<Match<'t>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let caps = match self.inner.next()? {
+            Ok(caps) => caps,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let (start, end) = match_range(&caps);
+        Some(Ok(Match {
+            line: self.line,
+            start: self.offsets[start],
+            end: self.offsets[end],
+        }))
+    }
+}
+
+/// マッチした箇所のキャプチャ位置を、行頭から順に列挙するイテレータ
+///
+/// 位置は文字単位で、chars全体の先頭からの位置となる。
+#[derive(Debug)]
+struct CaptureMatches<'r> {
+    re: &'r Regex,
+    chars: Vec<char>,
+    pos: usize, // 次にマッチングを開始する位置
+}
+
+impl<'r> CaptureMatches<'r> {
+    fn new(re: &'r Regex, line: &str) -> CaptureMatches<'r> {
+        CaptureMatches {
+            re,
+            chars: line.chars().collect(),
+            pos: 0,
+        }
+    }
+}
+
+impl Iterator for CaptureMatches<'_> {
+    type Item = Result<Vec<Option<usize>>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos <= self.chars.len() {
+            let pos = self.pos;
+            let caps = match evaluator::eval_captures(
+                &self.re.code,
+                &self.chars[pos..],
+                pos == 0,
+                self.re.slots,
+            ) {
+                Ok(Some(caps)) => caps,
+                Ok(None) => {
+                    self.pos += 1;
+                    continue;
+                }
+                Err(e) => {
+                    // エラー後は列挙を終了する
+                    self.pos = self.chars.len() + 1;
+                    return Some(Err(e.into()));
+                }
+            };
+
+            // capsはchars[pos..]の先頭からの位置なので、chars全体での位置に変換
+            let caps = caps.iter().map(|c| c.map(|c| c + pos)).collect::<Vec<_>>();
+            let (start, end) = match_range(&caps);
+
+            // 空文字列にマッチした場合は、無限ループにならないよう1文字進める
+            self.pos = if end == start { end + 1 } else { end };
+            return Some(Ok(caps));
+        }
+
+        None
+    }
+}
+
+/// キャプチャ位置から、マッチ全体の開始・終了位置を取り出す
+fn match_range(caps: &[Option<usize>]) -> (usize, usize) {
+    match (caps[0], caps[1]) {
+        (Some(start), Some(end)) => (start, end),
+        _ => unreachable!("slot 0 and 1 are always saved on match"),
+    }
+}
+
+/// 置換テンプレートを展開してresultに追加
+fn expand(template: &str, caps: &[Option<usize>], chars: &[char], result: &mut String) {
+    let mut it = template.chars().peekable();
+    while let Some(c) = it.next() {
+        if c != '$' {
+            result.push(c);
+            continue;
+        }
+
+        if it.next_if_eq(&'$').is_some() {
+            result.push('$');
+            continue;
+        }
+
+        // `$`に続く数字をグループ番号として読み込む
+        let mut digits = String::new();
+        while let Some(d) = it.next_if(|d| d.is_ascii_digit()) {
+            digits.push(d);
+        }
+
+        match digits.parse::<usize>() {
+            Ok(n) => {
+                let start = caps.get(n * 2).copied().flatten();
+                let end = caps.get(n * 2 + 1).copied().flatten();
+                if let (Some(start), Some(end)) = (start, end) {
+                    result.extend(&chars[start..end]);
+                }
+            }
+            Err(_) => {
+                // 数字が続かない`$`はそのまま出力
+                result.push('$');
+                result.push_str(&digits);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backends() {
+        let cases = [
+            ("abc", "xabc", false),
+            ("abc", "abc", true),
+            ("a(b|c)+d", "abcbd", true),
+            ("a?a?aa", "aa", true),
+            ("^ab$", "ab", true),
+            ("^ab$", "abc", false),
+            ("a.c", "ac", false),
+            ("^$", "", true),
+            ("a$|b", "a", true),
+            ("a$|c", "ab", false),
+            ("(a*)*b", "aab", true),
+            // 空文字列の繰り返しで無限に再帰しない
+            ("(a*)*c", "aab", false),
+            ("((^)*)+", "", true),
+        ];
+        for (expr, line, expected) in cases {
+            for backend in Backend::ALL {
+                assert_eq!(
+                    do_matching_with(expr, line, true, backend).unwrap(),
+                    expected,
+                    "{expr} {line} {backend}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_iter() {
+        let find = |expr, line| {
+            Regex::new(expr)
+                .unwrap()
+                .find_iter(line)
+                .map(|m| {
+                    let m = m.unwrap();
+                    (m.start(), m.end())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(find("ab", "abxab"), vec![(0, 2), (3, 5)]);
+        assert_eq!(find("a+", "aaa"), vec![(0, 3)]);
+        assert_eq!(find("x", "abc"), vec![]);
+        assert_eq!(find("b*", "ab"), vec![(0, 0), (1, 2), (2, 2)]);
+        assert_eq!(find("(a*)*b", "xaab"), vec![(1, 4)]);
+        // 空文字列にマッチした直後は繰り返しを抜ける
+        assert_eq!(find("(^|b)*", "b"), vec![(0, 0), (1, 1)]);
+
+        // 位置はバイト単位
+        assert_eq!(find("い", "あいう"), vec![(3, 6)]);
+    }
+
+    #[test]
+    fn test_replace_all() {
+        let re = Regex::new("a(b|c)").unwrap();
+        assert_eq!(re.replace_all("xabyac", "[$1]").unwrap(), "x[b]y[c]");
+        assert_eq!(re.replace_all("xabyac", "$0$0").unwrap(), "xababyacac");
+        assert_eq!(re.replace_all("xyz", "-").unwrap(), "xyz");
+
+        // `$$`と、数字が続かない`$`
+        assert_eq!(re.replace_all("ab", "$$1 $x").unwrap(), "$1 $x");
+
+        // 存在しないグループは空文字列
+        assert_eq!(re.replace_all("ab", "<$9>").unwrap(), "<>");
+
+        // 空文字列へのマッチ
+        let re = Regex::new("b*").unwrap();
+        assert_eq!(re.replace_all("abc", "-").unwrap(), "-a--c-");
+
+        // 行頭・行末
+        let re = Regex::new("^a|c$").unwrap();
+        assert_eq!(re.replace_all("abcac", "_").unwrap(), "_bca_");
+    }
 }
@@ -1,15 +1,17 @@
 //! ASTからコード生成を行う
 use super::{parser::AST, Instruction};
-use crate::helper::safe_add;
+use helper::{safe_add, safe_mul};
 use std::{
     error::Error,
     fmt::{self, Display},
 };
+use tracing::instrument;
 
 /// コード生成エラーを表す型
 #[derive(Debug)]
 pub enum CodeGenError {
     PCOverFlow,
+    SlotOverFlow,
     FailStar,
     FailOr,
     FailQuestion,
@@ -28,15 +30,40 @@ impl Error for CodeGenError {}
 struct Generator {
     pc: usize,
     insts: Vec<Instruction>,
+    captures: bool, // キャプチャ位置を保存するsave命令を生成するか
+    groups: usize,  // キャプチャグループの最大番号
 }
 
 /// コード生成を行う関数
+#[instrument(level = "trace", skip_all)]
 pub fn gen_code(ast: &AST) -> Result<Vec<Instruction>, CodeGenError> {
     let mut generator = Generator::default();
     generator.gen_code(ast)?;
     Ok(generator.insts)
 }
 
+/// キャプチャ位置を保存するsave命令を含めてコード生成を行う関数
+///
+/// マッチ全体の開始・終了位置をスロット0, 1に、
+/// n番目のキャプチャグループの開始・終了位置をスロット2n, 2n+1に保存する。
+/// 命令列と、必要なスロット数を返す。
+#[instrument(level = "trace", skip_all)]
+pub fn gen_code_with_captures(ast: &AST) -> Result<(Vec<Instruction>, usize), CodeGenError> {
+    let mut generator = Generator {
+        captures: true,
+        ..Generator::default()
+    };
+    generator.gen_save(0)?;
+    generator.gen_expr(ast)?;
+    generator.gen_save(1)?;
+    generator.inc_pc()?;
+    generator.insts.push(Instruction::Match);
+    // 最後のスロットは、最大番号のキャプチャグループの終了位置
+    let (_, mut slots) = slot_pair(generator.groups)?;
+    safe_add(&mut slots, &1, || CodeGenError::SlotOverFlow)?;
+    Ok((generator.insts, slots))
+}
+
 /// コード生成器のメソッド定義
 impl Generator {
     /// コード生成を行う関数の入り口
@@ -54,22 +81,17 @@ impl Generator {
             AST::Or(e1, e2) => self.gen_or(e1, e2)?,
             AST::Plus(e) => self.gen_plus(e)?,
             AST::Star(e1) => {
-                match &**e1 {
-                    // `(a*)*`のように`Star`が二重になっている場合にスタックオーバーフローする問題を回避するため、
-                    // このような`(((r*)*)*...*)*`を再帰的に処理して1つの`r*`へと変換する。
-                    AST::Star(_) => self.gen_expr(&e1)?,
-                    AST::Seq(e2) if e2.len() == 1 => {
-                        if let Some(e3 @ AST::Star(_)) = e2.get(0) {
-                            self.gen_expr(e3)?
-                        } else {
-                            self.gen_star(e1)?
-                        }
-                    }
-                    e => self.gen_star(&e)?,
+                // `(a*)*`のように`Star`が二重になっている場合にスタックオーバーフローする問題を回避するため、
+                // このような`(((r*)*)*...*)*`を再帰的に処理して1つの`r*`へと変換する。
+                if is_star(e1) {
+                    self.gen_expr(e1)?
+                } else {
+                    self.gen_star(e1)?
                 }
             }
             AST::Question(e) => self.gen_question(e)?,
             AST::Seq(v) => self.gen_seq(v)?,
+            AST::Capture(n, e) => self.gen_capture(*n, e)?,
             AST::Dot => {
                 self.insts.push(Instruction::AnyChar);
                 self.inc_pc()?;
@@ -198,9 +220,12 @@ impl Generator {
     /// ```text
     /// L1: split L2, L3
     /// L2: eのコード
-    ///     jump L1
+    ///     split L2, L3
     /// L3:
     /// ```
+    ///
+    /// `e*`を`(e+)?`として生成する。`(^|b)*`のようにeが空文字列にマッチする場合も、
+    /// 空文字列にマッチした直後は繰り返しを抜ける分岐が優先され、regexクレートと同じ箇所にマッチする。
     fn gen_star(&mut self, e: &AST) -> Result<(), CodeGenError> {
         // L1: split L2, L3
         let l1 = self.pc;
@@ -209,11 +234,12 @@ impl Generator {
         self.insts.push(split);
 
         // L2: eのコード
+        let l2 = self.pc;
         self.gen_expr(e)?;
 
-        // jump L1
+        // split L2, L3
         self.inc_pc()?;
-        self.insts.push(Instruction::Jump(l1));
+        self.insts.push(Instruction::Split(l2, self.pc));
 
         // L3の値を設定
         if let Some(Instruction::Split(_, l3)) = self.insts.get_mut(l1) {
@@ -224,6 +250,33 @@ impl Generator {
         }
     }
 
+    /// キャプチャグループのコード生成を行う関数
+    ///
+    /// キャプチャ位置を保存する場合は、以下のようなコードを生成
+    ///
+    /// ```text
+    ///     save 2n
+    ///     eのコード
+    ///     save 2n+1
+    /// ```
+    fn gen_capture(&mut self, n: usize, e: &AST) -> Result<(), CodeGenError> {
+        if !self.captures {
+            return self.gen_expr(e);
+        }
+
+        self.groups = self.groups.max(n);
+        let (start, end) = slot_pair(n)?;
+        self.gen_save(start)?;
+        self.gen_expr(e)?;
+        self.gen_save(end)
+    }
+
+    /// save命令生成関数
+    fn gen_save(&mut self, slot: usize) -> Result<(), CodeGenError> {
+        self.insts.push(Instruction::Save(slot));
+        self.inc_pc()
+    }
+
     /// シーケンスのコード生成を行う関数
     fn gen_seq(&mut self, exprs: &[AST]) -> Result<(), CodeGenError> {
         for e in exprs {
@@ -239,6 +292,25 @@ impl Generator {
     }
 }
 
+/// n番目のキャプチャグループの開始・終了位置を保存するスロット番号2n, 2n+1を返す
+fn slot_pair(n: usize) -> Result<(usize, usize), CodeGenError> {
+    let mut start = n;
+    safe_mul(&mut start, &2, || CodeGenError::SlotOverFlow)?;
+    let mut end = start;
+    safe_add(&mut end, &1, || CodeGenError::SlotOverFlow)?;
+    Ok((start, end))
+}
+
+/// `r*`、もしくは括弧で囲まれた`r*`であるかを判定
+fn is_star(ast: &AST) -> bool {
+    match ast {
+        AST::Star(_) => true,
+        AST::Capture(_, e) => is_star(e),
+        AST::Seq(v) if v.len() == 1 => is_star(&v[0]),
+        _ => false,
+    }
+}
+
 /// コード生成のテスト
 #[cfg(test)]
 mod tests {
@@ -294,10 +366,19 @@ mod tests {
                 Instruction::Split(1, 4),
                 Instruction::Char('a'),
                 Instruction::Char('b'),
-                Instruction::Jump(0),
+                Instruction::Split(1, 4),
                 Instruction::Char('c'),
                 Instruction::Match,
             ]
         );
     }
+
+    #[test]
+    fn test_nested_star() {
+        // 括弧で何重に囲まれていても、`r*`の繰り返しは1つの`r*`となる
+        let code = |expr| gen_code(&parse(expr).unwrap()).unwrap();
+        for expr in ["(a*)*", "((a*))*", "(((a*)*)*)*", "((((a*))*))*"] {
+            assert_eq!(code(expr), code("a*"), "{expr}");
+        }
+    }
 }
@@ -0,0 +1,178 @@
+//! 命令列を遅延評価のDFAに変換してマッチングを行う
+//!
+//! DFAの状態は、文字を消費する命令とMatch命令のpcの集合で表す。
+//! 状態と遷移は、マッチング中に必要になった時点で部分集合構成法により生成し、キャッシュする。
+//! 同じDfaで複数の行をマッチングする場合は、以前に生成した状態と遷移を再利用する。
+//!
+//! 行頭（`^`）は開始状態を生成するときのみ、行末（`$`）は入力の末尾に達したときのみ通過できる。
+//! そのため、行末の命令のpcも状態に含め、入力の末尾で改めて辿る。
+use super::{EvalError, Instruction};
+use std::collections::HashMap;
+
+/// 遅延評価のDFA
+#[derive(Debug)]
+pub struct Dfa<'a> {
+    inst: &'a [Instruction],
+    states: Vec<Vec<usize>>,                    // 状態ごとのpcの集合（昇順）
+    accepting: Vec<bool>,                       // 状態がMatch命令を含むか
+    ids: HashMap<Vec<usize>, usize>,            // pcの集合から状態への対応
+    transitions: HashMap<(usize, char), usize>, // 状態と文字から、遷移先の状態への対応
+    starts: [Option<usize>; 2],                 // 行頭を通過できない場合とできる場合の開始状態
+}
+
+impl<'a> Dfa<'a> {
+    /// 命令列instのDFAを返す
+    ///
+    /// 状態はマッチング時に生成するため、この時点では何も生成しない。
+    pub fn new(inst: &'a [Instruction]) -> Self {
+        Dfa {
+            inst,
+            states: Vec::new(),
+            accepting: Vec::new(),
+            ids: HashMap::new(),
+            transitions: HashMap::new(),
+            starts: [None; 2],
+        }
+    }
+
+    /// lineの先頭から始まる文字列が、命令列にマッチするかを判定
+    ///
+    /// include_head_of_lineがtrueの場合は、lineの先頭を行頭とみなす。
+    pub fn is_match(
+        &mut self,
+        line: &[char],
+        include_head_of_line: bool,
+    ) -> Result<bool, EvalError> {
+        let mut state = match self.starts[include_head_of_line as usize] {
+            Some(state) => state,
+            None => {
+                let pcs = self.closure(&[0], include_head_of_line, false)?;
+                let state = self.state(pcs);
+                self.starts[include_head_of_line as usize] = Some(state);
+                state
+            }
+        };
+
+        for &c in line {
+            if self.accepting[state] {
+                return Ok(true);
+            }
+            if self.states[state].is_empty() {
+                return Ok(false);
+            }
+            state = self.next(state, c)?;
+        }
+        if self.accepting[state] {
+            return Ok(true);
+        }
+
+        // 入力の末尾では行末の命令を通過できる
+        let ends = self.states[state]
+            .iter()
+            .filter(|&&pc| self.inst[pc] == Instruction::EndOfLine)
+            .map(|pc| pc + 1)
+            .collect::<Vec<_>>();
+        let head = include_head_of_line && line.is_empty();
+        let pcs = self.closure(&ends, head, true)?;
+        Ok(pcs.iter().any(|&pc| self.inst[pc] == Instruction::Match))
+    }
+
+    /// 状態stateから文字cで遷移した先の状態を返す
+    fn next(&mut self, state: usize, c: char) -> Result<usize, EvalError> {
+        if let Some(&next) = self.transitions.get(&(state, c)) {
+            return Ok(next);
+        }
+
+        let pcs = self.states[state]
+            .iter()
+            .filter(|&&pc| match &self.inst[pc] {
+                Instruction::Char(x) => *x == c,
+                Instruction::AnyChar => true,
+                Instruction::Class(ranges, negated) => {
+                    Instruction::class_matches(ranges, *negated, c)
+                }
+                _ => false,
+            })
+            .map(|pc| pc + 1)
+            .collect::<Vec<_>>();
+        let pcs = self.closure(&pcs, false, false)?;
+        let next = self.state(pcs);
+        self.transitions.insert((state, c), next);
+        Ok(next)
+    }
+
+    /// pcの集合に対応する状態を返す。未生成の場合は生成する
+    fn state(&mut self, pcs: Vec<usize>) -> usize {
+        if let Some(&id) = self.ids.get(&pcs) {
+            return id;
+        }
+        let id = self.states.len();
+        let accepting = pcs.iter().any(|&pc| self.inst[pc] == Instruction::Match);
+        self.ids.insert(pcs.clone(), id);
+        self.states.push(pcs);
+        self.accepting.push(accepting);
+        id
+    }
+
+    /// pcsから文字を消費せずに到達できる命令のうち、状態に含めるもののpcを昇順で返す
+    ///
+    /// at_startがtrueの場合は行頭の命令を、at_endがtrueの場合は行末の命令を通過する。
+    fn closure(
+        &self,
+        pcs: &[usize],
+        at_start: bool,
+        at_end: bool,
+    ) -> Result<Vec<usize>, EvalError> {
+        let mut visited = vec![false; self.inst.len()];
+        let mut stack = pcs.to_vec();
+        let mut result = Vec::new();
+        while let Some(pc) = stack.pop() {
+            let inst = self.inst.get(pc).ok_or(EvalError::InvalidPC)?;
+            if visited[pc] {
+                continue;
+            }
+            visited[pc] = true;
+
+            match inst {
+                Instruction::Char(_)
+                | Instruction::AnyChar
+                | Instruction::Class(..)
+                | Instruction::Match => result.push(pc),
+                Instruction::HeadOfLine if at_start => stack.push(pc + 1),
+                Instruction::HeadOfLine => {}
+                Instruction::EndOfLine if at_end => stack.push(pc + 1),
+                Instruction::EndOfLine => result.push(pc),
+                Instruction::Jump(addr) => stack.push(*addr),
+                Instruction::Split(addr1, addr2) => {
+                    stack.push(*addr2);
+                    stack.push(*addr1);
+                }
+                Instruction::Save(_) => stack.push(pc + 1),
+            }
+        }
+        result.sort_unstable();
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{codegen, parser};
+
+    #[test]
+    fn test_dfa() {
+        let code = codegen::gen_code(&parser::parse("a(b|c)*d$").unwrap()).unwrap();
+        let mut dfa = Dfa::new(&code);
+        let line = |s: &str| s.chars().collect::<Vec<_>>();
+        assert!(dfa.is_match(&line("abcbd"), true).unwrap());
+        assert!(dfa.is_match(&line("ad"), true).unwrap());
+        assert!(!dfa.is_match(&line("abdx"), true).unwrap());
+        assert!(!dfa.is_match(&line("xad"), true).unwrap());
+
+        // 2回目以降は生成済みの状態と遷移を再利用する
+        let states = dfa.states.len();
+        assert!(dfa.is_match(&line("acbd"), true).unwrap());
+        assert_eq!(dfa.states.len(), states);
+    }
+}
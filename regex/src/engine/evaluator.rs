@@ -1,11 +1,13 @@
 //! 命令列と入力文字列を受け取り、マッチングを行う
 
 use super::Instruction;
-use crate::helper::safe_add;
+use helper::safe_add;
 use std::{
+    collections::{HashSet, VecDeque},
     error::Error,
     fmt::{self, Display},
 };
+use tracing::instrument;
 
 #[derive(Debug)]
 pub enum EvalError {
@@ -25,21 +27,141 @@ impl Error for EvalError {}
 /// 命令列の評価を行う関数
 ///
 /// instが命令列となり、その命令列を用いて入力文字列lineにマッチさせる
+#[instrument(level = "trace", skip_all, fields(insts = inst.len(), chars = line.len()))]
 pub fn eval(
     inst: &[Instruction],
     line: &[char],
     include_head_of_line: bool,
 ) -> Result<bool, EvalError> {
-    eval_depth(inst, line, 0, 0, include_head_of_line)
+    let mut visited = HashSet::new();
+    eval_depth(
+        inst,
+        line,
+        0,
+        0,
+        include_head_of_line,
+        &mut Vec::new(),
+        &mut visited,
+    )
+}
+
+/// 幅優先探索で命令列の評価を行う関数
+///
+/// 実行中のスレッド（pcとspの組）をキューで管理し、
+/// 一度評価したスレッドは再び評価しないため、
+/// 深さ優先探索のように正規表現によって計算量が指数的に増加することがない。
+/// キャプチャ位置は記録しない。
+#[instrument(level = "trace", skip_all, fields(insts = inst.len(), chars = line.len()))]
+pub fn eval_width(
+    inst: &[Instruction],
+    line: &[char],
+    include_head_of_line: bool,
+) -> Result<bool, EvalError> {
+    let mut queue = VecDeque::new();
+    let mut visited = HashSet::new();
+    queue.push_back((0, 0));
+
+    while let Some((pc, sp)) = queue.pop_front() {
+        if !visited.insert((pc, sp)) {
+            continue;
+        }
+
+        let next = if let Some(i) = inst.get(pc) {
+            i
+        } else {
+            return Err(EvalError::InvalidPC);
+        };
+
+        let mut next_pc = pc;
+        safe_add(&mut next_pc, &1, || EvalError::PCOverFlow)?;
+        let mut next_sp = sp;
+        safe_add(&mut next_sp, &1, || EvalError::SPOverFlow)?;
+
+        match next {
+            Instruction::Char(c) => {
+                if line.get(sp) == Some(c) {
+                    queue.push_back((next_pc, next_sp));
+                }
+            }
+            Instruction::AnyChar => {
+                if sp < line.len() {
+                    queue.push_back((next_pc, next_sp));
+                }
+            }
+            Instruction::Class(ranges, negated) => {
+                if line
+                    .get(sp)
+                    .is_some_and(|&c| Instruction::class_matches(ranges, *negated, c))
+                {
+                    queue.push_back((next_pc, next_sp));
+                }
+            }
+            Instruction::HeadOfLine => {
+                if include_head_of_line && sp == 0 {
+                    queue.push_back((next_pc, sp));
+                }
+            }
+            Instruction::EndOfLine => {
+                if sp == line.len() {
+                    queue.push_back((next_pc, sp));
+                }
+            }
+            Instruction::Jump(addr) => queue.push_back((*addr, sp)),
+            Instruction::Split(addr1, addr2) => {
+                queue.push_back((*addr1, sp));
+                queue.push_back((*addr2, sp));
+            }
+            Instruction::Save(_) => queue.push_back((next_pc, sp)),
+            Instruction::Match => return Ok(true),
+        }
+    }
+
+    Ok(false)
+}
+
+/// キャプチャ位置を記録しながら命令列の評価を行う関数
+///
+/// マッチした場合は、save命令で記録したlineの位置をスロットごとに返す。
+/// slotsはスロットの数で、通常はコード生成時に得られた値を指定する。
+#[instrument(level = "trace", skip_all, fields(insts = inst.len(), chars = line.len()))]
+pub fn eval_captures(
+    inst: &[Instruction],
+    line: &[char],
+    include_head_of_line: bool,
+    slots: usize,
+) -> Result<Option<Vec<Option<usize>>>, EvalError> {
+    let mut caps = vec![None; slots];
+    let mut visited = HashSet::new();
+    if eval_depth(
+        inst,
+        line,
+        0,
+        0,
+        include_head_of_line,
+        &mut caps,
+        &mut visited,
+    )? {
+        Ok(Some(caps))
+    } else {
+        Ok(None)
+    }
 }
 
 /// 深さ優先探索で再起的にマッチングを行う評価関数
+///
+/// capsはsave命令で記録する位置で、バックトラックした場合は分岐前の値に戻す。
+/// visitedは評価を始めたsplit命令のpcとspの組で、同じ組を再び評価する場合は失敗とする。
+/// 一度失敗した組は再び失敗し、評価中の組に戻った場合は`(a*)*`のように空文字列を繰り返しているため、
+/// いずれも優先順位の高い分岐の結果は変わらない。これにより、空文字列の繰り返しで無限に再帰せず、
+/// 計算量も命令数と文字数の積に抑えられる。
 fn eval_depth(
     inst: &[Instruction],
     line: &[char],
     mut pc: usize,
     mut sp: usize,
     include_head_of_line: bool,
+    caps: &mut Vec<Option<usize>>,
+    visited: &mut HashSet<(usize, usize)>,
 ) -> Result<bool, EvalError> {
     loop {
         let next = if let Some(i) = inst.get(pc) {
@@ -62,6 +184,17 @@ fn eval_depth(
                 }
             }
             Instruction::AnyChar => {
+                if sp >= line.len() {
+                    return Ok(false);
+                }
+                safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+                safe_add(&mut sp, &1, || EvalError::SPOverFlow)?;
+            }
+            Instruction::Class(ranges, negated) => {
+                match line.get(sp) {
+                    Some(&c) if Instruction::class_matches(ranges, *negated, c) => {}
+                    _ => return Ok(false),
+                }
                 safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
                 safe_add(&mut sp, &1, || EvalError::SPOverFlow)?;
             }
@@ -80,14 +213,22 @@ fn eval_depth(
             Instruction::Jump(addr) => {
                 pc = *addr;
             }
+            Instruction::Save(slot) => {
+                if let Some(cap) = caps.get_mut(*slot) {
+                    *cap = Some(sp);
+                }
+                safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+            }
             Instruction::Split(addr1, addr2) => {
-                if eval_depth(inst, line, *addr1, sp, include_head_of_line)?
-                    || eval_depth(inst, line, *addr2, sp, include_head_of_line)?
-                {
-                    return Ok(true);
-                } else {
+                if !visited.insert((pc, sp)) {
                     return Ok(false);
                 }
+                let saved = caps.clone();
+                if eval_depth(inst, line, *addr1, sp, include_head_of_line, caps, visited)? {
+                    return Ok(true);
+                }
+                *caps = saved;
+                return eval_depth(inst, line, *addr2, sp, include_head_of_line, caps, visited);
             }
             Instruction::Match => {
                 return Ok(true);
@@ -95,3 +236,19 @@ fn eval_depth(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{codegen::gen_code, parser::parse};
+
+    #[test]
+    fn test_any_char() {
+        let code = gen_code(&parse("a.").unwrap()).unwrap();
+        let line = |s: &str| s.chars().collect::<Vec<_>>();
+        assert!(eval(&code, &line("ab"), true).unwrap());
+        // `.`は入力の末尾を越えてマッチしない
+        assert!(!eval(&code, &line("a"), true).unwrap());
+        assert!(!eval(&code, &line(""), true).unwrap());
+    }
+}
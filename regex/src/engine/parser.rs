@@ -1,31 +1,60 @@
 //! 正規表現の式をパースし、抽象構文木に変換
+//!
+//! `\`に続く特殊文字はその文字自体を、`\n`、`\t`、`\r`、`\0`は制御文字を表す。
+//! エスケープの解釈とエラーの範囲の表示には、電卓と共通のhelper::lexerを用いる。
+use helper::lexer::{self, Span};
 use std::{
     error::Error,
     fmt::{self, Display},
     mem::take,
 };
+use tracing::instrument;
 
 /// パースエラーを表すための型
+///
+/// 位置は、正規表現中のバイト単位の範囲で表す。
 #[derive(Debug)]
 pub enum ParseError {
-    InvalidEscape(usize, char), // 誤ったエスケープシーケンス
-    InvalidRightParen(usize),   // 左開き括弧なし
-    NoPrev(usize),              // +, |, *, ?の前に式がない
-    NoRightParen,               // 右閉じ括弧なし
-    Empty,                      // 空のパターン
+    InvalidEscape(Span, char), // 誤ったエスケープシーケンス（`\`を含む範囲）
+    InvalidRightParen(Span),   // 左開き括弧なし
+    NoPrev(Span),              // +, |, *, ?の前に式がない
+    NoRightParen,              // 右閉じ括弧なし
+    Empty,                     // 空のパターン
+}
+
+impl ParseError {
+    /// 正規表現exprにおけるエラーの範囲を返す
+    ///
+    /// 右閉じ括弧がない場合と空のパターンの場合は、exprの末尾を指す。
+    pub fn span(&self, expr: &str) -> Span {
+        match self {
+            ParseError::InvalidEscape(span, _)
+            | ParseError::InvalidRightParen(span)
+            | ParseError::NoPrev(span) => *span,
+            ParseError::NoRightParen | ParseError::Empty => Span::at(expr.len()),
+        }
+    }
+
+    /// 正規表現exprと、エラーの範囲を指すキャレットを含む診断メッセージを返す
+    pub fn diagnostic(&self, expr: &str) -> String {
+        self.span(expr).diagnostic(expr, self)
+    }
 }
 
 /// パースエラーを表示するために、Displayとレイトを実装
 impl Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ParseError::InvalidEscape(pos, c) => {
+            ParseError::InvalidEscape(span, c) => {
+                let pos = span.start;
                 write!(f, "ParseError: invalid escapse: pos = {pos}, char = '{c}'")
             }
-            ParseError::InvalidRightParen(pos) => {
+            ParseError::InvalidRightParen(span) => {
+                let pos = span.start;
                 write!(f, "ParseError: invalid right parenthesis: pos = {pos}")
             }
-            ParseError::NoPrev(pos) => {
+            ParseError::NoPrev(span) => {
+                let pos = span.start;
                 write!(f, "ParseError: no previous expression: pos = {pos}")
             }
             ParseError::NoRightParen => {
@@ -41,6 +70,7 @@ impl Display for ParseError {
 impl Error for ParseError {}
 
 /// 抽象構文木を表現するための型
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq)]
 pub enum AST {
     Char(char),
@@ -49,12 +79,14 @@ pub enum AST {
     Question(Box<AST>),
     Or(Box<AST>, Box<AST>),
     Seq(Vec<AST>),
+    Capture(usize, Box<AST>), // 括弧で囲まれたキャプチャグループと、その番号（1始まり）
     Dot,
     Hat,
     Dollar,
 }
 
 /// parse_plus_star_question関数で利用するための列挙型
+#[allow(clippy::upper_case_acronyms)]
 enum PSQ {
     Plus,
     Star,
@@ -62,46 +94,51 @@ enum PSQ {
 }
 
 /// 正規表現を抽象構文木に変換
+#[instrument(level = "trace")]
 pub fn parse(expr: &str) -> Result<AST, ParseError> {
     // 内部状態を表現するための型
     // Char状態: 文字列処理中
-    // Escape状態: エスケープシーケンス処理中
+    // Escape状態: エスケープシーケンス処理中（`\`の位置を保持）
     enum ParseState {
         Char,
-        Escape,
+        Escape(usize),
     }
 
     let mut seq = Vec::new();
     let mut seq_or = Vec::new();
     let mut stack = Vec::new();
     let mut state = ParseState::Char;
+    let mut group = 0; // 出現したキャプチャグループの数
 
-    for (i, c) in expr.chars().enumerate() {
+    for (i, c) in expr.char_indices() {
+        // 現在の文字の範囲
+        let span = Span::new(i, i + c.len_utf8());
         match &state {
             ParseState::Char => match c {
                 '^' => seq.push(AST::Hat),
                 '$' => seq.push(AST::Dollar),
                 '.' => seq.push(AST::Dot),
-                '+' => parse_plus_star_question(&mut seq, PSQ::Plus, i)?,
-                '*' => parse_plus_star_question(&mut seq, PSQ::Star, i)?,
-                '?' => parse_plus_star_question(&mut seq, PSQ::Question, i)?,
+                '+' => parse_plus_star_question(&mut seq, PSQ::Plus, span)?,
+                '*' => parse_plus_star_question(&mut seq, PSQ::Star, span)?,
+                '?' => parse_plus_star_question(&mut seq, PSQ::Question, span)?,
                 '(' => {
                     // 現在のコンテキストをスタックに追加し、現在のコンテキストを空の状態にする
                     let prev = take(&mut seq);
                     let prev_or = take(&mut seq_or);
-                    stack.push((prev, prev_or));
+                    group += 1;
+                    stack.push((prev, prev_or, group));
                 }
                 ')' => {
                     // 現在のコンテキストをスタックからポップ
-                    if let Some((mut prev, prev_or)) = stack.pop() {
+                    if let Some((mut prev, prev_or, n)) = stack.pop() {
                         // "()"のように式が空の場合はpushしない
                         if !seq.is_empty() {
                             seq_or.push(AST::Seq(seq));
                         }
 
-                        // Orを生成
+                        // Orを生成し、キャプチャグループとする
                         if let Some(ast) = fold_or(seq_or) {
-                            prev.push(ast);
+                            prev.push(AST::Capture(n, Box::new(ast)));
                         }
 
                         // 以前のコンテキストを現在のコンテキストにする
@@ -109,24 +146,24 @@ pub fn parse(expr: &str) -> Result<AST, ParseError> {
                         seq_or = prev_or;
                     } else {
                         // "abc)"のように、開き括弧がないのよに閉じ括弧がある場合はえらー
-                        return Err(ParseError::InvalidRightParen(i));
+                        return Err(ParseError::InvalidRightParen(span));
                     }
                 }
                 '|' => {
                     if seq.is_empty() {
                         // "||"、"(|abc)などのような場合はエラー
-                        return Err(ParseError::NoPrev(i));
+                        return Err(ParseError::NoPrev(span));
                     } else {
                         let prev = take(&mut seq);
                         seq_or.push(AST::Seq(prev));
                     }
                 }
-                '\\' => state = ParseState::Escape,
+                '\\' => state = ParseState::Escape(i),
                 _ => seq.push(AST::Char(c)),
             },
-            ParseState::Escape => {
+            ParseState::Escape(start) => {
                 // エスケープシーケンス処理
-                let ast = parse_escape(i, c)?;
+                let ast = parse_escape(Span::new(*start, span.end), c)?;
                 seq.push(ast);
                 state = ParseState::Char;
             }
@@ -159,7 +196,7 @@ pub fn parse(expr: &str) -> Result<AST, ParseError> {
 fn parse_plus_star_question(
     seq: &mut Vec<AST>,
     ast_type: PSQ,
-    pos: usize,
+    span: Span,
 ) -> Result<(), ParseError> {
     if let Some(prev) = seq.pop() {
         let ast = match ast_type {
@@ -170,18 +207,17 @@ fn parse_plus_star_question(
         seq.push(ast);
         Ok(())
     } else {
-        Err(ParseError::NoPrev(pos))
+        Err(ParseError::NoPrev(span))
     }
 }
 
 /// 特殊文字のエスケープ
-fn parse_escape(pos: usize, c: char) -> Result<AST, ParseError> {
-    match c {
-        '\\' | '(' | ')' | '|' | '+' | '*' | '?' | '.' | '^' | '$' => Ok(AST::Char(c)),
-        _ => {
-            let err = ParseError::InvalidEscape(pos, c);
-            Err(err)
-        }
+///
+/// spanは`\`を含むエスケープシーケンスの範囲。
+fn parse_escape(span: Span, c: char) -> Result<AST, ParseError> {
+    match lexer::unescape(c, "()|+*?.^$") {
+        Some(c) => Ok(AST::Char(c)),
+        None => Err(ParseError::InvalidEscape(span, c)),
     }
 }
 
@@ -233,11 +269,14 @@ mod tests {
         assert_eq!(
             parse("(abc)+|def").unwrap(),
             AST::Or(
-                Box::new(AST::Seq(vec![AST::Plus(Box::new(AST::Seq(vec![
-                    AST::Char('a'),
-                    AST::Char('b'),
-                    AST::Char('c')
-                ])))])),
+                Box::new(AST::Seq(vec![AST::Plus(Box::new(AST::Capture(
+                    1,
+                    Box::new(AST::Seq(vec![
+                        AST::Char('a'),
+                        AST::Char('b'),
+                        AST::Char('c')
+                    ]))
+                )))])),
                 Box::new(AST::Seq(vec![
                     AST::Char('d'),
                     AST::Char('e'),
@@ -246,4 +285,24 @@ mod tests {
             )
         )
     }
+
+    #[test]
+    fn test_parse_escape() {
+        assert_eq!(
+            parse(r"\(\n").unwrap(),
+            AST::Seq(vec![AST::Char('('), AST::Char('\n')])
+        );
+
+        let e = parse(r"aé\q").unwrap_err();
+        assert!(matches!(e, ParseError::InvalidEscape(span, 'q') if span == Span::new(3, 5)));
+        assert_eq!(
+            e.diagnostic(r"aé\q"),
+            "ParseError: invalid escapse: pos = 3, char = 'q'\n  aé\\q\n    ^^"
+        );
+        assert_eq!(
+            parse("ab)").unwrap_err().diagnostic("ab)"),
+            "ParseError: invalid right parenthesis: pos = 2\n  ab)\n    ^"
+        );
+        assert_eq!(parse("(a").unwrap_err().span("(a"), Span::at(2));
+    }
 }
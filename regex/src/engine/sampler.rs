@@ -0,0 +1,214 @@
+//! 命令列を辿り、正規表現にマッチする文字列をランダムに生成する
+//!
+//! Split命令ではどちらの分岐を先に試すかを乱数で選び、文字を消費する命令では文字を1つ生成する。
+//! 生成した文字列の長さが上限を超える場合や、行頭・行末の条件を満たせない場合は、
+//! 深さ優先探索と同様にバックトラックして別の分岐を試す。
+//!
+//! 以降に生成できる文字列は、プログラムカウンタ、生成済みの文字数、行末を過ぎたかのみで決まるため、
+//! 失敗した状態を記録して再び探索しないようにする。`.`と否定の文字クラスには、ASCIIの表示可能な文字を用いる。
+use super::Instruction;
+use helper::rng::Rng;
+use std::collections::HashSet;
+
+/// `.`や否定の文字クラスで生成する文字の範囲
+const PRINTABLE: std::ops::RangeInclusive<char> = ' '..='~';
+
+/// 探索の状態（プログラムカウンタ、生成済みの文字数、行末を過ぎたか）
+type State = (usize, usize, bool);
+
+/// 文字列の生成器
+struct Sampler<'a> {
+    code: &'a [Instruction],
+    rng: &'a mut Rng,
+    max_len: usize,
+    out: Vec<char>,         // 生成中の文字列
+    failed: HashSet<State>, // 失敗した状態
+    active: HashSet<State>, // 探索中の状態（文字を消費しないループの検出に用いる）
+}
+
+/// 命令列codeにマッチする、max_len文字以下の文字列をランダムに生成する
+///
+/// 生成した文字列は行全体として、つまり先頭を行頭、末尾を行末としてマッチする。
+/// そのような文字列がない場合はNone。
+pub(crate) fn sample(code: &[Instruction], rng: &mut Rng, max_len: usize) -> Option<String> {
+    let mut sampler = Sampler {
+        code,
+        rng,
+        max_len,
+        out: Vec::new(),
+        failed: HashSet::new(),
+        active: HashSet::new(),
+    };
+    sampler
+        .walk(0, false)
+        .then(|| sampler.out.into_iter().collect())
+}
+
+impl Sampler<'_> {
+    /// プログラムカウンタpcから命令列を辿り、Match命令に到達できればtrue
+    ///
+    /// endedは、行末（`$`）を過ぎて文字を生成できないことを表す。
+    fn walk(&mut self, pc: usize, ended: bool) -> bool {
+        let state = (pc, self.out.len(), ended);
+        // 探索中の状態に戻った場合は、文字を生成せずにループしたため失敗とする
+        if self.failed.contains(&state) || !self.active.insert(state) {
+            return false;
+        }
+        let result = self.step(pc, ended);
+        self.active.remove(&state);
+        if !result {
+            self.failed.insert(state);
+        }
+        result
+    }
+
+    /// pcの命令を1つ実行し、続きを辿る
+    fn step(&mut self, pc: usize, ended: bool) -> bool {
+        let Some(inst) = self.code.get(pc) else {
+            return false;
+        };
+        match inst {
+            Instruction::Match => true,
+            Instruction::Char(c) => self.push(*c, pc, ended),
+            Instruction::AnyChar => {
+                let c = self.printable(|_| true);
+                c.is_some_and(|c| self.push(c, pc, ended))
+            }
+            Instruction::Class(ranges, negated) => {
+                let c = if *negated {
+                    self.printable(|c| Instruction::class_matches(ranges, true, c))
+                } else {
+                    self.class(ranges)
+                };
+                c.is_some_and(|c| self.push(c, pc, ended))
+            }
+            Instruction::HeadOfLine => self.out.is_empty() && self.walk(pc + 1, ended),
+            Instruction::EndOfLine => self.walk(pc + 1, true),
+            Instruction::Jump(addr) => self.walk(*addr, ended),
+            Instruction::Split(addr1, addr2) => {
+                let (first, second) = if self.rng.next_u64() & 1 == 0 {
+                    (*addr1, *addr2)
+                } else {
+                    (*addr2, *addr1)
+                };
+                self.walk(first, ended) || self.walk(second, ended)
+            }
+            Instruction::Save(_) => self.walk(pc + 1, ended),
+        }
+    }
+
+    /// 文字cを生成してpcの次の命令へ進む
+    ///
+    /// 行末を過ぎた場合や、文字数が上限に達した場合は失敗する。
+    fn push(&mut self, c: char, pc: usize, ended: bool) -> bool {
+        if ended || self.out.len() >= self.max_len {
+            return false;
+        }
+        self.out.push(c);
+        if self.walk(pc + 1, ended) {
+            return true;
+        }
+        self.out.pop();
+        false
+    }
+
+    /// 0以上n未満の乱数を返す
+    fn index(&mut self, n: usize) -> usize {
+        self.rng.range(0, n as i64 - 1).unwrap_or(0) as usize
+    }
+
+    /// 表示可能な文字のうち、predを満たすものをランダムに選ぶ
+    fn printable(&mut self, pred: impl Fn(char) -> bool) -> Option<char> {
+        let chars = PRINTABLE.filter(|&c| pred(c)).collect::<Vec<_>>();
+        if chars.is_empty() {
+            return None;
+        }
+        Some(chars[self.index(chars.len())])
+    }
+
+    /// 文字の範囲の一覧rangesから、文字をランダムに選ぶ
+    ///
+    /// 各文字が等しい確率で選ばれるよう、範囲の幅に応じて範囲を選ぶ。
+    fn class(&mut self, ranges: &[(char, char)]) -> Option<char> {
+        let total = ranges
+            .iter()
+            .map(|&(lo, hi)| (hi as usize + 1).saturating_sub(lo as usize))
+            .sum::<usize>();
+        if total == 0 {
+            return None;
+        }
+        let mut n = self.index(total);
+        for &(lo, hi) in ranges {
+            let width = (hi as usize + 1).saturating_sub(lo as usize);
+            if n < width {
+                // サロゲートの範囲に当たった場合は、範囲の先頭の文字とする
+                return Some(char::from_u32(lo as u32 + n as u32).unwrap_or(lo));
+            }
+            n -= width;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Glob, Regex};
+    use helper::rng::Rng;
+
+    /// exprから生成した文字列を列挙する
+    fn samples(expr: &str, max_len: usize) -> Vec<Option<String>> {
+        let re = Regex::new(expr).unwrap();
+        let mut rng = Rng::new(0);
+        (0..100).map(|_| re.sample(&mut rng, max_len)).collect()
+    }
+
+    #[test]
+    fn test_sample() {
+        for s in samples("a(b|c)d", 8) {
+            assert!(matches!(s.as_deref(), Some("abd" | "acd")), "{s:?}");
+        }
+        for s in samples("ab*", 3) {
+            assert!(matches!(s.as_deref(), Some("a" | "ab" | "abb")), "{s:?}");
+        }
+        for s in samples("^x.y$|z", 8) {
+            let s = s.unwrap();
+            assert!(s == "z" || (s.len() == 3 && s.starts_with('x') && s.ends_with('y')));
+        }
+        // 文字を生成しないループと、その中の行頭
+        for s in samples("((^)*)+a", 8) {
+            assert_eq!(s.as_deref(), Some("a"));
+        }
+
+        // すべての分岐が現れる
+        let all = samples("a|b|c", 1);
+        for c in ["a", "b", "c"] {
+            assert!(all.iter().any(|s| s.as_deref() == Some(c)));
+        }
+    }
+
+    #[test]
+    fn test_sample_none() {
+        assert!(samples("abc", 2).iter().all(Option::is_none));
+        assert!(samples("a$b", 8).iter().all(Option::is_none));
+        assert!(samples("a^", 8).iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_sample_seed() {
+        let re = Regex::new("(a|b|.)+").unwrap();
+        let (mut a, mut b) = (Rng::new(42), Rng::new(42));
+        for _ in 0..10 {
+            assert_eq!(re.sample(&mut a, 16), re.sample(&mut b, 16));
+        }
+    }
+
+    #[test]
+    fn test_sample_class() {
+        let glob = Glob::new("[a-c]?[!x-z]");
+        let mut rng = Rng::new(0);
+        for _ in 0..100 {
+            let s = super::sample(glob.code(), &mut rng, 8).unwrap();
+            assert!(glob.matches(&s), "{s:?}");
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! 正規表現エンジンのエラー
+//!
+//! パース、コード生成、評価の各段階のエラーを1つの型にまとめる。
+//! 元のエラーはsourceで取り出せるため、呼び出し側は段階ごとに処理を変えられる。
+use crate::engine::{CodeGenError, EvalError, ParseError};
+use std::{
+    error,
+    fmt::{self, Display},
+};
+
+/// 正規表現エンジンのエラー
+#[derive(Debug)]
+pub enum Error {
+    Parse(ParseError),     // 正規表現の誤り
+    CodeGen(CodeGenError), // コード生成の失敗
+    Eval(EvalError),       // 評価の失敗
+}
+
+impl Error {
+    /// エラーの段階を表すコード
+    ///
+    /// パースエラーは`parse`、コード生成エラーは`codegen`、評価エラーは`eval`となる。
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Parse(_) => "parse",
+            Error::CodeGen(_) => "codegen",
+            Error::Eval(_) => "eval",
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(e) => write!(f, "{e}"),
+            Error::CodeGen(e) => write!(f, "{e}"),
+            Error::Eval(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Parse(e) => Some(e),
+            Error::CodeGen(e) => Some(e),
+            Error::Eval(e) => Some(e),
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Error::Parse(e)
+    }
+}
+
+impl From<CodeGenError> for Error {
+    fn from(e: CodeGenError) -> Self {
+        Error::CodeGen(e)
+    }
+}
+
+impl From<EvalError> for Error {
+    fn from(e: EvalError) -> Self {
+        Error::Eval(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, ParseError};
+    use std::error::Error as _;
+
+    #[test]
+    fn test_error() {
+        let e = crate::do_matching("a(b", "ab", true).unwrap_err();
+        assert!(matches!(e, Error::Parse(ParseError::NoRightParen)));
+        assert_eq!(e.code(), "parse");
+        assert_eq!(e.to_string(), "ParseError: no right parenthesis");
+        assert!(e.source().unwrap().is::<ParseError>());
+
+        assert_eq!(
+            crate::do_matching("*", "a", true).unwrap_err().code(),
+            "parse"
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! シェルのglobパターンを、正規表現エンジンの命令列へと変換してマッチングを行う
+//!
+//! 以下の記法に対応する。
+//!
+//! - `?`: `/`以外の任意の1文字
+//! - `*`: `/`以外の任意の0文字以上の文字列
+//! - `**`: `/`を含む任意の文字列。`**/`は0個以上のディレクトリにマッチ
+//! - `[abc]`、`[a-z]`: 文字クラス。`[!...]`もしくは`[^...]`で否定（`/`にはマッチしない）
+//! - `\x`: 文字xそのもの
+//!
+//! パターンはパス全体にマッチする必要がある。
+//!
+//! ```
+//! use regex::Glob;
+//! let glob = Glob::new("src/**/*.rs");
+//! assert!(glob.matches("src/engine/glob.rs"));
+//! assert!(!glob.matches("src/engine/glob.rs.orig"));
+//! ```
+use crate::engine::{evaluator, Instruction};
+use std::fmt::{self, Display};
+
+/// 命令列へとコンパイル済みのglobパターン
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    code: Vec<Instruction>,
+}
+
+/// globパターンの要素
+#[derive(Debug, PartialEq)]
+enum Token {
+    Char(char),
+    Any,                            // ?
+    Star,                           // *
+    Globstar,                       // **
+    GlobstarDir,                    // **/
+    Class(Vec<(char, char)>, bool), // [...]
+}
+
+impl Glob {
+    /// globパターンをコンパイルする
+    ///
+    /// 閉じられていない`[`は、文字`[`そのものとして扱う。
+    pub fn new(pattern: &str) -> Self {
+        Glob {
+            pattern: pattern.to_string(),
+            code: gen_code(&tokenize(pattern)),
+        }
+    }
+
+    /// パス全体がパターンにマッチするかを判定
+    pub fn matches(&self, path: &str) -> bool {
+        let chars: Vec<char> = path.chars().collect();
+        // 先頭からのみ評価し、命令列末尾のEndOfLineでパス全体にマッチしたことを確認する
+        evaluator::eval_width(&self.code, &chars, true).unwrap_or(false)
+    }
+
+    /// コンパイル元のパターンを返す
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// コンパイル済みの命令列を返す
+    pub fn code(&self) -> &[Instruction] {
+        &self.code
+    }
+}
+
+impl Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+/// パターンを要素に分解する
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                tokens.push(Token::Char(chars[i + 1]));
+                i += 2;
+            }
+            '?' => {
+                tokens.push(Token::Any);
+                i += 1;
+            }
+            '*' => {
+                let start = i;
+                while i < chars.len() && chars[i] == '*' {
+                    i += 1;
+                }
+                if i - start == 1 {
+                    tokens.push(Token::Star);
+                } else if chars.get(i) == Some(&'/') && (start == 0 || chars[start - 1] == '/') {
+                    // ディレクトリ区切りで囲まれた`**/`は、0個以上のディレクトリにマッチ
+                    tokens.push(Token::GlobstarDir);
+                    i += 1;
+                } else {
+                    tokens.push(Token::Globstar);
+                }
+            }
+            '[' => match parse_class(&chars[i + 1..]) {
+                Some((token, len)) => {
+                    tokens.push(token);
+                    i += len + 1;
+                }
+                None => {
+                    tokens.push(Token::Char('['));
+                    i += 1;
+                }
+            },
+            c => {
+                tokens.push(Token::Char(c));
+                i += 1;
+            }
+        }
+    }
+    tokens
+}
+
+/// `[`の直後からの文字クラスをパースし、要素と消費した文字数（`]`を含む）を返す
+///
+/// `]`で閉じられていない場合はNoneを返す。
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    // 先頭の`]`は、クラスの終わりではなく文字`]`として扱う
+    let mut first = true;
+    loop {
+        let c = *chars.get(i)?;
+        if c == ']' && !first {
+            break;
+        }
+        first = false;
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&c| c != ']') {
+            ranges.push((c, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+
+    // 否定したクラスも、ディレクトリ区切りにはマッチさせない
+    if negated {
+        ranges.push(('/', '/'));
+    }
+    Some((Token::Class(ranges, negated), i + 1))
+}
+
+/// 要素列から命令列を生成する
+///
+/// `*`は`/`以外の文字の、`**`は任意の文字の繰り返しとして、以下のようなコードを生成
+///
+/// ```text
+/// L1: split L2, L3
+/// L2: class [^/]  (**の場合はany)
+///     jump L1
+/// L3:
+/// ```
+///
+/// `**/`は、上記の`**`のコードと`char /`を`split`で省略可能にしたコードを生成する。
+fn gen_code(tokens: &[Token]) -> Vec<Instruction> {
+    let mut insts = Vec::new();
+    for token in tokens {
+        match token {
+            Token::Char(c) => insts.push(Instruction::Char(*c)),
+            Token::Any => insts.push(not_separator()),
+            Token::Star => gen_repeat(&mut insts, not_separator()),
+            Token::Globstar => gen_repeat(&mut insts, Instruction::AnyChar),
+            Token::GlobstarDir => {
+                // split L1, L2
+                let split_addr = insts.len();
+                insts.push(Instruction::Split(split_addr + 1, 0));
+                // L1: (.*)/
+                gen_repeat(&mut insts, Instruction::AnyChar);
+                insts.push(Instruction::Char('/'));
+                // L2:
+                let l2 = insts.len();
+                insts[split_addr] = Instruction::Split(split_addr + 1, l2);
+            }
+            Token::Class(ranges, negated) => {
+                insts.push(Instruction::Class(ranges.clone(), *negated))
+            }
+        }
+    }
+    insts.push(Instruction::EndOfLine);
+    insts.push(Instruction::Match);
+    insts
+}
+
+/// 1文字にマッチする命令instの、0回以上の繰り返しのコードを生成
+fn gen_repeat(insts: &mut Vec<Instruction>, inst: Instruction) {
+    let l1 = insts.len();
+    insts.push(Instruction::Split(l1 + 1, l1 + 3));
+    insts.push(inst);
+    insts.push(Instruction::Jump(l1));
+}
+
+/// ディレクトリ区切り以外の1文字にマッチする命令
+fn not_separator() -> Instruction {
+    Instruction::Class(vec![('/', '/')], true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let cases = [
+            ("*.rs", "main.rs", true),
+            ("*.rs", ".rs", true),
+            ("*.rs", "main.rs.orig", false),
+            ("*.rs", "src/main.rs", false),
+            ("?.txt", "a.txt", true),
+            ("?.txt", "ab.txt", false),
+            ("a?c", "a/c", false),
+            ("src/*/mod.rs", "src/engine/mod.rs", true),
+            ("src/*/mod.rs", "src/a/b/mod.rs", false),
+            ("src/**/*.rs", "src/main.rs", true),
+            ("src/**/*.rs", "src/a/b/main.rs", true),
+            ("src/**/*.rs", "lib/main.rs", false),
+            ("**/*.rs", "main.rs", true),
+            ("**/*.rs", "a/b/main.rs", true),
+            ("a/**", "a/b/c", true),
+            ("a**", "ab/c", true),
+            ("[abc].txt", "b.txt", true),
+            ("[abc].txt", "d.txt", false),
+            ("[a-z][0-9]", "x7", true),
+            ("[a-z][0-9]", "X7", false),
+            ("[!a-z]", "X", true),
+            ("[^a-z]", "x", false),
+            ("[!a]", "/", false),
+            ("[]]", "]", true),
+            ("[a-]", "-", true),
+            ("[ab", "[ab", true),
+            ("\\*", "*", true),
+            ("\\*", "a", false),
+            ("", "", true),
+            ("", "a", false),
+            ("日本*", "日本語.txt", true),
+        ];
+        for (pattern, path, expected) in cases {
+            assert_eq!(
+                Glob::new(pattern).matches(path),
+                expected,
+                "{pattern:?} against {path:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_code() {
+        assert_eq!(
+            Glob::new("a*").code(),
+            [
+                Instruction::Char('a'),
+                Instruction::Split(2, 4),
+                Instruction::Class(vec![('/', '/')], true),
+                Instruction::Jump(1),
+                Instruction::EndOfLine,
+                Instruction::Match,
+            ]
+        );
+        assert_eq!(
+            Glob::new("**/a").code(),
+            [
+                Instruction::Split(1, 5),
+                Instruction::Split(2, 4),
+                Instruction::AnyChar,
+                Instruction::Jump(1),
+                Instruction::Char('/'),
+                Instruction::Char('a'),
+                Instruction::EndOfLine,
+                Instruction::Match,
+            ]
+        );
+    }
+}
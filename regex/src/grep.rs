@@ -0,0 +1,1046 @@
+//! zerogrepコマンド
+//!
+//! ファイル中の正規表現にマッチする行を表示する。`zerogrep`と`zero grep`は、ともにmainを呼び出す。
+mod bench;
+mod config;
+
+use crate::{Glob, Regex, Rng};
+use clap::{error::ErrorKind, ArgAction, CommandFactory, Parser, Subcommand};
+use config::Config;
+use encoding_rs::Encoding;
+use std::{
+    error::Error,
+    ffi::OsString,
+    fmt::{self, Display},
+    fs,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+use tracing::instrument;
+
+/// エラーの場合の終了ステータス（grepと同じく、マッチしなかった場合の1と区別する）
+const EXIT_ERROR: u8 = 2;
+
+/// --generateで生成する文字列の最大の文字数
+const GENERATE_MAX_LEN: usize = 32;
+
+/// zerogrepのエラー
+#[derive(Debug)]
+enum GrepError {
+    Regex(crate::Error),            // 正規表現の誤りや、マッチングの失敗
+    Pattern(String),                // 正規表現の誤りと、その範囲を示す診断メッセージ
+    Io(Option<PathBuf>, io::Error), // 入出力の失敗と、読み込んでいたファイル（出力の場合はNone）
+    Config(PathBuf, String),        // 設定ファイルの誤り
+}
+
+impl GrepError {
+    /// pathの読み込みに失敗したことを表すエラーに変換する関数を返す
+    fn read(path: &Path) -> impl FnOnce(io::Error) -> GrepError + '_ {
+        move |e| GrepError::Io(Some(path.to_path_buf()), e)
+    }
+
+    /// 正規表現patternのエラーに変換する関数を返す
+    ///
+    /// パースエラーは、その範囲を示す診断メッセージとする。
+    fn pattern(pattern: &str) -> impl FnOnce(crate::Error) -> GrepError + '_ {
+        move |e| match e {
+            crate::Error::Parse(e) => GrepError::Pattern(e.diagnostic(pattern)),
+            e => GrepError::Regex(e),
+        }
+    }
+}
+
+impl Display for GrepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrepError::Regex(e) => write!(f, "{e}"),
+            GrepError::Pattern(diagnostic) => write!(f, "{diagnostic}"),
+            GrepError::Io(Some(path), e) => write!(f, "{}: {e}", path.display()),
+            GrepError::Io(None, e) => write!(f, "{e}"),
+            GrepError::Config(path, e) => write!(f, "{}: {e}", path.display()),
+        }
+    }
+}
+
+impl Error for GrepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GrepError::Regex(e) => Some(e),
+            GrepError::Io(_, e) => Some(e),
+            GrepError::Pattern(_) | GrepError::Config(..) => None,
+        }
+    }
+}
+
+impl From<crate::Error> for GrepError {
+    fn from(e: crate::Error) -> Self {
+        GrepError::Regex(e)
+    }
+}
+
+/// 出力の失敗
+impl From<io::Error> for GrepError {
+    fn from(e: io::Error) -> Self {
+        GrepError::Io(None, e)
+    }
+}
+
+/// 検索処理の統計情報
+#[derive(Debug, Default)]
+struct Stats {
+    files: usize,       // 検索したファイル数
+    lines: usize,       // 走査した行数
+    matches: usize,     // マッチした行数
+    bytes: usize,       // 読み込んだバイト数
+    occurrences: usize, // --count-matchesで数えたマッチ箇所の数
+}
+
+impl Stats {
+    /// 統計情報を標準エラー出力に表示
+    fn print(&self, elapsed: Duration) {
+        eprintln!("{} files searched", self.files);
+        eprintln!("{} lines scanned", self.lines);
+        eprintln!("{} matches found", self.matches);
+        eprintln!("{} bytes read", self.bytes);
+        eprintln!("{:.6} seconds elapsed", elapsed.as_secs_f64());
+    }
+}
+
+/// 検索オプション
+#[derive(Debug)]
+struct Options {
+    recursive: bool,                     // ディレクトリを再帰的に検索
+    follow: bool,                        // シンボリックリンクを辿る
+    max_depth: Option<usize>,            // 再帰の最大深さ
+    with_filename: bool,                 // 行頭にファイル名を表示
+    line_buffered: bool,                 // 1行出力するごとにフラッシュ
+    encoding: Option<&'static Encoding>, // 入力ファイルの文字コード
+    quiet: bool,                         // 何も出力せず、終了ステータスのみ返す
+    files_with_matches: bool,            // マッチしたファイル名のみ出力
+    max_count: Option<usize>,            // 1ファイルあたりの最大マッチ行数
+    regex: Regex,                        // マッチ箇所の置き換えや数え上げに使う正規表現
+    replace: Option<String>,             // マッチ箇所を置き換えるテンプレート
+    count_matches: bool,                 // マッチ箇所の数をファイルごとに出力
+    include: Vec<Glob>,                  // 検索対象とするファイルのパターン
+    exclude: Vec<Glob>,                  // 検索対象から除くファイルのパターン
+}
+
+impl Options {
+    /// 1ファイル中でcount行マッチした時点で、そのファイルの残りを読む必要がなくなるかを判定
+    fn file_done(&self, count: usize) -> bool {
+        (count > 0 && (self.quiet || self.files_with_matches))
+            || self.max_count.is_some_and(|max| count >= max)
+    }
+
+    /// これまでの検索結果から、検索全体を打ち切れるかを判定
+    fn search_done(&self, stats: &Stats) -> bool {
+        self.quiet && stats.matches > 0
+    }
+
+    /// マッチした行を出力するか
+    fn print_lines(&self) -> bool {
+        !self.quiet && !self.files_with_matches && !self.count_matches
+    }
+
+    /// --include、--excludeの指定に従い、ファイルを検索対象とするかを判定
+    ///
+    /// `/`を含むパターンはパス全体と、含まないパターンはファイル名と照合する。
+    /// `zerogrep -r foo .`で辿った`./a/x.rs`のようなパスは、先頭の`./`を除いて照合する。
+    fn selects(&self, file: &Path) -> bool {
+        let path = file.strip_prefix(".").unwrap_or(file).to_string_lossy();
+        let name = file
+            .file_name()
+            .map_or_else(|| path.clone(), |name| name.to_string_lossy());
+        let matches = |glob: &Glob| {
+            if glob.as_str().contains('/') {
+                glob.matches(&path)
+            } else {
+                glob.matches(&name)
+            }
+        };
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+/// Search files for lines matching a regular expression
+#[derive(Parser, Debug)]
+#[command(
+    name = "zerogrep",
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    disable_help_flag = true
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Regular expression to search for (to search for a subcommand name such as `lint`, use -e
+    /// or put `--` before it)
+    #[arg(required_unless_present = "regexp")]
+    pattern: Option<String>,
+
+    /// Files or directories to search
+    #[arg(required_unless_present_any = ["generate", "regexp"])]
+    paths: Vec<PathBuf>,
+
+    /// Use PATTERN as the regular expression; all positional arguments are then paths
+    #[arg(short = 'e', long, value_name = "PATTERN")]
+    regexp: Option<String>,
+
+    /// Print N random strings that PATTERN matches as a whole line, instead of searching
+    #[arg(long, value_name = "N")]
+    generate: Option<usize>,
+
+    /// Seed for --generate, to reproduce the same strings (defaults to the current time)
+    #[arg(long, requires = "generate")]
+    seed: Option<u64>,
+
+    /// Print the AST and the compiled program before searching
+    #[arg(long, alias = "dump-program")]
+    debug: bool,
+
+    /// Trace parsing, code generation and matching with timings on stderr (-vv for more detail;
+    /// RUST_LOG overrides)
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Print search statistics to stderr when finished
+    #[arg(long, overrides_with = "no_stats")]
+    stats: bool,
+
+    /// Do not print search statistics, even if the configuration file enables them
+    #[arg(long, overrides_with = "stats")]
+    no_stats: bool,
+
+    /// Search directories recursively
+    #[arg(short, long, overrides_with = "no_recursive")]
+    recursive: bool,
+
+    /// Do not search directories recursively, even if the configuration file enables it
+    #[arg(long, overrides_with = "recursive")]
+    no_recursive: bool,
+
+    /// Follow symbolic links when searching recursively
+    #[arg(long, overrides_with = "no_follow")]
+    follow: bool,
+
+    /// Do not follow symbolic links, even if the configuration file enables it
+    #[arg(long, overrides_with = "follow")]
+    no_follow: bool,
+
+    /// Descend at most N directory levels below each path
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Search only files whose name matches GLOB (may be repeated)
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Skip files whose name matches GLOB (may be repeated)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Flush output after every matching line
+    #[arg(long, overrides_with = "no_line_buffered")]
+    line_buffered: bool,
+
+    /// Do not flush after every line, even if the configuration file enables it
+    #[arg(long, overrides_with = "line_buffered")]
+    no_line_buffered: bool,
+
+    /// Transcode input files from ENCODING (e.g. utf-16le, shift_jis) before matching
+    #[arg(long, value_name = "ENCODING", value_parser = parse_encoding)]
+    encoding: Option<&'static Encoding>,
+
+    /// Print nothing; exit with status 0 on the first match
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Print only the names of files containing matches
+    #[arg(short = 'l', long)]
+    files_with_matches: bool,
+
+    /// Stop reading a file after NUM matching lines
+    #[arg(short = 'm', long, value_name = "NUM")]
+    max_count: Option<usize>,
+
+    /// Print matching lines with every match replaced by TEMPLATE ($0 is the match, $1... are groups)
+    #[arg(long, value_name = "TEMPLATE")]
+    replace: Option<String>,
+
+    /// Print the number of matches (not matching lines) per file and in total
+    #[arg(long)]
+    count_matches: bool,
+
+    /// Always print the file name for each match
+    #[arg(short = 'H', long, overrides_with = "no_filename")]
+    with_filename: bool,
+
+    /// Never print the file name for each match
+    #[arg(short = 'h', long, overrides_with = "with_filename")]
+    no_filename: bool,
+
+    /// Print help (-h is --no-filename, as in grep)
+    #[arg(long, action = ArgAction::Help)]
+    help: Option<bool>,
+
+    /// Ignore the configuration file
+    #[arg(long)]
+    no_config: bool,
+}
+
+impl Args {
+    /// -eで正規表現が指定された場合は、それをpatternとし、位置引数をすべて検索対象のパスとする
+    fn take_regexp(mut self) -> Result<Args, clap::Error> {
+        let Some(regexp) = self.regexp.take() else {
+            return Ok(self);
+        };
+        if let Some(path) = self.pattern.replace(regexp) {
+            self.paths.insert(0, PathBuf::from(path));
+        }
+        if self.paths.is_empty() && self.generate.is_none() {
+            return Err(Args::command().error(
+                ErrorKind::MissingRequiredArgument,
+                "the following required arguments were not provided:\n  <PATHS>...",
+            ));
+        }
+        Ok(self)
+    }
+}
+
+/// サブコマンド
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Measure the matching throughput of each engine backend on FILE
+    Bench {
+        /// Regular expression to benchmark
+        pattern: String,
+
+        /// File whose lines are matched
+        file: PathBuf,
+
+        /// Number of runs per backend; the fastest one is reported
+        #[arg(long, default_value_t = 3)]
+        iterations: usize,
+    },
+    /// Warn about redundant or potentially slow constructs in PATTERN; exit with status 1 if any
+    Lint {
+        /// Regular expression to check
+        pattern: String,
+    },
+}
+
+/// --encodingに指定された文字コード名をパース
+fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| format!("unknown encoding: {label}"))
+}
+
+/// コマンドライン引数argsに従って検索する
+///
+/// argsの最初の要素はコマンド名とする。
+/// マッチした行があれば終了ステータス0を、なければ1を、エラーの場合は2を返す。
+pub fn main<I, T>(args: I) -> ExitCode
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let args = Args::parse_from(args)
+        .take_regexp()
+        .unwrap_or_else(|e| e.exit());
+    match run(args) {
+        Ok(code) => code,
+        Err(e) => {
+            match &e {
+                GrepError::Regex(e @ crate::Error::Parse(_)) => eprintln!("invalid pattern: {e}"),
+                GrepError::Pattern(diagnostic) => eprintln!("invalid pattern: {diagnostic}"),
+                e => eprintln!("zerogrep: {e}"),
+            }
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// 正規表現patternの警告を、範囲を示すキャレットとともに表示する
+///
+/// 警告がなければ終了ステータス0を、あれば1を返す。
+fn lint(pattern: &str) -> Result<ExitCode, GrepError> {
+    let lints = crate::lint(pattern).map_err(GrepError::pattern(pattern))?;
+    for lint in &lints {
+        println!("{}", lint.diagnostic(pattern));
+    }
+    Ok(if lints.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// 正規表現patternに行全体がマッチする文字列をn個生成し、1行ずつ表示する
+///
+/// seedを省略した場合は、現在時刻をシードとする。
+/// マッチする文字列がない場合は何も表示せず、終了ステータス1を返す。
+fn generate(pattern: &str, n: usize, seed: Option<u64>) -> Result<ExitCode, GrepError> {
+    let regex = Regex::new(pattern).map_err(GrepError::pattern(pattern))?;
+    let mut rng = seed.map_or_else(Rng::default, Rng::new);
+    let mut out = BufWriter::new(io::stdout().lock());
+    for _ in 0..n {
+        match regex.sample(&mut rng, GENERATE_MAX_LEN) {
+            Some(s) => writeln!(out, "{s}")?,
+            None => return Ok(ExitCode::FAILURE),
+        }
+    }
+    out.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `--include`などで指定されたパターンargsと設定ファイルの値configから、Globのリストを作る
+///
+/// コマンドライン引数で1つでも指定された場合は、設定ファイルの値は使わない。
+fn globs(args: &[String], config: Option<&[String]>) -> Vec<Glob> {
+    let patterns = match config {
+        Some(config) if args.is_empty() => config,
+        _ => args,
+    };
+    patterns.iter().map(|p| Glob::new(p)).collect()
+}
+
+/// `--xxx`と`--no-xxx`の指定と設定ファイルの値configから、真偽値のオプションを決める
+///
+/// コマンドライン引数での指定が設定ファイルの値より優先され、どちらもなければfalseとなる。
+/// 両方の引数が指定された場合は、後に指定した方のみが有効となる（overrides_with）。
+fn flag(on: bool, off: bool, config: Option<bool>) -> bool {
+    if on {
+        true
+    } else if off {
+        false
+    } else {
+        config.unwrap_or(false)
+    }
+}
+
+/// コマンドライン引数に従って検索する
+fn run(args: Args) -> Result<ExitCode, GrepError> {
+    helper::trace::init(args.verbose, &["regex"]);
+    if let Some(Command::Bench {
+        pattern,
+        file,
+        iterations,
+    }) = &args.command
+    {
+        bench::run(pattern, file, *iterations)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(Command::Lint { pattern }) = &args.command {
+        return lint(pattern);
+    }
+    let pattern = args.pattern.as_deref().expect("PATTERN is required");
+    if let Some(n) = args.generate {
+        return generate(pattern, n, args.seed);
+    }
+    let config = if args.no_config {
+        Config::default()
+    } else {
+        Config::load()?
+    };
+
+    // コマンドライン引数で指定されなかった項目は、設定ファイルの値を利用
+    let recursive = flag(args.recursive, args.no_recursive, config.recursive);
+    let opts = Options {
+        recursive,
+        follow: flag(args.follow, args.no_follow, config.follow),
+        max_depth: args.max_depth.or(config.max_depth),
+        // 明示的な指定がない場合は、複数のファイルを検索する可能性があるときのみファイル名を表示
+        with_filename: if args.with_filename {
+            true
+        } else if args.no_filename {
+            false
+        } else {
+            recursive || args.paths.len() > 1
+        },
+        line_buffered: flag(
+            args.line_buffered,
+            args.no_line_buffered,
+            config.line_buffered,
+        ),
+        encoding: args.encoding,
+        quiet: args.quiet,
+        files_with_matches: args.files_with_matches,
+        max_count: args.max_count,
+        regex: Regex::new(pattern).map_err(GrepError::pattern(pattern))?,
+        replace: args.replace,
+        count_matches: args.count_matches,
+        include: globs(&args.include, config.include.as_deref()),
+        exclude: globs(&args.exclude, config.exclude.as_deref()),
+    };
+    let stats = flag(args.stats, args.no_stats, config.stats);
+
+    if args.debug {
+        crate::print(pattern)?;
+        println!();
+    }
+
+    // 行ごとのprintln!はシステムコールが多くなるため、ロックしたstdoutをバッファリングして出力
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+
+    let start = Instant::now();
+    let mut result = Stats::default();
+    for path in &args.paths {
+        if opts.search_done(&result) {
+            break;
+        }
+        if opts.recursive {
+            let mut ancestors = Vec::new();
+            search_path(path, 0, &mut ancestors, &opts, &mut result, &mut out)?;
+        } else if opts.selects(path) {
+            match_file(path, &opts, &mut result, &mut out)?;
+        }
+    }
+    if opts.count_matches && opts.with_filename && !opts.quiet {
+        writeln!(out, "total:{}", result.occurrences)?;
+    }
+    out.flush()?;
+    if stats {
+        result.print(start.elapsed());
+    }
+
+    if result.matches > 0 {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+/// パスを再帰的に辿り、見つかったファイルに対してマッチングを行う。
+///
+/// depthは起点からの深さで、max_depthより深いエントリは検索しない。
+/// 起点として与えられたパスは、シンボリックリンクであっても常に辿る。
+///
+/// ancestorsには起点から現在のディレクトリまでの正規化済みパスを保持し、
+/// シンボリックリンクが祖先ディレクトリを指している場合はループとみなしてスキップする。
+fn search_path<W: Write>(
+    path: &Path,
+    depth: usize,
+    ancestors: &mut Vec<PathBuf>,
+    opts: &Options,
+    stats: &mut Stats,
+    out: &mut W,
+) -> Result<(), GrepError> {
+    if !path.is_dir() {
+        if !opts.selects(path) {
+            return Ok(());
+        }
+        return match_file(path, opts, stats, out);
+    }
+
+    let canonical = fs::canonicalize(path).map_err(GrepError::read(path))?;
+    if ancestors.contains(&canonical) {
+        eprintln!("{}: symlink loop detected, skipping", path.display());
+        return Ok(());
+    }
+
+    if opts.max_depth.is_some_and(|max| depth >= max) {
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(path)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+        .map_err(GrepError::read(path))?;
+    entries.sort_by_key(|e| e.file_name());
+
+    ancestors.push(canonical);
+    for entry in entries {
+        if opts.search_done(stats) {
+            break;
+        }
+
+        let entry_path = entry.path();
+        let file_type = entry.file_type().map_err(GrepError::read(&entry_path))?;
+        if file_type.is_symlink() && !opts.follow {
+            continue;
+        }
+
+        // 読み込めないファイルがあっても、残りの検索は続ける
+        match search_path(&entry_path, depth + 1, ancestors, opts, stats, out) {
+            Err(e @ GrepError::Io(Some(_), _)) => eprintln!("{e}"),
+            result => result?,
+        }
+    }
+    ancestors.pop();
+
+    Ok(())
+}
+
+/// ファイルをオープンし、行ごとにマッチングを行う。
+///
+/// ファイル先頭にBOMがある場合はその文字コードで、
+/// --encodingが指定された場合はその文字コードで、ファイル全体をUTF-8に変換してから検索する。
+/// いずれでもない場合はUTF-8とみなし、1行ずつ読み込みながら検索する。
+///
+/// -q、-l、-mの指定により結果が確定した時点で、そのファイルの読み込みを打ち切る。
+///
+/// マッチした行はoutに出力し、検索中に集計した統計情報はstatsに加算される。
+#[instrument(level = "debug", skip_all, fields(file = %file.display()))]
+fn match_file<W: Write>(
+    file: &Path,
+    opts: &Options,
+    stats: &mut Stats,
+    out: &mut W,
+) -> Result<(), GrepError> {
+    let f = File::open(file).map_err(GrepError::read(file))?;
+    let mut reader = BufReader::new(f);
+    stats.files += 1;
+
+    // このファイル中でマッチした行数
+    let mut count = 0;
+    let occurrences = stats.occurrences;
+    if opts.file_done(count) {
+        return Ok(());
+    }
+
+    let bom = Encoding::for_bom(reader.fill_buf().map_err(GrepError::read(file))?);
+    let bom = bom.map(|(enc, _)| enc);
+    if let Some(encoding) = bom.or(opts.encoding) {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(GrepError::read(file))?;
+        stats.bytes += bytes.len();
+
+        // BOMは取り除かれ、不正なバイト列はU+FFFDに置き換えられる
+        let (text, _, _) = encoding.decode(&bytes);
+        for line in text.lines() {
+            stats.lines += 1;
+            if match_line(line, file, opts, stats, out)? {
+                count += 1;
+                if opts.file_done(count) {
+                    break;
+                }
+            }
+        }
+        return print_file_summary(file, count, stats.occurrences - occurrences, opts, out);
+    }
+
+    let mut buf = String::new();
+    loop {
+        buf.clear();
+        let n = reader.read_line(&mut buf).map_err(GrepError::read(file))?;
+        if n == 0 {
+            break;
+        }
+        stats.bytes += n;
+        stats.lines += 1;
+
+        // 改行文字を取り除く
+        let line = buf.trim_end_matches(&['\n', '\r'][..]);
+        if match_line(line, file, opts, stats, out)? {
+            count += 1;
+            if opts.file_done(count) {
+                break;
+            }
+        }
+    }
+
+    print_file_summary(file, count, stats.occurrences - occurrences, opts, out)
+}
+
+/// ファイルの検索が終わった時点で出力する内容を出力する。
+///
+/// -lが指定されている場合は、マッチした行があればファイル名を出力する。
+/// --count-matchesが指定されている場合は、ファイル中のマッチ箇所の数occurrencesを出力する。
+fn print_file_summary<W: Write>(
+    file: &Path,
+    count: usize,
+    occurrences: usize,
+    opts: &Options,
+    out: &mut W,
+) -> Result<(), GrepError> {
+    if opts.quiet {
+        return Ok(());
+    }
+
+    if opts.files_with_matches {
+        if count > 0 {
+            writeln!(out, "{}", file.display())?;
+        }
+    } else if opts.count_matches {
+        if opts.with_filename {
+            writeln!(out, "{}:{occurrences}", file.display())?;
+        } else {
+            writeln!(out, "{occurrences}")?;
+        }
+    }
+
+    if opts.line_buffered {
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// 1行に対してマッチングを行い、マッチした場合はtrueを返す。
+///
+/// マッチした行は、-qまたは-lが指定されていなければoutに出力する。
+/// --replaceが指定されている場合は、マッチ箇所をテンプレートで置き換えてから出力する。
+/// --count-matchesが指定されている場合は、行中のマッチ箇所を数えてstatsに加算する。
+///
+/// マッチングにはコンパイル済みの正規表現を用い、行頭から1文字ずつずらして最初にマッチする箇所を探す。
+/// 空の行も、`^$`や`a*`のように空文字列にマッチする正規表現ではマッチする。
+fn match_line<W: Write>(
+    line: &str,
+    file: &Path,
+    opts: &Options,
+    stats: &mut Stats,
+    out: &mut W,
+) -> Result<bool, GrepError> {
+    match opts.regex.find_iter(line).next() {
+        Some(m) => m?,
+        None => return Ok(false),
+    };
+
+    if opts.print_lines() {
+        let replaced;
+        let line = if let Some(template) = &opts.replace {
+            replaced = opts.regex.replace_all(line, template)?;
+            &replaced
+        } else {
+            line
+        };
+
+        if opts.with_filename {
+            writeln!(out, "{}:{line}", file.display())?;
+        } else {
+            writeln!(out, "{line}")?;
+        }
+        if opts.line_buffered {
+            out.flush()?;
+        }
+    }
+    if opts.count_matches {
+        for m in opts.regex.find_iter(line) {
+            m?;
+            stats.occurrences += 1;
+        }
+    }
+    stats.matches += 1;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 正規表現patternで、その他は既定の値の検索オプション
+    fn options(pattern: &str) -> Options {
+        Options {
+            recursive: false,
+            follow: false,
+            max_depth: None,
+            with_filename: false,
+            line_buffered: false,
+            encoding: None,
+            quiet: false,
+            files_with_matches: false,
+            max_count: None,
+            regex: Regex::new(pattern).unwrap(),
+            replace: None,
+            count_matches: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+
+    /// テスト用の一時ディレクトリ
+    ///
+    /// テストは並行して実行されるため、テストごとに異なるnameを指定する。ドロップ時に削除する。
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let dir = std::env::temp_dir().join(format!("zerogrep-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        /// ディレクトリからの相対パスpathにファイルを作成
+        fn write(&self, path: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+            path
+        }
+
+        /// ディレクトリ以下をoptsで検索し、出力と統計情報を返す
+        ///
+        /// 出力中のパスは、ディレクトリからの相対パスとする。
+        fn search(&self, opts: &Options) -> (String, Stats) {
+            let mut stats = Stats::default();
+            let mut out = Vec::new();
+            search_path(&self.0, 0, &mut Vec::new(), opts, &mut stats, &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            (out.replace(&format!("{}/", self.0.display()), ""), stats)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// linesの各行をpatternで検索し、出力とマッチした行数を返す
+    fn grep(pattern: &str, lines: &[&str]) -> (String, usize) {
+        let opts = options(pattern);
+        let mut stats = Stats::default();
+        let mut out = Vec::new();
+        for line in lines {
+            match_line(line, Path::new("f"), &opts, &mut stats, &mut out).unwrap();
+        }
+        (String::from_utf8(out).unwrap(), stats.matches)
+    }
+
+    #[test]
+    fn test_match_line() {
+        assert_eq!(grep("b+c", &["abbc", "ac", "bc"]), ("abbc\nbc\n".into(), 2));
+        assert_eq!(grep("^b", &["ab", "ba"]), ("ba\n".into(), 1));
+
+        // 空の行も、空文字列にマッチする正規表現ではマッチする
+        assert_eq!(grep("^$", &["a", "", "b"]), ("\n".into(), 1));
+        assert_eq!(grep("a*", &["", "b"]), ("\nb\n".into(), 2));
+        assert_eq!(grep("a", &[""]), ("".into(), 0));
+    }
+
+    #[test]
+    fn test_selects() {
+        let selects = |include: &[&str], exclude: &[&str], file: &str| {
+            let mut opts = options("a");
+            opts.include = include.iter().map(|p| Glob::new(p)).collect();
+            opts.exclude = exclude.iter().map(|p| Glob::new(p)).collect();
+            opts.selects(Path::new(file))
+        };
+
+        assert!(selects(&[], &[], "src/a.rs"));
+
+        // `/`を含まないパターンはファイル名と照合する
+        assert!(selects(&["*.rs"], &[], "src/a.rs"));
+        assert!(!selects(&["*.rs"], &[], "src/a.txt"));
+
+        // `/`を含むパターンはパス全体と照合する
+        assert!(selects(&["src/**/*.rs"], &[], "src/a.rs"));
+        assert!(selects(&["src/**/*.rs"], &[], "src/b/c.rs"));
+        assert!(!selects(&["src/**/*.rs"], &[], "tests/a.rs"));
+        assert!(selects(&["src/**/*.rs"], &[], "./src/a.rs"));
+        assert!(!selects(&[], &["a/**"], "./a/x.rs"));
+        assert!(selects(&[], &["a/**"], "./b/x.rs"));
+
+        // --excludeは--includeより優先する
+        assert!(!selects(&["*.rs"], &["a.*"], "src/a.rs"));
+        assert!(selects(&["*.rs"], &["a.*"], "src/b.rs"));
+    }
+
+    #[test]
+    fn test_regexp() {
+        let parse = |args: &[&str]| {
+            let args = Args::try_parse_from(["zerogrep"].iter().chain(args))?.take_regexp()?;
+            Ok::<_, clap::Error>((args.pattern.unwrap(), args.paths))
+        };
+        let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+        assert_eq!(parse(&["a", "f"]).unwrap(), ("a".into(), paths(&["f"])));
+        assert_eq!(
+            parse(&["-e", "lint", "f", "g"]).unwrap(),
+            ("lint".into(), paths(&["f", "g"]))
+        );
+        assert_eq!(
+            parse(&["--", "bench", "f"]).unwrap(),
+            ("bench".into(), paths(&["f"]))
+        );
+        assert!(parse(&["-e", "a"]).is_err());
+        assert!(parse(&["a"]).is_err());
+    }
+
+    #[test]
+    fn test_flag() {
+        let parse = |args: &[&str]| {
+            let args = Args::parse_from(["zerogrep"].iter().chain(args).chain(&["a", "f"]));
+            (args.recursive, args.no_recursive)
+        };
+
+        // 設定ファイルの値は、コマンドライン引数がない場合のみ用いる
+        let (on, off) = parse(&[]);
+        assert!(!flag(on, off, None));
+        assert!(flag(on, off, Some(true)));
+        let (on, off) = parse(&["-r"]);
+        assert!(flag(on, off, Some(false)));
+        let (on, off) = parse(&["--no-recursive"]);
+        assert!(!flag(on, off, Some(true)));
+
+        // 後に指定した方が有効
+        let (on, off) = parse(&["-r", "--no-recursive"]);
+        assert!(!flag(on, off, Some(true)));
+        let (on, off) = parse(&["--no-recursive", "-r"]);
+        assert!(flag(on, off, Some(false)));
+    }
+
+    #[test]
+    fn test_globs() {
+        let globs = |args: &[&str], config: Option<&[&str]>| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let config: Option<Vec<String>> =
+                config.map(|c| c.iter().map(|s| s.to_string()).collect());
+            globs(&args, config.as_deref())
+                .iter()
+                .map(|g| g.as_str().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(globs(&[], None).is_empty());
+        assert_eq!(globs(&[], Some(&["target/**"])), ["target/**"]);
+        // コマンドライン引数で指定した場合は、設定ファイルの値を置き換える
+        assert_eq!(globs(&["*.rs"], Some(&["target/**"])), ["*.rs"]);
+        assert_eq!(globs(&["*.rs"], None), ["*.rs"]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let dir = TempDir::new("stats");
+        dir.write("a.txt", "foo\nbar\n");
+        dir.write("sub/b.txt", "foo foo\nbaz");
+
+        let mut opts = options("foo");
+        opts.recursive = true;
+        let (out, stats) = dir.search(&opts);
+        assert_eq!(out, "foo\nfoo foo\n");
+        assert_eq!(
+            (stats.files, stats.lines, stats.matches, stats.bytes),
+            (2, 4, 2, 19)
+        );
+        assert_eq!(stats.occurrences, 0);
+
+        // --count-matchesでは、行中のマッチ箇所も数える
+        opts.count_matches = true;
+        let (_, stats) = dir.search(&opts);
+        assert_eq!((stats.matches, stats.occurrences), (2, 3));
+    }
+
+    #[test]
+    fn test_recursive() {
+        let dir = TempDir::new("recursive");
+        dir.write("a.txt", "foo");
+        dir.write("sub/b.txt", "foo\nbar");
+        dir.write("sub/deep/c.txt", "foo");
+
+        let mut opts = options("foo");
+        opts.recursive = true;
+        opts.with_filename = true;
+        let (out, stats) = dir.search(&opts);
+        assert_eq!(out, "a.txt:foo\nsub/b.txt:foo\nsub/deep/c.txt:foo\n");
+        assert_eq!(stats.files, 3);
+
+        // --max-depthより深いエントリは検索しない
+        opts.max_depth = Some(2);
+        assert_eq!(dir.search(&opts).0, "a.txt:foo\nsub/b.txt:foo\n");
+        opts.max_depth = Some(1);
+        assert_eq!(dir.search(&opts).0, "a.txt:foo\n");
+        opts.max_depth = Some(0);
+        assert_eq!(dir.search(&opts).1.files, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow() {
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new("follow");
+        let a = dir.write("a.txt", "foo");
+        dir.write("sub/b.txt", "foo");
+        symlink(&a, dir.0.join("sub/link.txt")).unwrap();
+        // 祖先のディレクトリを指すリンク
+        symlink(&dir.0, dir.0.join("sub/loop")).unwrap();
+
+        // 既定では、シンボリックリンクは辿らない
+        let mut opts = options("foo");
+        opts.recursive = true;
+        opts.with_filename = true;
+        let (out, stats) = dir.search(&opts);
+        assert_eq!(out, "a.txt:foo\nsub/b.txt:foo\n");
+        assert_eq!(stats.files, 2);
+
+        // --followではリンクを辿り、ループになるリンクはスキップする
+        opts.follow = true;
+        let (out, stats) = dir.search(&opts);
+        assert_eq!(out, "a.txt:foo\nsub/b.txt:foo\nsub/link.txt:foo\n");
+        assert_eq!(stats.files, 3);
+    }
+
+    #[test]
+    fn test_encoding() {
+        let dir = TempDir::new("encoding");
+        let utf16 = |s: &str| {
+            let mut bytes = vec![0xFF, 0xFE];
+            bytes.extend(s.encode_utf16().flat_map(u16::to_le_bytes));
+            bytes
+        };
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode("日本語\r\n英語\r\n");
+        dir.write("utf16.txt", utf16("héllo\r\nworld\r\n"));
+        dir.write("utf8.txt", "\u{FEFF}héllo\n");
+        dir.write("sjis.txt", &sjis);
+
+        // BOMがあるファイルは、その文字コードで読み込む
+        let mut opts = options("^hé");
+        opts.recursive = true;
+        opts.with_filename = true;
+        opts.exclude = vec![Glob::new("sjis.txt")];
+        let (out, stats) = dir.search(&opts);
+        assert_eq!(out, "utf16.txt:héllo\nutf8.txt:héllo\n");
+        assert_eq!(stats.lines, 3);
+
+        // --encodingは、BOMがないファイルに適用する
+        let mut opts = options("語$");
+        opts.encoding = Some(encoding_rs::SHIFT_JIS);
+        let file = dir.0.join("sjis.txt");
+        let mut stats = Stats::default();
+        let mut out = Vec::new();
+        match_file(&file, &opts, &mut stats, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "日本語\n英語\n");
+        assert_eq!(stats.bytes, sjis.len());
+    }
+
+    #[test]
+    fn test_early_exit() {
+        let dir = TempDir::new("early-exit");
+        dir.write("a.txt", "a1\nb\na2\na3\n");
+        dir.write("b.txt", "a4\n");
+
+        let mut opts = options("a");
+        opts.recursive = true;
+        opts.with_filename = true;
+        let (_, stats) = dir.search(&opts);
+        assert_eq!((stats.files, stats.lines, stats.matches), (2, 5, 4));
+
+        // -mでは、ファイルごとに指定した行数マッチした時点で読み込みを打ち切る
+        opts.max_count = Some(2);
+        let (out, stats) = dir.search(&opts);
+        assert_eq!(out, "a.txt:a1\na.txt:a2\nb.txt:a4\n");
+        assert_eq!((stats.files, stats.lines, stats.matches), (2, 4, 3));
+        opts.max_count = None;
+
+        // -lでは、各ファイルの最初のマッチで打ち切る
+        opts.files_with_matches = true;
+        let (out, stats) = dir.search(&opts);
+        assert_eq!(out, "a.txt\nb.txt\n");
+        assert_eq!((stats.files, stats.lines, stats.matches), (2, 2, 2));
+        opts.files_with_matches = false;
+
+        // -qでは、最初のマッチで検索全体を打ち切る
+        opts.quiet = true;
+        let (out, stats) = dir.search(&opts);
+        assert_eq!(out, "");
+        assert_eq!((stats.files, stats.lines, stats.matches), (1, 1, 1));
+    }
+}
@@ -0,0 +1,64 @@
+//! benchサブコマンド
+//!
+//! ファイル中の各行に対して、評価器ごとにgrepと同じマッチングを行い、スループットを計測する。
+//! パースとコード生成、行の文字列への変換は計測の前に1度だけ行い、評価器による評価の時間のみを計測する。
+//! DFAの状態は計測の度に空の状態から生成するため、状態の生成にかかる時間も含まれる。
+use super::GrepError;
+use crate::{
+    engine::{self, dfa::Dfa, evaluator, Instruction},
+    Backend,
+};
+use std::{fs, path::Path, time::Instant};
+
+/// 評価器ごとにマッチングを行い、スループットをMB/sで表示する
+///
+/// 計測はiterations回繰り返し、最も速かった結果を採用する。
+pub fn run(expr: &str, file: &Path, iterations: usize) -> Result<(), GrepError> {
+    let text = fs::read_to_string(file).map_err(GrepError::read(file))?;
+    let bytes = text.len();
+    let lines = text
+        .lines()
+        .map(|line| line.chars().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    println!("{}: {bytes} bytes, {} lines", file.display(), lines.len());
+
+    let code = engine::compile(expr).map_err(GrepError::pattern(expr))?;
+    for backend in Backend::ALL {
+        let mut best = f64::INFINITY;
+        let mut matches = 0;
+        for _ in 0..iterations.max(1) {
+            let start = Instant::now();
+            matches = match_lines(&code, &lines, backend)?;
+            best = best.min(start.elapsed().as_secs_f64());
+        }
+
+        let throughput = bytes as f64 / best / 1_000_000.0;
+        println!("{backend}: {throughput:>10.3} MB/s ({best:.6} s, {matches} matching lines)");
+    }
+
+    Ok(())
+}
+
+/// grepと同様に、各行の先頭から1文字ずつずらしてマッチングを行い、マッチした行数を返す
+fn match_lines(
+    code: &[Instruction],
+    lines: &[Vec<char>],
+    backend: Backend,
+) -> Result<usize, GrepError> {
+    let mut dfa = Dfa::new(code);
+    let mut matches = 0;
+    for line in lines {
+        for i in 0..=line.len() {
+            let found = match backend {
+                Backend::DepthFirst => evaluator::eval(code, &line[i..], i == 0),
+                Backend::BreadthFirst => evaluator::eval_width(code, &line[i..], i == 0),
+                Backend::Dfa => dfa.is_match(&line[i..], i == 0),
+            };
+            if found.map_err(crate::Error::from)? {
+                matches += 1;
+                break;
+            }
+        }
+    }
+    Ok(matches)
+}
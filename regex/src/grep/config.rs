@@ -0,0 +1,95 @@
+//! 設定ファイルの読み込み
+//!
+//! `$XDG_CONFIG_HOME/zerogrep.toml`（未設定の場合は`~/.config/zerogrep.toml`）から
+//! オプションのデフォルト値を読み込む。コマンドライン引数で指定された値が常に優先される。
+//! 設定ファイルで有効にした真偽値のオプションは、`--no-recursive`などで無効にできる。
+//! `include`と`exclude`は、コマンドラインで`--include`や`--exclude`を指定した場合はそちらに置き換わる。
+//! zerogrepには色付けや前後の行の表示のオプションがないため、それらの項目はない。
+//!
+//! ```toml
+//! recursive = true
+//! max_depth = 4
+//! exclude = ["target/**", "*.lock"]
+//! ```
+use super::GrepError;
+use serde::Deserialize;
+use std::{env, fs, io::ErrorKind, path::PathBuf};
+
+/// 設定ファイルの内容
+///
+/// 設定ファイルに記述されなかった項目はNoneとなる。
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub stats: Option<bool>,
+    pub recursive: Option<bool>,
+    pub follow: Option<bool>,
+    pub max_depth: Option<usize>,
+    pub line_buffered: Option<bool>,
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+}
+
+impl Config {
+    /// 設定ファイルを読み込む
+    ///
+    /// 設定ファイルが存在しない場合は、すべての項目が未設定のConfigを返す。
+    pub fn load() -> Result<Config, GrepError> {
+        let Some(path) = config_path() else {
+            return Ok(Config::default());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(s) => Config::parse(&s).map_err(|e| GrepError::Config(path, e.to_string())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(GrepError::Io(Some(path), e)),
+        }
+    }
+
+    /// TOML形式の文字列をパース
+    fn parse(s: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(s)
+    }
+}
+
+/// 設定ファイルのパスを返す
+fn config_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("zerogrep.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert_eq!(
+            Config::parse("recursive = true\nmax_depth = 3").unwrap(),
+            Config {
+                recursive: Some(true),
+                max_depth: Some(3),
+                ..Config::default()
+            }
+        );
+
+        assert_eq!(
+            Config::parse("include = [\"*.rs\"]\nexclude = [\"target/**\", \"*.lock\"]").unwrap(),
+            Config {
+                include: Some(vec!["*.rs".to_string()]),
+                exclude: Some(vec!["target/**".to_string(), "*.lock".to_string()]),
+                ..Config::default()
+            }
+        );
+
+        // 未知の項目や型の誤りはエラー
+        assert!(Config::parse("colour = true").is_err());
+        assert!(Config::parse("recursive = 1").is_err());
+        assert!(Config::parse("exclude = \"target/**\"").is_err());
+        assert!(Config::parse("context = 2").is_err());
+    }
+}
@@ -6,10 +6,25 @@
 //! use regex;
 //! let expr = "a(bc)+|c(def)*"; // 正規表現
 //! let line = "cdefdefdef"; // マッチ対象文字列
-//! regex::do_matching(expr, line, true); // 幅優先探索でマッチング
+//! regex::do_matching(expr, line, true); // 深さ優先探索でマッチング
 //! regex::print(expr); // 正規表現のASTと命令列を表示
 //! ```
+//!
+//! `Regex::sample`は、正規表現にマッチする文字列をランダムに生成する。
+//! `lint`は、正規表現の冗長な構文や、遅くなりうる構文を警告する。
+//! `glob`モジュールは、シェルのglobパターンを同じ命令列へと変換してマッチングを行う。
+//! `grep`モジュールは、このクレートを用いた`zerogrep`コマンドの実装である。
 mod engine;
-mod helper;
+mod error;
+pub mod glob;
+pub mod grep;
+pub mod lint;
 
-pub use engine::{do_matching, print};
+pub use engine::{
+    do_matching, do_matching_with, print, Backend, CodeGenError, EvalError, Match, Matches,
+    ParseError, Regex,
+};
+pub use error::Error;
+pub use glob::Glob;
+pub use helper::rng::Rng;
+pub use lint::{lint, Lint, LintKind};
@@ -0,0 +1,367 @@
+//! 正規表現の冗長な構文や、遅くなりうる構文を検出する
+//!
+//! 以下の構文を、その部分式の範囲とともに警告する。
+//!
+//! - `a**`、`a+?`のように、量指定子を重ねた冗長な式（1つの量指定子で書ける）
+//! - `(a+)+`のように、繰り返しの中に上限のない繰り返しを含む式（このエンジンの深さ優先探索は
+//!   評価済みの分岐を再び評価しないが、他のバックトラックするエンジンでは指数的な時間がかかりうる）
+//! - `a|`や`(a|)`の空の選択肢（パーサは無視するため、`a|`は`a`にのみマッチする）
+//! - `a|a`のように、前の選択肢と同じ選択肢
+//! - 空文字列にのみマッチする空のグループ`()`（パーサは無視する）
+//!
+//! この正規表現には文字クラスがないため、常に空となる文字クラスは検出の対象とならない。
+//!
+//! ```
+//! let lints = regex::lint("(a+)+|b**").unwrap();
+//! assert_eq!(lints.len(), 2);
+//! assert_eq!(lints[1].text, "b**");
+//! assert_eq!(lints[1].to_string(), "redundant quantifier: `b**` matches the same as `b*`");
+//! ```
+use crate::{engine::parser::parse, Error};
+use helper::lexer::Span;
+use std::fmt::{self, Display};
+
+/// 解析する括弧のネストの深さの上限
+///
+/// これより深いグループは中身を解析せず、再帰でスタックが溢れないようにする。
+const MAX_DEPTH: usize = 256;
+
+/// 警告の種類
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintKind {
+    RedundantQuantifier(String), // 量指定子を重ねた式と、同じ意味の式
+    NestedQuantifier,            // 上限のない繰り返しを含む繰り返し
+    EmptyBranch,                 // 空の選択肢
+    DuplicateBranch,             // 前の選択肢と同じ選択肢
+    EmptyGroup,                  // 空のグループ
+}
+
+/// 警告
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub kind: LintKind,
+    pub span: Span,   // 正規表現中の範囲（バイト単位）
+    pub text: String, // 警告の対象の部分式
+}
+
+impl Lint {
+    /// 正規表現exprと、警告の範囲を指すキャレットを含むメッセージを返す
+    pub fn diagnostic(&self, expr: &str) -> String {
+        self.span.diagnostic(expr, format!("warning: {self}"))
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = &self.text;
+        match &self.kind {
+            LintKind::RedundantQuantifier(simplified) => write!(
+                f,
+                "redundant quantifier: `{text}` matches the same as `{simplified}`"
+            ),
+            LintKind::NestedQuantifier => {
+                write!(
+                    f,
+                    "nested quantifier: `{text}` may backtrack exponentially in other engines"
+                )
+            }
+            LintKind::EmptyBranch => write!(
+                f,
+                "empty alternative is ignored; use `?` to make the expression optional"
+            ),
+            LintKind::DuplicateBranch => {
+                write!(f, "duplicate alternative: `{text}` is already tried before")
+            }
+            LintKind::EmptyGroup => write!(f, "empty group `{text}` matches only the empty string"),
+        }
+    }
+}
+
+/// 正規表現exprを検査し、警告を出現順に返す
+///
+/// exprに構文の誤りがある場合は、パースエラーを返す。
+pub fn lint(expr: &str) -> Result<Vec<Lint>, Error> {
+    parse(expr)?;
+
+    let mut reader = Reader { expr, pos: 0 };
+    let node = reader.alternation(0);
+    let mut lints = Vec::new();
+    check(expr, &node, &mut lints);
+    lints.sort_by_key(|lint| (lint.span.start, lint.span.end));
+    Ok(lints)
+}
+
+/// 範囲付きの構文木のノード
+#[derive(Debug)]
+struct Node {
+    kind: NodeKind,
+    span: Span,
+}
+
+#[derive(Debug)]
+enum NodeKind {
+    Atom,                     // 文字、`.`、`^`、`$`、エスケープ
+    Seq(Vec<Node>),           // 連接
+    Or(Vec<Node>, Vec<Span>), // 選択肢と、空の選択肢の直前の`|`の範囲
+    Repeat(Box<Node>, char),  // 量指定子`*`、`+`、`?`
+    Group(Option<Box<Node>>), // 括弧で囲まれた式（空のグループはNone）
+    Opaque,                   // 深すぎて解析しなかったグループ
+}
+
+/// 構文の正しい正規表現から、範囲付きの構文木を読み込む
+struct Reader<'a> {
+    expr: &'a str,
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn peek(&self) -> Option<char> {
+        self.expr[self.pos..].chars().next()
+    }
+
+    /// `|`で区切られた選択肢を、`)`または末尾まで読み込む
+    fn alternation(&mut self, depth: usize) -> Node {
+        let start = self.pos;
+        let mut branches = vec![self.sequence(depth)];
+        let mut empty = Vec::new();
+        while self.peek() == Some('|') {
+            let bar = Span::new(self.pos, self.pos + 1);
+            self.pos += 1;
+            let branch = self.sequence(depth);
+            if branch.span.start == branch.span.end {
+                empty.push(bar);
+            } else {
+                branches.push(branch);
+            }
+        }
+
+        let span = Span::new(start, self.pos);
+        if branches.len() == 1 && empty.is_empty() {
+            branches.pop().unwrap()
+        } else {
+            Node {
+                kind: NodeKind::Or(branches, empty),
+                span,
+            }
+        }
+    }
+
+    /// 量指定子付きの式の連接を、`|`、`)`または末尾まで読み込む
+    fn sequence(&mut self, depth: usize) -> Node {
+        let start = self.pos;
+        let mut seq = Vec::new();
+        while let Some(c) = self.peek() {
+            let atom_start = self.pos;
+            let mut node = match c {
+                '|' | ')' => break,
+                '(' => self.group(depth),
+                '\\' => {
+                    self.pos += 1;
+                    self.pos += self.peek().map_or(0, char::len_utf8);
+                    self.node(NodeKind::Atom, atom_start)
+                }
+                c => {
+                    self.pos += c.len_utf8();
+                    self.node(NodeKind::Atom, atom_start)
+                }
+            };
+            while let Some(q @ ('*' | '+' | '?')) = self.peek() {
+                self.pos += 1;
+                node = self.node(NodeKind::Repeat(Box::new(node), q), atom_start);
+            }
+            seq.push(node);
+        }
+
+        if seq.len() == 1 {
+            seq.pop().unwrap()
+        } else {
+            self.node(NodeKind::Seq(seq), start)
+        }
+    }
+
+    /// `(`から対応する`)`までを読み込む
+    fn group(&mut self, depth: usize) -> Node {
+        let start = self.pos;
+        self.pos += 1;
+        if depth >= MAX_DEPTH {
+            self.skip_group();
+            return self.node(NodeKind::Opaque, start);
+        }
+
+        let inner = self.alternation(depth + 1);
+        self.pos += 1; // `)`
+        let inner = (inner.span.start < inner.span.end).then(|| Box::new(inner));
+        self.node(NodeKind::Group(inner), start)
+    }
+
+    /// 解析せずに、対応する`)`の直後まで読み飛ばす
+    fn skip_group(&mut self) {
+        let mut level = 1;
+        let mut chars = self.expr[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                '(' => level += 1,
+                ')' => {
+                    level -= 1;
+                    if level == 0 {
+                        self.pos += i + 1;
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.pos = self.expr.len();
+    }
+
+    /// startから現在の位置までを範囲とするノードを生成する
+    fn node(&self, kind: NodeKind, start: usize) -> Node {
+        Node {
+            kind,
+            span: Span::new(start, self.pos),
+        }
+    }
+}
+
+/// ノードnodeとその子孫を検査し、警告をlintsに追加する
+fn check(expr: &str, node: &Node, lints: &mut Vec<Lint>) {
+    let push = |lints: &mut Vec<Lint>, kind, span: Span| {
+        lints.push(Lint {
+            kind,
+            span,
+            text: expr[span.start..span.end].to_string(),
+        })
+    };
+
+    match &node.kind {
+        NodeKind::Atom | NodeKind::Opaque => {}
+        NodeKind::Seq(nodes) => {
+            for node in nodes {
+                check(expr, node, lints);
+            }
+        }
+        NodeKind::Or(branches, empty) => {
+            for &bar in empty {
+                push(lints, LintKind::EmptyBranch, bar);
+            }
+            for (i, branch) in branches.iter().enumerate() {
+                let text = &expr[branch.span.start..branch.span.end];
+                if branches[..i]
+                    .iter()
+                    .any(|b| &expr[b.span.start..b.span.end] == text)
+                {
+                    push(lints, LintKind::DuplicateBranch, branch.span);
+                }
+            }
+            for branch in branches {
+                check(expr, branch, lints);
+            }
+        }
+        NodeKind::Repeat(inner, q) => {
+            // `a**`のように重なった量指定子は、まとめて1つの警告とする
+            let mut operand = &**inner;
+            let mut combined = *q;
+            let mut stacked = false;
+            while let NodeKind::Repeat(e, q) = &operand.kind {
+                stacked = true;
+                combined = match (combined, *q) {
+                    ('+', '+') => '+',
+                    ('?', '?') => '?',
+                    _ => '*',
+                };
+                operand = e;
+            }
+            if stacked {
+                let simplified =
+                    format!("{}{combined}", &expr[operand.span.start..operand.span.end]);
+                push(lints, LintKind::RedundantQuantifier(simplified), node.span);
+            } else if *q != '?' && matches!(operand.kind, NodeKind::Group(_)) && unbounded(operand)
+            {
+                push(lints, LintKind::NestedQuantifier, node.span);
+            }
+            check(expr, operand, lints);
+        }
+        NodeKind::Group(None) => push(lints, LintKind::EmptyGroup, node.span),
+        NodeKind::Group(Some(inner)) => check(expr, inner, lints),
+    }
+}
+
+/// ノードnodeが上限のない繰り返し（`*`または`+`）を含むかを判定
+fn unbounded(node: &Node) -> bool {
+    match &node.kind {
+        NodeKind::Atom | NodeKind::Opaque | NodeKind::Group(None) => false,
+        NodeKind::Seq(nodes) | NodeKind::Or(nodes, _) => nodes.iter().any(unbounded),
+        NodeKind::Repeat(inner, q) => *q != '?' || unbounded(inner),
+        NodeKind::Group(Some(inner)) => unbounded(inner),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(expr: &str) -> Vec<(LintKind, &str)> {
+        lint(expr)
+            .unwrap()
+            .into_iter()
+            .map(|l| (l.kind, &expr[l.span.start..l.span.end]))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        use LintKind::*;
+        assert_eq!(kinds("ab(c|d)*e?"), []);
+        assert_eq!(
+            kinds("xa**y"),
+            [(RedundantQuantifier("a*".to_string()), "a**")]
+        );
+        assert_eq!(
+            kinds("(ab)+?+"),
+            [(RedundantQuantifier("(ab)*".to_string()), "(ab)+?+")]
+        );
+        assert_eq!(
+            kinds("a??"),
+            [(RedundantQuantifier("a?".to_string()), "a??")]
+        );
+        assert_eq!(kinds("(a+)+b"), [(NestedQuantifier, "(a+)+")]);
+        assert_eq!(kinds("(a|b*c)*"), [(NestedQuantifier, "(a|b*c)*")]);
+        assert_eq!(kinds("(a?)*"), []);
+        assert_eq!(kinds("(a*)?"), []);
+        assert_eq!(kinds("a|"), [(EmptyBranch, "|")]);
+        assert_eq!(kinds("(a|)b"), [(EmptyBranch, "|")]);
+        assert_eq!(kinds("a|b|a"), [(DuplicateBranch, "a")]);
+        assert_eq!(kinds("x()y"), [(EmptyGroup, "()")]);
+        assert_eq!(
+            kinds("(a()|b)**"),
+            [
+                (RedundantQuantifier("(a()|b)*".to_string()), "(a()|b)**"),
+                (EmptyGroup, "()"),
+            ]
+        );
+
+        // エスケープされた記号は量指定子や括弧とみなさない
+        assert_eq!(kinds(r"\*\*\(\)"), []);
+    }
+
+    #[test]
+    fn test_lint_error() {
+        assert!(matches!(lint("a(b"), Err(Error::Parse(_))));
+
+        let deep = format!("{}a{}", "(".repeat(1000), ")**".repeat(1000));
+        assert!(lint(&deep).is_ok());
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let expr = "ab|ab";
+        let lints = lint(expr).unwrap();
+        assert_eq!(
+            lints[0].diagnostic(expr),
+            "warning: duplicate alternative: `ab` is already tried before\n  ab|ab\n     ^^"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! regexクレートとの差分テスト
+//!
+//! 対応する構文の範囲でランダムな正規表現と入力文字列を生成し、このエンジンのマッチング結果が
+//! regexクレート（regex_refとして参照）と一致するかを検査する。
+//! 深さ優先、幅優先、DFAの評価器は、互いに参照実装として結果が一致することも検査する。
+//! また、`Regex::sample`で生成した文字列に、regexクレートでも行全体がマッチすることを検査する。
+//!
+//! 量指定子は常に括弧で囲んだ式に付ける。regexクレートでは`a*?`が最短一致を表すなど、
+//! 量指定子を重ねた場合の意味が異なるためである。
+use proptest::prelude::*;
+use regex::{do_matching_with, Backend, Regex, Rng};
+
+/// 正規表現を生成する
+///
+/// 文字はa、b、cに限り、入力文字列にない文字もエスケープとして含める。
+fn pattern() -> impl Strategy<Value = String> {
+    let atom = prop_oneof![
+        4 => "[abc]",
+        1 => Just(".".to_string()),
+        1 => Just(r"\.".to_string()),
+        1 => Just("^".to_string()),
+        1 => Just("$".to_string()),
+    ];
+    atom.prop_recursive(4, 32, 4, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 2..4).prop_map(|es| es.concat()),
+            (inner.clone(), inner.clone()).prop_map(|(e1, e2)| format!("{e1}|{e2}")),
+            (inner.clone(), prop::sample::select(vec!['*', '+', '?']))
+                .prop_map(|(e, q)| format!("({e}){q}")),
+            inner.prop_map(|e| format!("({e})")),
+        ]
+    })
+}
+
+/// 入力文字列を生成する
+fn haystack() -> impl Strategy<Value = String> {
+    "[abc]{0,8}"
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    /// 行頭から始まるマッチの有無が一致する
+    #[test]
+    fn matches_at_head(expr in pattern(), line in haystack()) {
+        let expected = regex_ref::Regex::new(&format!(r"\A(?:{expr})"))
+            .unwrap()
+            .is_match(&line);
+        for backend in Backend::ALL {
+            let result = do_matching_with(&expr, &line, true, backend).unwrap();
+            prop_assert_eq!(result, expected, "backend = {:?}", backend);
+        }
+    }
+
+    /// 最も左にあるマッチの範囲が一致する
+    #[test]
+    fn leftmost_first(expr in pattern(), line in haystack()) {
+        let expected = regex_ref::Regex::new(&expr)
+            .unwrap()
+            .find(&line)
+            .map(|m| (m.start(), m.end()));
+        let found = Regex::new(&expr)
+            .unwrap()
+            .find_iter(&line)
+            .next()
+            .map(|m| m.map(|m| (m.start(), m.end())).unwrap());
+        prop_assert_eq!(found, expected);
+    }
+
+    /// 生成した文字列は、行全体がマッチする
+    #[test]
+    fn sample_matches(expr in pattern(), seed in any::<u64>()) {
+        let re = regex_ref::Regex::new(&format!(r"\A(?:{expr})\z")).unwrap();
+        if let Some(s) = Regex::new(&expr).unwrap().sample(&mut Rng::new(seed), 8) {
+            prop_assert!(s.chars().count() <= 8, "sample = {:?}", s);
+            prop_assert!(re.is_match(&s), "sample = {:?}", s);
+        }
+    }
+}
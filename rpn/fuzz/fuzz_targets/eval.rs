@@ -0,0 +1,44 @@
+//! 任意の入力をパースして評価し、パニックや算術演算のオーバーフローが起きないことを検査する
+//!
+//! 入力の先頭のバイトを3で割った余りで記法を選ぶ（`0`は前置記法、`1`は後置記法、`2`は中置記法）。
+//! 残りを1行の入力として、同じ環境で`;`で区切られた各文を順に評価し、結果を表示する。
+//! オーバーフローの検査は、`cargo fuzz`が既定で有効にするdebug assertionsによる。
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rpn::{
+    eval,
+    parser::{self, Notation, DEFAULT_MAX_DEPTH},
+    Env,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&first, rest)) = data.split_first() else {
+        return;
+    };
+    let Ok(line) = std::str::from_utf8(rest) else {
+        return;
+    };
+    let notation = match first % 3 {
+        0 => Notation::Prefix,
+        1 => Notation::Postfix,
+        _ => Notation::Infix,
+    };
+
+    let Ok(stmts) = parser::parse_line(line, notation, DEFAULT_MAX_DEPTH) else {
+        return;
+    };
+    let mut env = Env::new();
+    for stmt in &stmts {
+        match eval::exec(stmt, &mut env) {
+            Ok(Some(v)) => {
+                let _ = v.to_string();
+                env.push_result(v);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                let _ = e.to_string();
+            }
+        }
+    }
+});
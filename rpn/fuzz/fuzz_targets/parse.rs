@@ -0,0 +1,31 @@
+//! 任意の入力を各記法とスタックモードでパースし、パニックしないことを検査する
+//!
+//! パースに成功した式は中置記法、LaTeX、DOT言語で表示し、失敗した場合は診断メッセージを生成する。
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rpn::parser::{self, Notation, Stmt, DEFAULT_MAX_DEPTH};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(line) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    for notation in [Notation::Prefix, Notation::Postfix, Notation::Infix] {
+        match parser::parse_line(line, notation, DEFAULT_MAX_DEPTH) {
+            Ok(stmts) => {
+                for stmt in &stmts {
+                    let (Stmt::Expr(e) | Stmt::FnDef(_, _, e)) = stmt;
+                    let _ = (e.to_string(), e.to_latex(), e.to_dot());
+                }
+            }
+            Err(e) => {
+                let _ = (e.diagnostic(line), e.is_incomplete(line));
+            }
+        }
+    }
+
+    if let Err(e) = parser::stack::parse_line(line, DEFAULT_MAX_DEPTH) {
+        let _ = e.diagnostic(line);
+    }
+});
@@ -0,0 +1,464 @@
+//! 組み込み関数
+//!
+//! 関数名、引数の名前、説明、関数本体を表に登録し、evalから名前で呼び出す。
+//! 引数の名前と説明は、REPLの`:doc`で表示する。
+//! `sqrt`、`sin`、`cos`、`ln`は、整数の引数も浮動小数点数に変換して計算する。
+//! `float`は、有理数などの値を浮動小数点数に変換する。
+//!
+//! `sqrt`、`sin`、`cos`、`ln`、`abs`は複素数の引数にも対応する。
+//! 負の実数の平方根や対数は、複素数ではなくNaNとなる。
+//! `re`、`im`、`conj`、`arg`は、複素数の実部、虚部、共役、偏角を返す。
+//!
+//! `interval(lo, hi)`は区間を作り、`lo`、`hi`、`mid`は区間の下限、上限、中点を返す。
+//! `sqrt`、`sin`、`cos`、`ln`、`abs`は区間の引数にも対応し、値の取りうる範囲を区間で返す。
+//!
+//! `sum`、`mean`、`median`、`stddev`、`minl`、`maxl`は、リストの要素の合計、平均、中央値、
+//! 標準偏差、最小値、最大値を返す。`sum`以外は、空のリストの場合はエラーとなる。
+//!
+//! `rand()`は0以上1未満の浮動小数点数の乱数を、`rand_int(a, b)`はa以上b以下の整数の乱数を返す。
+//! 乱数は、環境が保持する乱数生成器で生成する。
+//! これらは呼び出すごとに値が変わるため、純粋な関数としない。
+use crate::{decimal::Rounding, eval::EvalError, interval::Interval, rng::Rng, value::Value};
+use num_complex::Complex64;
+use num_traits::{Signed, ToPrimitive};
+use std::cmp::Ordering;
+
+/// 組み込み関数を表す型
+struct Builtin {
+    name: &'static str,
+    params: &'static [&'static str], // 引数の名前
+    doc: &'static str,               // `:doc`で表示する説明
+    pure: bool,                      // 同じ引数に対して常に同じ値を返すか
+    func: fn(&[Value], &mut Rng) -> Result<Value, EvalError>,
+}
+
+/// 組み込み関数の一覧
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "abs",
+        params: &["x"],
+        doc: "Absolute value of a number, or magnitude of a complex number",
+        pure: true,
+        func: |args, _| abs(&args[0]),
+    },
+    Builtin {
+        name: "min",
+        params: &["a", "b"],
+        doc: "Smaller of two numbers",
+        pure: true,
+        func: |args, _| min_max(&args[0], &args[1], true),
+    },
+    Builtin {
+        name: "max",
+        params: &["a", "b"],
+        doc: "Larger of two numbers",
+        pure: true,
+        func: |args, _| min_max(&args[0], &args[1], false),
+    },
+    Builtin {
+        name: "gcd",
+        params: &["a", "b"],
+        doc: "Greatest common divisor of two integers",
+        pure: true,
+        func: |args, _| Ok(Value::Int(gcd(to_int(&args[0])?, to_int(&args[1])?)?)),
+    },
+    Builtin {
+        name: "lcm",
+        params: &["a", "b"],
+        doc: "Least common multiple of two integers",
+        pure: true,
+        func: |args, _| Ok(Value::Int(lcm(to_int(&args[0])?, to_int(&args[1])?)?)),
+    },
+    Builtin {
+        name: "pow",
+        params: &["x", "y"],
+        doc: "x raised to the power y",
+        pure: true,
+        func: |args, _| args[0].clone().pow(args[1].clone()),
+    },
+    Builtin {
+        name: "sqrt",
+        params: &["x"],
+        doc: "Square root",
+        pure: true,
+        func: |args, _| {
+            math(&args[0], f64::sqrt, Complex64::sqrt, |x| {
+                x.map_increasing(f64::sqrt)
+            })
+        },
+    },
+    Builtin {
+        name: "sin",
+        params: &["x"],
+        doc: "Sine of an angle in radians",
+        pure: true,
+        func: |args, _| math(&args[0], f64::sin, Complex64::sin, Interval::sin),
+    },
+    Builtin {
+        name: "cos",
+        params: &["x"],
+        doc: "Cosine of an angle in radians",
+        pure: true,
+        func: |args, _| math(&args[0], f64::cos, Complex64::cos, Interval::cos),
+    },
+    Builtin {
+        name: "ln",
+        params: &["x"],
+        doc: "Natural logarithm",
+        pure: true,
+        func: |args, _| {
+            math(&args[0], f64::ln, Complex64::ln, |x| {
+                x.map_increasing(f64::ln)
+            })
+        },
+    },
+    Builtin {
+        name: "re",
+        params: &["z"],
+        doc: "Real part of a complex number",
+        pure: true,
+        func: |args, _| Ok(Value::Float(to_complex(&args[0])?.re)),
+    },
+    Builtin {
+        name: "im",
+        params: &["z"],
+        doc: "Imaginary part of a complex number",
+        pure: true,
+        func: |args, _| Ok(Value::Float(to_complex(&args[0])?.im)),
+    },
+    Builtin {
+        name: "conj",
+        params: &["z"],
+        doc: "Complex conjugate",
+        pure: true,
+        func: |args, _| Ok(Value::Complex(to_complex(&args[0])?.conj())),
+    },
+    Builtin {
+        name: "arg",
+        params: &["z"],
+        doc: "Argument (phase angle) of a complex number in radians",
+        pure: true,
+        func: |args, _| Ok(Value::Float(to_complex(&args[0])?.arg())),
+    },
+    Builtin {
+        name: "interval",
+        params: &["lo", "hi"],
+        doc: "Interval from lo to hi",
+        pure: true,
+        func: |args, _| {
+            Interval::new(bound(&args[0])?, bound(&args[1])?)
+                .map(Value::Interval)
+                .ok_or(EvalError::InvalidInterval)
+        },
+    },
+    Builtin {
+        name: "lo",
+        params: &["x"],
+        doc: "Lower bound of an interval",
+        pure: true,
+        func: |args, _| Ok(Value::Float(to_interval(&args[0])?.lo())),
+    },
+    Builtin {
+        name: "hi",
+        params: &["x"],
+        doc: "Upper bound of an interval",
+        pure: true,
+        func: |args, _| Ok(Value::Float(to_interval(&args[0])?.hi())),
+    },
+    Builtin {
+        name: "mid",
+        params: &["x"],
+        doc: "Midpoint of an interval",
+        pure: true,
+        func: |args, _| Ok(Value::Float(to_interval(&args[0])?.mid())),
+    },
+    Builtin {
+        name: "float",
+        params: &["x"],
+        doc: "Convert a number to a floating-point number",
+        pure: true,
+        func: |args, _| Ok(Value::Float(args[0].to_f64()?)),
+    },
+    Builtin {
+        name: "len",
+        params: &["xs"],
+        doc: "Number of elements in a list",
+        pure: true,
+        func: |args, _| match &args[0] {
+            Value::List(xs) => i64::try_from(xs.len())
+                .map(Value::Int)
+                .map_err(|_| EvalError::Overflow),
+            _ => Err(EvalError::NotList),
+        },
+    },
+    Builtin {
+        name: "sum",
+        params: &["xs"],
+        doc: "Sum of the elements of a list",
+        pure: true,
+        func: |args, _| sum(to_list(&args[0])?),
+    },
+    Builtin {
+        name: "mean",
+        params: &["xs"],
+        doc: "Mean of the elements of a non-empty list",
+        pure: true,
+        func: |args, _| mean(non_empty(&args[0])?),
+    },
+    Builtin {
+        name: "median",
+        params: &["xs"],
+        doc: "Median of the elements of a non-empty list",
+        pure: true,
+        func: |args, _| median(non_empty(&args[0])?),
+    },
+    Builtin {
+        name: "stddev",
+        params: &["xs"],
+        doc: "Standard deviation of the elements of a non-empty list",
+        pure: true,
+        func: |args, _| stddev(non_empty(&args[0])?),
+    },
+    Builtin {
+        name: "minl",
+        params: &["xs"],
+        doc: "Smallest element of a non-empty list",
+        pure: true,
+        func: |args, _| min_max_list(non_empty(&args[0])?, true),
+    },
+    Builtin {
+        name: "maxl",
+        params: &["xs"],
+        doc: "Largest element of a non-empty list",
+        pure: true,
+        func: |args, _| min_max_list(non_empty(&args[0])?, false),
+    },
+    Builtin {
+        name: "rand",
+        params: &[],
+        doc: "Random floating-point number in [0, 1)",
+        pure: false,
+        func: |_, rng| Ok(Value::Float(rng.next_f64())),
+    },
+    Builtin {
+        name: "rand_int",
+        params: &["a", "b"],
+        doc: "Random integer from a to b inclusive",
+        pure: false,
+        func: |args, rng| {
+            rng.range(to_int(&args[0])?, to_int(&args[1])?)
+                .map(Value::Int)
+                .ok_or(EvalError::InvalidRange)
+        },
+    },
+];
+
+/// 組み込み関数nameを引数argsで呼び出す
+///
+/// 乱数はrngで生成する。関数が存在しない場合や、引数の数が誤っている場合はエラー。
+pub fn call(name: &str, args: &[Value], rng: &mut Rng) -> Result<Value, EvalError> {
+    let Some(builtin) = BUILTINS.iter().find(|b| b.name == name) else {
+        return Err(EvalError::UndefinedFunction(name.to_string(), None));
+    };
+
+    if args.len() != builtin.params.len() {
+        return Err(EvalError::WrongArity(
+            name.to_string(),
+            builtin.params.len(),
+            args.len(),
+        ));
+    }
+
+    (builtin.func)(args, rng)
+}
+
+/// 組み込み関数の名前を返す
+pub fn names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().map(|b| b.name)
+}
+
+/// nameが、同じ引数に対して常に同じ値を返す組み込み関数かを判定
+///
+/// 乱数を返す`rand`などや、組み込み関数でない場合はfalse。
+pub fn is_pure(name: &str) -> bool {
+    BUILTINS.iter().any(|b| b.name == name && b.pure)
+}
+
+/// 組み込み関数nameの`sqrt(x)`のような書式と説明を返す
+pub fn doc(name: &str) -> Option<(String, &'static str)> {
+    let builtin = BUILTINS.iter().find(|b| b.name == name)?;
+    Some((
+        format!("{name}({})", builtin.params.join(", ")),
+        builtin.doc,
+    ))
+}
+
+/// 整数に変換
+///
+/// 多倍長整数や分母が1の有理数は、i64の範囲内の場合のみ変換できる。
+fn to_int(v: &Value) -> Result<i64, EvalError> {
+    match v.to_integer() {
+        Some(n) => n.to_i64().ok_or(EvalError::Overflow),
+        None => Err(EvalError::NotInteger),
+    }
+}
+
+/// リストの要素を返す
+fn to_list(v: &Value) -> Result<&[Value], EvalError> {
+    match v {
+        Value::List(xs) => Ok(xs),
+        _ => Err(EvalError::NotList),
+    }
+}
+
+/// 空でないリストの要素を返す
+fn non_empty(v: &Value) -> Result<&[Value], EvalError> {
+    match to_list(v)? {
+        [] => Err(EvalError::EmptyList),
+        xs => Ok(xs),
+    }
+}
+
+/// 複素数に変換
+fn to_complex(v: &Value) -> Result<Complex64, EvalError> {
+    v.to_complex().ok_or(EvalError::NotNumber)
+}
+
+/// 区間に変換
+fn to_interval(v: &Value) -> Result<Interval, EvalError> {
+    v.to_interval().ok_or(EvalError::NotNumber)
+}
+
+/// 区間の端点となる実数に変換
+///
+/// 区間の設定では数値が1点のみからなる区間となるため、そのような区間も端点として受け付ける。
+fn bound(v: &Value) -> Result<f64, EvalError> {
+    match v {
+        Value::Interval(x) if x.lo() == x.hi() => Ok(x.lo()),
+        v => v.to_f64(),
+    }
+}
+
+/// 実数の場合はreal_opを、複素数の場合はcomplex_opを、区間の場合はinterval_opを適用する
+fn math(
+    v: &Value,
+    real_op: fn(f64) -> f64,
+    complex_op: fn(Complex64) -> Complex64,
+    interval_op: fn(Interval) -> Interval,
+) -> Result<Value, EvalError> {
+    match v {
+        Value::Complex(z) => Ok(Value::Complex(complex_op(*z))),
+        Value::Interval(x) => Ok(Value::Interval(interval_op(*x))),
+        v => Ok(Value::Float(real_op(v.to_f64()?))),
+    }
+}
+
+fn abs(v: &Value) -> Result<Value, EvalError> {
+    match v {
+        Value::Int(n) => n.checked_abs().map(Value::Int).ok_or(EvalError::Overflow),
+        Value::Big(n) => Ok(Value::Big(n.abs())),
+        Value::Ratio(r) => Ok(Value::Ratio(Box::new(r.abs()))),
+        Value::Decimal(d) => Ok(Value::Decimal(Box::new(d.abs()))),
+        Value::Float(x) => Ok(Value::Float(x.abs())),
+        // 複素数の絶対値は、原点からの距離とする
+        Value::Complex(z) => Ok(Value::Float(z.norm())),
+        Value::Interval(x) => Ok(Value::Interval(x.abs())),
+        _ => Err(EvalError::NotNumber),
+    }
+}
+
+/// 小さい方（minがfalseの場合は大きい方）の値を返す
+///
+/// 整数と浮動小数点数を比較した場合も、元の値をそのまま返す。
+fn min_max(a: &Value, b: &Value, min: bool) -> Result<Value, EvalError> {
+    // 大きな整数はf64では正確に比較できないため、Value::compareで比較する
+    let a_is_less = a.compare(b, Ordering::is_lt)?.to_bool()?;
+    Ok(if a_is_less == min { a } else { b }.clone())
+}
+
+/// 最大公約数
+fn gcd(a: i64, b: i64) -> Result<i64, EvalError> {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    i64::try_from(a).map_err(|_| EvalError::Overflow)
+}
+
+/// 最小公倍数
+fn lcm(a: i64, b: i64) -> Result<i64, EvalError> {
+    if a == 0 || b == 0 {
+        return Ok(0);
+    }
+    (a / gcd(a, b)?)
+        .checked_mul(b)
+        .and_then(i64::checked_abs)
+        .ok_or(EvalError::Overflow)
+}
+
+/// 要素の合計
+///
+/// 要素同士を加算するため、整数のみのリストの合計は整数となる。空のリストの合計は0。
+fn sum(xs: &[Value]) -> Result<Value, EvalError> {
+    let Some((first, rest)) = xs.split_first() else {
+        return Ok(Value::Int(0));
+    };
+    rest.iter()
+        .try_fold(first.clone(), |acc, x| acc.add(x.clone()))
+}
+
+/// 要素の平均
+///
+/// 整数のみのリストでも切り捨てないよう、要素数を浮動小数点数として除算する。
+fn mean(xs: &[Value]) -> Result<Value, EvalError> {
+    sum(xs)?.div(Value::Float(xs.len() as f64), Rounding::default())
+}
+
+/// 要素の中央値
+///
+/// 要素数が偶数の場合は、中央の2つの要素の平均とする。
+fn median(xs: &[Value]) -> Result<Value, EvalError> {
+    let mut sorted = xs.to_vec();
+    let mut error = None;
+    sorted.sort_by(
+        |a, b| match a.compare(b, Ordering::is_lt).and_then(|v| v.to_bool()) {
+            Ok(true) => Ordering::Less,
+            Ok(false) => Ordering::Greater,
+            Err(e) => {
+                error.get_or_insert(e);
+                Ordering::Equal
+            }
+        },
+    );
+    if let Some(e) = error {
+        return Err(e);
+    }
+
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 1 {
+        Ok(sorted.swap_remove(mid))
+    } else {
+        mean(&sorted[mid - 1..=mid])
+    }
+}
+
+/// 要素の母標準偏差
+///
+/// 要素を浮動小数点数に変換して計算する。
+fn stddev(xs: &[Value]) -> Result<Value, EvalError> {
+    let xs = xs
+        .iter()
+        .map(Value::to_f64)
+        .collect::<Result<Vec<_>, _>>()?;
+    let n = xs.len() as f64;
+    let mean = xs.iter().sum::<f64>() / n;
+    let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+    Ok(Value::Float(var.sqrt()))
+}
+
+/// 最小（minがfalseの場合は最大）の要素を返す
+fn min_max_list(xs: &[Value], min: bool) -> Result<Value, EvalError> {
+    let (first, rest) = xs.split_first().ok_or(EvalError::EmptyList)?;
+    rest.iter()
+        .try_fold(first.clone(), |acc, x| min_max(&acc, x, min))
+}
@@ -0,0 +1,843 @@
+//! `rpn`コマンド
+//!
+//! REPLのほか、`-e`での式の評価、`run`や`watch`でのスクリプトファイルの実行、
+//! `lisp`でのs式の評価を行う。
+//! `rpn`と`zero calc`は、ともにmainを呼び出す。
+mod command;
+mod lisp;
+mod output;
+mod repl;
+mod save;
+mod style;
+mod transcript;
+
+use crate::{
+    builtin, diff, env, eval,
+    parser::{self, Expr, Notation, Stmt},
+    simplify, stack, value, wasm,
+};
+use clap::Parser;
+use notify::{EventKind, RecursiveMode, Watcher};
+use rustyline::{error::ReadlineError, Config, Editor};
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, BufRead, BufReader, IsTerminal},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+/// 履歴ファイルに保存する入力の数の既定値
+const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+/// `watch`で、ファイルの変更から実行し直すまでに続く変更を待つ時間
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// 評価エラーの場合の終了コード
+const EXIT_EVAL_ERROR: i32 = 1;
+/// パースエラーの場合の終了コード（2はclapがコマンドライン引数の誤りに用いる）
+const EXIT_PARSE_ERROR: i32 = 3;
+/// ファイルの読み込みに失敗した場合の終了コード
+const EXIT_IO_ERROR: i32 = 4;
+
+/// Evaluate arithmetic expressions interactively
+#[derive(Parser, Debug)]
+#[command(
+    name = "rpn",
+    version,
+    after_help = "Exit status with -e, `run` or piped input: 1 on evaluation errors, \
+                  3 on parse errors, 4 if the input cannot be read"
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Notation of input expressions: rpn (postfix), infix or prefix
+    #[arg(long, global = true, default_value = "prefix")]
+    notation: Notation,
+
+    /// Maximum nesting depth of parentheses and operators in expressions (at most 512)
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        default_value_t = parser::DEFAULT_MAX_DEPTH,
+        value_parser = parse_max_depth
+    )]
+    max_depth: usize,
+
+    /// Print the result of every `;`-separated expression, not only the last one
+    #[arg(long, global = true)]
+    all_results: bool,
+
+    /// Print the syntax tree of each expression to stderr before evaluating it, and trace
+    /// parsing, compilation and evaluation with timings (-vv for more detail; RUST_LOG overrides)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Integer width used when checking for overflow: i64 or i128
+    #[arg(long, global = true, value_name = "WIDTH", default_value = "i64")]
+    int_width: value::IntWidth,
+
+    /// Compile EXPR to a WebAssembly module exporting `eval` and write it to FILE
+    #[arg(long, value_name = "FILE", requires = "expr")]
+    emit_wasm: Option<PathBuf>,
+
+    /// Evaluate EXPR, print only its result and exit (with a non-zero status on error)
+    #[arg(
+        short = 'e',
+        long = "eval",
+        value_name = "EXPR",
+        conflicts_with = "emit_wasm"
+    )]
+    eval: Option<String>,
+
+    /// Start the REPL in stack mode: numbers push onto a persistent stack and operators pop them
+    #[arg(long)]
+    stack: bool,
+
+    /// Output format for scripts and piped input: text, or json/csv records of each line
+    #[arg(long, global = true, value_name = "FORMAT", default_value = "text")]
+    format: output::Format,
+
+    /// Do not load or save the history file (~/.rpn_history)
+    #[arg(long)]
+    no_history: bool,
+
+    /// Maximum number of entries kept in the history file
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_HISTORY_SIZE)]
+    history_size: usize,
+
+    /// Prompt shown before each REPL input [default: ">> ", or `prompt` in ~/.rpnrc]
+    #[arg(long, value_name = "STR")]
+    prompt: Option<String>,
+
+    /// Color results green and errors red in the REPL: auto, always or never
+    /// [default: auto, or `color` in ~/.rpnrc]
+    #[arg(long, value_name = "WHEN")]
+    color: Option<style::ColorMode>,
+
+    /// Key bindings for editing REPL input: emacs or vi [default: emacs, or `edit-mode` in ~/.rpnrc]
+    #[arg(long, value_name = "MODE")]
+    edit_mode: Option<style::EditMode>,
+
+    /// Append every REPL input, result and error to FILE with timestamps
+    #[arg(long, value_name = "FILE")]
+    log: Option<PathBuf>,
+
+    /// Expression to compile with --emit-wasm; its free variables become the parameters of `eval`
+    #[arg(requires = "emit_wasm", allow_hyphen_values = true)]
+    expr: Option<String>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Evaluate a file of expressions line by line
+    Run(RunArgs),
+    /// Evaluate a file like `run`, and again with a fresh environment whenever it changes
+    Watch(RunArgs),
+    /// Evaluate s-expressions (define, lambda, if, let, lists) in a REPL, or from FILE
+    Lisp(LispArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct LispArgs {
+    /// File of s-expressions; the value of each expression other than a definition is printed
+    file: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct RunArgs {
+    /// Script file; each line is evaluated like a REPL input
+    file: PathBuf,
+
+    /// Continue with the next line after an error instead of stopping
+    #[arg(long)]
+    keep_going: bool,
+
+    /// Print only the final result instead of the result of every line
+    #[arg(long)]
+    last: bool,
+}
+
+/// `--max-depth`の値をパース
+///
+/// 深すぎるネストでスタックが溢れないように、parser::MAX_MAX_DEPTHを超える値はエラーとする。
+fn parse_max_depth(s: &str) -> Result<usize, String> {
+    let n: usize = s.parse().map_err(|e| format!("{e}"))?;
+    if n > parser::MAX_MAX_DEPTH {
+        return Err(format!("must be at most {}", parser::MAX_MAX_DEPTH));
+    }
+    Ok(n)
+}
+
+/// コマンドライン引数argsに従って実行する
+///
+/// argsの最初の要素はコマンド名とする。エラーの場合は、終了コードを返さずにプロセスを終了する。
+pub fn main<I, T>(args: I)
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let args = Args::parse_from(args);
+    helper::trace::init(args.verbose, &["rpn"]);
+    let notation = args.notation;
+    let mut env = env::Env::new();
+    env.settings.width = args.int_width;
+
+    if let (Some(path), Some(expr)) = (&args.emit_wasm, &args.expr) {
+        if let Err(e) = emit_wasm(path, expr, notation, args.max_depth) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(Command::Run(opts)) = &args.command {
+        let status = run_file(&opts.file, opts, &args, &mut env);
+        if status != 0 {
+            std::process::exit(status);
+        }
+        return;
+    }
+
+    if let Some(Command::Watch(opts)) = &args.command {
+        std::process::exit(watch_file(&opts.file, opts, &args));
+    }
+
+    if let Some(Command::Lisp(opts)) = &args.command {
+        let status = match &opts.file {
+            Some(path) => lisp::run_file(path),
+            None if !io::stdin().is_terminal() => match io::read_to_string(io::stdin()) {
+                Ok(src) => lisp::run_source("<stdin>", &src),
+                Err(e) => {
+                    eprintln!("<stdin>: {e}");
+                    EXIT_IO_ERROR
+                }
+            },
+            None => {
+                style::set_color(args.color.unwrap_or_default().enabled());
+                lisp::repl();
+                0
+            }
+        };
+        if status != 0 {
+            std::process::exit(status);
+        }
+        return;
+    }
+
+    if let Some(c) = &args.eval {
+        if let Err(e) = exec_line(c, &args, notation, &mut env, |v| println!("{v}")) {
+            eprintln!("{e}");
+            std::process::exit(e.exit_code());
+        }
+        return;
+    }
+
+    // パイプなどから入力する場合は、プロンプトを表示せずに各行を実行する
+    if !io::stdin().is_terminal() {
+        let status = run_lines("<stdin>", io::stdin().lock(), true, false, &args, &mut env);
+        if status != 0 {
+            std::process::exit(status);
+        }
+        return;
+    }
+
+    // プロンプト、色、キー操作は、コマンドラインでの指定、設定ファイル、既定値の順に決める
+    let config = match home_path(".rpnrc").map(|path| style::Config::load(&path)) {
+        Some(Ok(config)) => config,
+        Some(Err(e)) => {
+            eprintln!("warning: {e}");
+            style::Config::default()
+        }
+        None => style::Config::default(),
+    };
+    let prompt = (args.prompt.clone())
+        .or(config.prompt)
+        .unwrap_or_else(|| style::DEFAULT_PROMPT.to_string());
+    style::set_color(args.color.or(config.color).unwrap_or_default().enabled());
+
+    if let Some(path) = &args.log {
+        if let Err(e) = transcript::start(Some(path), || None) {
+            eprintln!("warning: failed to open {}: {e}", path.display());
+        }
+    }
+
+    let mut session = command::Session {
+        env,
+        notation,
+        max_depth: args.max_depth,
+        stack: args.stack.then(stack::Stack::new),
+        registers: Default::default(),
+    };
+    let config = Config::builder()
+        .max_history_size(args.history_size)
+        .bracketed_paste(true)
+        .edit_mode(
+            args.edit_mode
+                .or(config.edit_mode)
+                .unwrap_or_default()
+                .into(),
+        )
+        .build();
+    let mut rl = Editor::<repl::ReplHelper>::with_config(config).unwrap();
+    rl.set_helper(Some(repl::ReplHelper::new()));
+
+    // 以前のセッションの入力を履歴ファイルから読み込む
+    let history = home_path(".rpn_history").filter(|_| !args.no_history);
+    if let Some(path) = history.as_ref().filter(|path| path.exists()) {
+        if let Err(e) = rl.load_history(path) {
+            eprintln!(
+                "warning: failed to load history from {}: {e}",
+                path.display()
+            );
+        }
+    }
+
+    'repl: loop {
+        // 補完の候補を、これまでに定義した変数や関数に更新する
+        if let Some(helper) = rl.helper_mut() {
+            helper.refresh(&session.env);
+        }
+        let mut readline = match rl.readline(&prompt) {
+            Ok(line) => line,
+            // Ctrl-Cの場合は、入力中の行を取り消して次の行を読み込む
+            Err(ReadlineError::Interrupted) => continue,
+            // Ctrl-Dの場合は終了する
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                style::error!("error: {e}");
+                break;
+            }
+        };
+
+        // 複数行を貼り付けた場合は、各行を入力した場合と同様に順に実行し、結果をまとめて表示する
+        if readline.contains('\n') {
+            let lines = repl::split_pasted(&readline, |c| {
+                session.stack.is_none()
+                    && !c.trim_start().starts_with(':')
+                    && is_incomplete(c, session.notation, session.max_depth)
+            });
+            for line in &lines {
+                rl.add_history_entry(line.as_str());
+            }
+            let mut out = repl::Printer::batch();
+            let flow = exec_pasted(&mut session, &lines, &args, &mut out);
+            out.flush();
+            if flow == command::Flow::Quit {
+                break 'repl;
+            }
+            continue;
+        }
+
+        // 閉じ括弧がないなど入力が途中の場合は、続きの行を読み込んで連結する
+        while session.stack.is_none()
+            && !readline.trim_start().starts_with(':')
+            && is_incomplete(&readline, session.notation, session.max_depth)
+        {
+            match rl.readline(".. ") {
+                Ok(line) => repl::join_line(&mut readline, &line),
+                // Ctrl-Cの場合は、途中までの入力も含めて取り消す
+                Err(ReadlineError::Interrupted) => continue 'repl,
+                // 続きがない場合は、そのままパースしてエラーを表示する
+                Err(_) => break,
+            }
+        }
+        rl.add_history_entry(readline.as_str());
+
+        let flow = exec_input(
+            &mut session,
+            &readline,
+            &args,
+            &mut repl::Printer::default(),
+        );
+        if flow == command::Flow::Quit {
+            break;
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Err(e) = rl.save_history(path) {
+            eprintln!("warning: failed to save history to {}: {e}", path.display());
+        }
+    }
+}
+
+/// 貼り付けた複数行の入力linesを順に実行する
+///
+/// outを溜める表示先とすると、結果とエラーは入力ごとに表示されず、実行した順に溜まる。
+/// `:quit`などで終了する場合は、以降の入力を実行しない。
+fn exec_pasted(
+    session: &mut command::Session,
+    lines: &[String],
+    args: &Args,
+    out: &mut repl::Printer,
+) -> command::Flow {
+    let mut flow = command::Flow::Continue;
+    for line in lines {
+        flow = exec_input(session, line, args, out);
+        if flow == command::Flow::Quit {
+            break;
+        }
+    }
+    flow
+}
+
+/// 入力行全体が`simplify(式)`の呼び出しの場合は、その引数を返す
+///
+/// 関数呼び出しの構文のため、記法に関わらず中置記法としてパースする。
+/// `simplify(x) + simplify(y)`のように、呼び出しが式の一部である場合はNone。
+fn simplify_args(line: &str, max_depth: usize) -> Option<Vec<Expr>> {
+    match parser::parse(line, Notation::Infix, max_depth) {
+        Ok(Stmt::Expr(Expr::Call(name, args))) if name == "simplify" => Some(args),
+        _ => None,
+    }
+}
+
+/// REPLの1つの入力を実行し、結果をoutに表示する
+///
+/// `:`から始まる場合はコマンドとして、スタックモードではスタックへの操作として実行する。
+/// コマンドは自身で表示を行うため、outに溜めている結果を先に表示してから実行する。
+fn exec_input(
+    session: &mut command::Session,
+    line: &str,
+    args: &Args,
+    out: &mut repl::Printer,
+) -> command::Flow {
+    transcript::input(line);
+
+    // `:`から始まる行は、REPLのコマンドとして実行する
+    if line.trim_start().starts_with(':') {
+        out.flush();
+    }
+    if let Some(flow) = command::dispatch(session, line) {
+        return flow;
+    }
+
+    // スタックモードでは、各行の操作を実行してスタック全体を表示する
+    if let Some(stack) = &mut session.stack {
+        match parser::stack::parse_line(line, session.max_depth) {
+            Ok(ops) => match stack.exec(&ops, &mut session.env) {
+                Ok(()) => out.println(style::result(
+                    stack.to_string_with(&session.env.settings.number_format()),
+                )),
+                Err(e) => out.error(e),
+            },
+            Err(e) => out.error(e.diagnostic(line)),
+        }
+        return command::Flow::Continue;
+    }
+
+    // `simplify(式)`で、式を簡約した結果を中置記法で表示する
+    if let Some(args) = simplify_args(line, session.max_depth) {
+        match args.as_slice() {
+            [e] => out.println(style::result(simplify::simplify(e))),
+            _ => out.error("usage: simplify(expr)"),
+        }
+        return command::Flow::Continue;
+    }
+
+    // `diff(式, 変数)`で、式を変数で微分し、簡約した結果を中置記法で表示する
+    if let Some((arg, x)) = line
+        .trim()
+        .strip_prefix("diff(")
+        .and_then(|c| c.strip_suffix(')'))
+        .and_then(|c| c.rsplit_once(','))
+    {
+        match parser::parse(arg, session.notation, session.max_depth) {
+            Ok(Stmt::Expr(e)) => match diff::diff(&e, x.trim()) {
+                Ok(d) => out.println(style::result(simplify::simplify(&d))),
+                Err(e) => out.error(e),
+            },
+            Ok(Stmt::FnDef(..)) => out.error("usage: diff(expr, variable)"),
+            Err(e) => out.error(e.diagnostic(arg)),
+        }
+        return command::Flow::Continue;
+    }
+
+    // `式 to hex`のように、式ごとに結果を表示する基数を指定できる
+    let (line, radix) = command::split_radix_suffix(line);
+    let mut format = session.env.settings.number_format();
+    format.radix = radix.unwrap_or(format.radix);
+    if let Some(stmts) = parse(line, session.notation, session.max_depth, out) {
+        if args.verbose > 0 {
+            print_ast(&stmts);
+        }
+        for warning in shadowing_warnings(&stmts) {
+            out.error(warning);
+        }
+        if let Err(e) = run(&stmts, &mut session.env, args.all_results, |v| {
+            out.println(format!(
+                "result: {}",
+                style::result(v.to_string_with(&format))
+            ))
+        }) {
+            out.error(e);
+        }
+    }
+    command::Flow::Continue
+}
+
+/// 履歴ファイル（`~/.rpn_history`）や設定ファイル（`~/.rpnrc`）など、ホームディレクトリのファイルのパスを返す
+///
+/// ホームディレクトリが分からない場合はNone。
+fn home_path(name: &str) -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").filter(|home| !home.is_empty())?;
+    Some(PathBuf::from(home).join(name))
+}
+
+/// `;`で区切られた式を左から順に実行し、結果をemitに渡す
+///
+/// allがfalseの場合は、最後の結果のみを渡す。エラーが発生した場合は、以降の式を実行しない。
+fn run(
+    stmts: &[Stmt],
+    env: &mut env::Env,
+    all: bool,
+    mut emit: impl FnMut(value::Value),
+) -> Result<(), eval::EvalError> {
+    let mut last = None;
+    for s in stmts {
+        if let Some(v) = eval::exec(s, env)? {
+            env.push_result(v.clone());
+            if all {
+                emit(v);
+            } else {
+                last = Some(v);
+            }
+        }
+    }
+
+    if let Some(v) = last {
+        emit(v);
+    }
+    Ok(())
+}
+
+/// 1行を実行し、結果を表示する文字列をemitに渡す
+///
+/// 整数の結果は、行末の`to hex`などで指定した基数か、設定の基数で表す。
+/// パースや評価のエラーの場合は、そのエラーメッセージを返す。
+fn exec_line(
+    c: &str,
+    args: &Args,
+    notation: Notation,
+    env: &mut env::Env,
+    mut emit: impl FnMut(String),
+) -> Result<(), LineError> {
+    let (c, radix) = command::split_radix_suffix(c);
+    let mut format = env.settings.number_format();
+    format.radix = radix.unwrap_or(format.radix);
+    let stmts = parser::parse_line(c, notation, args.max_depth)
+        .map_err(|e| LineError::Parse(e.diagnostic(c)))?;
+    if args.verbose > 0 {
+        print_ast(&stmts);
+    }
+    for warning in shadowing_warnings(&stmts) {
+        eprintln!("{warning}");
+    }
+    run(&stmts, env, args.all_results, |v| {
+        emit(v.to_string_with(&format))
+    })
+    .map_err(|e| LineError::Eval(e.to_string()))
+}
+
+/// 組み込み関数と同じ名前の関数を定義する文について、警告のメッセージを返す
+///
+/// 定義した関数は組み込み関数より優先されるため、以降の呼び出しでは組み込み関数を呼べなくなる。
+fn shadowing_warnings(stmts: &[Stmt]) -> impl Iterator<Item = String> + '_ {
+    stmts.iter().filter_map(|s| match s {
+        Stmt::FnDef(name, ..) if builtin::names().any(|b| b == name) => Some(format!(
+            "warning: fn {name} shadows the built-in function {name}"
+        )),
+        _ => None,
+    })
+}
+
+/// 1行の実行で発生したエラー
+#[derive(Debug)]
+enum LineError {
+    Parse(String), // パースエラーのメッセージ
+    Eval(String),  // 評価エラーのメッセージ
+}
+
+impl LineError {
+    /// エラーの種類に応じた終了コード
+    fn exit_code(&self) -> i32 {
+        match self {
+            LineError::Parse(_) => EXIT_PARSE_ERROR,
+            LineError::Eval(_) => EXIT_EVAL_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Display for LineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LineError::Parse(msg) | LineError::Eval(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+/// スクリプトファイルの各行を順に実行し、結果を表示する
+///
+/// 終了コードを返す。
+fn run_file(path: &Path, opts: &RunArgs, args: &Args, env: &mut env::Env) -> i32 {
+    match File::open(path) {
+        Ok(file) => run_lines(
+            &path.display().to_string(),
+            BufReader::new(file),
+            opts.keep_going,
+            opts.last,
+            args,
+            env,
+        ),
+        Err(e) => {
+            eprintln!("{}: {e}", path.display());
+            EXIT_IO_ERROR
+        }
+    }
+}
+
+/// スクリプトファイルpathを実行し、ファイルが変更されるたびに新たな環境で実行し直す
+///
+/// エディタが保存時にファイルを置き換える場合にも変更を検知できるよう、ファイルのあるディレクトリを監視する。
+/// 続けて発生した変更はまとめて1回だけ実行する。監視を続けられなくなった場合のみ、終了コードを返す。
+fn watch_file(path: &Path, opts: &RunArgs, args: &Args) -> i32 {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(tx) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("failed to watch {}: {e}", path.display());
+            return EXIT_IO_ERROR;
+        }
+    };
+    if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+        eprintln!("failed to watch {}: {e}", dir.display());
+        return EXIT_IO_ERROR;
+    }
+
+    loop {
+        let mut env = env::Env::new();
+        env.settings.width = args.int_width;
+        run_file(path, opts, args, &mut env);
+        eprintln!("-- watching {} for changes --", path.display());
+
+        // ファイル自体の作成、変更、削除を待つ（読み込みなどのイベントは無視する）
+        loop {
+            match rx.recv() {
+                Ok(Ok(event)) => {
+                    let changed = !matches!(event.kind, EventKind::Access(_))
+                        && event
+                            .paths
+                            .iter()
+                            .any(|p| p.file_name() == path.file_name());
+                    if changed {
+                        break;
+                    }
+                }
+                Ok(Err(e)) => eprintln!("warning: {e}"),
+                Err(_) => return EXIT_IO_ERROR,
+            }
+        }
+        while rx.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+    }
+}
+
+/// 入力の各行を順に実行し、結果を1行ずつ表示する
+///
+/// 結果とエラーは、`--format`で指定した形式のレコードとして出力する。textの場合、エラーは
+/// 入力の名前nameと行番号を付けて標準エラー出力に表示する。エラーの場合、keep_goingが
+/// falseであれば以降の行を実行しない。lastがtrueの場合は、最後の結果のみを表示する。
+/// すべての行を実行できた場合は0を、エラーがあった場合は最初のエラーに応じた終了コードを返す。
+fn run_lines(
+    name: &str,
+    input: impl BufRead,
+    keep_going: bool,
+    last: bool,
+    args: &Args,
+    env: &mut env::Env,
+) -> i32 {
+    let format = args.format;
+    if let Some(header) = format.header() {
+        println!("{header}");
+    }
+
+    let mut status = 0;
+    let mut final_result = None;
+    for (n, line) in input.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("{name}: {e}");
+                return EXIT_IO_ERROR;
+            }
+        };
+        let record = |result, error| output::Record {
+            line: n + 1,
+            input: line.clone(),
+            result,
+            error,
+        };
+        let result = exec_line(&line, args, args.notation, env, |v| {
+            if last {
+                final_result = Some(record(Some(v), None));
+            } else {
+                format.emit(name, &record(Some(v), None));
+            }
+        });
+        if let Err(e) = result {
+            format.emit(name, &record(None, Some(e.to_string())));
+            if status == 0 {
+                status = e.exit_code();
+            }
+            if !keep_going {
+                break;
+            }
+        }
+    }
+
+    if let Some(r) = final_result {
+        format.emit(name, &r);
+    }
+    status
+}
+
+/// 式をWebAssemblyのモジュールに変換してファイルに書き出し、`eval`のシグネチャを表示する
+fn emit_wasm(path: &Path, c: &str, notation: Notation, max_depth: usize) -> Result<(), String> {
+    let e = match parser::parse(c, notation, max_depth) {
+        Ok(Stmt::Expr(e)) => e,
+        Ok(Stmt::FnDef(..)) => return Err("cannot compile a function definition".to_string()),
+        Err(e) => return Err(e.diagnostic(c)),
+    };
+    let module = wasm::compile(&e).map_err(|e| e.to_string())?;
+    std::fs::write(path, &module.bytes).map_err(|e| format!("{}: {e}", path.display()))?;
+    println!("{}", module.signature());
+    Ok(())
+}
+
+/// 入力が途中で終わっていて、続きの行が必要かを判定
+fn is_incomplete(c: &str, notation: Notation, max_depth: usize) -> bool {
+    match parser::parse_line(c, notation, max_depth) {
+        Ok(_) => false,
+        Err(e) => e.is_incomplete(c),
+    }
+}
+
+/// 各文の抽象構文木をS式の形で標準エラー出力に表示する
+fn print_ast(stmts: &[Stmt]) {
+    for s in stmts {
+        match s {
+            Stmt::Expr(e) => eprintln!("ast: {}", e.to_sexp()),
+            Stmt::FnDef(name, params, e) => {
+                eprintln!("ast: fn {name}({}) = {}", params.join(", "), e.to_sexp())
+            }
+        }
+    }
+}
+
+fn parse(
+    c: &str,
+    notation: Notation,
+    max_depth: usize,
+    out: &mut repl::Printer,
+) -> Option<Vec<Stmt>> {
+    match parser::parse_line(c, notation, max_depth) {
+        // 空行やコメントのみの行は何もしない
+        Ok(e) if e.is_empty() => None,
+        Ok(e) => Some(e),
+        Err(e) => {
+            out.error(e.diagnostic(c));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use repl::Printed;
+
+    #[test]
+    fn test_exec_pasted() {
+        let mut session = command::Session {
+            env: env::Env::new(),
+            notation: Notation::Infix,
+            max_depth: parser::DEFAULT_MAX_DEPTH,
+            stack: None,
+            registers: Default::default(),
+        };
+        let args = Args::parse_from(["rpn"]);
+        let lines = ["x = 2", "x * 3", "1 +", "x + 1"].map(String::from);
+
+        // 各行の結果とエラーは、表示されずに実行した順に溜まる
+        let mut out = repl::Printer::batch();
+        let flow = exec_pasted(&mut session, &lines, &args, &mut out);
+        assert_eq!(flow, command::Flow::Continue);
+        assert_eq!(
+            out.take(),
+            [
+                Printed::Result("result: 2".to_string()),
+                Printed::Result("result: 6".to_string()),
+                Printed::Error("ParseError: expected a number\n  1 +\n     ^".to_string()),
+                Printed::Result("result: 3".to_string()),
+            ]
+        );
+
+        // 終了する場合は、以降の入力を実行しない
+        let lines = [":quit", "x = 5"].map(String::from);
+        let flow = exec_pasted(&mut session, &lines, &args, &mut out);
+        assert_eq!(flow, command::Flow::Quit);
+        assert_eq!(session.env.get("x"), Some(value::Value::Int(2)));
+    }
+
+    #[test]
+    fn test_simplify_args() {
+        let args = |c| simplify_args(c, parser::DEFAULT_MAX_DEPTH);
+        assert_eq!(
+            args(" simplify(x + x) "),
+            Some(vec![Expr::Add(
+                Box::new(Expr::Var("x".to_string())),
+                Box::new(Expr::Var("x".to_string()))
+            )])
+        );
+        assert_eq!(args("simplify(1, 2)").map(|a| a.len()), Some(2));
+
+        // 呼び出しが式の一部である場合や、パースできない場合はコマンドとしない
+        assert_eq!(args("simplify(x) + simplify(y)"), None);
+        assert_eq!(args("f(simplify(x))"), None);
+        assert_eq!(args("simplify(x"), None);
+        assert_eq!(args("x + x"), None);
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let parse = |n: &str| Args::try_parse_from(["rpn", "--max-depth", n]).map(|a| a.max_depth);
+        assert_eq!(parse("512").ok(), Some(parser::MAX_MAX_DEPTH));
+        assert_eq!(parse("1").ok(), Some(1));
+        assert!(parse("513").is_err());
+        assert!(parse("-1").is_err());
+        assert_eq!(
+            parse_max_depth("100000"),
+            Err("must be at most 512".to_string())
+        );
+    }
+
+    #[test]
+    fn test_shadowing_warnings() {
+        let warnings = |c| {
+            let stmts = parser::parse_line(c, Notation::Infix, parser::DEFAULT_MAX_DEPTH).unwrap();
+            shadowing_warnings(&stmts).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            warnings("fn abs(x) = 0; fn f(x) = x; abs(1)"),
+            ["warning: fn abs shadows the built-in function abs"]
+        );
+        assert!(warnings("fn f(x) = abs(x)").is_empty());
+    }
+}
@@ -0,0 +1,790 @@
+//! REPLのコマンド
+//!
+//! `:`から始まる入力行はコマンドとして、式のパースより前に処理する。
+//! コマンドの名前、引数の書式、説明、処理を表に登録し、名前で呼び出す。
+//! `:help`は、この表からコマンドの一覧を表示する。
+use super::{
+    save,
+    style::{self, error},
+    transcript,
+};
+use crate::{
+    builtin,
+    env::Env,
+    eval,
+    parser::{self, Expr, Notation, Stmt},
+    rng::Rng,
+    stack::Stack,
+    value::{Radix, Value},
+};
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// REPLの状態
+#[derive(Debug)]
+pub struct Session {
+    pub env: Env,
+    pub notation: Notation,                 // 入力の記法
+    pub max_depth: usize,                   // 式のネストの深さの上限
+    pub stack: Option<Stack>, // スタックモードのスタック（スタックモードでない場合はNone）
+    pub registers: BTreeMap<String, Value>, // `:sto`で値を保存したレジスタ
+}
+
+/// コマンドを実行した後の制御
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue, // 次の入力行を読み込む
+    Quit,     // REPLを終了する
+}
+
+/// コマンドを表す型
+struct Command {
+    name: &'static str,
+    usage: &'static str, // 引数の書式
+    help: &'static str,
+    run: fn(&mut Session, &str) -> Flow,
+}
+
+/// コマンドの一覧
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        usage: "",
+        help: "Show this list of commands",
+        run: |_, _| {
+            help();
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "quit",
+        usage: "",
+        help: "Exit the REPL",
+        run: |_, _| Flow::Quit,
+    },
+    Command {
+        name: "vars",
+        usage: "",
+        help: "List defined variables and functions",
+        run: |s, _| {
+            vars(&s.env);
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "doc",
+        usage: "NAME",
+        help: "Show the signature and description of a function",
+        run: |s, arg| {
+            match doc(&s.env, arg) {
+                Ok(doc) => println!("{doc}"),
+                Err(e) => error!("{e}"),
+            }
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "clear",
+        usage: "",
+        help: "Remove all variables, functions and results",
+        run: |s, _| {
+            s.env.clear();
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "history",
+        usage: "",
+        help: "List previous results",
+        run: |s, _| {
+            for (i, v) in s.env.history().iter().enumerate() {
+                println!("${} = {v}", i + 1);
+            }
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "notation",
+        usage: "[rpn|infix|prefix]",
+        help: "Show or change the notation of input expressions",
+        run: |s, arg| {
+            match arg {
+                "" => println!("notation: {}", s.notation),
+                name => match name.parse() {
+                    Ok(n) => s.notation = n,
+                    Err(e) => error!("{e}"),
+                },
+            }
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "set",
+        usage: "[name value]",
+        help: "Show or change evaluation settings",
+        run: |s, arg| {
+            match arg.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => println!("{}", s.env.settings),
+                [name, value] => {
+                    if let Err(e) = s.env.settings.set(name, value) {
+                        error!("{e}");
+                    }
+                }
+                _ => error!("usage: :set [name value]"),
+            }
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "seed",
+        usage: "N",
+        help: "Seed the random number generator",
+        run: |s, arg| {
+            match arg.parse() {
+                Ok(seed) => s.env.rng = Rng::new(seed),
+                Err(_) => error!("usage: :seed N"),
+            }
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "optimize",
+        usage: "",
+        help: "Toggle optimization of expressions",
+        run: |s, _| {
+            let settings = &mut s.env.settings;
+            settings.optimize = !settings.optimize;
+            println!("optimize: {}", switch(settings.optimize));
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "trace",
+        usage: "",
+        help: "Toggle tracing of each evaluation step",
+        run: |s, _| {
+            let settings = &mut s.env.settings;
+            settings.trace = !settings.trace;
+            println!("trace: {}", switch(settings.trace));
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "stack",
+        usage: "",
+        help: "Toggle stack mode (HP-style RPN with a persistent stack)",
+        run: |s, _| {
+            s.stack = match s.stack {
+                Some(_) => None,
+                None => Some(Stack::new()),
+            };
+            println!("stack mode: {}", switch(s.stack.is_some()));
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "undo",
+        usage: "",
+        help: "Undo the last change to the stack in stack mode",
+        run: |s, _| change_stack(s, Stack::undo, "undo"),
+    },
+    Command {
+        name: "redo",
+        usage: "",
+        help: "Redo the last undone change to the stack",
+        run: |s, _| change_stack(s, Stack::redo, "redo"),
+    },
+    Command {
+        name: "sto",
+        usage: "NAME",
+        help: "Store the top of the stack (or the last result) in a register",
+        run: |s, arg| {
+            store(s, arg);
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "rcl",
+        usage: "NAME",
+        help: "Recall a register onto the stack (or as a new result)",
+        run: |s, arg| {
+            recall(s, arg);
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "mem",
+        usage: "",
+        help: "List the registers",
+        run: |s, _| {
+            for (name, v) in &s.registers {
+                println!(
+                    "{name} = {}",
+                    v.to_string_with(&s.env.settings.number_format())
+                );
+            }
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "save",
+        usage: "FILE",
+        help: "Save variables, functions, registers and the stack to a file",
+        run: |s, arg| {
+            match arg {
+                "" => error!("usage: :save FILE"),
+                path => match save::save(Path::new(path), s) {
+                    Ok(()) => println!("saved session to {path}"),
+                    Err(e) => error!("{e}"),
+                },
+            }
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "load",
+        usage: "FILE",
+        help: "Restore a session saved with :save",
+        run: |s, arg| {
+            match arg {
+                "" => error!("usage: :load FILE"),
+                path => match save::load(Path::new(path), s) {
+                    Ok(()) => println!("loaded session from {path}"),
+                    Err(e) => error!("{e}"),
+                },
+            }
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "dec",
+        usage: "",
+        help: "Display integer results in decimal",
+        run: |s, _| set_radix(s, Radix::Dec),
+    },
+    Command {
+        name: "hex",
+        usage: "",
+        help: "Display integer results in hexadecimal",
+        run: |s, _| set_radix(s, Radix::Hex),
+    },
+    Command {
+        name: "bin",
+        usage: "",
+        help: "Display integer results in binary",
+        run: |s, _| set_radix(s, Radix::Bin),
+    },
+    Command {
+        name: "oct",
+        usage: "",
+        help: "Display integer results in octal",
+        run: |s, _| set_radix(s, Radix::Oct),
+    },
+    Command {
+        name: "log",
+        usage: "[on|off|FILE]",
+        help: "Show, start or stop logging inputs and results to a transcript file",
+        run: |_, arg| {
+            log(arg);
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "ast",
+        usage: "EXPR",
+        help: "Show the syntax tree of an expression",
+        run: |s, arg| {
+            ast(s, arg);
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "latex",
+        usage: "EXPR",
+        help: "Show an expression as LaTeX math",
+        run: |s, arg| {
+            latex(s, arg);
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "dot",
+        usage: "EXPR",
+        help: "Show the syntax tree of an expression as a Graphviz DOT graph",
+        run: |s, arg| {
+            dot(s, arg);
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "time",
+        usage: "[-n RUNS] EXPR",
+        help: "Evaluate an expression repeatedly and show the min and average time",
+        run: |s, arg| {
+            time(s, arg);
+            Flow::Continue
+        },
+    },
+    Command {
+        name: "bytecode",
+        usage: "EXPR",
+        help: "Show the instructions compiled from an expression",
+        run: |s, arg| {
+            bytecode(s, arg);
+            Flow::Continue
+        },
+    },
+];
+
+/// `:time`で式を評価する回数の既定値
+const TIME_RUNS: usize = 1000;
+
+/// 入力行がコマンドの場合は実行する
+///
+/// `:`から始まらない行の場合はNone。未知のコマンドの場合は、エラーを表示して次の入力へ進む。
+pub fn dispatch(session: &mut Session, line: &str) -> Option<Flow> {
+    let line = line.trim().strip_prefix(':')?;
+    let (name, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    match COMMANDS.iter().find(|c| c.name == name) {
+        Some(command) => Some((command.run)(session, arg.trim())),
+        None => {
+            error!("unknown command ':{name}' (type :help for a list of commands)");
+            Some(Flow::Continue)
+        }
+    }
+}
+
+/// コマンドの名前を`:`を付けて返す
+pub fn names() -> impl Iterator<Item = String> {
+    COMMANDS.iter().map(|c| format!(":{}", c.name))
+}
+
+/// 入力行の末尾の`to hex`のような指定を取り除き、結果を表示する基数とともに返す
+///
+/// 指定がない場合は、入力行をそのまま返す。
+pub fn split_radix_suffix(line: &str) -> (&str, Option<Radix>) {
+    let split = || {
+        let (rest, name) = line.trim_end().rsplit_once(char::is_whitespace)?;
+        let radix = name.parse().ok()?;
+        let rest = rest.trim_end().strip_suffix("to")?;
+        rest.ends_with(char::is_whitespace).then_some((rest, radix))
+    };
+    match split() {
+        Some((rest, radix)) => (rest, Some(radix)),
+        None => (line, None),
+    }
+}
+
+/// レジスタの名前かを判定
+///
+/// 名前には英数字と`_`を用いることができ、`:sto 1`のように番号も名前とする。
+fn is_register_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// `:sto 名前`で、スタックモードではスタックの先頭の値を、それ以外では直前の結果をレジスタに保存する
+fn store(s: &mut Session, name: &str) {
+    if !is_register_name(name) {
+        error!("usage: :sto NAME");
+        return;
+    }
+    let v = match &s.stack {
+        Some(stack) => stack.top(),
+        None => s.env.history().last(),
+    };
+    match v {
+        Some(v) => {
+            println!(
+                "{name} = {}",
+                v.to_string_with(&s.env.settings.number_format())
+            );
+            s.registers.insert(name.to_string(), v.clone());
+        }
+        None => error!("nothing to store"),
+    }
+}
+
+/// `:rcl 名前`で、スタックモードではレジスタの値をスタックに積み、それ以外では新たな結果とする
+fn recall(s: &mut Session, name: &str) {
+    if !is_register_name(name) {
+        error!("usage: :rcl NAME");
+        return;
+    }
+    let Some(v) = s.registers.get(name).cloned() else {
+        error!("register '{name}' is empty");
+        return;
+    };
+    let format = s.env.settings.number_format();
+    match &mut s.stack {
+        Some(stack) => {
+            stack.push(v);
+            println!("{}", style::result(stack.to_string_with(&format)));
+        }
+        None => {
+            println!("result: {}", style::result(v.to_string_with(&format)));
+            s.env.push_result(v);
+        }
+    }
+}
+
+/// スタックモードのスタックをfで変更し、変更後のスタックを表示する
+fn change_stack(s: &mut Session, f: fn(&mut Stack) -> bool, action: &str) -> Flow {
+    match &mut s.stack {
+        None => error!("not in stack mode (type :stack to enter it)"),
+        Some(stack) => {
+            if f(stack) {
+                println!(
+                    "{}",
+                    style::result(stack.to_string_with(&s.env.settings.number_format()))
+                );
+            } else {
+                error!("nothing to {action}");
+            }
+        }
+    }
+    Flow::Continue
+}
+
+fn set_radix(s: &mut Session, radix: Radix) -> Flow {
+    s.env.settings.radix = radix;
+    println!("radix: {radix}");
+    Flow::Continue
+}
+
+fn switch(b: bool) -> &'static str {
+    if b {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// コマンドの一覧を、書式と説明を揃えて表示する
+fn help() {
+    let heads: Vec<_> = COMMANDS
+        .iter()
+        .map(|c| format!(":{} {}", c.name, c.usage))
+        .collect();
+    let width = heads.iter().map(|h| h.len()).max().unwrap_or(0);
+    for (head, c) in heads.iter().zip(COMMANDS) {
+        println!("  {head:<width$}  {}", c.help);
+    }
+}
+
+/// 変数と関数を名前順に表示する
+fn vars(env: &Env) {
+    for (name, v) in env.vars() {
+        println!("{name} = {v}");
+    }
+    for (name, f) in env.fns() {
+        println!("fn {name}({})", f.params.join(", "));
+    }
+}
+
+/// `:doc 名前`で表示する、関数の書式と説明
+///
+/// ユーザ定義関数は、組み込み関数より優先して本体の式とともに表示する。
+fn doc(env: &Env, name: &str) -> Result<String, String> {
+    if name.is_empty() {
+        return Err("usage: :doc NAME".to_string());
+    }
+    if let Some(f) = env.get_fn(name) {
+        return Ok(format!(
+            "fn {name}({}) = {}\nUser-defined function",
+            f.params.join(", "),
+            f.body
+        ));
+    }
+    match builtin::doc(name) {
+        Some((signature, doc)) => Ok(format!("{signature}\n{doc}")),
+        None => {
+            let similar = env.similar_fn(name);
+            Err(eval::EvalError::UndefinedFunction(name.to_string(), similar).to_string())
+        }
+    }
+}
+
+/// `:log`で、セッションの記録の状態を表示し、記録を始めたり終えたりする
+///
+/// `:log on`は、最後に記録したファイルか、ホームディレクトリの既定のファイルへの記録を始める。
+fn log(arg: &str) {
+    let start = |path: Option<&Path>| match transcript::start(path, || {
+        super::home_path(transcript::DEFAULT_FILE)
+    }) {
+        Ok(path) => println!("logging to {}", path.display()),
+        Err(e) => error!("failed to start logging: {e}"),
+    };
+    match arg {
+        "" => match transcript::path() {
+            Some(path) => println!("log: on ({})", path.display()),
+            None => println!("log: off"),
+        },
+        "on" => start(None),
+        "off" => match transcript::stop() {
+            Some(path) => println!("stopped logging to {}", path.display()),
+            None => error!("not logging"),
+        },
+        path => start(Some(Path::new(path))),
+    }
+}
+
+/// `:ast 式`で、式の抽象構文木をS式と木の形で表示する
+fn ast(s: &Session, arg: &str) {
+    match parser::parse_line(arg, s.notation, s.max_depth) {
+        Ok(stmts) => {
+            for s in &stmts {
+                let e = match s {
+                    Stmt::Expr(e) => e,
+                    Stmt::FnDef(name, params, e) => {
+                        println!("fn {name}({}) =", params.join(", "));
+                        e
+                    }
+                };
+                println!("{}", e.to_sexp());
+                print!("{}", e.to_tree());
+            }
+        }
+        Err(e) => error!("{}", e.diagnostic(arg)),
+    }
+}
+
+/// `:latex 式`で、式をLaTeXの数式で表示する
+fn latex(s: &Session, arg: &str) {
+    match parser::parse_line(arg, s.notation, s.max_depth) {
+        Ok(stmts) => {
+            for stmt in &stmts {
+                match stmt {
+                    Stmt::Expr(e) => println!("{}", e.to_latex()),
+                    Stmt::FnDef(name, params, e) => {
+                        // 関数の左辺は、引数を変数とする関数呼び出しの式として表す
+                        let params = params.iter().map(|p| Expr::Var(p.clone())).collect();
+                        let lhs = Expr::Call(name.clone(), params);
+                        println!("{} = {}", lhs.to_latex(), e.to_latex());
+                    }
+                }
+            }
+        }
+        Err(e) => error!("{}", e.diagnostic(arg)),
+    }
+}
+
+/// `:dot 式`で、式の構文木をGraphvizのDOT言語で表示する
+///
+/// 関数定義の場合は、本体の式の構文木を表示する。
+fn dot(s: &Session, arg: &str) {
+    match parser::parse_line(arg, s.notation, s.max_depth) {
+        Ok(stmts) => {
+            for stmt in &stmts {
+                let (Stmt::Expr(e) | Stmt::FnDef(_, _, e)) = stmt;
+                print!("{}", e.to_dot());
+            }
+        }
+        Err(e) => error!("{}", e.diagnostic(arg)),
+    }
+}
+
+/// `:time 式`で、式を評価する時間を計測する
+///
+/// 式をruns回評価し、1回あたりの最短時間と平均時間を、時間に影響する最適化とJITの有無とともに表示する。
+/// 評価ごとに式の変換も行うため、変換の時間も含む。
+fn time(s: &mut Session, arg: &str) {
+    let Some((runs, c)) = split_runs(arg) else {
+        error!("usage: :time [-n RUNS] EXPR");
+        return;
+    };
+    let e = match parser::parse_expr(c, s.notation, s.max_depth) {
+        Ok(e) => e,
+        Err(e) => {
+            error!("{}", e.diagnostic(c));
+            return;
+        }
+    };
+
+    let mut times = Vec::with_capacity(runs);
+    let mut result = None;
+    for _ in 0..runs {
+        let start = Instant::now();
+        let v = eval::eval(&e, &mut s.env);
+        times.push(start.elapsed());
+        match v {
+            Ok(v) => result = Some(v),
+            Err(e) => {
+                error!("{e}");
+                return;
+            }
+        }
+    }
+
+    if let Some(v) = result {
+        println!(
+            "result: {}",
+            style::result(v.to_string_with(&s.env.settings.number_format()))
+        );
+    }
+    let min = times.iter().min().copied().unwrap_or_default();
+    let avg = times.iter().sum::<Duration>() / runs as u32;
+    println!(
+        "{runs} runs: min {min:?}, avg {avg:?} (optimize {}, jit {})",
+        switch(s.env.settings.optimize),
+        switch(cfg!(feature = "jit"))
+    );
+}
+
+/// `:time`の引数を、評価する回数と式に分ける
+///
+/// `-n 回数`の指定がない場合は、TIME_RUNS回とする。`-n`の後が数でない場合は、`-n * 2`のような式とする。
+/// 回数が0の場合や、式がない場合はNone。
+fn split_runs(arg: &str) -> Option<(usize, &str)> {
+    let runs = arg.strip_prefix("-n").and_then(|rest| {
+        let rest = rest.strip_prefix(char::is_whitespace)?.trim_start();
+        let (n, c) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        Some((n.parse::<usize>().ok()?, c.trim_start()))
+    });
+    match runs {
+        Some((n, c)) => (0 < n && n <= u32::MAX as usize && !c.is_empty()).then_some((n, c)),
+        None => Some((TIME_RUNS, arg)),
+    }
+}
+
+/// `:bytecode 式`で、式を変換した命令列を表示する
+fn bytecode(s: &Session, arg: &str) {
+    match parser::parse_line(arg, s.notation, s.max_depth) {
+        Ok(stmts) => {
+            for stmt in &stmts {
+                let (Stmt::Expr(e) | Stmt::FnDef(_, _, e)) = stmt;
+                let code = eval::compile(e, s.env.settings.optimize);
+                for (n, c) in code.iter().enumerate() {
+                    println!("{:>04}: {}", n, c);
+                }
+            }
+        }
+        Err(e) => error!("{}", e.diagnostic(arg)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::DEFAULT_MAX_DEPTH,
+        value::{NumberFormat, Value},
+    };
+
+    #[test]
+    fn test_dispatch() {
+        let mut s = Session {
+            env: Env::new(),
+            notation: Notation::Prefix,
+            max_depth: DEFAULT_MAX_DEPTH,
+            stack: None,
+            registers: BTreeMap::new(),
+        };
+        s.env.set("x", Value::Int(1)).unwrap();
+        s.env.push_result(Value::Int(1));
+
+        assert_eq!(dispatch(&mut s, "+ 1 2"), None);
+        assert_eq!(dispatch(&mut s, " :notation infix "), Some(Flow::Continue));
+        assert_eq!(s.notation, Notation::Infix);
+        assert_eq!(dispatch(&mut s, ":set rational on"), Some(Flow::Continue));
+        assert!(s.env.settings.rational);
+        assert_eq!(dispatch(&mut s, ":nosuch"), Some(Flow::Continue));
+
+        assert_eq!(dispatch(&mut s, ":clear"), Some(Flow::Continue));
+        assert_eq!(s.env.get("x"), None);
+        assert!(s.env.history().is_empty());
+        // 設定は変更しない
+        assert!(s.env.settings.rational);
+
+        assert_eq!(dispatch(&mut s, ":hex"), Some(Flow::Continue));
+        assert_eq!(s.env.settings.radix, Radix::Hex);
+
+        dispatch(&mut s, ":stack");
+        assert!(s.stack.is_some());
+        dispatch(&mut s, ":stack");
+        assert!(s.stack.is_none());
+
+        assert_eq!(dispatch(&mut s, ":quit"), Some(Flow::Quit));
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut s = Session {
+            env: Env::new(),
+            notation: Notation::Infix,
+            max_depth: DEFAULT_MAX_DEPTH,
+            stack: None,
+            registers: BTreeMap::new(),
+        };
+        // 結果がない場合は何も保存しない
+        dispatch(&mut s, ":sto 1");
+        assert!(s.registers.is_empty());
+
+        s.env.push_result(Value::Int(42));
+        dispatch(&mut s, ":sto 1");
+        dispatch(&mut s, ":sto x y");
+        assert_eq!(s.registers.len(), 1);
+        dispatch(&mut s, ":rcl 1");
+        assert_eq!(s.env.history(), [Value::Int(42), Value::Int(42)]);
+
+        // スタックモードでは、スタックの先頭を保存し、呼び出した値を積む
+        dispatch(&mut s, ":stack");
+        let stack = s.stack.as_mut().unwrap();
+        stack.push(Value::Int(7));
+        dispatch(&mut s, ":sto r");
+        dispatch(&mut s, ":rcl r");
+        dispatch(&mut s, ":rcl 1");
+        dispatch(&mut s, ":rcl nosuch");
+        let stack = s.stack.as_ref().unwrap();
+        assert_eq!(
+            stack.to_string_with(&NumberFormat::default()),
+            "3: 7\n2: 7\n1: 42"
+        );
+        assert_eq!(s.registers["r"], Value::Int(7));
+    }
+
+    #[test]
+    fn test_doc() {
+        let mut env = Env::new();
+        assert_eq!(
+            doc(&env, "rand_int"),
+            Ok("rand_int(a, b)\nRandom integer from a to b inclusive".to_string())
+        );
+        assert_eq!(
+            doc(&env, "sqr"),
+            Err("EvalError: undefined function 'sqr' (did you mean 'sqrt'?)".to_string())
+        );
+        assert!(doc(&env, "").is_err());
+
+        // ユーザ定義関数は、同名の組み込み関数より優先する
+        let stmt = parser::parse("fn sqrt(x) = x ^ 2", Notation::Infix, DEFAULT_MAX_DEPTH);
+        eval::exec(&stmt.unwrap(), &mut env).unwrap();
+        assert_eq!(
+            doc(&env, "sqrt"),
+            Ok("fn sqrt(x) = x ^ 2\nUser-defined function".to_string())
+        );
+    }
+
+    #[test]
+    fn test_split_runs() {
+        assert_eq!(split_runs("1 + 2"), Some((TIME_RUNS, "1 + 2")));
+        assert_eq!(split_runs("-n 10  f(3)"), Some((10, "f(3)")));
+        assert_eq!(split_runs("-n 0 1"), None);
+        assert_eq!(split_runs("-n * 2"), Some((TIME_RUNS, "-n * 2")));
+        assert_eq!(split_runs("-n 10"), None);
+    }
+
+    #[test]
+    fn test_radix_suffix() {
+        assert_eq!(split_radix_suffix("255 to hex"), ("255 ", Some(Radix::Hex)));
+        assert_eq!(
+            split_radix_suffix("0xFF + 1  to  bin "),
+            ("0xFF + 1  ", Some(Radix::Bin))
+        );
+        assert_eq!(split_radix_suffix("toto hex"), ("toto hex", None));
+        assert_eq!(split_radix_suffix("to hex"), ("to hex", None));
+        assert_eq!(split_radix_suffix("1 to foo"), ("1 to foo", None));
+    }
+}
@@ -0,0 +1,85 @@
+//! `rpn lisp`のREPLと、s式のファイルの実行
+use super::{style, EXIT_EVAL_ERROR, EXIT_IO_ERROR, EXIT_PARSE_ERROR};
+use crate::lisp::{self, Interp, LispError};
+use rustyline::{error::ReadlineError, Editor};
+use std::{fs, path::Path};
+
+/// REPLのプロンプト
+const PROMPT: &str = "lisp> ";
+
+/// ファイルpathのs式を順に評価する
+///
+/// 終了コードを返す。
+pub fn run_file(path: &Path) -> i32 {
+    match fs::read_to_string(path) {
+        Ok(src) => run_source(&path.display().to_string(), &src),
+        Err(e) => {
+            eprintln!("{}: {e}", path.display());
+            EXIT_IO_ERROR
+        }
+    }
+}
+
+/// テキストsrcのs式を順に評価し、定義以外の式の値を表示する
+///
+/// エラーの場合は、入力の名前nameを付けて表示し、以降の式は評価しない。終了コードを返す。
+pub fn run_source(name: &str, src: &str) -> i32 {
+    let forms = match lisp::read(src) {
+        Ok(forms) => forms,
+        Err(e) => {
+            eprintln!("{name}: {e}");
+            return EXIT_PARSE_ERROR;
+        }
+    };
+
+    let mut interp = Interp::new();
+    for x in &forms {
+        match interp.eval(x) {
+            Ok(v) if !lisp::is_definition(x) => println!("{v}"),
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("{name}: {e}");
+                return EXIT_EVAL_ERROR;
+            }
+        }
+    }
+    0
+}
+
+/// s式を1つずつ入力して評価するREPLを実行する
+pub fn repl() {
+    let mut rl = Editor::<()>::new().unwrap();
+    let mut interp = Interp::new();
+    'repl: loop {
+        let mut input = match rl.readline(PROMPT) {
+            Ok(line) => line,
+            // Ctrl-Cの場合は、入力中の行を取り消して次の行を読み込む
+            Err(ReadlineError::Interrupted) => continue,
+            // Ctrl-Dの場合は終了する
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                style::error!("error: {e}");
+                break;
+            }
+        };
+
+        // 閉じ括弧が足りない場合は、続きの行を読み込んで連結する
+        while matches!(lisp::read(&input), Err(LispError::UnexpectedEof)) {
+            match rl.readline(".. ") {
+                Ok(line) => {
+                    input.push('\n');
+                    input.push_str(&line);
+                }
+                Err(ReadlineError::Interrupted) => continue 'repl,
+                Err(_) => break,
+            }
+        }
+        rl.add_history_entry(input.as_str());
+
+        match interp.eval_str(&input) {
+            Ok(Some(v)) => println!("{}", style::result(v)),
+            Ok(None) => {}
+            Err(e) => style::error!("{e}"),
+        }
+    }
+}
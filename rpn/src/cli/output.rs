@@ -0,0 +1,164 @@
+//! スクリプトやパイプから入力した行の実行結果の出力形式
+//!
+//! textでは結果を1行ずつ標準出力に、エラーを入力の名前と行番号を付けて標準エラー出力に表示する。
+//! jsonとcsvでは、行番号、入力行、結果、エラーを1件のレコードとして標準出力に表示し、
+//! 他のツールで処理できるようにする。jsonは1行に1つのオブジェクトを出力するJSON Lines形式で、
+//! csvは先頭にヘッダ行を出力する。
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+/// 出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+/// 1件の結果またはエラー
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub line: usize, // 行番号（1始まり）
+    pub input: String,
+    pub result: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Format {
+    /// 最初に出力するヘッダ行
+    pub fn header(self) -> Option<&'static str> {
+        match self {
+            Format::Csv => Some("line,input,result,error"),
+            Format::Text | Format::Json => None,
+        }
+    }
+
+    /// レコードを出力する
+    ///
+    /// nameは入力の名前で、textの場合のエラーメッセージに用いる。
+    pub fn emit(self, name: &str, r: &Record) {
+        match (self, &r.error) {
+            (Format::Text, Some(e)) => eprintln!("{name}:{}: {e}", r.line),
+            _ => println!("{}", self.format(r)),
+        }
+    }
+
+    /// レコードをこの形式の1行の文字列に変換
+    fn format(self, r: &Record) -> String {
+        match self {
+            Format::Text => r.result.clone().or(r.error.clone()).unwrap_or_default(),
+            Format::Json => {
+                let field = |s: &Option<String>| s.as_deref().map_or("null".to_string(), json);
+                format!(
+                    "{{\"line\":{},\"input\":{},\"result\":{},\"error\":{}}}",
+                    r.line,
+                    json(&r.input),
+                    field(&r.result),
+                    field(&r.error)
+                )
+            }
+            Format::Csv => {
+                let field = |s: &Option<String>| s.as_deref().map_or(String::new(), csv);
+                format!(
+                    "{},{},{},{}",
+                    r.line,
+                    csv(&r.input),
+                    field(&r.result),
+                    field(&r.error)
+                )
+            }
+        }
+    }
+}
+
+/// 文字列をJSONの文字列リテラルに変換
+fn json(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// 文字列をCSVのフィールドに変換
+///
+/// `,`、`"`、改行を含む場合は`"`で囲み、`"`は`""`とする。
+fn csv(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!(
+                "unknown output format '{s}' (expected text, json or csv)"
+            )),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Text => write!(f, "text"),
+            Format::Json => write!(f, "json"),
+            Format::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let ok = Record {
+            line: 1,
+            input: "max(1, 2)".to_string(),
+            result: Some("2".to_string()),
+            error: None,
+        };
+        let err = Record {
+            line: 2,
+            input: "1 / 0 # \"x\"".to_string(),
+            result: None,
+            error: Some("EvalError: division by zero".to_string()),
+        };
+        assert_eq!(
+            Format::Json.format(&ok),
+            r#"{"line":1,"input":"max(1, 2)","result":"2","error":null}"#
+        );
+        assert_eq!(
+            Format::Json.format(&err),
+            r#"{"line":2,"input":"1 / 0 # \"x\"","result":null,"error":"EvalError: division by zero"}"#
+        );
+        assert_eq!(Format::Csv.format(&ok), r#"1,"max(1, 2)",2,"#);
+        assert_eq!(
+            Format::Csv.format(&err),
+            r#"2,"1 / 0 # ""x""",,EvalError: division by zero"#
+        );
+        assert_eq!(json("a\nb\u{1}"), r#""a\nb\u0001""#);
+    }
+}
@@ -0,0 +1,419 @@
+//! REPLの入力行の編集を補助する
+//!
+//! rustylineのHelperとして、変数名、関数名、`:set`などのREPLのコマンドを補完する。
+//! 変数や関数は入力のたびに変わるため、REPLのループで行を読み込む前にrefreshで候補を更新する。
+//!
+//! 色を付ける場合、入力中の行は、数値と演算子を色分けし、対応する閉じ括弧や開き括弧がない括弧を赤で表示する。
+//! 入力が以前の入力の先頭と一致する場合は、その残りの部分を灰色のヒントとして表示する。
+//!
+//! 端末のbracketed pasteにより、貼り付けた複数行は改行を含む1つの入力として読み込まれる。
+//! REPLのループでは、split_pastedで入力ごとに分けて順に実行し、結果はPrinterに溜めて最後にまとめて表示する。
+use super::{command, style, transcript};
+use crate::{builtin, env::Env, parser};
+use rustyline::{
+    completion::{Completer, Pair},
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Context, Helper,
+};
+use std::{borrow::Cow, fmt::Display};
+
+/// 数値の色（シアン）
+const NUMBER_COLOR: &str = "\x1b[36m";
+/// 演算子の色（黄）
+const OPERATOR_COLOR: &str = "\x1b[33m";
+/// 対応する括弧がない括弧の色（太字の赤）
+const UNMATCHED_COLOR: &str = "\x1b[1;31m";
+/// ヒントの色（灰）
+const HINT_COLOR: &str = "\x1b[90m";
+/// 色を元に戻す
+const RESET: &str = "\x1b[0m";
+
+/// 演算子に用いる文字
+const OPERATOR_CHARS: &str = "+-*/%^&|<>=!~";
+
+/// REPLの入力行の編集を補助するHelper
+#[derive(Debug, Default)]
+pub struct ReplHelper {
+    vars: Vec<String>, // 変数名と定数名
+    fns: Vec<String>,  // ユーザ定義関数と組み込み関数の名前
+}
+
+impl ReplHelper {
+    pub fn new() -> Self {
+        ReplHelper::default()
+    }
+
+    /// 補完の候補を環境envの変数名と関数名に更新する
+    pub fn refresh(&mut self, env: &Env) {
+        self.vars = env.var_names().map(str::to_string).collect();
+        self.fns = env.fn_names().map(str::to_string).collect();
+        self.fns.extend(builtin::names().map(str::to_string));
+        self.vars.sort();
+        self.fns.sort();
+        self.fns.dedup();
+    }
+
+    /// 入力行lineのカーソル位置posの直前の語を補完する
+    ///
+    /// 語の開始位置と候補を返す。関数名の候補には`(`を付ける。
+    fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<Pair>) {
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .find(|&(_, c)| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let word = &line[start..pos];
+
+        // 行頭の`:`から始まる語は、REPLのコマンドとして補完する
+        if line[..start].trim_start() == ":" {
+            let start = start - 1;
+            let word = &line[start..pos];
+            let commands = command::names()
+                .filter(|c| c.starts_with(word))
+                .map(|c| pair(&c, c.clone()))
+                .collect();
+            return (start, commands);
+        }
+
+        // 数値の途中や、`$1`のような履歴の参照は補完しない
+        if word.is_empty() || word.starts_with(|c: char| c.is_ascii_digit()) {
+            return (pos, vec![]);
+        }
+        let vars = self
+            .vars
+            .iter()
+            .filter(|v| v.starts_with(word))
+            .map(|v| pair(v, v.clone()));
+        let fns = self
+            .fns
+            .iter()
+            .filter(|f| f.starts_with(word))
+            .map(|f| pair(f, format!("{f}(")));
+        (start, vars.chain(fns).collect())
+    }
+}
+
+/// 入力行lineを色分けした文字列を返す
+///
+/// 数字から始まる英数字の並びを数値とし、`0xFF`や`1e3`、`2.5`も1つの数値として色を付ける。
+fn highlight_line(line: &str) -> String {
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let unmatched = unmatched_brackets(&chars);
+
+    let mut s = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (pos, c) = chars[i];
+        if c.is_ascii_digit() && !in_ident(&chars, i) {
+            let mut j = i + 1;
+            while j < chars.len() && is_number_char(&chars, j) {
+                j += 1;
+            }
+            let end = chars.get(j).map_or(line.len(), |&(p, _)| p);
+            s.push_str(&format!("{NUMBER_COLOR}{}{RESET}", &line[pos..end]));
+            i = j;
+            continue;
+        }
+        if unmatched.contains(&i) {
+            s.push_str(&format!("{UNMATCHED_COLOR}{c}{RESET}"));
+        } else if OPERATOR_CHARS.contains(c) {
+            s.push_str(&format!("{OPERATOR_COLOR}{c}{RESET}"));
+        } else {
+            s.push(c);
+        }
+        i += 1;
+    }
+    s
+}
+
+/// i番目の文字が識別子の途中（`x1`の`1`など）かを判定
+fn in_ident(chars: &[(usize, char)], i: usize) -> bool {
+    i > 0 && matches!(chars[i - 1].1, c if c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+/// i番目の文字が数値の続きかを判定
+///
+/// 指数の符号（`1e-3`の`-`）も数値の一部とする。
+fn is_number_char(chars: &[(usize, char)], i: usize) -> bool {
+    match chars[i].1 {
+        c if c.is_alphanumeric() || c == '_' || c == '.' => true,
+        '+' | '-' => matches!(chars[i - 1].1, 'e' | 'E') && chars[i - 2].1.is_ascii_digit(),
+        _ => false,
+    }
+}
+
+/// 対応する括弧がない括弧の位置（文字の番号）を返す
+fn unmatched_brackets(chars: &[(usize, char)]) -> Vec<usize> {
+    let mut open = Vec::new();
+    let mut unmatched = Vec::new();
+    for (i, &(_, c)) in chars.iter().enumerate() {
+        match c {
+            '(' | '[' => open.push((i, c)),
+            ')' | ']' => match open.last() {
+                Some(&(_, o)) if (o, c) == ('(', ')') || (o, c) == ('[', ']') => {
+                    open.pop();
+                }
+                _ => unmatched.push(i),
+            },
+            _ => (),
+        }
+    }
+    unmatched.extend(open.into_iter().map(|(i, _)| i));
+    unmatched
+}
+
+fn pair(display: &str, replacement: String) -> Pair {
+    Pair {
+        display: display.to_string(),
+        replacement,
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        Ok(self.candidates(line, pos))
+    }
+}
+
+/// 以前の入力のうち、入力中の行から始まる最も新しいものの残りの部分をヒントとする
+impl Hinter for ReplHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<String> {
+        if line.trim().is_empty() || pos < line.len() {
+            return None;
+        }
+        ctx.history()
+            .iter()
+            .rev()
+            .find(|entry| entry.len() > line.len() && entry.starts_with(line))
+            .map(|entry| entry[line.len()..].to_string())
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        if !style::color() {
+            return Cow::Borrowed(line);
+        }
+        Cow::Owned(highlight_line(line))
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        if !style::color() {
+            return Cow::Borrowed(hint);
+        }
+        Cow::Owned(format!("{HINT_COLOR}{hint}{RESET}"))
+    }
+
+    /// 括弧の対応は行全体で決まるため、1文字入力するごとに行全体を色分けし直す
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        true
+    }
+}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// REPLの入力を実行した結果の表示
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Printed {
+    Result(String), // 標準出力に表示する結果
+    Error(String),  // 標準エラー出力に表示するエラーメッセージ
+}
+
+/// REPLの入力を実行した結果の表示先
+///
+/// 通常はすぐに表示する。貼り付けた複数行を実行する間は結果とエラーを溜めておき、flushでまとめて表示する。
+/// セッションの記録には、溜める場合も実行した順に記録する。
+#[derive(Debug, Default)]
+pub struct Printer {
+    pending: Option<Vec<Printed>>, // 溜めている表示（すぐに表示する場合はNone）
+}
+
+impl Printer {
+    /// 結果とエラーを溜めておく表示先を返す
+    pub fn batch() -> Self {
+        Printer {
+            pending: Some(Vec::new()),
+        }
+    }
+
+    /// 結果の行を表示する
+    ///
+    /// 結果はstyle::resultで色を付け、セッションに記録しておく。
+    pub fn println(&mut self, line: String) {
+        self.push(Printed::Result(line));
+    }
+
+    /// エラーメッセージを表示する
+    pub fn error(&mut self, message: impl Display) {
+        let message = message.to_string();
+        transcript::error(&message);
+        self.push(Printed::Error(style::error_message(message)));
+    }
+
+    /// 溜めている結果とエラーを、実行した順に表示する
+    pub fn flush(&mut self) {
+        for p in self
+            .pending
+            .iter_mut()
+            .flat_map(|pending| pending.drain(..))
+        {
+            show(&p);
+        }
+    }
+
+    /// 溜めている結果とエラーを、表示せずに取り出す
+    #[cfg(test)]
+    pub fn take(&mut self) -> Vec<Printed> {
+        self.pending
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn push(&mut self, p: Printed) {
+        match &mut self.pending {
+            Some(pending) => pending.push(p),
+            None => show(&p),
+        }
+    }
+}
+
+/// 結果を標準出力に、エラーを標準エラー出力に表示する
+fn show(p: &Printed) {
+    match p {
+        Printed::Result(s) => println!("{s}"),
+        Printed::Error(e) => eprintln!("{e}"),
+    }
+}
+
+/// 貼り付けた複数行のテキストを、REPLの入力ごとに分ける
+///
+/// 空行は除く。is_incompleteがtrueを返す途中までの入力は、join_lineで続きの行とつないで1つの入力とする。
+pub fn split_pasted(text: &str, is_incomplete: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut inputs = Vec::new();
+    let mut pending = String::new();
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        join_line(&mut pending, line);
+        if !is_incomplete(&pending) {
+            inputs.push(std::mem::take(&mut pending));
+        }
+    }
+    // 最後まで途中の入力は、そのまま実行してエラーを表示する
+    if !pending.is_empty() {
+        inputs.push(pending);
+    }
+    inputs
+}
+
+/// 途中までの入力pendingに、続きの行lineを空白でつなぐ
+///
+/// 1行の入力として`#`以降はコメントとなるため、つなぐ前にpendingのコメントを除く。
+/// これにより、`max(1, # 1つ目` の次の行に続きを入力できる。
+pub fn join_line(pending: &mut String, line: &str) {
+    pending.truncate(parser::strip_comment(pending).trim_end().len());
+    if !pending.is_empty() {
+        pending.push(' ');
+    }
+    pending.push_str(line);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+    use rustyline::history::History;
+
+    fn complete(helper: &ReplHelper, line: &str) -> (usize, Vec<String>) {
+        let (start, pairs) = helper.candidates(line, line.len());
+        (start, pairs.into_iter().map(|p| p.replacement).collect())
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut env = Env::new();
+        env.set("total", Value::Int(1)).unwrap();
+        env.set("tally", Value::Int(2)).unwrap();
+        let mut helper = ReplHelper::new();
+        helper.refresh(&env);
+
+        assert_eq!(
+            complete(&helper, "1 + ta"),
+            (4, vec!["tally".to_string(), "tau".to_string()])
+        );
+        assert_eq!(complete(&helper, "sq"), (0, vec!["sqrt(".to_string()]));
+        assert_eq!(complete(&helper, "max(to"), (4, vec!["total".to_string()]));
+        assert_eq!(
+            complete(&helper, ":se"),
+            (0, vec![":set".to_string(), ":seed".to_string()])
+        );
+        assert_eq!(complete(&helper, "12"), (2, vec![]));
+        assert_eq!(complete(&helper, "1 + "), (4, vec![]));
+    }
+
+    #[test]
+    fn test_highlight() {
+        let num = |s| format!("{NUMBER_COLOR}{s}{RESET}");
+        let op = |s| format!("{OPERATOR_COLOR}{s}{RESET}");
+        let unmatched = |s| format!("{UNMATCHED_COLOR}{s}{RESET}");
+
+        assert_eq!(
+            highlight_line("1.5 + x2"),
+            format!("{} {} x2", num("1.5"), op("+"))
+        );
+        assert_eq!(
+            highlight_line("0xFF*1e-3"),
+            format!("{}{}{}", num("0xFF"), op("*"), num("1e-3"))
+        );
+        assert_eq!(
+            highlight_line("(1))"),
+            format!("({}){}", num("1"), unmatched(")"))
+        );
+        assert_eq!(
+            highlight_line("max([1, 2]"),
+            format!("max{}[{}, {}]", unmatched("("), num("1"), num("2"))
+        );
+    }
+
+    #[test]
+    fn test_hint() {
+        let mut history = History::new();
+        history.add("1 + 2");
+        history.add("1 + 20 * 3");
+        history.add("sqrt(2)");
+        let ctx = Context::new(&history);
+        let helper = ReplHelper::new();
+
+        assert_eq!(helper.hint("1 + 2", 5, &ctx), Some("0 * 3".to_string()));
+        assert_eq!(helper.hint("sq", 2, &ctx), Some("rt(2)".to_string()));
+        assert_eq!(helper.hint("sq", 1, &ctx), None);
+        assert_eq!(helper.hint("", 0, &ctx), None);
+        assert_eq!(helper.hint("cos", 3, &ctx), None);
+    }
+
+    #[test]
+    fn test_split_pasted() {
+        let is_incomplete = |c: &str| c.matches('(').count() > c.matches(')').count();
+        assert_eq!(
+            split_pasted("1 + 2\n\n  x = (3 *\n4)\n:vars\n", is_incomplete),
+            ["1 + 2", "  x = (3 * 4)", ":vars"]
+        );
+        assert_eq!(split_pasted("(1 +\n2", is_incomplete), ["(1 + 2"]);
+        assert_eq!(
+            split_pasted("max(1, # first\n2) # second\n3", is_incomplete),
+            ["max(1, 2) # second", "3"]
+        );
+    }
+}
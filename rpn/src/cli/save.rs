@@ -0,0 +1,286 @@
+//! REPLのセッションの保存と復元
+//!
+//! `:save ファイル`で変数、ユーザ定義関数、レジスタ、スタックモードのスタックをファイルに書き出し、
+//! `:load ファイル`で読み込んで現在のセッションに追加する。同じ名前の変数などは上書きする。
+//!
+//! ファイルは1行に1つの項目を記述するテキスト形式で、値は`int 3`や`list 2 int 1 float 2.5`のように
+//! 型名に続けて内容を空白で区切って記述する。浮動小数点数や多倍長整数なども、値を損なわずに復元できる。
+//! 関数は、本体の式を中置記法で`fn f(x, y) = x * y`のように記述する。
+//!
+//! ```text
+//! # rpn session
+//! var x int 3
+//! fn f(x, y) = x * y
+//! reg r float 0.5
+//! stack
+//! push int 1
+//! ```
+use super::command::Session;
+use crate::{
+    decimal::Decimal,
+    eval,
+    interval::Interval,
+    parser::{self, Notation, Stmt},
+    stack::Stack,
+    unit::{Dim, Quantity},
+    value::Value,
+};
+use num_complex::Complex64;
+use num_rational::BigRational;
+use std::{fs, path::Path};
+
+/// ファイルの先頭行
+const HEADER: &str = "# rpn session";
+
+/// ファイルの1行の項目
+enum Entry {
+    Var(String, Value),
+    Fn(Stmt),
+    Register(String, Value),
+    Stack,       // スタックモードのスタック（以降のpushで値を積む）
+    Push(Value), // スタックに積む値
+}
+
+/// セッションをファイルpathに書き出す
+pub fn save(path: &Path, s: &Session) -> Result<(), String> {
+    let mut lines = vec![HEADER.to_string()];
+    for (name, v) in s.env.vars() {
+        lines.push(format!("var {name} {}", encode(v)));
+    }
+    for (name, f) in s.env.fns() {
+        lines.push(format!("fn {name}({}) = {}", f.params.join(", "), f.body));
+    }
+    for (name, v) in &s.registers {
+        lines.push(format!("reg {name} {}", encode(v)));
+    }
+    if let Some(stack) = &s.stack {
+        lines.push("stack".to_string());
+        for v in stack.values() {
+            lines.push(format!("push {}", encode(v)));
+        }
+    }
+    lines.push(String::new());
+    fs::write(path, lines.join("\n")).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// ファイルpathからセッションを読み込み、sに追加する
+///
+/// ファイルに誤りがある場合は、何も変更せずにエラーを返す。
+pub fn load(path: &Path, s: &mut Session) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let entries = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| {
+            parse_entry(line, s.max_depth)
+                .ok_or_else(|| format!("{}:{}: invalid session entry", path.display(), n + 1))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for entry in entries {
+        match entry {
+            Entry::Var(name, v) => s.env.set(&name, v).map_err(|e| e.to_string())?,
+            Entry::Fn(stmt) => {
+                eval::exec(&stmt, &mut s.env).map_err(|e| e.to_string())?;
+            }
+            Entry::Register(name, v) => {
+                s.registers.insert(name, v);
+            }
+            Entry::Stack => s.stack = Some(Stack::new()),
+            Entry::Push(v) => s.stack.get_or_insert_with(Stack::new).push(v),
+        }
+    }
+    Ok(())
+}
+
+/// 1行の項目をパース
+fn parse_entry(line: &str, max_depth: usize) -> Option<Entry> {
+    let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let mut tokens = rest.split_whitespace();
+    let entry = match kind {
+        "var" | "reg" => {
+            let name = tokens.next()?.to_string();
+            let v = decode(&mut tokens)?;
+            if kind == "var" {
+                Entry::Var(name, v)
+            } else {
+                Entry::Register(name, v)
+            }
+        }
+        "fn" => match parser::parse(line, Notation::Infix, max_depth) {
+            Ok(stmt @ Stmt::FnDef(..)) => return Some(Entry::Fn(stmt)),
+            _ => return None,
+        },
+        "stack" => Entry::Stack,
+        "push" => Entry::Push(decode(&mut tokens)?),
+        _ => return None,
+    };
+    // 値の後に余分な入力がある場合は誤りとする
+    tokens.next().is_none().then_some(entry)
+}
+
+/// 値を、型名に続けて内容を空白で区切った文字列に変換
+fn encode(v: &Value) -> String {
+    match v {
+        Value::Int(n) => format!("int {n}"),
+        Value::Big(n) => format!("big {n}"),
+        Value::Ratio(r) => format!("ratio {} {}", r.numer(), r.denom()),
+        Value::Decimal(d) => format!("decimal {d}"),
+        Value::Float(x) => format!("float {x:?}"),
+        Value::Complex(z) => format!("complex {:?} {:?}", z.re, z.im),
+        Value::Interval(x) => format!("interval {:?} {:?}", x.lo(), x.hi()),
+        Value::Quantity(q) => {
+            let [m, kg, s] = q.dim.0;
+            format!("quantity {:?} {m} {kg} {s}", q.value)
+        }
+        Value::Bool(b) => format!("bool {b}"),
+        Value::List(xs) => {
+            let mut s = format!("list {}", xs.len());
+            for x in xs {
+                s.push(' ');
+                s.push_str(&encode(x));
+            }
+            s
+        }
+    }
+}
+
+/// encodeで変換した値を、空白で区切った語の並びから復元する
+fn decode<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Option<Value> {
+    let mut next = || tokens.next();
+    let v = match next()? {
+        "int" => Value::Int(next()?.parse().ok()?),
+        "big" => Value::Big(next()?.parse().ok()?),
+        "ratio" => {
+            let (numer, denom) = (next()?.parse().ok()?, next()?.parse().ok()?);
+            if denom == 0.into() {
+                return None;
+            }
+            Value::Ratio(Box::new(BigRational::new(numer, denom)))
+        }
+        "decimal" => Value::Decimal(Box::new(next()?.parse::<Decimal>().ok()?)),
+        "float" => Value::Float(next()?.parse().ok()?),
+        "complex" => Value::Complex(Complex64::new(next()?.parse().ok()?, next()?.parse().ok()?)),
+        "interval" => Value::Interval(Interval::new(next()?.parse().ok()?, next()?.parse().ok()?)?),
+        "quantity" => {
+            let value = next()?.parse().ok()?;
+            let dim = [
+                next()?.parse().ok()?,
+                next()?.parse().ok()?,
+                next()?.parse().ok()?,
+            ];
+            Value::Quantity(Quantity {
+                value,
+                dim: Dim(dim),
+            })
+        }
+        "bool" => Value::Bool(next()?.parse().ok()?),
+        "list" => {
+            let len: usize = next()?.parse().ok()?;
+            let xs = (0..len)
+                .map(|_| decode(tokens))
+                .collect::<Option<Vec<_>>>()?;
+            Value::List(xs)
+        }
+        _ => return None,
+    };
+    Some(v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{env::Env, parser::DEFAULT_MAX_DEPTH};
+    use num_bigint::BigInt;
+    use std::collections::BTreeMap;
+
+    fn session() -> Session {
+        Session {
+            env: Env::new(),
+            notation: Notation::Infix,
+            max_depth: DEFAULT_MAX_DEPTH,
+            stack: None,
+            registers: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        let values = [
+            Value::Int(-3),
+            Value::Big(BigInt::from(i64::MAX) * 10),
+            Value::Ratio(Box::new(BigRational::new((-1).into(), 3.into()))),
+            Value::Decimal(Box::new("1.50".parse().unwrap())),
+            Value::Float(0.1),
+            Value::Float(f64::NEG_INFINITY),
+            Value::Complex(Complex64::new(3.0, -4.0)),
+            Value::Interval(Interval::new(1.0, 2.5).unwrap()),
+            Value::Quantity(unit_quantity()),
+            Value::Bool(true),
+            Value::List(vec![Value::Int(1), Value::List(vec![]), Value::Float(2.5)]),
+        ];
+        for v in values {
+            let s = encode(&v);
+            let mut tokens = s.split_whitespace();
+            assert_eq!(decode(&mut tokens), Some(v), "{s}");
+            assert_eq!(tokens.next(), None);
+        }
+        assert_eq!(decode(&mut "list 2 int 1".split_whitespace()), None);
+        assert_eq!(decode(&mut "ratio 1 0".split_whitespace()), None);
+    }
+
+    fn unit_quantity() -> Quantity {
+        let u = crate::unit::parse("km/h").unwrap();
+        Quantity {
+            value: 90.0 * u.value,
+            dim: u.dim,
+        }
+    }
+
+    #[test]
+    fn test_save_load() {
+        let path = std::env::temp_dir().join(format!("rpn-session-{}", std::process::id()));
+        let mut s = session();
+        for c in [
+            "x = 0.1 * 3",
+            "fn f(x, y) = x * (y + 1)",
+            "y = [1, 2]",
+            "fn g(x) = x * 3 km",
+            "fn h(x) = x + [0.9 .. 1.1]",
+        ] {
+            let stmt = parser::parse(c, Notation::Infix, DEFAULT_MAX_DEPTH).unwrap();
+            eval::exec(&stmt, &mut s.env).unwrap();
+        }
+        s.registers.insert("1".to_string(), Value::Bool(false));
+        let mut stack = Stack::new();
+        stack.push(Value::Int(7));
+        s.stack = Some(stack);
+        save(&path, &s).unwrap();
+
+        let mut t = session();
+        load(&path, &mut t).unwrap();
+        assert_eq!(t.env.get("x"), s.env.get("x"));
+        assert_eq!(t.env.get("y"), s.env.get("y"));
+        let stmt = parser::parse("f(2, 3)", Notation::Infix, DEFAULT_MAX_DEPTH).unwrap();
+        assert_eq!(eval::exec(&stmt, &mut t.env), Ok(Some(Value::Int(8))));
+        // 単位や区間を含む本体も、元の関数と同じ値となる
+        for c in ["g(2)", "h(1)"] {
+            let stmt = parser::parse(c, Notation::Infix, DEFAULT_MAX_DEPTH).unwrap();
+            let expected = eval::exec(&stmt, &mut s.env).unwrap();
+            assert!(expected.is_some());
+            assert_eq!(eval::exec(&stmt, &mut t.env), Ok(expected));
+        }
+        assert_eq!(t.registers, s.registers);
+        assert_eq!(t.stack.unwrap().values(), [Value::Int(7)]);
+
+        // 誤りがある場合は何も変更しない
+        fs::write(&path, "var z int 1\nvar w int\n").unwrap();
+        let mut u = session();
+        assert!(load(&path, &mut u)
+            .unwrap_err()
+            .ends_with(":2: invalid session entry"));
+        assert_eq!(u.env.get("z"), None);
+        fs::remove_file(&path).unwrap();
+    }
+}
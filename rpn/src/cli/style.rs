@@ -0,0 +1,254 @@
+//! REPLのプロンプト、出力の色、キー操作
+//!
+//! `--prompt`、`--color`、`--edit-mode`で指定するほか、設定ファイル`~/.rpnrc`に次のように記述できる。
+//! コマンドラインでの指定は、設定ファイルより優先する。
+//!
+//! ```text
+//! # 末尾の空白を含める場合は""で囲む
+//! prompt = "calc> "
+//! color = always
+//! edit-mode = vi
+//! ```
+//!
+//! 色を付ける場合は、結果を緑で、エラーを赤で表示し、入力中の行も色分けする。
+//! autoの場合は、標準出力と標準エラー出力がともに端末で、環境変数NO_COLORがない場合に色を付ける。
+//!
+//! 入力行の編集は、既定のemacs風のキー操作のほか、viのキー操作も選べる。
+use super::transcript;
+use std::{
+    fmt::{self, Display},
+    fs,
+    io::{self, IsTerminal},
+    path::Path,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// プロンプトの既定値
+pub const DEFAULT_PROMPT: &str = ">> ";
+
+/// 結果の色（緑）
+const RESULT_COLOR: &str = "\x1b[32m";
+/// エラーの色（赤）
+const ERROR_COLOR: &str = "\x1b[31m";
+/// 色を元に戻す
+const RESET: &str = "\x1b[0m";
+
+/// 色を付けるか
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// 色を付けるかの指定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    /// 色を付けるかを決める
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Auto => {
+                io::stdout().is_terminal()
+                    && io::stderr().is_terminal()
+                    && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!(
+                "unknown color mode '{s}' (expected auto, always or never)"
+            )),
+        }
+    }
+}
+
+impl Display for ColorMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorMode::Auto => write!(f, "auto"),
+            ColorMode::Always => write!(f, "always"),
+            ColorMode::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// 入力行の編集のキー操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EditMode {
+    #[default]
+    Emacs,
+    Vi,
+}
+
+impl From<EditMode> for rustyline::EditMode {
+    fn from(mode: EditMode) -> Self {
+        match mode {
+            EditMode::Emacs => rustyline::EditMode::Emacs,
+            EditMode::Vi => rustyline::EditMode::Vi,
+        }
+    }
+}
+
+impl FromStr for EditMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "emacs" => Ok(EditMode::Emacs),
+            "vi" => Ok(EditMode::Vi),
+            _ => Err(format!("unknown edit mode '{s}' (expected emacs or vi)")),
+        }
+    }
+}
+
+impl Display for EditMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditMode::Emacs => write!(f, "emacs"),
+            EditMode::Vi => write!(f, "vi"),
+        }
+    }
+}
+
+/// 設定ファイルの内容
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    pub prompt: Option<String>,
+    pub color: Option<ColorMode>,
+    pub edit_mode: Option<EditMode>,
+}
+
+impl Config {
+    /// 設定ファイルpathを読み込む
+    ///
+    /// ファイルがない場合は、何も設定しない。
+    pub fn load(path: &Path) -> Result<Config, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text).map_err(|e| format!("{}:{e}", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("{}: {e}", path.display())),
+        }
+    }
+
+    /// 設定ファイルの内容をパース
+    ///
+    /// 誤りがある場合は、`3: unknown setting 'x'`のように行番号を付けたエラーを返す。
+    fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |e: String| format!("{}: {e}", n + 1);
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| err(format!("expected 'name = value', found '{line}'")))?;
+            let value = value.trim();
+            // `"`で囲んだ値は、囲みを取り除いてそのまま用いる
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            match name.trim() {
+                "prompt" => config.prompt = Some(value.to_string()),
+                "color" => config.color = Some(value.parse().map_err(err)?),
+                "edit-mode" => config.edit_mode = Some(value.parse().map_err(err)?),
+                name => return Err(err(format!("unknown setting '{name}'"))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// 色を付けるかを設定する
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+/// 色を付けるか
+pub fn color() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// 結果を表示する文字列
+///
+/// セッションを記録している場合は、結果を記録する。
+pub fn result(s: impl Display) -> String {
+    let s = s.to_string();
+    transcript::result(&s);
+    paint(RESULT_COLOR, s)
+}
+
+/// エラーを表示する文字列
+pub fn error_message(s: impl Display) -> String {
+    paint(ERROR_COLOR, s)
+}
+
+fn paint(color: &str, s: impl Display) -> String {
+    if self::color() {
+        format!("{color}{s}{RESET}")
+    } else {
+        s.to_string()
+    }
+}
+
+/// エラーメッセージを、色を付ける場合は赤で標準エラー出力に表示する
+///
+/// セッションを記録している場合は、エラーを記録する。
+macro_rules! error {
+    ($($arg:tt)*) => {{
+        let message = format!($($arg)*);
+        $crate::cli::transcript::error(&message);
+        eprintln!("{}", $crate::cli::style::error_message(message))
+    }};
+}
+pub(crate) use error;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config =
+            Config::parse("# comment\n\nprompt = \"calc> \"\n color=never \nedit-mode = vi")
+                .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                prompt: Some("calc> ".to_string()),
+                color: Some(ColorMode::Never),
+                edit_mode: Some(EditMode::Vi),
+            }
+        );
+        assert_eq!(Config::parse(""), Ok(Config::default()));
+        assert_eq!(
+            Config::parse("prompt = $\ncolour = on"),
+            Err("2: unknown setting 'colour'".to_string())
+        );
+        assert_eq!(
+            Config::parse("color = on"),
+            Err("1: unknown color mode 'on' (expected auto, always or never)".to_string())
+        );
+        assert_eq!(
+            Config::parse("edit-mode = vim"),
+            Err("1: unknown edit mode 'vim' (expected emacs or vi)".to_string())
+        );
+        assert!(Config::parse("prompt").is_err());
+    }
+}
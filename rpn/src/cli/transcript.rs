@@ -0,0 +1,151 @@
+//! REPLのセッションの記録
+//!
+//! `--log FILE`や`:log on`で記録を始めると、REPLへの入力、結果、エラーを、時刻とともにファイルに追記する。
+//! 記録は次のような形式で、時刻はUTCで表す。
+//!
+//! ```text
+//! [2026-10-16T09:30:00Z] > x = 2 ^ 10
+//! [2026-10-16T09:30:00Z] = 1024
+//! [2026-10-16T09:30:05Z] > 1 / 0
+//! [2026-10-16T09:30:05Z] ! EvalError: division by zero
+//! ```
+//!
+//! 結果とエラーは、表示と同時に`style`から記録する。
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// `:log on`で記録するファイルの既定の名前（ホームディレクトリに作る）
+pub const DEFAULT_FILE: &str = ".rpn_transcript";
+
+/// 記録の状態
+static TRANSCRIPT: Mutex<Transcript> = Mutex::new(Transcript {
+    path: None,
+    file: None,
+});
+
+#[derive(Debug)]
+struct Transcript {
+    path: Option<PathBuf>, // 最後に記録したファイル
+    file: Option<File>,    // 記録中のファイル（記録していない場合はNone）
+}
+
+/// ファイルpathへの記録を始める
+///
+/// pathがNoneの場合は、最後に記録したファイルへの記録を再開する。
+/// 記録したことがない場合は、既定のファイルdefaultに記録する。記録するファイルのパスを返す。
+pub fn start(
+    path: Option<&Path>,
+    default: impl FnOnce() -> Option<PathBuf>,
+) -> io::Result<PathBuf> {
+    let mut t = TRANSCRIPT.lock().unwrap();
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => t.path.clone().or_else(default).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no transcript file (use :log FILE)",
+            )
+        })?,
+    };
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    t.file = Some(file);
+    t.path = Some(path.clone());
+    Ok(path)
+}
+
+/// 記録を終える
+///
+/// 記録していた場合は、そのファイルのパスを返す。
+pub fn stop() -> Option<PathBuf> {
+    let mut t = TRANSCRIPT.lock().unwrap();
+    t.file.take().and(t.path.clone())
+}
+
+/// 記録中のファイルのパス
+pub fn path() -> Option<PathBuf> {
+    let t = TRANSCRIPT.lock().unwrap();
+    t.file.as_ref().and(t.path.clone())
+}
+
+/// 入力行を記録する
+pub fn input(line: &str) {
+    record('>', line);
+}
+
+/// 結果を記録する
+pub fn result(s: &str) {
+    record('=', s);
+}
+
+/// エラーを記録する
+pub fn error(s: &str) {
+    record('!', s);
+}
+
+/// 記録中の場合は、textの各行を時刻とmarkを付けて追記する
+///
+/// 書き込みに失敗した場合は、警告を表示して記録を終える。
+fn record(mark: char, text: &str) {
+    let mut t = TRANSCRIPT.lock().unwrap();
+    let Some(file) = &mut t.file else {
+        return;
+    };
+    let time = timestamp(SystemTime::now());
+    let lines: String = text
+        .lines()
+        .map(|line| format!("[{time}] {mark} {line}\n"))
+        .collect();
+    if let Err(e) = file.write_all(lines.as_bytes()) {
+        t.file = None;
+        let path = t.path.as_deref().unwrap_or(Path::new("")).display();
+        eprintln!("warning: stopped logging to {path}: {e}");
+    }
+}
+
+/// 時刻tを`2026-10-16T09:30:00Z`のようにUTCで表す
+fn timestamp(t: SystemTime) -> String {
+    let secs = t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// 1970年1月1日からの日数を、グレゴリオ暦の年月日に変換する
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // 3月1日から始まる400年周期で数える
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097; // 周期の何日目か
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365; // 周期の何年目か
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100); // 3月1日から何日目か
+    let mp = (5 * doy + 2) / 153; // 3月を0とする月
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_timestamp() {
+        let at = |secs| timestamp(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(at(0), "1970-01-01T00:00:00Z");
+        assert_eq!(at(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(at(1_700_000_000), "2023-11-14T22:13:20Z");
+        assert_eq!(at(1_735_689_599), "2024-12-31T23:59:59Z");
+    }
+}
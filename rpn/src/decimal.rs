@@ -0,0 +1,337 @@
+//! 任意精度の10進小数
+//!
+//! 値を整数digitsと小数点以下の桁数scaleの組で、digits / 10^scale として表す。
+//! 2進数の浮動小数点数と異なり`0.1 + 0.2`は正確に`0.3`となる。
+//!
+//! 加算、減算、乗算、剰余は丸めずに正確に計算し、除算のみ
+//! Roundingで指定された小数点以下の桁数と丸め方で丸める。
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Signed, Zero};
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+/// 10進小数
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Decimal {
+    digits: BigInt,
+    scale: u32, // 小数点以下の桁数
+}
+
+/// 除算の丸め方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoundingMode {
+    #[default]
+    HalfEven, // 最近接偶数への丸め（銀行型丸め）
+    HalfUp,  // 四捨五入
+    Down,    // 0方向への切り捨て
+    Up,      // 0から遠い方向への切り上げ
+    Floor,   // 負の無限大方向への丸め
+    Ceiling, // 正の無限大方向への丸め
+}
+
+impl FromStr for RoundingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half-even" => Ok(RoundingMode::HalfEven),
+            "half-up" => Ok(RoundingMode::HalfUp),
+            "down" => Ok(RoundingMode::Down),
+            "up" => Ok(RoundingMode::Up),
+            "floor" => Ok(RoundingMode::Floor),
+            "ceiling" => Ok(RoundingMode::Ceiling),
+            _ => Err(format!(
+                "unknown rounding mode '{s}' (expected half-even, half-up, down, up, floor or ceiling)"
+            )),
+        }
+    }
+}
+
+impl Display for RoundingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RoundingMode::HalfEven => "half-even",
+            RoundingMode::HalfUp => "half-up",
+            RoundingMode::Down => "down",
+            RoundingMode::Up => "up",
+            RoundingMode::Floor => "floor",
+            RoundingMode::Ceiling => "ceiling",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// 除算結果の小数点以下の桁数と丸め方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rounding {
+    pub scale: u32,
+    pub mode: RoundingMode,
+}
+
+impl Default for Rounding {
+    fn default() -> Self {
+        Rounding {
+            scale: 20,
+            mode: RoundingMode::default(),
+        }
+    }
+}
+
+impl Decimal {
+    /// 浮動小数点数を、その最短の10進表記と等しい小数に変換
+    ///
+    /// 無限大とNaNは変換できない。
+    pub fn from_f64(x: f64) -> Option<Decimal> {
+        if !x.is_finite() {
+            return None;
+        }
+        format!("{x:?}").parse().ok()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_zero()
+    }
+
+    /// 小数部が0の場合は整数として返す
+    pub fn to_integer(&self) -> Option<BigInt> {
+        let (q, r) = self.digits.div_rem(&pow10(self.scale));
+        r.is_zero().then_some(q)
+    }
+
+    /// 分子と分母の組に変換
+    pub fn to_fraction(&self) -> (BigInt, BigInt) {
+        (self.digits.clone(), pow10(self.scale))
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    pub fn add(&self, rhs: &Decimal) -> Decimal {
+        let (a, b, scale) = align(self, rhs);
+        Decimal::new(a + b, scale)
+    }
+
+    pub fn sub(&self, rhs: &Decimal) -> Decimal {
+        let (a, b, scale) = align(self, rhs);
+        Decimal::new(a - b, scale)
+    }
+
+    pub fn mul(&self, rhs: &Decimal) -> Decimal {
+        Decimal::new(&self.digits * &rhs.digits, self.scale + rhs.scale)
+    }
+
+    /// 除算
+    ///
+    /// 結果をrounding.scale桁に丸め、末尾の0を取り除く。0による除算の場合はNone。
+    pub fn div(&self, rhs: &Decimal, rounding: Rounding) -> Option<Decimal> {
+        if rhs.is_zero() {
+            return None;
+        }
+
+        // self / rhs * 10^scale = (a * 10^(rhs.scale + scale)) / (b * 10^self.scale)
+        let n = &self.digits * pow10(rhs.scale + rounding.scale);
+        let d = &rhs.digits * pow10(self.scale);
+        let (q, r) = n.div_rem(&d);
+        let q = if r.is_zero() {
+            q
+        } else {
+            round(q, &r, &d, n.is_negative() != d.is_negative(), rounding.mode)
+        };
+        Some(Decimal::new(q, rounding.scale).normalize())
+    }
+
+    /// 剰余
+    ///
+    /// 剰余の符号は被除数と同じ。0による除算の場合はNone。
+    pub fn rem(&self, rhs: &Decimal) -> Option<Decimal> {
+        if rhs.is_zero() {
+            return None;
+        }
+        let (a, b, scale) = align(self, rhs);
+        Some(Decimal::new(a % b, scale))
+    }
+
+    /// 整数乗
+    ///
+    /// 結果のdigitsのビット数、またはscaleの桁数がmax_bitsを超える場合はNone。
+    pub fn pow(&self, exp: u32, max_bits: u64) -> Option<Decimal> {
+        let bits = (self.digits.bits().saturating_sub(1)).saturating_mul(u64::from(exp));
+        let scale = self.scale.checked_mul(exp)?;
+        if bits > max_bits || u64::from(scale) > max_bits {
+            return None;
+        }
+        Some(Decimal::new(self.digits.pow(exp), scale))
+    }
+
+    pub fn neg(&self) -> Decimal {
+        Decimal::new(-&self.digits, self.scale)
+    }
+
+    pub fn abs(&self) -> Decimal {
+        Decimal::new(self.digits.abs(), self.scale)
+    }
+
+    fn new(digits: BigInt, scale: u32) -> Decimal {
+        Decimal { digits, scale }
+    }
+
+    /// 小数部の末尾の0を取り除く
+    fn normalize(mut self) -> Decimal {
+        let ten = BigInt::from(10);
+        while self.scale > 0 && (&self.digits % &ten).is_zero() {
+            self.digits /= &ten;
+            self.scale -= 1;
+        }
+        self
+    }
+}
+
+impl From<BigInt> for Decimal {
+    fn from(n: BigInt) -> Self {
+        Decimal::new(n, 0)
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = ();
+
+    /// `-1.25`や`1e-3`のような10進表記をパース
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mantissa, exp) = match s.split_once(['e', 'E']) {
+            Some((m, e)) => (m, e.parse::<i32>().map_err(|_| ())?),
+            None => (s, 0),
+        };
+        let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits: BigInt = format!("{int}{frac}").parse().map_err(|_| ())?;
+
+        let scale = frac.len() as i64 - i64::from(exp);
+        if scale >= 0 {
+            let scale = u32::try_from(scale).map_err(|_| ())?;
+            Ok(Decimal::new(digits, scale))
+        } else {
+            let shift = u32::try_from(-scale).map_err(|_| ())?;
+            Ok(Decimal::new(digits * pow10(shift), 0))
+        }
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.digits.is_negative() { "-" } else { "" };
+        let s = self.digits.abs().to_string();
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{sign}{s}");
+        }
+
+        // 小数点以下の桁数に満たない場合は、先頭を0で埋める
+        let s = format!("{s:0>width$}", width = scale + 1);
+        let (int, frac) = s.split_at(s.len() - scale);
+        write!(f, "{sign}{int}.{frac}")
+    }
+}
+
+/// 10^n
+fn pow10(n: u32) -> BigInt {
+    BigInt::from(10).pow(n)
+}
+
+/// 2つの小数の桁数を揃え、揃えた後のdigitsとscaleを返す
+fn align(a: &Decimal, b: &Decimal) -> (BigInt, BigInt, u32) {
+    match a.scale.cmp(&b.scale) {
+        Ordering::Less => (
+            &a.digits * pow10(b.scale - a.scale),
+            b.digits.clone(),
+            b.scale,
+        ),
+        Ordering::Greater => (
+            a.digits.clone(),
+            &b.digits * pow10(a.scale - b.scale),
+            a.scale,
+        ),
+        Ordering::Equal => (a.digits.clone(), b.digits.clone(), a.scale),
+    }
+}
+
+/// 0方向に切り捨てた商qを、余りrと除数dからmodeに従って丸める
+///
+/// negativeは商が負かどうか。
+fn round(q: BigInt, r: &BigInt, d: &BigInt, negative: bool, mode: RoundingMode) -> BigInt {
+    // 余りが除数の半分より大きいか、ちょうど半分か
+    let half = (r.abs() << 1u32).cmp(&d.abs());
+    let away = match mode {
+        RoundingMode::HalfEven => half.is_gt() || (half.is_eq() && q.is_odd()),
+        RoundingMode::HalfUp => half.is_ge(),
+        RoundingMode::Down => false,
+        RoundingMode::Up => true,
+        RoundingMode::Floor => negative,
+        RoundingMode::Ceiling => !negative,
+    };
+
+    match (away, negative) {
+        (false, _) => q,
+        (true, false) => q + BigInt::one(),
+        (true, true) => q - BigInt::one(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_display() {
+        assert_eq!(dec("1.25").to_string(), "1.25");
+        assert_eq!(dec("-0.05").to_string(), "-0.05");
+        assert_eq!(dec("1e-3").to_string(), "0.001");
+        assert_eq!(dec("1.5e2").to_string(), "150");
+        assert_eq!(Decimal::from_f64(0.1).unwrap().to_string(), "0.1");
+        assert!(Decimal::from_f64(f64::INFINITY).is_none());
+        assert!("1.2.3".parse::<Decimal>().is_err());
+    }
+
+    #[test]
+    fn test_arith() {
+        assert_eq!(dec("0.1").add(&dec("0.2")), dec("0.3"));
+        assert_eq!(dec("1.10").add(&dec("2.20")).to_string(), "3.30");
+        assert_eq!(dec("1.5").mul(&dec("-0.2")).to_string(), "-0.30");
+        assert_eq!(dec("7.5").rem(&dec("2")).unwrap().to_string(), "1.5");
+        assert!(dec("1").rem(&dec("0.0")).is_none());
+        assert_eq!(dec("1.50").to_fraction(), (150.into(), 100.into()));
+        assert_eq!(dec("3.000").to_integer(), Some(3.into()));
+        assert_eq!(dec("3.001").to_integer(), None);
+    }
+
+    #[test]
+    fn test_div_rounding() {
+        let div = |a: &str, b: &str, scale, mode| {
+            dec(a)
+                .div(&dec(b), Rounding { scale, mode })
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(div("1", "3", 5, RoundingMode::HalfEven), "0.33333");
+        assert_eq!(div("2", "3", 5, RoundingMode::HalfEven), "0.66667");
+        assert_eq!(div("2", "3", 5, RoundingMode::Down), "0.66666");
+        assert_eq!(div("1", "4", 0, RoundingMode::HalfUp), "0");
+        assert_eq!(div("1", "8", 2, RoundingMode::HalfEven), "0.12");
+        assert_eq!(div("3", "8", 2, RoundingMode::HalfEven), "0.38");
+        assert_eq!(div("1", "8", 2, RoundingMode::HalfUp), "0.13");
+        assert_eq!(div("-1", "3", 2, RoundingMode::Floor), "-0.34");
+        assert_eq!(div("-1", "3", 2, RoundingMode::Ceiling), "-0.33");
+        assert_eq!(div("1", "3", 2, RoundingMode::Up), "0.34");
+        assert_eq!(div("1", "4", 20, RoundingMode::HalfEven), "0.25");
+        assert!(dec("1").div(&dec("0"), Rounding::default()).is_none());
+    }
+}
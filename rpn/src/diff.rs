@@ -0,0 +1,183 @@
+//! 式を記号的に微分する
+//!
+//! 和・差・積・商・べき乗の微分の規則と、`sin`、`cos`、`ln`、`sqrt`、`abs`、`pow`の連鎖律を適用する。
+//! 微分する変数を含まない部分式は定数とみなし、その微分を`0`とする。
+//! 結果の式は簡約しないため、必要に応じて`simplify`で簡約する。
+use crate::parser::Expr;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// 微分のエラーを表す型
+#[derive(Debug, PartialEq)]
+pub enum DiffError {
+    Unsupported(String), // 微分できない式
+}
+
+impl Display for DiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffError::Unsupported(e) => write!(f, "DiffError: cannot differentiate '{e}'"),
+        }
+    }
+}
+
+impl Error for DiffError {}
+
+/// 式eを変数xで微分した式を返す関数
+pub fn diff(e: &Expr, x: &str) -> Result<Expr, DiffError> {
+    if !contains(e, x) {
+        return Ok(Expr::Num(0));
+    }
+
+    let d = |e| diff(e, x);
+    Ok(match e {
+        Expr::Var(_) => Expr::Num(1),
+        Expr::Add(a, b) => add(d(a)?, d(b)?),
+        Expr::Sub(a, b) => sub(d(a)?, d(b)?),
+        Expr::Neg(a) => neg(d(a)?),
+        // (ab)' = a'b + ab'
+        Expr::Mul(a, b) => add(mul(d(a)?, *b.clone()), mul(*a.clone(), d(b)?)),
+        // (a/b)' = (a'b - ab') / b^2
+        Expr::Div(a, b) => div(
+            sub(mul(d(a)?, *b.clone()), mul(*a.clone(), d(b)?)),
+            pow(*b.clone(), Expr::Num(2)),
+        ),
+        Expr::Pow(a, b) => diff_pow(a, b, x)?,
+        Expr::Call(name, args) => match (name.as_str(), &args[..]) {
+            ("pow", [a, b]) => diff_pow(a, b, x)?,
+            (name, [a]) => mul(diff_call(name, a).ok_or_else(|| unsupported(e))?, d(a)?),
+            _ => return Err(unsupported(e)),
+        },
+        // 条件分岐は、それぞれの場合の式を微分する
+        Expr::If(cond, then, els) => Expr::If(cond.clone(), Box::new(d(then)?), Box::new(d(els)?)),
+        _ => return Err(unsupported(e)),
+    })
+}
+
+/// べき乗`a ^ b`を変数xで微分する
+fn diff_pow(a: &Expr, b: &Expr, x: &str) -> Result<Expr, DiffError> {
+    let e = pow(a.clone(), b.clone());
+    Ok(match (contains(a, x), contains(b, x)) {
+        // (a^n)' = n a^(n-1) a'
+        (_, false) => mul(
+            mul(b.clone(), pow(a.clone(), sub(b.clone(), Expr::Num(1)))),
+            diff(a, x)?,
+        ),
+        // (c^b)' = c^b ln(c) b'
+        (false, true) => mul(mul(e, ln(a.clone())), diff(b, x)?),
+        // (a^b)' = a^b (b' ln(a) + b a' / a)
+        (true, true) => mul(
+            e,
+            add(
+                mul(diff(b, x)?, ln(a.clone())),
+                div(mul(b.clone(), diff(a, x)?), a.clone()),
+            ),
+        ),
+    })
+}
+
+/// 1引数の組み込み関数nameの導関数に、引数aを与えた式を返す
+///
+/// 微分できない関数の場合はNone。
+fn diff_call(name: &str, a: &Expr) -> Option<Expr> {
+    let call = |name: &str| Expr::Call(name.to_string(), vec![a.clone()]);
+    Some(match name {
+        "sin" => call("cos"),
+        "cos" => neg(call("sin")),
+        "ln" => div(Expr::Num(1), a.clone()),
+        "sqrt" => div(Expr::Num(1), mul(Expr::Num(2), call("sqrt"))),
+        "abs" => div(a.clone(), call("abs")),
+        _ => return None,
+    })
+}
+
+/// 式eが変数xを含むかを判定する
+fn contains(e: &Expr, x: &str) -> bool {
+    match e {
+        Expr::Var(name) => name == x,
+        _ => e.children().into_iter().any(|c| contains(c, x)),
+    }
+}
+
+fn unsupported(e: &Expr) -> DiffError {
+    DiffError::Unsupported(e.to_string())
+}
+
+fn add(a: Expr, b: Expr) -> Expr {
+    Expr::Add(Box::new(a), Box::new(b))
+}
+
+fn sub(a: Expr, b: Expr) -> Expr {
+    Expr::Sub(Box::new(a), Box::new(b))
+}
+
+fn mul(a: Expr, b: Expr) -> Expr {
+    Expr::Mul(Box::new(a), Box::new(b))
+}
+
+fn div(a: Expr, b: Expr) -> Expr {
+    Expr::Div(Box::new(a), Box::new(b))
+}
+
+fn pow(a: Expr, b: Expr) -> Expr {
+    Expr::Pow(Box::new(a), Box::new(b))
+}
+
+fn neg(a: Expr) -> Expr {
+    Expr::Neg(Box::new(a))
+}
+
+/// 自然対数`ln(a)`を返す
+///
+/// aがネイピア数の定数`e`の場合は`1`とする。
+fn ln(a: Expr) -> Expr {
+    match a {
+        Expr::Var(name) if name == "e" => Expr::Num(1),
+        a => Expr::Call("ln".to_string(), vec![a]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::{parse, Notation, Stmt, DEFAULT_MAX_DEPTH},
+        simplify::simplify,
+    };
+
+    fn d(c: &str, x: &str) -> Result<String, DiffError> {
+        match parse(c, Notation::Infix, DEFAULT_MAX_DEPTH).unwrap() {
+            Stmt::Expr(e) => diff(&e, x).map(|e| simplify(&e).to_string()),
+            s => panic!("not an expression: {s:?}"),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(d("3 * x + 2", "x").unwrap(), "3");
+        assert_eq!(d("x * x", "x").unwrap(), "2 * x");
+        assert_eq!(d("x ^ 3 + y", "x").unwrap(), "3 * x ^ 2");
+        assert_eq!(d("x * y + y", "y").unwrap(), "x + 1");
+        assert_eq!(d("f(y) + 1", "x").unwrap(), "0");
+        assert_eq!(d("sin(2 * x)", "x").unwrap(), "2 * cos(2 * x)");
+        assert_eq!(d("cos(x)", "x").unwrap(), "-sin(x)");
+        assert_eq!(d("e ^ x", "x").unwrap(), "e ^ x");
+        assert_eq!(d("ln(x)", "x").unwrap(), "1 / x");
+        assert_eq!(d("1 / x", "x").unwrap(), "-1 / x ^ 2");
+        assert_eq!(
+            d("if x > 0 then x * x else -x", "x").unwrap(),
+            "if x > 0 then 2 * x else -1"
+        );
+
+        assert_eq!(
+            d("f(x)", "x"),
+            Err(DiffError::Unsupported("f(x)".to_string()))
+        );
+        assert_eq!(
+            d("x % 2", "x"),
+            Err(DiffError::Unsupported("x % 2".to_string()))
+        );
+    }
+}
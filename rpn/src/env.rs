@@ -0,0 +1,329 @@
+//! 変数の値を保持する環境
+//!
+//! REPLの入力行をまたいで値を保持するため、REPLのループで1つ生成して評価に渡す。
+//! これまでの評価結果も保持し、`ans`で直前の結果を、`$1`、`$2`、…でn番目の結果を参照できる。
+//!
+//! ユーザ定義関数の呼び出し中は、引数の値を保持するフレームを積む。
+//! 変数は、実行中の関数の引数、大域変数、定数の順に探す。
+//!
+//! 未定義の変数や関数を参照した場合は、編集距離の近い定義済みの名前をエラーメッセージで示す。
+//!
+//! REPLの`:set`で変更できる評価の設定と、`rand()`などで用いる乱数生成器も保持する。
+use crate::{
+    builtin,
+    decimal::Rounding,
+    eval::{EvalError, Instruction},
+    parser::Expr,
+    rng::Rng,
+    value::{IntWidth, NumberFormat, OverflowMode, Radix, Value},
+};
+use num_complex::Complex64;
+use std::{
+    collections::HashMap,
+    f64::consts::{E, PI, TAU},
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+/// 直前の評価結果を表す変数名
+const ANS: &str = "ans";
+
+/// 名前付きの定数
+const CONSTANTS: [(&str, Value); 6] = [
+    ("pi", Value::Float(PI)),
+    ("e", Value::Float(E)),
+    ("tau", Value::Float(TAU)),
+    ("i64_max", Value::Int(i64::MAX)),
+    ("i64_min", Value::Int(i64::MIN)),
+    ("i", Value::Complex(Complex64::new(0.0, 1.0))), // 虚数単位
+];
+
+/// 関数呼び出しの深さの上限
+pub const MAX_CALL_DEPTH: usize = 128;
+
+/// ユーザ定義関数
+#[derive(Debug)]
+pub struct Function {
+    pub params: Vec<String>,
+    pub body: Expr,             // 関数本体の式（`:save`で保存する）
+    pub code: Vec<Instruction>, // 関数本体の命令列
+    #[cfg(feature = "jit")]
+    pub native: Option<crate::eval::NativeFn>, // JITコンパイルした関数本体
+}
+
+/// `:set 名前 値`で変更できる評価の設定
+#[derive(Debug, Default, Clone)]
+pub struct Settings {
+    pub bigint: bool,             // 整数を多倍長整数として扱う
+    pub rational: bool,           // 整数を有理数として扱う
+    pub decimal: bool,            // 数値を10進小数として扱う
+    pub rounding: Rounding,       // 10進小数の除算結果の桁数と丸め方
+    pub width: IntWidth,          // 多倍長整数が無効な場合の整数の幅
+    pub overflow: OverflowMode,   // 整数の幅を超えた場合の動作
+    pub optimize: bool,           // 評価の前に式を最適化する
+    pub trace: bool,              // 実行した命令とスタックを表示する
+    pub interval: bool,           // 数値を区間として扱う
+    pub radix: Radix,             // 整数の結果を表示する基数
+    pub precision: Option<usize>, // 浮動小数点数の結果を表示する小数点以下の桁数
+    pub sci: bool,                // 浮動小数点数の結果を指数表記で表示する
+    pub separator: bool,          // 10進数の結果の整数部を3桁ごとに区切って表示する
+}
+
+impl Settings {
+    /// 名前がnameの設定をvalueに変更
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "bigint" => self.bigint = parse_switch(value)?,
+            "rational" => self.rational = parse_switch(value)?,
+            "decimal" => self.decimal = parse_switch(value)?,
+            "scale" => {
+                self.rounding.scale = value
+                    .parse()
+                    .map_err(|_| format!("invalid scale '{value}'"))?
+            }
+            "rounding" => self.rounding.mode = value.parse()?,
+            "width" => self.width = value.parse()?,
+            "overflow" => self.overflow = value.parse()?,
+            "optimize" => self.optimize = parse_switch(value)?,
+            "trace" => self.trace = parse_switch(value)?,
+            "interval" => self.interval = parse_switch(value)?,
+            "radix" => self.radix = value.parse()?,
+            "precision" => {
+                self.precision = match value {
+                    "auto" => None,
+                    _ => Some(value.parse().map_err(|_| {
+                        format!("invalid precision '{value}' (expected a number or auto)")
+                    })?),
+                }
+            }
+            "sci" => self.sci = parse_switch(value)?,
+            "separator" => self.separator = parse_switch(value)?,
+            _ => return Err(format!("unknown setting '{name}'")),
+        }
+        Ok(())
+    }
+
+    /// 結果の表示形式を返す
+    pub fn number_format(&self) -> NumberFormat {
+        NumberFormat {
+            radix: self.radix,
+            precision: self.precision,
+            sci: self.sci,
+            separator: self.separator,
+        }
+    }
+}
+
+impl Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let switch = |b| if b { "on" } else { "off" };
+        writeln!(f, "bigint = {}", switch(self.bigint))?;
+        writeln!(f, "rational = {}", switch(self.rational))?;
+        writeln!(f, "decimal = {}", switch(self.decimal))?;
+        writeln!(f, "scale = {}", self.rounding.scale)?;
+        writeln!(f, "rounding = {}", self.rounding.mode)?;
+        writeln!(f, "width = {}", self.width)?;
+        writeln!(f, "overflow = {}", self.overflow)?;
+        writeln!(f, "optimize = {}", switch(self.optimize))?;
+        writeln!(f, "trace = {}", switch(self.trace))?;
+        writeln!(f, "interval = {}", switch(self.interval))?;
+        writeln!(f, "radix = {}", self.radix)?;
+        match self.precision {
+            Some(p) => writeln!(f, "precision = {p}")?,
+            None => writeln!(f, "precision = auto")?,
+        }
+        writeln!(f, "sci = {}", switch(self.sci))?;
+        write!(f, "separator = {}", switch(self.separator))
+    }
+}
+
+/// on/offの設定値をパース
+fn parse_switch(value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("invalid value '{value}' (expected on or off)")),
+    }
+}
+
+/// 候補candidatesのうち、nameに最も似た名前を返す
+///
+/// 編集距離がnameの長さの1/3以下（ただし1以上）で、nameの長さ未満の名前を似た名前とする。
+/// 最も近い名前が複数ある場合は、名前順で最初の名前とする。
+fn similar<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    let len = name.chars().count();
+    let max = (len / 3).max(1);
+    candidates
+        .filter(|&c| c != name)
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|&(d, _)| d <= max && d < len)
+        .min()
+        .map(|(_, c)| c.to_string())
+}
+
+/// 文字列aとbの編集距離
+///
+/// 1文字の挿入、削除、置換と、隣り合う2文字の入れ替えを、それぞれ1回の編集とする。
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // d[i][j]は、aの先頭i文字とbの先頭j文字の編集距離
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, x) in d[0].iter_mut().enumerate() {
+        *x = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j - 1] + cost)
+                .min(d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// 変数の値を保持する環境
+#[derive(Debug, Default)]
+pub struct Env {
+    pub settings: Settings,
+    pub rng: Rng, // `:seed N`でシードを設定できる乱数生成器
+    vars: HashMap<String, Value>,
+    history: Vec<Value>,                 // これまでの評価結果
+    fns: HashMap<String, Rc<Function>>,  // ユーザ定義関数
+    frames: Vec<HashMap<String, Value>>, // 呼び出し中の関数の引数
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Env::default()
+    }
+
+    /// 変数の値を返す
+    pub fn get(&self, name: &str) -> Option<Value> {
+        if let Some(v) = self.frames.last().and_then(|frame| frame.get(name)) {
+            return Some(v.clone());
+        }
+        if name == ANS {
+            return self.history.last().cloned();
+        }
+        if let Some(v) = self.vars.get(name) {
+            return Some(v.clone());
+        }
+        CONSTANTS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.clone())
+    }
+
+    /// 変数に値を設定
+    ///
+    /// `ans`や定数など、読み取り専用の変数には設定できない。
+    pub fn set(&mut self, name: &str, value: Value) -> Result<(), EvalError> {
+        if name == ANS || CONSTANTS.iter().any(|(n, _)| *n == name) {
+            return Err(EvalError::ReadOnlyVariable(name.to_string()));
+        }
+        self.vars.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// 評価結果を履歴に追加し、その番号（1始まり）を返す
+    pub fn push_result(&mut self, value: Value) -> usize {
+        self.history.push(value);
+        self.history.len()
+    }
+
+    /// n番目（1始まり）の評価結果を返す
+    pub fn result(&self, n: usize) -> Option<Value> {
+        self.history.get(n.checked_sub(1)?).cloned()
+    }
+
+    /// 関数を定義
+    ///
+    /// 同じ名前の関数が定義済みの場合は上書きする。
+    pub fn define_fn(&mut self, name: &str, f: Function) {
+        self.fns.insert(name.to_string(), Rc::new(f));
+    }
+
+    /// ユーザ定義関数を返す
+    pub fn get_fn(&self, name: &str) -> Option<Rc<Function>> {
+        self.fns.get(name).cloned()
+    }
+
+    /// 関数呼び出しのフレームを積む
+    ///
+    /// 呼び出しの深さがMAX_CALL_DEPTHを超える場合はエラー。
+    pub fn push_frame(&mut self, frame: HashMap<String, Value>) -> Result<(), EvalError> {
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(EvalError::RecursionLimit);
+        }
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    /// 関数呼び出しのフレームを取り除く
+    pub fn pop_frame(&mut self) {
+        self.frames.pop();
+    }
+
+    /// 定義済みの変数と定数の名前を返す
+    ///
+    /// 呼び出し中の関数の引数は含まない。
+    pub fn var_names(&self) -> impl Iterator<Item = &str> {
+        self.vars
+            .keys()
+            .map(String::as_str)
+            .chain(CONSTANTS.iter().map(|(name, _)| *name))
+            .chain([ANS])
+    }
+
+    /// ユーザ定義関数の名前を返す
+    pub fn fn_names(&self) -> impl Iterator<Item = &str> {
+        self.fns.keys().map(String::as_str)
+    }
+
+    /// 未定義の変数nameに似た、呼び出し中の関数の引数、変数、定数の名前を返す
+    pub fn similar_var(&self, name: &str) -> Option<String> {
+        let params = self.frames.last().into_iter().flat_map(|f| f.keys());
+        similar(name, params.map(String::as_str).chain(self.var_names()))
+    }
+
+    /// 未定義の関数nameに似た、ユーザ定義関数や組み込み関数の名前を返す
+    pub fn similar_fn(&self, name: &str) -> Option<String> {
+        let builtins: Vec<&str> = builtin::names().collect();
+        similar(name, self.fn_names().chain(builtins))
+    }
+
+    /// これまでの評価結果を返す
+    pub fn history(&self) -> &[Value] {
+        &self.history
+    }
+
+    /// 大域変数の名前と値を名前順に返す
+    pub fn vars(&self) -> Vec<(&str, &Value)> {
+        let mut vars: Vec<_> = self.vars.iter().map(|(k, v)| (k.as_str(), v)).collect();
+        vars.sort_by_key(|&(name, _)| name);
+        vars
+    }
+
+    /// ユーザ定義関数の名前と関数を名前順に返す
+    pub fn fns(&self) -> Vec<(&str, &Function)> {
+        let mut fns: Vec<_> = self.fns.iter().map(|(k, f)| (k.as_str(), &**f)).collect();
+        fns.sort_by_key(|&(name, _)| name);
+        fns
+    }
+
+    /// 変数、ユーザ定義関数、評価結果をすべて削除する
+    ///
+    /// 設定と乱数生成器はそのまま残す。
+    pub fn clear(&mut self) {
+        self.vars.clear();
+        self.fns.clear();
+        self.history.clear();
+    }
+}
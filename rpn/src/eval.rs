@@ -0,0 +1,1016 @@
+//! 抽象構文木を評価
+//!
+//! 式を`codegen`で命令列に変換し、`vm`のスタックマシンで実行する。
+//! 最適化の設定が有効な場合は、変換の前に`optimizer`で式を最適化する。
+//!
+//! `jit`フィーチャが有効な場合は、ユーザ定義関数を定義時に`jit`でネイティブコードに変換し、
+//! 整数の引数で呼び出された際に使う。
+mod codegen;
+#[cfg(feature = "jit")]
+mod jit;
+mod optimizer;
+mod vm;
+
+#[cfg(feature = "jit")]
+pub use jit::NativeFn;
+pub use optimizer::optimize;
+
+use crate::{
+    env::{Env, Function},
+    parser::{Expr, Stmt},
+    value::Value,
+};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+use tracing::instrument;
+
+/// 評価エラーを表す型
+#[derive(Debug, PartialEq)]
+pub enum EvalError {
+    DivideByZero,                              // 0による除算
+    Overflow,                                  // 演算結果がi64の範囲を超えた
+    NegativeExponent,                          // 整数同士のべき乗で指数が負
+    NegativeFactorial,                         // 負の数の階乗
+    NotInteger,                                // ビット演算や階乗の被演算子が整数でない
+    InvalidShift,                              // シフト量が範囲外
+    NotNumber,                                 // 算術演算や大小比較の被演算子が数値でない
+    NotReal,                                   // 大小比較や剰余の被演算子が複素数や区間
+    InvalidInterval,                           // 区間の下限が上限より大きい
+    InvalidRange,                              // 乱数の範囲の下限が上限より大きい
+    AmbiguousComparison,                       // 区間の比較結果が区間内の値によって異なる
+    IncompatibleUnits,                         // 次元が異なる量の加減算や比較
+    UnknownUnit(String),                       // 未知の単位
+    NotBool,                                   // 論理演算の被演算子が真偽値でない
+    UndefinedVariable(String, Option<String>), // 未定義の変数、似た名前の変数
+    ReadOnlyVariable(String),                  // 読み取り専用の変数への代入
+    NoHistory(usize),                          // 存在しない評価結果の参照
+    UndefinedFunction(String, Option<String>), // 未定義の関数、似た名前の関数
+    WrongArity(String, usize, usize),          // 関数名、引数の数の期待値、実際の引数の数
+    RecursionLimit,                            // 関数呼び出しが深すぎる
+    NotList,                                   // 要素を参照する値がリストでない
+    EmptyList,                                 // 統計の関数の引数が空のリスト
+    IndexOutOfRange(i64, usize),               // 添字、リストの長さ
+    LengthMismatch(usize, usize),              // 要素ごとの演算でのリストの長さ
+    StackUnderflow,                            // スタックモードで、スタックの値が足りない
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::DivideByZero => write!(f, "EvalError: division by zero"),
+            EvalError::Overflow => write!(f, "EvalError: arithmetic overflow"),
+            EvalError::NegativeExponent => {
+                write!(f, "EvalError: negative exponent in integer power")
+            }
+            EvalError::NegativeFactorial => {
+                write!(f, "EvalError: factorial of a negative number")
+            }
+            EvalError::NotInteger => write!(f, "EvalError: expected an integer"),
+            EvalError::InvalidShift => write!(f, "EvalError: shift amount out of range"),
+            EvalError::NotNumber => write!(f, "EvalError: expected a number"),
+            EvalError::NotReal => write!(f, "EvalError: expected a real number"),
+            EvalError::InvalidInterval => {
+                write!(f, "EvalError: lower bound of interval exceeds upper bound")
+            }
+            EvalError::InvalidRange => {
+                write!(f, "EvalError: lower bound of range exceeds upper bound")
+            }
+            EvalError::AmbiguousComparison => {
+                write!(
+                    f,
+                    "EvalError: comparison of overlapping intervals is ambiguous"
+                )
+            }
+            EvalError::IncompatibleUnits => write!(f, "EvalError: incompatible units"),
+            EvalError::UnknownUnit(name) => write!(f, "EvalError: unknown unit '{name}'"),
+            EvalError::NotBool => write!(f, "EvalError: expected a boolean"),
+            EvalError::UndefinedVariable(name, similar) => {
+                write!(f, "EvalError: undefined variable '{name}'")?;
+                write_suggestion(f, similar)
+            }
+            EvalError::ReadOnlyVariable(name) => {
+                write!(f, "EvalError: cannot assign to read-only variable '{name}'")
+            }
+            EvalError::NoHistory(n) => write!(f, "EvalError: no result ${n} in history"),
+            EvalError::UndefinedFunction(name, similar) => {
+                write!(f, "EvalError: undefined function '{name}'")?;
+                write_suggestion(f, similar)
+            }
+            EvalError::WrongArity(name, expected, found) => write!(
+                f,
+                "EvalError: function '{name}' takes {expected} argument(s) but {found} were given"
+            ),
+            EvalError::RecursionLimit => {
+                write!(f, "EvalError: maximum function call depth exceeded")
+            }
+            EvalError::NotList => write!(f, "EvalError: expected a list"),
+            EvalError::EmptyList => write!(f, "EvalError: expected a non-empty list"),
+            EvalError::IndexOutOfRange(i, len) => write!(
+                f,
+                "EvalError: index {i} out of range for list of length {len}"
+            ),
+            EvalError::LengthMismatch(a, b) => {
+                write!(f, "EvalError: list lengths differ ({a} and {b})")
+            }
+            EvalError::StackUnderflow => write!(f, "EvalError: too few values on the stack"),
+        }
+    }
+}
+
+impl Error for EvalError {}
+
+/// 似た名前がある場合は、`(did you mean 'x'?)`と表示する
+fn write_suggestion(f: &mut fmt::Formatter<'_>, similar: &Option<String>) -> fmt::Result {
+    match similar {
+        Some(name) => write!(f, " (did you mean '{name}'?)"),
+        None => Ok(()),
+    }
+}
+
+/// 命令列
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Push(Value),         // 値を積む
+    Load(String),        // 変数の値を積む
+    Store(String),       // スタックの先頭の値を変数に代入する
+    History(usize),      // n番目の評価結果を積む
+    Unit(String),        // 単位の大きさを表す量を積む
+    Call(String, usize), // 関数名、引数の数
+    List(usize),         // 要素数
+    Index,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Neg,
+    Fact,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    BitNot,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Not,
+    Jump(usize),
+    JumpIfTrue(usize),  // 真偽値を取り出し、真の場合に飛ぶ
+    JumpIfFalse(usize), // 真偽値を取り出し、偽の場合に飛ぶ
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Push(v) => write!(f, "push {v}"),
+            Instruction::Load(name) => write!(f, "load {name}"),
+            Instruction::Store(name) => write!(f, "store {name}"),
+            Instruction::History(n) => write!(f, "history {n}"),
+            Instruction::Unit(name) => write!(f, "unit {name}"),
+            Instruction::Call(name, argc) => write!(f, "call {name}, {argc}"),
+            Instruction::List(len) => write!(f, "list {len}"),
+            Instruction::Index => write!(f, "index"),
+            Instruction::Add => write!(f, "add"),
+            Instruction::Sub => write!(f, "sub"),
+            Instruction::Mul => write!(f, "mul"),
+            Instruction::Div => write!(f, "div"),
+            Instruction::Rem => write!(f, "rem"),
+            Instruction::Pow => write!(f, "pow"),
+            Instruction::Neg => write!(f, "neg"),
+            Instruction::Fact => write!(f, "fact"),
+            Instruction::BitAnd => write!(f, "bitand"),
+            Instruction::BitOr => write!(f, "bitor"),
+            Instruction::BitXor => write!(f, "bitxor"),
+            Instruction::Shl => write!(f, "shl"),
+            Instruction::Shr => write!(f, "shr"),
+            Instruction::BitNot => write!(f, "bitnot"),
+            Instruction::Eq => write!(f, "eq"),
+            Instruction::Ne => write!(f, "ne"),
+            Instruction::Lt => write!(f, "lt"),
+            Instruction::Le => write!(f, "le"),
+            Instruction::Gt => write!(f, "gt"),
+            Instruction::Ge => write!(f, "ge"),
+            Instruction::Not => write!(f, "not"),
+            Instruction::Jump(addr) => write!(f, "jump {:>04}", addr),
+            Instruction::JumpIfTrue(addr) => write!(f, "jumpiftrue {:>04}", addr),
+            Instruction::JumpIfFalse(addr) => write!(f, "jumpiffalse {:>04}", addr),
+        }
+    }
+}
+
+/// 1行の入力を実行
+///
+/// 式の場合はその値を返し、関数定義の場合は関数をenvに登録してNoneを返す。
+/// 関数本体は定義時に命令列へ変換する。
+pub fn exec(s: &Stmt, env: &mut Env) -> Result<Option<Value>, EvalError> {
+    match s {
+        Stmt::Expr(e) => eval(e, env).map(Some),
+        Stmt::FnDef(name, params, body) => {
+            let code = compile(body, env.settings.optimize);
+            let f = Function {
+                params: params.clone(),
+                body: body.clone(),
+                #[cfg(feature = "jit")]
+                native: jit::compile(params, &code),
+                code,
+            };
+            env.define_fn(name, f);
+            Ok(None)
+        }
+    }
+}
+
+/// 式を命令列に変換
+///
+/// optimizeがtrueの場合は、定数の畳み込みなどの最適化を行ってから変換する。
+#[instrument(level = "debug", skip(e), fields(expr = %e))]
+pub fn compile(e: &Expr, optimize: bool) -> Vec<Instruction> {
+    if optimize {
+        codegen::gen_code(&optimizer::optimize(e))
+    } else {
+        codegen::gen_code(e)
+    }
+}
+
+/// 式を評価
+///
+/// 式を命令列に変換し、スタックマシンで実行する。
+pub fn eval(e: &Expr, env: &mut Env) -> Result<Value, EvalError> {
+    let code = compile(e, env.settings.optimize);
+    vm::run(&code, env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::Interval;
+    use crate::parser::{parse, Notation, DEFAULT_MAX_DEPTH};
+    use crate::rng::Rng;
+    use num_complex::Complex64;
+
+    /// 入力行を実行し、式の値を返す
+    fn run(c: &str, notation: Notation, env: &mut Env) -> Result<Value, EvalError> {
+        let stmt = parse(c, notation, DEFAULT_MAX_DEPTH).unwrap();
+        exec(&stmt, env).map(|v| v.expect("statement is an expression"))
+    }
+
+    fn calc(c: &str) -> Result<Value, EvalError> {
+        run(c, Notation::Infix, &mut Env::new())
+    }
+
+    fn calc_prefix(c: &str) -> Result<Value, EvalError> {
+        run(c, Notation::Prefix, &mut Env::new())
+    }
+
+    #[test]
+    fn test_div() {
+        assert_eq!(calc("7 / 2"), Ok(Value::Int(3)));
+        assert_eq!(calc("1 + 6 / 3 * 2"), Ok(Value::Int(5)));
+        assert_eq!(calc("1 / 0"), Err(EvalError::DivideByZero));
+        assert_eq!(calc("1 / 0 + 1"), Err(EvalError::DivideByZero));
+    }
+
+    #[test]
+    fn test_pow() {
+        assert_eq!(calc("2 ^ 10"), Ok(Value::Int(1024)));
+        assert_eq!(calc("2 ^ 3 ^ 2"), Ok(Value::Int(512)));
+        assert_eq!(calc("2 ^ 0"), Ok(Value::Int(1)));
+        assert_eq!(calc("1 ^ 10000000000"), Ok(Value::Int(1)));
+        assert_eq!(calc("2 ^ 63"), Err(EvalError::Overflow));
+        assert_eq!(calc_prefix("^ 2 - 0 1"), Err(EvalError::NegativeExponent));
+    }
+
+    #[test]
+    fn test_float() {
+        assert_eq!(calc("1.5 + 2"), Ok(Value::Float(3.5)));
+        assert_eq!(calc("7 / 2.0"), Ok(Value::Float(3.5)));
+        assert_eq!(calc("1e-3 * 1000"), Ok(Value::Float(1.0)));
+        assert_eq!(calc("2 ^ -1.0"), Ok(Value::Float(0.5)));
+        assert_eq!(calc("-2.5E1"), Ok(Value::Float(-25.0)));
+        assert_eq!(calc("5.5 % 2"), Ok(Value::Float(1.5)));
+        assert_eq!(calc("1.0 / 0"), Err(EvalError::DivideByZero));
+        assert_eq!(calc_prefix("* -1.5 2"), Ok(Value::Float(-3.0)));
+    }
+
+    #[test]
+    fn test_bitwise() {
+        assert_eq!(calc("12 & 10"), Ok(Value::Int(8)));
+        assert_eq!(calc("12 | 10"), Ok(Value::Int(14)));
+        assert_eq!(calc("12 xor 10"), Ok(Value::Int(6)));
+        assert_eq!(calc("~0"), Ok(Value::Int(-1)));
+        assert_eq!(calc("1 << 63"), Ok(Value::Int(i64::MIN)));
+        assert_eq!(calc("-16 >> 2"), Ok(Value::Int(-4)));
+        assert_eq!(calc("0xFF & ~0x0F"), Ok(Value::Int(0xF0)));
+
+        assert_eq!(calc("1 << 64"), Err(EvalError::InvalidShift));
+        assert_eq!(calc("1 >> -1"), Err(EvalError::InvalidShift));
+        assert_eq!(calc("1.0 & 1"), Err(EvalError::NotInteger));
+        assert_eq!(calc("~1.5"), Err(EvalError::NotInteger));
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(calc("1 + 1 == 2"), Ok(Value::Bool(true)));
+        assert_eq!(calc("1 != 1.0"), Ok(Value::Bool(false)));
+        assert_eq!(calc("1 < 2 && 2 <= 2"), Ok(Value::Bool(true)));
+        assert_eq!(calc("3 > 4 || 1.5 >= 2"), Ok(Value::Bool(false)));
+        assert_eq!(calc("!(1 > 2)"), Ok(Value::Bool(true)));
+        assert_eq!(calc("true == !false"), Ok(Value::Bool(true)));
+
+        // 短絡評価により、右辺は評価されない
+        assert_eq!(calc("false && 1 / 0 == 0"), Ok(Value::Bool(false)));
+        assert_eq!(calc("true || 1 / 0 == 0"), Ok(Value::Bool(true)));
+
+        assert_eq!(calc("true + 1"), Err(EvalError::NotNumber));
+        assert_eq!(calc("true < false"), Err(EvalError::NotNumber));
+        assert_eq!(calc("1 && true"), Err(EvalError::NotBool));
+        assert_eq!(calc("!1"), Err(EvalError::NotBool));
+    }
+
+    #[test]
+    fn test_if() {
+        assert_eq!(calc("if 1 < 2 then 10 else 20"), Ok(Value::Int(10)));
+        assert_eq!(calc("if false then 1 else 2 + 3"), Ok(Value::Int(5)));
+        assert_eq!(calc("1 + if true then 2 else 3 * 4"), Ok(Value::Int(3)));
+        assert_eq!(
+            calc_prefix("if > 1 2 then 1 else if true then 2 else 3"),
+            Ok(Value::Int(2))
+        );
+
+        // 選ばれなかった式は評価されない
+        assert_eq!(calc("if true then 1 else 1 / 0"), Ok(Value::Int(1)));
+        assert_eq!(calc("if 1 then 2 else 3"), Err(EvalError::NotBool));
+    }
+
+    #[test]
+    fn test_var() {
+        let mut env = Env::new();
+        let mut run = |c| run(c, Notation::Infix, &mut env);
+
+        assert_eq!(run("x = 3"), Ok(Value::Int(3)));
+        assert_eq!(run("y = x * 2"), Ok(Value::Int(6)));
+        assert_eq!(run("x + y"), Ok(Value::Int(9)));
+        assert_eq!(run("x = x + 1"), Ok(Value::Int(4)));
+        assert_eq!(run("x"), Ok(Value::Int(4)));
+        assert_eq!(
+            run("z + 1"),
+            Err(EvalError::UndefinedVariable("z".to_string(), None))
+        );
+    }
+
+    #[test]
+    fn test_similar_name() {
+        let mut env = Env::new();
+        let stmt = parse(
+            "fn area(width, height) = width * heigt",
+            Notation::Infix,
+            64,
+        )
+        .unwrap();
+        exec(&stmt, &mut env).unwrap();
+        let mut run = |c| run(c, Notation::Infix, &mut env).map_err(|e| e.to_string());
+
+        run("total = 1").unwrap();
+        assert_eq!(
+            run("totl + 1"),
+            Err("EvalError: undefined variable 'totl' (did you mean 'total'?)".to_string())
+        );
+        assert_eq!(
+            run("area(2, 3)"),
+            Err("EvalError: undefined variable 'heigt' (did you mean 'height'?)".to_string())
+        );
+        assert_eq!(
+            run("sqr(2) + aera(1, 2)"),
+            Err("EvalError: undefined function 'sqr' (did you mean 'sqrt'?)".to_string())
+        );
+        assert_eq!(
+            run("aera(1, 2)"),
+            Err("EvalError: undefined function 'aera' (did you mean 'area'?)".to_string())
+        );
+        // 似た名前がない場合は示さない
+        assert_eq!(
+            run("foo(1) + p"),
+            Err("EvalError: undefined function 'foo'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let mut env = Env::new();
+        let run = |c, env: &mut Env| {
+            let v = run(c, Notation::Infix, env)?;
+            env.push_result(v.clone());
+            Ok(v)
+        };
+
+        assert_eq!(
+            run("ans", &mut env),
+            Err(EvalError::UndefinedVariable("ans".to_string(), None))
+        );
+        assert_eq!(run("1 + 2", &mut env), Ok(Value::Int(3)));
+        assert_eq!(run("ans * 10", &mut env), Ok(Value::Int(30)));
+        assert_eq!(run("$1 + $2", &mut env), Ok(Value::Int(33)));
+        assert_eq!(
+            env.history(),
+            [Value::Int(3), Value::Int(30), Value::Int(33)]
+        );
+
+        assert_eq!(run("$4", &mut env), Err(EvalError::NoHistory(4)));
+        assert_eq!(run("$0", &mut env), Err(EvalError::NoHistory(0)));
+        assert_eq!(
+            run("ans = 1", &mut env),
+            Err(EvalError::ReadOnlyVariable("ans".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_builtin() {
+        assert_eq!(calc("abs(-3) + abs(-1.5)"), Ok(Value::Float(4.5)));
+        assert_eq!(calc("min(3, 2.5)"), Ok(Value::Float(2.5)));
+        assert_eq!(calc("max(3, 2.5)"), Ok(Value::Int(3)));
+        assert_eq!(calc("gcd(12, -18)"), Ok(Value::Int(6)));
+        assert_eq!(calc("lcm(4, 6)"), Ok(Value::Int(12)));
+        assert_eq!(calc("pow(2, 10)"), Ok(Value::Int(1024)));
+        assert_eq!(calc("sqrt(16)"), Ok(Value::Float(4.0)));
+        assert_eq!(calc("sin(0) + cos(0) + ln(1)"), Ok(Value::Float(1.0)));
+        assert_eq!(calc("max(min(1, 2), 3 - 1) * 2"), Ok(Value::Int(4)));
+        assert_eq!(calc_prefix("max(+ 1 2, 4)"), Ok(Value::Int(4)));
+
+        assert_eq!(
+            calc("foo(1)"),
+            Err(EvalError::UndefinedFunction("foo".to_string(), None))
+        );
+        assert_eq!(
+            calc("max(1)"),
+            Err(EvalError::WrongArity("max".to_string(), 2, 1))
+        );
+        assert_eq!(calc("gcd(1.5, 2)"), Err(EvalError::NotInteger));
+        assert_eq!(
+            calc("abs(-9223372036854775807 - 1)"),
+            Err(EvalError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(calc("sum([1, 2, 3, 4])"), Ok(Value::Int(10)));
+        assert_eq!(calc("sum([])"), Ok(Value::Int(0)));
+        assert_eq!(calc("mean([1, 2, 3, 4])"), Ok(Value::Float(2.5)));
+        assert_eq!(calc("median([3, 1, 2])"), Ok(Value::Int(2)));
+        assert_eq!(calc("median([4, 1, 3, 2])"), Ok(Value::Float(2.5)));
+        assert_eq!(
+            calc("stddev([2, 4, 4, 4, 5, 5, 7, 9])"),
+            Ok(Value::Float(2.0))
+        );
+        assert_eq!(calc("minl([3, -1.5, 2])"), Ok(Value::Float(-1.5)));
+        assert_eq!(calc("maxl([3, -1.5, 2])"), Ok(Value::Int(3)));
+        assert_eq!(
+            calc("mean([1 m, 3 m])").map(|v| v.to_string()),
+            Ok("2.0 m".to_string())
+        );
+
+        assert_eq!(calc("mean([])"), Err(EvalError::EmptyList));
+        assert_eq!(calc("maxl([])"), Err(EvalError::EmptyList));
+        assert_eq!(calc("sum(3)"), Err(EvalError::NotList));
+        assert_eq!(calc("median([1, true])"), Err(EvalError::NotNumber));
+    }
+
+    #[test]
+    fn test_rand() {
+        // 同じシードからは同じ乱数列となる
+        let seeded = |seed| {
+            let mut env = Env::new();
+            env.rng = Rng::new(seed);
+            (0..5)
+                .map(|_| run("[rand(), rand_int(1, 6)]", Notation::Infix, &mut env))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(seeded(42), seeded(42));
+        assert_ne!(seeded(42), seeded(43));
+
+        for _ in 0..100 {
+            match calc("rand_int(-2, 2)") {
+                Ok(Value::Int(n)) => assert!((-2..=2).contains(&n)),
+                v => panic!("unexpected result: {v:?}"),
+            }
+        }
+        assert_eq!(calc("rand_int(3, 3)"), Ok(Value::Int(3)));
+        assert_eq!(calc("rand_int(2, 1)"), Err(EvalError::InvalidRange));
+        assert_eq!(calc("rand_int(1.5, 2)"), Err(EvalError::NotInteger));
+    }
+
+    #[test]
+    fn test_fn_def() {
+        let mut env = Env::new();
+        let mut exec = |c| {
+            exec(
+                &parse(c, Notation::Infix, DEFAULT_MAX_DEPTH).unwrap(),
+                &mut env,
+            )
+        };
+
+        assert_eq!(exec("fn f(x, y) = x * x + y"), Ok(None));
+        assert_eq!(exec("f(3, 1)"), Ok(Some(Value::Int(10))));
+
+        // 引数は大域変数より優先される
+        assert_eq!(exec("x = 100"), Ok(Some(Value::Int(100))));
+        assert_eq!(exec("f(2, x)"), Ok(Some(Value::Int(104))));
+        assert_eq!(exec("x"), Ok(Some(Value::Int(100))));
+
+        // 再帰呼び出し
+        assert_eq!(
+            exec("fn fact(n) = if n <= 1 then 1 else n * fact(n - 1)"),
+            Ok(None)
+        );
+        assert_eq!(exec("fact(10)"), Ok(Some(Value::Int(3628800))));
+        assert_eq!(exec("fn loop(n) = loop(n + 1)"), Ok(None));
+        assert_eq!(exec("loop(0)"), Err(EvalError::RecursionLimit));
+
+        // エラーの後も呼び出しできる
+        assert_eq!(exec("f(1, 1)"), Ok(Some(Value::Int(2))));
+        assert_eq!(
+            exec("f(1)"),
+            Err(EvalError::WrongArity("f".to_string(), 2, 1))
+        );
+
+        // ユーザ定義関数は組み込み関数より優先される
+        assert_eq!(exec("fn abs(x) = 42"), Ok(None));
+        assert_eq!(exec("abs(-1)"), Ok(Some(Value::Int(42))));
+    }
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(calc("pi"), Ok(Value::Float(std::f64::consts::PI)));
+        assert_eq!(calc("tau / 2 == pi"), Ok(Value::Bool(true)));
+        assert_eq!(calc("ln(e)"), Ok(Value::Float(1.0)));
+        assert_eq!(calc("i64_max"), Ok(Value::Int(i64::MAX)));
+        assert_eq!(
+            calc("pi = 3"),
+            Err(EvalError::ReadOnlyVariable("pi".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_complex() {
+        let c = |re, im| Value::Complex(Complex64::new(re, im));
+        assert_eq!(calc("3 + 4i"), Ok(c(3.0, 4.0)));
+        assert_eq!(calc("(3 + 4i) * (1 - 2i)"), Ok(c(11.0, -2.0)));
+        assert_eq!(calc("i ^ 2"), Ok(c(-1.0, 0.0)));
+        assert_eq!(calc("1 / (2 * i)"), Ok(c(0.0, -0.5)));
+        assert_eq!(calc("i * i == -1"), Ok(Value::Bool(true)));
+        assert_eq!(calc("abs(3 + 4i)"), Ok(Value::Float(5.0)));
+        assert_eq!(
+            calc("re(1 + 2i) + im(conj(1 + 2i))"),
+            Ok(Value::Float(-1.0))
+        );
+        assert_eq!(calc("sqrt(-1 + 0i)"), Ok(c(0.0, 1.0)));
+        assert_eq!(
+            calc("[1, 2] * i"),
+            Ok(Value::List(vec![c(0.0, 1.0), c(0.0, 2.0)]))
+        );
+        assert_eq!(calc_prefix("+ 3 -4i"), Ok(c(3.0, -4.0)));
+        assert_eq!(calc("i < 1"), Err(EvalError::NotReal));
+        assert_eq!(calc("i % 2"), Err(EvalError::NotReal));
+        assert_eq!(calc("i / 0"), Err(EvalError::DivideByZero));
+        assert_eq!(calc("i + true"), Err(EvalError::NotNumber));
+        assert_eq!(calc("1 - 2.5i").unwrap().to_string(), "1.0-2.5i");
+    }
+
+    #[test]
+    fn test_interval() {
+        let iv = |lo, hi| Value::Interval(Interval::new(lo, hi).unwrap());
+        assert_eq!(calc("[0.9 .. 1.1] * 2 + 1"), Ok(iv(2.8, 3.2)));
+        assert_eq!(calc("[1 .. 2] / [2 .. 4]"), Ok(iv(0.25, 1.0)));
+        assert_eq!(calc("[-2 .. 3] ^ 2"), Ok(iv(0.0, 9.0)));
+        assert_eq!(calc("sqrt([4 .. 9])"), Ok(iv(2.0, 3.0)));
+        assert_eq!(calc("mid([1 .. 2])"), Ok(Value::Float(1.5)));
+        assert_eq!(calc("[1 .. 2] < 3"), Ok(Value::Bool(true)));
+        assert_eq!(calc("[1 .. 3] < 2"), Err(EvalError::AmbiguousComparison));
+        assert_eq!(calc("[1 .. 2] / [-1 .. 1]"), Err(EvalError::DivideByZero));
+        assert_eq!(calc("[1 .. 2] % 2"), Err(EvalError::NotReal));
+        assert_eq!(calc("[1 .. 2] ^ 0.5"), Err(EvalError::NotInteger));
+        assert_eq!(calc("[2 .. 1]"), Err(EvalError::InvalidInterval));
+        assert_eq!(calc("[1 .. 2] + i"), Err(EvalError::NotNumber));
+        assert_eq!(calc("[1 .. 2]").unwrap().to_string(), "[1.0 .. 2.0]");
+
+        // 区間の設定では、数値を1点のみからなる区間として扱う
+        let mut env = Env::new();
+        env.settings.interval = true;
+        run("x = 2", Notation::Infix, &mut env).unwrap();
+        assert_eq!(run("x * 3", Notation::Infix, &mut env), Ok(iv(6.0, 6.0)));
+        assert_eq!(
+            run("[0.9 .. 1.1] * x", Notation::Infix, &mut env),
+            Ok(iv(1.8, 2.2))
+        );
+        assert_eq!(
+            run("[x .. x + 1]", Notation::Infix, &mut env),
+            Ok(iv(2.0, 3.0))
+        );
+        assert_eq!(
+            run("[[1 .. 2] .. 3]", Notation::Infix, &mut env),
+            Err(EvalError::NotReal)
+        );
+    }
+
+    #[test]
+    fn test_radix() {
+        use crate::value::Radix;
+        let show = |c, radix| calc(c).unwrap().to_string_radix(radix);
+        assert_eq!(show("0xFF + 1", Radix::Hex), "0x100");
+        assert_eq!(show("-10", Radix::Bin), "-0b1010");
+        assert_eq!(show("8", Radix::Oct), "0o10");
+        assert_eq!(show("i64_min", Radix::Hex), "-0x8000000000000000");
+        assert_eq!(show("[10, 2.5]", Radix::Hex), "[0xA, 2.5]");
+        assert_eq!(show("255", Radix::Dec), "255");
+    }
+
+    #[test]
+    fn test_number_format() {
+        let mut env = Env::new();
+        let mut show = |c, settings: &[(&str, &str)]| {
+            for (name, value) in settings {
+                env.settings.set(name, value).unwrap();
+            }
+            let v = run(c, Notation::Infix, &mut env).unwrap();
+            v.to_string_with(&env.settings.number_format())
+        };
+        assert_eq!(show("1 / 3.0", &[("precision", "3")]), "0.333");
+        assert_eq!(show("2 + 0.5i", &[]), "2.000+0.500i");
+        assert_eq!(show("[0.9 .. 1.1]", &[]), "[0.900 .. 1.100]");
+        assert_eq!(show("1234.5 + 0.25", &[("sci", "on")]), "1.235e3");
+        assert_eq!(show("90 km/h", &[("precision", "auto")]), "2.5e1 m/s");
+        assert_eq!(
+            show(
+                "[-1234567, 1e6 / 8]",
+                &[("sci", "off"), ("separator", "on")]
+            ),
+            "[-1,234,567, 125,000.0]"
+        );
+        assert_eq!(show("0xFFFFF", &[("radix", "hex")]), "0xFFFFF");
+        assert!(env.settings.set("precision", "-1").is_err());
+    }
+
+    #[test]
+    fn test_units() {
+        let show = |c| calc(c).map(|v| v.to_string());
+        assert_eq!(show("3 km + 200 m"), Ok("3.2 km".to_string()));
+        assert_eq!(show("90 km/h * 2 h"), Ok("180.0 km".to_string()));
+        assert_eq!(show("90 km/h"), Ok("25.0 m/s".to_string()));
+        assert_eq!(show("2 m * 3 m"), Ok("6.0 m^2".to_string()));
+        assert_eq!(show("(3 m) ^ 2"), Ok("9.0 m^2".to_string()));
+        assert_eq!(show("-(30 s) * 4"), Ok("-2.0 min".to_string()));
+        assert_eq!(show("1 km / 2 m"), Ok("500.0".to_string()));
+        assert_eq!(calc("1 km > 999 m"), Ok(Value::Bool(true)));
+        assert_eq!(calc("60 s == 1 min"), Ok(Value::Bool(true)));
+        assert_eq!(calc("1 m + 1 s"), Err(EvalError::IncompatibleUnits));
+        assert_eq!(calc("1 m + 1"), Err(EvalError::IncompatibleUnits));
+        assert_eq!(calc("1 m < 1 s"), Err(EvalError::IncompatibleUnits));
+        assert_eq!(calc("sqrt(4 m)"), Err(EvalError::IncompatibleUnits));
+        assert_eq!(calc("1 m + i"), Err(EvalError::NotReal));
+        assert_eq!(calc("2 ^ (1 m)"), Err(EvalError::IncompatibleUnits));
+        assert_eq!(
+            calc_prefix("+ 3km 200m").map(|v| v.to_string()),
+            Ok("3.2 km".to_string())
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert_eq!(calc("i64_max + 1"), Err(EvalError::Overflow));
+        assert_eq!(calc("i64_min - 1"), Err(EvalError::Overflow));
+        assert_eq!(calc("4294967296 * 4294967296"), Err(EvalError::Overflow));
+        assert_eq!(calc("[1, i64_max] + 1"), Err(EvalError::Overflow));
+        assert_eq!(calc("i64_max - 1 + 1"), Ok(Value::Int(i64::MAX)));
+
+        // i64の範囲を超える整数のリテラルもオーバーフローとなるが、i64::MINは記述できる
+        assert_eq!(calc("9223372036854775808"), Err(EvalError::Overflow));
+        assert_eq!(calc("-9223372036854775808"), Ok(Value::Int(i64::MIN)));
+        assert_eq!(calc("-0x8000000000000000"), Ok(Value::Int(i64::MIN)));
+
+        // 浮動小数点数は無限大となる
+        assert_eq!(calc("1e308 * 10"), Ok(Value::Float(f64::INFINITY)));
+    }
+
+    #[test]
+    fn test_bigint() {
+        let mut env = Env::new();
+        env.settings.set("bigint", "on").unwrap();
+        let calc = |c, env: &mut Env| run(c, Notation::Infix, env).map(|v| v.to_string());
+
+        assert_eq!(
+            calc("2 ^ 100", &mut env),
+            Ok("1267650600228229401496703205376".to_string())
+        );
+        assert_eq!(
+            calc("25!", &mut env),
+            Ok("15511210043330985984000000".to_string())
+        );
+        assert_eq!(
+            calc("x = i64_max + 1", &mut env),
+            Ok("9223372036854775808".to_string())
+        );
+        assert_eq!(calc("x - 1 == i64_max", &mut env), Ok("true".to_string()));
+        assert_eq!(
+            calc("123456789012345678901234567890 + 1", &mut env),
+            Ok("123456789012345678901234567891".to_string())
+        );
+        assert_eq!(
+            calc("-0x10000000000000000", &mut env),
+            Ok("-18446744073709551616".to_string())
+        );
+        assert_eq!(calc("-7 / 2", &mut env), Ok("-3".to_string()));
+        assert_eq!(calc("1 << 70 >> 69", &mut env), Ok("2".to_string()));
+        assert_eq!(calc("1 / 2.0", &mut env), Ok("0.5".to_string()));
+        assert_eq!(calc("gcd(12, 18)", &mut env), Ok("6".to_string()));
+        assert_eq!(calc("(-1) ^ (10 ^ 30 + 1)", &mut env), Ok("-1".to_string()));
+        assert_eq!(calc("2 ^ (10 ^ 30)", &mut env), Err(EvalError::Overflow));
+        assert_eq!(calc("1 / 0", &mut env), Err(EvalError::DivideByZero));
+
+        // 設定を無効にすると、i64の範囲でオーバーフローを検出する
+        env.settings.set("bigint", "off").unwrap();
+        assert_eq!(calc("2 ^ 100", &mut env), Err(EvalError::Overflow));
+        assert!(env.settings.set("bigint", "yes").is_err());
+    }
+
+    #[test]
+    fn test_rational() {
+        let mut env = Env::new();
+        env.settings.set("rational", "on").unwrap();
+        let calc = |c, env: &mut Env| run(c, Notation::Infix, env).map(|v| v.to_string());
+
+        assert_eq!(calc("1/3 + 1/6", &mut env), Ok("1/2".to_string()));
+        assert_eq!(calc("6 / 4", &mut env), Ok("3/2".to_string()));
+        assert_eq!(calc("-2 / 4", &mut env), Ok("-1/2".to_string()));
+        assert_eq!(calc("1/3 * 3", &mut env), Ok("1".to_string()));
+        assert_eq!(calc("(2/3) ^ -2", &mut env), Ok("9/4".to_string()));
+        assert_eq!(calc("7/2 % 1", &mut env), Ok("1/2".to_string()));
+        assert_eq!(calc("1/3 < 0.34", &mut env), Ok("true".to_string()));
+        assert_eq!(calc("1/2 == 0.5", &mut env), Ok("true".to_string()));
+        assert_eq!(calc("float(1/4)", &mut env), Ok("0.25".to_string()));
+        assert_eq!(calc("4 ^ (1/2)", &mut env), Ok("2.0".to_string()));
+        assert_eq!(calc("(4/2)!", &mut env), Ok("2".to_string()));
+        assert_eq!(calc("[1, 2] / 4", &mut env), Ok("[1/4, 1/2]".to_string()));
+        assert_eq!(calc("1 / 0", &mut env), Err(EvalError::DivideByZero));
+        assert_eq!(calc("0 ^ -1", &mut env), Err(EvalError::DivideByZero));
+        assert_eq!(calc("(1/2) & 1", &mut env), Err(EvalError::NotInteger));
+
+        // 設定を無効にすると、整数の除算は切り捨てとなる
+        env.settings.set("rational", "off").unwrap();
+        assert_eq!(calc("1/3 + 1/6", &mut env), Ok("0".to_string()));
+    }
+
+    #[test]
+    fn test_decimal() {
+        let mut env = Env::new();
+        env.settings.set("decimal", "on").unwrap();
+        let calc = |c, env: &mut Env| run(c, Notation::Infix, env).map(|v| v.to_string());
+
+        assert_eq!(calc("0.1 + 0.2", &mut env), Ok("0.3".to_string()));
+        assert_eq!(calc("0.1 + 0.2 == 0.3", &mut env), Ok("true".to_string()));
+        assert_eq!(calc("19.99 * 3", &mut env), Ok("59.97".to_string()));
+        assert_eq!(calc("1 / 4", &mut env), Ok("0.25".to_string()));
+        assert_eq!(
+            calc("1 / 3", &mut env),
+            Ok("0.33333333333333333333".to_string())
+        );
+        assert_eq!(calc("1.5 ^ 2", &mut env), Ok("2.25".to_string()));
+        assert_eq!(calc("7.5 % 2", &mut env), Ok("1.5".to_string()));
+        assert_eq!(calc("(2.0)!", &mut env), Ok("2".to_string()));
+        assert_eq!(calc("1 / 0.0", &mut env), Err(EvalError::DivideByZero));
+
+        // 除算の桁数と丸め方
+        env.settings.set("scale", "2").unwrap();
+        assert_eq!(calc("2 / 3", &mut env), Ok("0.67".to_string()));
+        assert_eq!(calc("0.125 / 1", &mut env), Ok("0.12".to_string()));
+        env.settings.set("rounding", "half-up").unwrap();
+        assert_eq!(calc("0.125 / 1", &mut env), Ok("0.13".to_string()));
+        env.settings.set("rounding", "floor").unwrap();
+        assert_eq!(calc("-2 / 3", &mut env), Ok("-0.67".to_string()));
+        assert!(env.settings.set("rounding", "nearest").is_err());
+        assert!(env.settings.set("scale", "-1").is_err());
+
+        // 設定を無効にすると、2進数の浮動小数点数で計算する
+        env.settings.set("decimal", "off").unwrap();
+        assert_eq!(
+            calc("0.1 + 0.2", &mut env),
+            Ok("0.30000000000000004".to_string())
+        );
+    }
+
+    #[test]
+    fn test_int_width() {
+        let mut env = Env::new();
+        env.settings.set("width", "i128").unwrap();
+        let calc = |c, env: &mut Env| run(c, Notation::Infix, env).map(|v| v.to_string());
+
+        assert_eq!(
+            calc("i64_max * 2", &mut env),
+            Ok("18446744073709551614".to_string())
+        );
+        assert_eq!(
+            calc("2 ^ 127 - 1 + 2 ^ 127", &mut env),
+            Err(EvalError::Overflow)
+        );
+        assert_eq!(
+            calc("-(2 ^ 126) - 2 ^ 126", &mut env),
+            Ok(i128::MIN.to_string())
+        );
+        assert_eq!(
+            calc("[1, 2 ^ 100] * 2 ^ 28", &mut env),
+            Err(EvalError::Overflow)
+        );
+        assert_eq!(calc("34!", &mut env), Err(EvalError::Overflow));
+        assert!(env.settings.set("width", "i32").is_err());
+
+        // i128の範囲の整数は、リテラルとしても記述できる
+        assert_eq!(
+            calc("170141183460469231731687303715884105727", &mut env),
+            Ok(i128::MAX.to_string())
+        );
+        assert_eq!(
+            calc("-170141183460469231731687303715884105728", &mut env),
+            Ok(i128::MIN.to_string())
+        );
+        assert_eq!(
+            calc("170141183460469231731687303715884105728", &mut env),
+            Err(EvalError::Overflow)
+        );
+
+        // 多倍長整数が有効な場合は、幅に関わらず範囲を超えられる
+        env.settings.set("bigint", "on").unwrap();
+        assert_eq!(
+            calc("2 ^ 128", &mut env),
+            Ok("340282366920938463463374607431768211456".to_string())
+        );
+    }
+
+    #[test]
+    fn test_overflow_mode() {
+        let mut env = Env::new();
+        let calc = |c, env: &mut Env| run(c, Notation::Infix, env).map(|v| v.to_string());
+
+        env.settings.set("overflow", "wrap").unwrap();
+        assert_eq!(calc("i64_max + 1", &mut env), Ok(i64::MIN.to_string()));
+        assert_eq!(calc("i64_min - 1", &mut env), Ok(i64::MAX.to_string()));
+        assert_eq!(
+            calc("3 ^ 41", &mut env),
+            Ok(3i64.wrapping_pow(41).to_string())
+        );
+        assert_eq!(calc("i64_min / -1", &mut env), Ok(i64::MIN.to_string()));
+        assert_eq!(
+            calc("(i64_max + 1) / 2", &mut env),
+            Ok((i64::MIN / 2).to_string())
+        );
+
+        env.settings.set("overflow", "saturate").unwrap();
+        assert_eq!(calc("i64_max + 1", &mut env), Ok(i64::MAX.to_string()));
+        assert_eq!(calc("i64_min * 3", &mut env), Ok(i64::MIN.to_string()));
+        assert_eq!(
+            calc("[30!, -5] * 2", &mut env),
+            Ok(format!("[{}, -10]", i64::MAX))
+        );
+
+        // i128の幅でも同様に動作する
+        env.settings.set("width", "i128").unwrap();
+        assert_eq!(calc("2 ^ 200", &mut env), Ok(i128::MAX.to_string()));
+        env.settings.set("overflow", "wrap").unwrap();
+        assert_eq!(calc("2 ^ 127", &mut env), Ok(i128::MIN.to_string()));
+
+        env.settings.set("overflow", "error").unwrap();
+        assert_eq!(calc("2 ^ 127", &mut env), Err(EvalError::Overflow));
+        assert!(env.settings.set("overflow", "clamp").is_err());
+    }
+
+    #[test]
+    fn test_optimize() {
+        let mut env = Env::new();
+        env.settings.set("optimize", "on").unwrap();
+        let calc = |c, env: &mut Env| run(c, Notation::Infix, env).map(|v| v.to_string());
+
+        assert_eq!(calc("x = 2 * 3 + 1", &mut env), Ok("7".to_string()));
+        assert_eq!(calc("x * (4 - 3) + 0", &mut env), Ok("7".to_string()));
+        assert_eq!(
+            calc("if 1 < 2 then 1 else 1 / 0", &mut env),
+            Ok("1".to_string())
+        );
+        assert_eq!(calc("i64_max + 1", &mut env), Err(EvalError::Overflow));
+
+        // 最適化しても設定に応じた値となる
+        env.settings.set("rational", "on").unwrap();
+        assert_eq!(calc("(1 + 6) / 2", &mut env), Ok("7/2".to_string()));
+        env.settings.set("rational", "off").unwrap();
+        env.settings.set("bigint", "on").unwrap();
+        assert_eq!(calc("1 << 70 >> 69", &mut env), Ok("2".to_string()));
+    }
+
+    #[test]
+    fn test_neg() {
+        assert_eq!(calc("-2 ^ 2"), Ok(Value::Int(-4)));
+        assert_eq!(calc("3 * -2"), Ok(Value::Int(-6)));
+        assert_eq!(calc("- - 3"), Ok(Value::Int(3)));
+        assert_eq!(calc_prefix("+ -5 3"), Ok(Value::Int(-2)));
+        assert_eq!(calc_prefix("- 5 3"), Ok(Value::Int(2)));
+        assert_eq!(calc_prefix("neg - 5 3"), Ok(Value::Int(-2)));
+        assert_eq!(
+            calc_prefix("neg -9223372036854775808"),
+            Err(EvalError::Overflow)
+        );
+        assert_eq!(
+            run("3 neg 2 *", Notation::Postfix, &mut Env::new()),
+            Ok(Value::Int(-6))
+        );
+    }
+
+    #[test]
+    fn test_fact() {
+        assert_eq!(calc("0!"), Ok(Value::Int(1)));
+        assert_eq!(calc("5! / 2"), Ok(Value::Int(60)));
+        assert_eq!(calc("20!"), Ok(Value::Int(2432902008176640000)));
+        assert_eq!(calc("21!"), Err(EvalError::Overflow));
+        assert_eq!(calc("(0 - 3)!"), Err(EvalError::NegativeFactorial));
+        assert_eq!(calc("2.0!"), Err(EvalError::NotInteger));
+        assert_eq!(calc("3! != 6"), Ok(Value::Bool(false)));
+        assert_eq!(calc_prefix("factorial 4"), Ok(Value::Int(24)));
+    }
+
+    #[test]
+    fn test_list() {
+        let list = |xs: &[i64]| Value::List(xs.iter().map(|&n| Value::Int(n)).collect());
+
+        assert_eq!(calc("[1, 2, 3] * 2"), Ok(list(&[2, 4, 6])));
+        assert_eq!(calc("10 - [1, 2]"), Ok(list(&[9, 8])));
+        assert_eq!(calc("[1, 2] + [10, 20]"), Ok(list(&[11, 22])));
+        assert_eq!(calc("-[1, 2]!"), Ok(list(&[-1, -2])));
+        assert_eq!(calc("[1, 2] + [1]"), Err(EvalError::LengthMismatch(2, 1)));
+        assert_eq!(calc("[1, 0] / 0"), Err(EvalError::DivideByZero));
+        assert_eq!(calc("[1, 2] == [1, 2.0]"), Ok(Value::Bool(true)));
+        assert_eq!(
+            calc("[[1], 2] * 3"),
+            Ok(Value::List(vec![list(&[3]), Value::Int(6)]))
+        );
+
+        // 要素の参照
+        let mut env = Env::new();
+        run("xs = [10, 20, 30]", Notation::Infix, &mut env).unwrap();
+        assert_eq!(run("xs[1]", Notation::Infix, &mut env), Ok(Value::Int(20)));
+        assert_eq!(run("xs[-1]", Notation::Infix, &mut env), Ok(Value::Int(30)));
+        assert_eq!(
+            run("xs[3]", Notation::Infix, &mut env),
+            Err(EvalError::IndexOutOfRange(3, 3))
+        );
+        assert_eq!(run("len(xs)", Notation::Infix, &mut env), Ok(Value::Int(3)));
+        assert_eq!(
+            run("1[0]", Notation::Infix, &mut env),
+            Err(EvalError::NotList)
+        );
+        assert_eq!(
+            run("[1, 2 3 +] 2 *", Notation::Postfix, &mut env),
+            Ok(list(&[2, 10]))
+        );
+    }
+
+    #[test]
+    fn test_rem() {
+        assert_eq!(calc("7 % 3"), Ok(Value::Int(1)));
+        assert_eq!(calc("1 + 7 % 3 * 2"), Ok(Value::Int(3)));
+        assert_eq!(calc("7 % 0"), Err(EvalError::DivideByZero));
+
+        // 剰余の符号は被除数に従う
+        assert_eq!(calc_prefix("% - 0 7 3"), Ok(Value::Int(-1)));
+        assert_eq!(calc_prefix("% 7 - 0 3"), Ok(Value::Int(1)));
+        assert_eq!(calc_prefix("% - 0 7 - 0 3"), Ok(Value::Int(-1)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        for c in [
+            "fn f(x) = x * 2",
+            "-(1 + 2.5) ^ 3",
+            "[max(1, x), 2 + 3i, [1 .. 2], 3 km]",
+        ] {
+            let stmt = parse(c, Notation::Infix, DEFAULT_MAX_DEPTH).unwrap();
+            let json = serde_json::to_string(&stmt).unwrap();
+            assert_eq!(serde_json::from_str::<Stmt>(&json).unwrap(), stmt, "{json}");
+        }
+
+        let mut env = Env::new();
+        env.set("x", Value::Int(5)).unwrap();
+        env.settings.set("decimal", "on").unwrap();
+        let v = run(
+            "[max(1, x), 0.1, 2 + 3i, [1 .. 2], 3 km]",
+            Notation::Infix,
+            &mut env,
+        )
+        .unwrap();
+        let json = serde_json::to_string(&v).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), v, "{json}");
+    }
+}
@@ -0,0 +1,223 @@
+//! 抽象構文木から命令列を生成する
+use super::Instruction;
+use crate::{parser::Expr, value::Value};
+
+/// コード生成器
+#[derive(Default, Debug)]
+struct Generator {
+    insts: Vec<Instruction>,
+}
+
+/// 式を評価する命令列を生成する関数
+///
+/// 命令列を実行し終えると、スタックには式の値が1つだけ残る。
+pub fn gen_code(e: &Expr) -> Vec<Instruction> {
+    let mut generator = Generator::default();
+    generator.gen_expr(e);
+    generator.insts
+}
+
+/// コード生成器のメソッド定義
+impl Generator {
+    /// 式をパターン分けし、コード生成を行う関数
+    fn gen_expr(&mut self, e: &Expr) {
+        match e {
+            Expr::Num(n) => self.insts.push(Instruction::Push(Value::Int(*n))),
+            Expr::BigNum(n) => self.insts.push(Instruction::Push(Value::Big(n.clone()))),
+            Expr::Float(x) => self.insts.push(Instruction::Push(Value::Float(*x))),
+            Expr::Bool(b) => self.insts.push(Instruction::Push(Value::Bool(*b))),
+            Expr::Var(name) => self.insts.push(Instruction::Load(name.clone())),
+            Expr::Assign(name, a) => {
+                self.gen_expr(a);
+                self.insts.push(Instruction::Store(name.clone()));
+            }
+            Expr::History(n) => self.insts.push(Instruction::History(*n)),
+            Expr::Unit(name) => self.insts.push(Instruction::Unit(name.clone())),
+            Expr::Call(name, args) => {
+                for a in args {
+                    self.gen_expr(a);
+                }
+                self.insts.push(Instruction::Call(name.clone(), args.len()));
+            }
+            Expr::List(es) => {
+                for e in es {
+                    self.gen_expr(e);
+                }
+                self.insts.push(Instruction::List(es.len()));
+            }
+            Expr::Index(a, i) => self.gen_binary(a, i, Instruction::Index),
+            Expr::Add(a, b) => self.gen_binary(a, b, Instruction::Add),
+            Expr::Sub(a, b) => self.gen_binary(a, b, Instruction::Sub),
+            Expr::Mul(a, b) => self.gen_binary(a, b, Instruction::Mul),
+            Expr::Div(a, b) => self.gen_binary(a, b, Instruction::Div),
+            Expr::Rem(a, b) => self.gen_binary(a, b, Instruction::Rem),
+            Expr::Pow(a, b) => self.gen_binary(a, b, Instruction::Pow),
+            Expr::Neg(a) => self.gen_unary(a, Instruction::Neg),
+            Expr::Fact(a) => self.gen_unary(a, Instruction::Fact),
+            Expr::BitAnd(a, b) => self.gen_binary(a, b, Instruction::BitAnd),
+            Expr::BitOr(a, b) => self.gen_binary(a, b, Instruction::BitOr),
+            Expr::BitXor(a, b) => self.gen_binary(a, b, Instruction::BitXor),
+            Expr::Shl(a, b) => self.gen_binary(a, b, Instruction::Shl),
+            Expr::Shr(a, b) => self.gen_binary(a, b, Instruction::Shr),
+            Expr::BitNot(a) => self.gen_unary(a, Instruction::BitNot),
+            Expr::Eq(a, b) => self.gen_binary(a, b, Instruction::Eq),
+            Expr::Ne(a, b) => self.gen_binary(a, b, Instruction::Ne),
+            Expr::Lt(a, b) => self.gen_binary(a, b, Instruction::Lt),
+            Expr::Le(a, b) => self.gen_binary(a, b, Instruction::Le),
+            Expr::Gt(a, b) => self.gen_binary(a, b, Instruction::Gt),
+            Expr::Ge(a, b) => self.gen_binary(a, b, Instruction::Ge),
+            Expr::And(a, b) => self.gen_logic(a, b, false),
+            Expr::Or(a, b) => self.gen_logic(a, b, true),
+            Expr::Not(a) => self.gen_unary(a, Instruction::Not),
+            Expr::If(cond, then, els) => self.gen_if(cond, then, els),
+        }
+    }
+
+    /// 単項演算のコード生成を行う関数
+    fn gen_unary(&mut self, a: &Expr, inst: Instruction) {
+        self.gen_expr(a);
+        self.insts.push(inst);
+    }
+
+    /// 二項演算のコード生成を行う関数
+    ///
+    /// 左辺、右辺の順に評価し、その値に演算を適用する。
+    fn gen_binary(&mut self, a: &Expr, b: &Expr, inst: Instruction) {
+        self.gen_expr(a);
+        self.gen_expr(b);
+        self.insts.push(inst);
+    }
+
+    /// 論理積（shortがtrueの場合は論理和）のコード生成を行う関数
+    ///
+    /// 以下のような、短絡評価するコードを生成
+    ///
+    /// ```text
+    ///     aのコード
+    ///     jumpiffalse L1   ; 論理和の場合はjumpiftrue
+    ///     bのコード
+    ///     jumpiffalse L1
+    ///     push true        ; 論理和の場合はfalse
+    ///     jump L2
+    /// L1: push false       ; 論理和の場合はtrue
+    /// L2:
+    /// ```
+    fn gen_logic(&mut self, a: &Expr, b: &Expr, short: bool) {
+        let branch = |addr| {
+            if short {
+                Instruction::JumpIfTrue(addr)
+            } else {
+                Instruction::JumpIfFalse(addr)
+            }
+        };
+
+        self.gen_expr(a);
+        let branch1 = self.insts.len();
+        self.insts.push(branch(0)); // 飛び先は後で設定
+
+        self.gen_expr(b);
+        let branch2 = self.insts.len();
+        self.insts.push(branch(0));
+
+        self.insts.push(Instruction::Push(Value::Bool(!short)));
+        let jump = self.insts.len();
+        self.insts.push(Instruction::Jump(0));
+
+        let l1 = self.insts.len();
+        self.insts.push(Instruction::Push(Value::Bool(short)));
+        let l2 = self.insts.len();
+
+        self.insts[branch1] = branch(l1);
+        self.insts[branch2] = branch(l1);
+        self.insts[jump] = Instruction::Jump(l2);
+    }
+
+    /// if式のコード生成を行う関数
+    ///
+    /// 以下のようなコードを生成
+    ///
+    /// ```text
+    ///     condのコード
+    ///     jumpiffalse L1
+    ///     thenのコード
+    ///     jump L2
+    /// L1: elsのコード
+    /// L2:
+    /// ```
+    fn gen_if(&mut self, cond: &Expr, then: &Expr, els: &Expr) {
+        self.gen_expr(cond);
+        let branch = self.insts.len();
+        self.insts.push(Instruction::JumpIfFalse(0)); // 飛び先は後で設定
+
+        self.gen_expr(then);
+        let jump = self.insts.len();
+        self.insts.push(Instruction::Jump(0));
+
+        let l1 = self.insts.len();
+        self.gen_expr(els);
+        let l2 = self.insts.len();
+
+        self.insts[branch] = Instruction::JumpIfFalse(l1);
+        self.insts[jump] = Instruction::Jump(l2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, Notation, Stmt, DEFAULT_MAX_DEPTH};
+
+    fn gen(c: &str) -> Vec<Instruction> {
+        match parse(c, Notation::Infix, DEFAULT_MAX_DEPTH).unwrap() {
+            Stmt::Expr(e) => gen_code(&e),
+            s => panic!("not an expression: {s:?}"),
+        }
+    }
+
+    #[test]
+    fn test_code_gen() {
+        assert_eq!(
+            gen("x = 1 + y * 2"),
+            vec![
+                Instruction::Push(Value::Int(1)),
+                Instruction::Load("y".to_string()),
+                Instruction::Push(Value::Int(2)),
+                Instruction::Mul,
+                Instruction::Add,
+                Instruction::Store("x".to_string()),
+            ]
+        );
+        assert_eq!(
+            gen("max(-$1, [2])"),
+            vec![
+                Instruction::History(1),
+                Instruction::Neg,
+                Instruction::Push(Value::Int(2)),
+                Instruction::List(1),
+                Instruction::Call("max".to_string(), 2),
+            ]
+        );
+        assert_eq!(
+            gen("if a then 1 else 2"),
+            vec![
+                Instruction::Load("a".to_string()),
+                Instruction::JumpIfFalse(4),
+                Instruction::Push(Value::Int(1)),
+                Instruction::Jump(5),
+                Instruction::Push(Value::Int(2)),
+            ]
+        );
+        assert_eq!(
+            gen("a || b"),
+            vec![
+                Instruction::Load("a".to_string()),
+                Instruction::JumpIfTrue(6),
+                Instruction::Load("b".to_string()),
+                Instruction::JumpIfTrue(6),
+                Instruction::Push(Value::Bool(false)),
+                Instruction::Jump(7),
+                Instruction::Push(Value::Bool(true)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,481 @@
+//! ユーザ定義関数の命令列を、craneliftでネイティブコードにJITコンパイルする
+//!
+//! 引数がすべて整数で、途中の値が整数と真偽値のみとなる関数を対象とする。
+//! 変数への代入、関数呼び出し、べき乗、リストなどを含む命令列はコンパイルせず、
+//! インタプリタで実行する。
+//!
+//! ネイティブコードは、オーバーフローや0による除算を検出すると失敗を返す。
+//! その場合、呼び出し側はインタプリタで評価し直し、インタプリタと同じエラーとする。
+use super::Instruction;
+use crate::value::Value;
+use cranelift_codegen::{
+    ir::{self, condcodes::IntCC, types, AbiParam, Block, InstBuilder, MemFlags},
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+use std::{fmt, mem};
+
+/// 命令列中の値の型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Int,
+    Bool,
+}
+
+/// ネイティブコードの関数の型
+///
+/// 引数の配列と、成功したかを書き込む領域を受け取り、結果を返す。
+type Entry = extern "C" fn(*const i64, *mut u8) -> i64;
+
+/// JITコンパイルした関数
+pub struct NativeFn {
+    module: Option<JITModule>, // ネイティブコードを保持するモジュール。dropで解放する
+    entry: Entry,
+    arity: usize,
+    result: Type,
+}
+
+impl fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NativeFn({} -> {:?})", self.arity, self.result)
+    }
+}
+
+impl Drop for NativeFn {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // entryはこの後使われないため、解放しても安全
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+impl NativeFn {
+    /// 整数の引数argsで呼び出す
+    ///
+    /// オーバーフローなどで失敗した場合はNone。
+    pub fn call(&self, args: &[i64]) -> Option<Value> {
+        if args.len() != self.arity {
+            return None;
+        }
+
+        let mut ok = 0;
+        let result = (self.entry)(args.as_ptr(), &mut ok);
+        if ok == 0 {
+            return None;
+        }
+
+        Some(match self.result {
+            Type::Int => Value::Int(result),
+            Type::Bool => Value::Bool(result != 0),
+        })
+    }
+}
+
+/// 引数paramsを取る関数の命令列codeをコンパイルする
+///
+/// 対象外の命令を含む場合や、コンパイルに失敗した場合はNone。
+pub fn compile(params: &[String], code: &[Instruction]) -> Option<NativeFn> {
+    let (states, result) = infer(params, code)?;
+
+    let isa = cranelift_native::builder()
+        .ok()?
+        .finish(settings::Flags::new({
+            let mut flags = settings::builder();
+            flags.set("opt_level", "speed").ok()?;
+            flags
+        }))
+        .ok()?;
+    let mut module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+
+    let mut ctx = module.make_context();
+    let ptr = module.target_config().pointer_type();
+    ctx.func.signature.params.push(AbiParam::new(ptr));
+    ctx.func.signature.params.push(AbiParam::new(ptr));
+    ctx.func.signature.returns.push(AbiParam::new(types::I64));
+
+    let mut fctx = FunctionBuilderContext::new();
+    let mut b = FunctionBuilder::new(&mut ctx.func, &mut fctx);
+    let mut generator = Generator::new(&mut b, params, &states);
+    generator.gen_code(code);
+    b.seal_all_blocks();
+    b.finalize();
+
+    let id = module
+        .declare_function("f", Linkage::Export, &ctx.func.signature)
+        .ok()?;
+    module.define_function(id, &mut ctx).ok()?;
+    module.clear_context(&mut ctx);
+    module.finalize_definitions().ok()?;
+
+    // 生成した関数のシグネチャはEntryと一致する
+    let entry = unsafe { mem::transmute::<*const u8, Entry>(module.get_finalized_function(id)) };
+    Some(NativeFn {
+        module: Some(module),
+        entry,
+        arity: params.len(),
+        result,
+    })
+}
+
+/// 各命令の実行前のスタックの型と、結果の型を求める
+///
+/// 飛び先は常に元の命令より後ろにあるため、命令列を先頭から1度走査すればよい。
+/// 到達しない命令のスタックはNoneとする。末尾の要素は命令列の終端のスタック。
+fn infer(params: &[String], code: &[Instruction]) -> Option<(Vec<Option<Vec<Type>>>, Type)> {
+    let mut states = vec![None; code.len() + 1];
+    states[0] = Some(Vec::new());
+
+    for (pc, inst) in code.iter().enumerate() {
+        let Some(mut stack) = states[pc].clone() else {
+            continue;
+        };
+
+        let pop = |stack: &mut Vec<Type>, ty| (stack.pop()? == ty).then_some(());
+        match inst {
+            Instruction::Push(Value::Int(_)) => stack.push(Type::Int),
+            Instruction::Push(Value::Bool(_)) => stack.push(Type::Bool),
+            Instruction::Load(name) if params.contains(name) => stack.push(Type::Int),
+            Instruction::Add
+            | Instruction::Sub
+            | Instruction::Mul
+            | Instruction::Div
+            | Instruction::Rem
+            | Instruction::BitAnd
+            | Instruction::BitOr
+            | Instruction::BitXor => {
+                pop(&mut stack, Type::Int)?;
+                pop(&mut stack, Type::Int)?;
+                stack.push(Type::Int);
+            }
+            Instruction::Neg | Instruction::BitNot => {
+                pop(&mut stack, Type::Int)?;
+                stack.push(Type::Int);
+            }
+            Instruction::Eq | Instruction::Ne => {
+                let ty = *stack.last()?;
+                pop(&mut stack, ty)?;
+                pop(&mut stack, ty)?;
+                stack.push(Type::Bool);
+            }
+            Instruction::Lt | Instruction::Le | Instruction::Gt | Instruction::Ge => {
+                pop(&mut stack, Type::Int)?;
+                pop(&mut stack, Type::Int)?;
+                stack.push(Type::Bool);
+            }
+            Instruction::Not => {
+                pop(&mut stack, Type::Bool)?;
+                stack.push(Type::Bool);
+            }
+            Instruction::Jump(addr) => {
+                merge(&mut states, pc, *addr, stack)?;
+                continue;
+            }
+            Instruction::JumpIfTrue(addr) | Instruction::JumpIfFalse(addr) => {
+                pop(&mut stack, Type::Bool)?;
+                merge(&mut states, pc, *addr, stack.clone())?;
+            }
+            _ => return None,
+        }
+        merge(&mut states, pc, pc + 1, stack)?;
+    }
+
+    let result = match states[code.len()].as_deref()? {
+        [ty] => *ty,
+        _ => return None,
+    };
+    Some((states, result))
+}
+
+/// pcからaddrへ移る際のスタックstackを、addrのスタックの型として記録する
+///
+/// 前方への移動でない場合や、既に記録した型と異なる場合はNone。
+fn merge(states: &mut [Option<Vec<Type>>], pc: usize, addr: usize, stack: Vec<Type>) -> Option<()> {
+    if addr <= pc || addr >= states.len() {
+        return None;
+    }
+    match &states[addr] {
+        Some(s) if *s != stack => None,
+        Some(_) => Some(()),
+        None => {
+            states[addr] = Some(stack);
+            Some(())
+        }
+    }
+}
+
+/// craneliftのIRの生成器
+///
+/// 到達する命令ごとにブロックを作り、実行前のスタックの値をブロックの引数として渡す。
+/// スタックの値は、真偽値も0か1のi64として扱う。
+struct Generator<'a, 'b> {
+    b: &'a mut FunctionBuilder<'b>,
+    params: &'a [String],
+    args: ir::Value,            // 引数の配列
+    status: ir::Value,          // 成功したかを書き込む領域
+    blocks: Vec<Option<Block>>, // 各命令のブロック。末尾は命令列の終端のブロック
+    fail: Block,                // 失敗を返すブロック
+    stack: Vec<ir::Value>,      // 現在の命令の実行前のスタック
+}
+
+impl<'a, 'b> Generator<'a, 'b> {
+    fn new(
+        b: &'a mut FunctionBuilder<'b>,
+        params: &'a [String],
+        states: &[Option<Vec<Type>>],
+    ) -> Self {
+        let entry = b.create_block();
+        b.append_block_params_for_function_params(entry);
+        b.switch_to_block(entry);
+        let (args, status) = (b.block_params(entry)[0], b.block_params(entry)[1]);
+
+        let blocks = states
+            .iter()
+            .map(|state| {
+                let state = state.as_ref()?;
+                let block = b.create_block();
+                for _ in state {
+                    b.append_block_param(block, types::I64);
+                }
+                Some(block)
+            })
+            .collect::<Vec<_>>();
+        b.ins().jump(blocks[0].expect("entry is reachable"), &[]);
+
+        let fail = b.create_block();
+        Generator {
+            b,
+            params,
+            args,
+            status,
+            blocks,
+            fail,
+            stack: Vec::new(),
+        }
+    }
+
+    /// 命令列codeのIRを生成する
+    fn gen_code(&mut self, code: &[Instruction]) {
+        for (pc, inst) in code.iter().enumerate() {
+            let Some(block) = self.blocks[pc] else {
+                continue;
+            };
+            self.b.switch_to_block(block);
+            self.stack = self.b.block_params(block).to_vec();
+            if self.gen_inst(inst, pc) {
+                let next = self.block(pc + 1);
+                self.b.ins().jump(next, &self.stack);
+            }
+        }
+
+        // 終端では、成功を書き込んで結果を返す
+        let exit = self.block(code.len());
+        self.b.switch_to_block(exit);
+        let result = self.b.block_params(exit)[0];
+        self.set_status(1);
+        self.b.ins().return_(&[result]);
+
+        self.b.switch_to_block(self.fail);
+        self.set_status(0);
+        let zero = self.b.ins().iconst(types::I64, 0);
+        self.b.ins().return_(&[zero]);
+    }
+
+    /// 1命令のIRを生成する
+    ///
+    /// 次の命令へ進む場合はtrue、飛び先へ移る場合はfalseを返す。
+    fn gen_inst(&mut self, inst: &Instruction, pc: usize) -> bool {
+        match inst {
+            Instruction::Push(Value::Int(n)) => {
+                let v = self.b.ins().iconst(types::I64, *n);
+                self.stack.push(v);
+            }
+            Instruction::Push(Value::Bool(x)) => {
+                let v = self.b.ins().iconst(types::I64, i64::from(*x));
+                self.stack.push(v);
+            }
+            Instruction::Load(name) => {
+                let i = self.params.iter().position(|p| p == name).unwrap();
+                let offset = i32::try_from(i * 8).unwrap();
+                let v = self
+                    .b
+                    .ins()
+                    .load(types::I64, MemFlags::trusted(), self.args, offset);
+                self.stack.push(v);
+            }
+            Instruction::Add => self.gen_checked(|b, x, y| b.ins().sadd_overflow(x, y)),
+            Instruction::Sub => self.gen_checked(|b, x, y| b.ins().ssub_overflow(x, y)),
+            Instruction::Mul => self.gen_checked(|b, x, y| b.ins().smul_overflow(x, y)),
+            Instruction::Div | Instruction::Rem => {
+                let y = self.stack.pop().unwrap();
+                let x = self.stack.pop().unwrap();
+                // 0による除算と、i64::MIN / -1 は失敗とする
+                let zero = self.b.ins().icmp_imm(IntCC::Equal, y, 0);
+                self.fail_if(zero);
+                let min = self.b.ins().icmp_imm(IntCC::Equal, x, i64::MIN);
+                let minus_one = self.b.ins().icmp_imm(IntCC::Equal, y, -1);
+                let overflow = self.b.ins().band(min, minus_one);
+                self.fail_if(overflow);
+                let v = if matches!(inst, Instruction::Div) {
+                    self.b.ins().sdiv(x, y)
+                } else {
+                    self.b.ins().srem(x, y)
+                };
+                self.stack.push(v);
+            }
+            Instruction::Neg => {
+                let x = self.stack.pop().unwrap();
+                let min = self.b.ins().icmp_imm(IntCC::Equal, x, i64::MIN);
+                self.fail_if(min);
+                let v = self.b.ins().ineg(x);
+                self.stack.push(v);
+            }
+            Instruction::BitAnd => self.gen_binary(|b, x, y| b.ins().band(x, y)),
+            Instruction::BitOr => self.gen_binary(|b, x, y| b.ins().bor(x, y)),
+            Instruction::BitXor => self.gen_binary(|b, x, y| b.ins().bxor(x, y)),
+            Instruction::BitNot => {
+                let x = self.stack.pop().unwrap();
+                let v = self.b.ins().bnot(x);
+                self.stack.push(v);
+            }
+            Instruction::Not => {
+                let x = self.stack.pop().unwrap();
+                let v = self.b.ins().bxor_imm(x, 1);
+                self.stack.push(v);
+            }
+            Instruction::Eq => self.gen_compare(IntCC::Equal),
+            Instruction::Ne => self.gen_compare(IntCC::NotEqual),
+            Instruction::Lt => self.gen_compare(IntCC::SignedLessThan),
+            Instruction::Le => self.gen_compare(IntCC::SignedLessThanOrEqual),
+            Instruction::Gt => self.gen_compare(IntCC::SignedGreaterThan),
+            Instruction::Ge => self.gen_compare(IntCC::SignedGreaterThanOrEqual),
+            Instruction::Jump(addr) => {
+                let target = self.block(*addr);
+                self.b.ins().jump(target, &self.stack);
+                return false;
+            }
+            Instruction::JumpIfTrue(addr) | Instruction::JumpIfFalse(addr) => {
+                let cond = self.stack.pop().unwrap();
+                let (target, next) = (self.block(*addr), self.block(pc + 1));
+                if matches!(inst, Instruction::JumpIfTrue(_)) {
+                    self.b
+                        .ins()
+                        .brif(cond, target, &self.stack, next, &self.stack);
+                } else {
+                    self.b
+                        .ins()
+                        .brif(cond, next, &self.stack, target, &self.stack);
+                }
+                return false;
+            }
+            _ => unreachable!("unsupported instructions are rejected by infer"),
+        }
+        true
+    }
+
+    /// 二項演算のIRを生成する
+    fn gen_binary(
+        &mut self,
+        op: impl FnOnce(&mut FunctionBuilder<'b>, ir::Value, ir::Value) -> ir::Value,
+    ) {
+        let y = self.stack.pop().unwrap();
+        let x = self.stack.pop().unwrap();
+        let v = op(self.b, x, y);
+        self.stack.push(v);
+    }
+
+    /// オーバーフローを検出する二項演算のIRを生成する
+    fn gen_checked(
+        &mut self,
+        op: impl FnOnce(&mut FunctionBuilder<'b>, ir::Value, ir::Value) -> (ir::Value, ir::Value),
+    ) {
+        let y = self.stack.pop().unwrap();
+        let x = self.stack.pop().unwrap();
+        let (v, overflow) = op(self.b, x, y);
+        self.fail_if(overflow);
+        self.stack.push(v);
+    }
+
+    /// 比較のIRを生成する
+    fn gen_compare(&mut self, cc: IntCC) {
+        self.gen_binary(|b, x, y| {
+            let c = b.ins().icmp(cc, x, y);
+            b.ins().uextend(types::I64, c)
+        });
+    }
+
+    /// condが真の場合に失敗を返すよう分岐し、偽の場合の新しいブロックへ移る
+    fn fail_if(&mut self, cond: ir::Value) {
+        let next = self.b.create_block();
+        self.b.ins().brif(cond, self.fail, &[], next, &[]);
+        self.b.switch_to_block(next);
+    }
+
+    /// 成功したかをstatusに書き込む
+    fn set_status(&mut self, ok: i64) {
+        let v = self.b.ins().iconst(types::I8, ok);
+        self.b.ins().store(MemFlags::trusted(), v, self.status, 0);
+    }
+
+    /// 命令pcのブロックを返す
+    fn block(&self, pc: usize) -> Block {
+        self.blocks[pc].expect("jump target is reachable")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        eval::compile as gen_code,
+        parser::{parse, Notation, Stmt, DEFAULT_MAX_DEPTH},
+    };
+
+    /// 関数定義をパースしてJITコンパイルする
+    fn jit(c: &str) -> Option<NativeFn> {
+        match parse(c, Notation::Infix, DEFAULT_MAX_DEPTH).unwrap() {
+            Stmt::FnDef(_, params, body) => compile(&params, &gen_code(&body, false)),
+            s => panic!("not a function definition: {s:?}"),
+        }
+    }
+
+    #[test]
+    fn test_jit() {
+        let f = jit("fn f(x, y) = x * x + y % 7 - (x / 2)").unwrap();
+        assert_eq!(f.call(&[3, 10]), Some(Value::Int(3 * 3 + 10 % 7 - 3 / 2)));
+        assert_eq!(f.call(&[-5, -10]), Some(Value::Int(25 + -10 % 7 - -5 / 2)));
+        assert_eq!(f.call(&[1]), None);
+
+        let abs = jit("fn abs(n) = if n < 0 then -n else n").unwrap();
+        assert_eq!(abs.call(&[-7]), Some(Value::Int(7)));
+        assert_eq!(abs.call(&[7]), Some(Value::Int(7)));
+
+        let between = jit("fn between(x) = 0 < x && !(x >= 10) || x == -1").unwrap();
+        assert_eq!(between.call(&[5]), Some(Value::Bool(true)));
+        assert_eq!(between.call(&[10]), Some(Value::Bool(false)));
+        assert_eq!(between.call(&[-1]), Some(Value::Bool(true)));
+
+        let bits = jit("fn bits(x) = ~x & 0xFF | 1 xor 3").unwrap();
+        assert_eq!(bits.call(&[0x0F]), Some(Value::Int(!0x0F & 0xFF | 1 ^ 3)));
+    }
+
+    #[test]
+    fn test_jit_fail() {
+        // オーバーフローや0による除算は失敗となる
+        let sq = jit("fn sq(x) = x * x").unwrap();
+        assert_eq!(sq.call(&[i64::MAX]), None);
+        let div = jit("fn div(x, y) = x / y").unwrap();
+        assert_eq!(div.call(&[1, 0]), None);
+        assert_eq!(div.call(&[i64::MIN, -1]), None);
+        assert_eq!(jit("fn minus(x) = -x").unwrap().call(&[i64::MIN]), None);
+
+        // 対象外の命令を含む関数や、型の合わない関数はコンパイルしない
+        assert!(jit("fn fact(n) = if n <= 1 then 1 else n * fact(n - 1)").is_none());
+        assert!(jit("fn g(x) = x + y").is_none());
+        assert!(jit("fn h(x) = x + 1.5").is_none());
+        assert!(jit("fn k(x) = x + (x > 0)").is_none());
+        assert!(jit("fn l(x) = if x then 1 else 2").is_none());
+    }
+}
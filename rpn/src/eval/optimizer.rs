@@ -0,0 +1,196 @@
+//! 抽象構文木を最適化する
+//!
+//! 定数の部分式を畳み込み、`x * 1`や`x + 0`のような恒等式を簡約する。
+//! 最適化後の式は`:set`の設定に依らず元の式と同じ値になるように、
+//! 整数の範囲で結果が変わらない演算のみを畳み込む。
+//! 除算や左シフトは有理数や多倍長整数の設定で結果が変わり、
+//! 浮動小数点数は10進小数の設定で結果が変わるため、畳み込まない。
+//! 演算がエラーとなる場合も畳み込まず、実行時にエラーとする。
+//!
+//! 恒等式の簡約により、`true * 1`のような数値でない値の演算のエラーは検出されなくなる。
+use super::EvalError;
+use crate::{parser::Expr, value::Value};
+use std::cmp::Ordering;
+
+/// 式を最適化する関数
+pub fn optimize(e: &Expr) -> Expr {
+    match e {
+        Expr::Num(_)
+        | Expr::BigNum(_)
+        | Expr::Float(_)
+        | Expr::Bool(_)
+        | Expr::Var(_)
+        | Expr::History(_)
+        | Expr::Unit(_) => e.clone(),
+        Expr::Assign(name, a) => Expr::Assign(name.clone(), Box::new(optimize(a))),
+        Expr::Call(name, args) => Expr::Call(name.clone(), args.iter().map(optimize).collect()),
+        Expr::List(es) => Expr::List(es.iter().map(optimize).collect()),
+        Expr::Index(a, i) => Expr::Index(Box::new(optimize(a)), Box::new(optimize(i))),
+        Expr::Add(a, b) => match (optimize(a), optimize(b)) {
+            (x, Expr::Num(0)) | (Expr::Num(0), x) => x,
+            (a, b) => fold(a, b, Expr::Add, Value::add),
+        },
+        Expr::Sub(a, b) => match (optimize(a), optimize(b)) {
+            (x, Expr::Num(0)) => x,
+            (a, b) => fold(a, b, Expr::Sub, Value::sub),
+        },
+        Expr::Mul(a, b) => match (optimize(a), optimize(b)) {
+            (x, Expr::Num(1)) | (Expr::Num(1), x) => x,
+            (a, b) => fold(a, b, Expr::Mul, Value::mul),
+        },
+        Expr::Div(a, b) => Expr::Div(Box::new(optimize(a)), Box::new(optimize(b))),
+        Expr::Rem(a, b) => fold(optimize(a), optimize(b), Expr::Rem, Value::rem),
+        Expr::Pow(a, b) => fold(optimize(a), optimize(b), Expr::Pow, Value::pow),
+        Expr::Neg(a) => fold_unary(optimize(a), Expr::Neg, Value::neg),
+        Expr::Fact(a) => fold_unary(optimize(a), Expr::Fact, Value::fact),
+        Expr::BitAnd(a, b) => fold(optimize(a), optimize(b), Expr::BitAnd, Value::bitand),
+        Expr::BitOr(a, b) => fold(optimize(a), optimize(b), Expr::BitOr, Value::bitor),
+        Expr::BitXor(a, b) => fold(optimize(a), optimize(b), Expr::BitXor, Value::bitxor),
+        Expr::Shl(a, b) => Expr::Shl(Box::new(optimize(a)), Box::new(optimize(b))),
+        Expr::Shr(a, b) => fold(optimize(a), optimize(b), Expr::Shr, Value::shr),
+        Expr::BitNot(a) => fold_unary(optimize(a), Expr::BitNot, Value::bitnot),
+        Expr::Eq(a, b) => fold(optimize(a), optimize(b), Expr::Eq, |a, b| {
+            Ok(Value::Bool(a.equals(&b)?))
+        }),
+        Expr::Ne(a, b) => fold(optimize(a), optimize(b), Expr::Ne, |a, b| {
+            Ok(Value::Bool(!a.equals(&b)?))
+        }),
+        Expr::Lt(a, b) => fold(optimize(a), optimize(b), Expr::Lt, |a, b| {
+            a.compare(&b, Ordering::is_lt)
+        }),
+        Expr::Le(a, b) => fold(optimize(a), optimize(b), Expr::Le, |a, b| {
+            a.compare(&b, Ordering::is_le)
+        }),
+        Expr::Gt(a, b) => fold(optimize(a), optimize(b), Expr::Gt, |a, b| {
+            a.compare(&b, Ordering::is_gt)
+        }),
+        Expr::Ge(a, b) => fold(optimize(a), optimize(b), Expr::Ge, |a, b| {
+            a.compare(&b, Ordering::is_ge)
+        }),
+        // 右辺が評価されない場合は、右辺を取り除く
+        Expr::And(a, b) => match (optimize(a), optimize(b)) {
+            (Expr::Bool(false), _) => Expr::Bool(false),
+            (Expr::Bool(true), Expr::Bool(b)) => Expr::Bool(b),
+            (a, b) => Expr::And(Box::new(a), Box::new(b)),
+        },
+        Expr::Or(a, b) => match (optimize(a), optimize(b)) {
+            (Expr::Bool(true), _) => Expr::Bool(true),
+            (Expr::Bool(false), Expr::Bool(b)) => Expr::Bool(b),
+            (a, b) => Expr::Or(Box::new(a), Box::new(b)),
+        },
+        Expr::Not(a) => match optimize(a) {
+            Expr::Bool(b) => Expr::Bool(!b),
+            a => Expr::Not(Box::new(a)),
+        },
+        // 条件が定数の場合は、選ばれる式のみを残す
+        Expr::If(cond, then, els) => match optimize(cond) {
+            Expr::Bool(true) => optimize(then),
+            Expr::Bool(false) => optimize(els),
+            cond => Expr::If(
+                Box::new(cond),
+                Box::new(optimize(then)),
+                Box::new(optimize(els)),
+            ),
+        },
+    }
+}
+
+/// 両辺が整数の定数の場合は二項演算opを畳み込み、そうでない場合は式をそのまま組み立てる
+fn fold(
+    a: Expr,
+    b: Expr,
+    expr: fn(Box<Expr>, Box<Expr>) -> Expr,
+    op: impl Fn(Value, Value) -> Result<Value, EvalError>,
+) -> Expr {
+    if let (Expr::Num(x), Expr::Num(y)) = (&a, &b) {
+        if let Some(e) = to_expr(op(Value::Int(*x), Value::Int(*y))) {
+            return e;
+        }
+    }
+    expr(Box::new(a), Box::new(b))
+}
+
+/// 被演算子が整数の定数の場合は単項演算opを畳み込み、そうでない場合は式をそのまま組み立てる
+fn fold_unary(
+    a: Expr,
+    expr: fn(Box<Expr>) -> Expr,
+    op: impl Fn(Value) -> Result<Value, EvalError>,
+) -> Expr {
+    if let Expr::Num(x) = a {
+        if let Some(e) = to_expr(op(Value::Int(x))) {
+            return e;
+        }
+    }
+    expr(Box::new(a))
+}
+
+/// 演算結果を定数の式に変換
+///
+/// エラーの場合や、整数・真偽値以外の場合はNone。
+fn to_expr(v: Result<Value, EvalError>) -> Option<Expr> {
+    match v {
+        Ok(Value::Int(n)) => Some(Expr::Num(n)),
+        Ok(Value::Bool(b)) => Some(Expr::Bool(b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, Notation, Stmt, DEFAULT_MAX_DEPTH};
+
+    fn opt(c: &str) -> Expr {
+        match parse(c, Notation::Infix, DEFAULT_MAX_DEPTH).unwrap() {
+            Stmt::Expr(e) => optimize(&e),
+            s => panic!("not an expression: {s:?}"),
+        }
+    }
+
+    fn var(name: &str) -> Box<Expr> {
+        Box::new(Expr::Var(name.to_string()))
+    }
+
+    #[test]
+    fn test_optimize() {
+        // 定数の畳み込み
+        assert_eq!(opt("1 + 2 * 3 - 4"), Expr::Num(3));
+        assert_eq!(opt("2 ^ 10 % 1000 == 24"), Expr::Bool(true));
+        assert_eq!(opt("-(3!) & ~1"), Expr::Num(-6));
+        assert_eq!(
+            opt("x + 2 * 3"),
+            Expr::Add(var("x"), Box::new(Expr::Num(6)))
+        );
+
+        // 恒等式の簡約
+        assert_eq!(opt("x * 1 + 0"), *var("x"));
+        assert_eq!(opt("1 * (x - 0)"), *var("x"));
+        assert_eq!(opt("x * (3 - 2)"), *var("x"));
+
+        // 論理演算と条件分岐
+        assert_eq!(opt("false && x"), Expr::Bool(false));
+        assert_eq!(
+            opt("!(1 < 2) || x"),
+            Expr::Or(Box::new(Expr::Bool(false)), var("x"))
+        );
+        assert_eq!(opt("if 1 > 2 then y else x + 0"), *var("x"));
+
+        // 設定やエラーにより結果が変わる演算は畳み込まない
+        assert_eq!(
+            opt("7 / 2"),
+            Expr::Div(Box::new(Expr::Num(7)), Box::new(Expr::Num(2)))
+        );
+        assert_eq!(
+            opt("i64_max + 1"),
+            Expr::Add(var("i64_max"), Box::new(Expr::Num(1)))
+        );
+        assert_eq!(
+            opt("9223372036854775807 + 1"),
+            Expr::Add(Box::new(Expr::Num(i64::MAX)), Box::new(Expr::Num(1)))
+        );
+        assert_eq!(
+            opt("1.5 + 1"),
+            Expr::Add(Box::new(Expr::Float(1.5)), Box::new(Expr::Num(1)))
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//! 命令列を受け取り、スタックマシンで実行する
+//!
+//! ユーザ定義関数の呼び出しでは、戻り先を呼び出しスタックに積んで関数の命令列へ移るため、
+//! 関数呼び出しが深くなってもRustのスタックを消費しない。
+//!
+//! トレースの設定が有効な場合は、命令を1つ実行するごとに、その命令と実行後のスタックを表示する。
+//! ユーザ定義関数の命令は、呼び出しの深さに応じて字下げする。
+use super::{EvalError, Instruction};
+use crate::{
+    builtin,
+    env::{Env, Function},
+    unit,
+    value::{IntWidth, OverflowMode, Value},
+};
+use std::{cmp::Ordering, rc::Rc};
+use tracing::instrument;
+
+/// 1命令を実行した後の制御
+enum Control {
+    Next,               // 次の命令へ進む
+    Jump(usize),        // 指定したアドレスへ飛ぶ
+    Call(Rc<Function>), // ユーザ定義関数を呼び出す
+}
+
+/// スタックマシン
+#[derive(Default)]
+struct Machine {
+    stack: Vec<Value>,                         // 値のスタック
+    calls: Vec<(Option<Rc<Function>>, usize)>, // 呼び出し元の関数と戻り先のアドレス
+}
+
+/// 命令列codeを実行し、スタックに残った値を返す
+///
+/// 変数はenvから参照し、代入された値はenvに設定する。
+#[instrument(level = "debug", skip_all, fields(instructions = code.len()))]
+pub fn run(code: &[Instruction], env: &mut Env) -> Result<Value, EvalError> {
+    let mut machine = Machine::default();
+    let result = machine.run(code, env);
+
+    // エラーで中断した場合は、呼び出し中の関数のフレームを取り除く
+    for _ in &machine.calls {
+        env.pop_frame();
+    }
+    result
+}
+
+impl Machine {
+    fn run(&mut self, code: &[Instruction], env: &mut Env) -> Result<Value, EvalError> {
+        let mut func: Option<Rc<Function>> = None; // 実行中のユーザ定義関数
+        let mut pc = 0;
+
+        loop {
+            let insts = func.as_ref().map_or(code, |f| &f.code[..]);
+            let Some(inst) = insts.get(pc) else {
+                // 関数の命令列の終端に達したら、呼び出し元へ戻る
+                match self.calls.pop() {
+                    Some((caller, ret)) => {
+                        if env.settings.trace {
+                            self.trace("return", self.calls.len() + 1);
+                        }
+                        env.pop_frame();
+                        (func, pc) = (caller, ret);
+                        continue;
+                    }
+                    None => break,
+                }
+            };
+
+            let control = self.step(inst, env)?;
+            if env.settings.trace {
+                self.trace(&format!("{:>04}: {}", pc, inst), self.calls.len());
+            }
+
+            match control {
+                Control::Next => pc += 1,
+                Control::Jump(addr) => pc = addr,
+                Control::Call(f) => {
+                    self.calls.push((func.replace(f), pc + 1));
+                    pc = 0;
+                }
+            }
+        }
+
+        Ok(self.pop())
+    }
+
+    /// 1命令を実行する
+    fn step(&mut self, inst: &Instruction, env: &mut Env) -> Result<Control, EvalError> {
+        match inst {
+            Instruction::Push(v) => self.push(promote(v.clone(), env), env)?,
+            Instruction::Load(name) => match env.get(name) {
+                Some(v) => self.push(promote(v, env), env)?,
+                None => {
+                    let similar = env.similar_var(name);
+                    return Err(EvalError::UndefinedVariable(name.clone(), similar));
+                }
+            },
+            Instruction::Store(name) => {
+                let v = self.stack.last().expect("stack is empty").clone();
+                env.set(name, v)?;
+            }
+            Instruction::History(n) => match env.result(*n) {
+                Some(v) => self.push(promote(v, env), env)?,
+                None => return Err(EvalError::NoHistory(*n)),
+            },
+            Instruction::Unit(name) => match unit::parse(name) {
+                Some(q) => self.push(Value::Quantity(q), env)?,
+                None => return Err(EvalError::UnknownUnit(name.clone())),
+            },
+            Instruction::Call(name, argc) => {
+                let args = self.stack.split_off(self.stack.len() - argc);
+                return self.call(name, args, env);
+            }
+            Instruction::List(len) => {
+                let xs = self.stack.split_off(self.stack.len() - len);
+                self.push(Value::List(xs), env)?;
+            }
+            Instruction::Index => self.binary(env, Value::index)?,
+            Instruction::Add => self.binary(env, Value::add)?,
+            Instruction::Sub => self.binary(env, Value::sub)?,
+            Instruction::Mul => self.binary(env, Value::mul)?,
+            Instruction::Div => {
+                let rounding = env.settings.rounding;
+                self.binary(env, |a, b| a.div(b, rounding))?
+            }
+            Instruction::Rem => self.binary(env, Value::rem)?,
+            Instruction::Pow => self.binary(env, Value::pow)?,
+            Instruction::Neg => self.unary(env, Value::neg)?,
+            Instruction::Fact => self.unary(env, Value::fact)?,
+            Instruction::BitAnd => self.binary(env, Value::bitand)?,
+            Instruction::BitOr => self.binary(env, Value::bitor)?,
+            Instruction::BitXor => self.binary(env, Value::bitxor)?,
+            Instruction::Shl => self.binary(env, Value::shl)?,
+            Instruction::Shr => self.binary(env, Value::shr)?,
+            Instruction::BitNot => self.unary(env, Value::bitnot)?,
+            Instruction::Eq => self.binary(env, |a, b| Ok(Value::Bool(a.equals(&b)?)))?,
+            Instruction::Ne => self.binary(env, |a, b| Ok(Value::Bool(!a.equals(&b)?)))?,
+            Instruction::Lt => self.binary(env, |a, b| a.compare(&b, Ordering::is_lt))?,
+            Instruction::Le => self.binary(env, |a, b| a.compare(&b, Ordering::is_le))?,
+            Instruction::Gt => self.binary(env, |a, b| a.compare(&b, Ordering::is_gt))?,
+            Instruction::Ge => self.binary(env, |a, b| a.compare(&b, Ordering::is_ge))?,
+            Instruction::Not => self.unary(env, Value::not)?,
+            Instruction::Jump(addr) => return Ok(Control::Jump(*addr)),
+            Instruction::JumpIfTrue(addr) => {
+                if self.pop().to_bool()? {
+                    return Ok(Control::Jump(*addr));
+                }
+            }
+            Instruction::JumpIfFalse(addr) => {
+                if !self.pop().to_bool()? {
+                    return Ok(Control::Jump(*addr));
+                }
+            }
+        }
+
+        Ok(Control::Next)
+    }
+
+    /// 実行した命令instと、スタックの内容を表示する
+    ///
+    /// depthは関数呼び出しの深さ。
+    fn trace(&self, inst: &str, depth: usize) {
+        let stack = self
+            .stack
+            .iter()
+            .map(Value::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let inst = format!("{}{}", "  ".repeat(depth), inst);
+        println!("{inst:<32} stack: [{stack}]");
+    }
+
+    /// 値をスタックに積む
+    ///
+    /// 多倍長整数で計算している場合は、部分式ごとに結果を整数の幅に収める。
+    fn push(&mut self, v: Value, env: &Env) -> Result<(), EvalError> {
+        let v = if env.settings.bigint {
+            v
+        } else {
+            env.settings.width.fit(v, env.settings.overflow)?
+        };
+        self.stack.push(v);
+        Ok(())
+    }
+
+    /// スタックから値を取り出す
+    ///
+    /// 命令列はスタックの値が足りなくならないように生成されるため、空の場合はパニックする。
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack is empty")
+    }
+
+    /// スタックの値に単項演算opを適用する
+    fn unary(
+        &mut self,
+        env: &Env,
+        op: impl Fn(Value) -> Result<Value, EvalError>,
+    ) -> Result<(), EvalError> {
+        let a = self.pop();
+        self.push(op(a)?, env)
+    }
+
+    /// スタックの上から2つの値に二項演算opを適用する
+    ///
+    /// 先に積まれた値が左辺となる。
+    fn binary(
+        &mut self,
+        env: &Env,
+        op: impl Fn(Value, Value) -> Result<Value, EvalError>,
+    ) -> Result<(), EvalError> {
+        let b = self.pop();
+        let a = self.pop();
+        self.push(op(a, b)?, env)
+    }
+
+    /// 関数nameを引数argsで呼び出す
+    ///
+    /// ユーザ定義関数を組み込み関数より優先する。
+    /// ユーザ定義関数の場合は、引数のフレームを積んで関数の命令列へ移る。
+    fn call(&mut self, name: &str, args: Vec<Value>, env: &mut Env) -> Result<Control, EvalError> {
+        let Some(f) = env.get_fn(name) else {
+            if !builtin::names().any(|b| b == name) {
+                let similar = env.similar_fn(name);
+                return Err(EvalError::UndefinedFunction(name.to_string(), similar));
+            }
+            self.push(builtin::call(name, &args, &mut env.rng)?, env)?;
+            return Ok(Control::Next);
+        };
+
+        #[cfg(feature = "jit")]
+        if let Some(v) = call_native(&f, &args, env) {
+            self.push(v, env)?;
+            return Ok(Control::Next);
+        }
+
+        if args.len() != f.params.len() {
+            return Err(EvalError::WrongArity(
+                name.to_string(),
+                f.params.len(),
+                args.len(),
+            ));
+        }
+
+        let frame = f.params.iter().cloned().zip(args).collect();
+        env.push_frame(frame)?;
+        Ok(Control::Call(f))
+    }
+}
+
+/// JITコンパイルした関数fを引数argsで呼び出す
+///
+/// 引数がすべてi64の整数で、整数の計算に関する設定が既定値の場合のみ呼び出す。
+/// トレースの設定が有効な場合は、命令を表示するため呼び出さない。
+/// 呼び出さなかった場合や、ネイティブコードが失敗した場合はNone。
+#[cfg(feature = "jit")]
+fn call_native(f: &Function, args: &[Value], env: &Env) -> Option<Value> {
+    let settings = &env.settings;
+    if settings.bigint
+        || settings.rational
+        || settings.decimal
+        || settings.width != IntWidth::I64
+        || settings.overflow != OverflowMode::Error
+        || settings.trace
+        || settings.interval
+    {
+        return None;
+    }
+
+    let args = args
+        .iter()
+        .map(|v| match v {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    f.native.as_ref()?.call(&args)
+}
+
+/// 区間、10進小数、有理数、多倍長整数の設定が有効な場合は、数値をそれぞれの値に変換
+///
+/// 複数が有効な場合は、区間、10進小数、有理数、多倍長整数の順に優先する。
+/// 区間の場合は、複素数以外の数値を1点のみからなる区間に変換する。
+/// 10進小数の場合は、浮動小数点数も10進小数に変換する。
+/// 整数の幅がi128の場合や、オーバーフロー時にエラーとしない場合は、
+/// 多倍長整数で計算してからpushで整数の幅に収める。
+fn promote(v: Value, env: &Env) -> Value {
+    let settings = &env.settings;
+    if settings.interval {
+        v.into_interval()
+    } else if settings.decimal {
+        v.into_decimal()
+    } else if settings.rational {
+        v.into_ratio()
+    } else if settings.bigint
+        || settings.width == IntWidth::I128
+        || settings.overflow != OverflowMode::Error
+    {
+        v.into_big()
+    } else {
+        v
+    }
+}
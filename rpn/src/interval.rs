@@ -0,0 +1,271 @@
+//! 区間演算
+//!
+//! 値を下限loと上限hiの閉区間`[lo .. hi]`で表し、演算結果は、それぞれの区間から
+//! 値を選んで演算した結果をすべて含む区間とする。誤差を含む値の計算で、結果の範囲を見積もるために用いる。
+//!
+//! 端点は浮動小数点数で計算し、丸め誤差による区間の拡大は考慮しない。
+//! 比較は、区間内のすべての値の組で結果が同じ場合のみ真偽が定まる。
+use std::{
+    cmp::Ordering,
+    f64::consts::{PI, TAU},
+    fmt::{self, Display},
+};
+
+/// 閉区間
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Interval {
+    lo: f64,
+    hi: f64,
+}
+
+impl Interval {
+    /// 区間`[lo .. hi]`を返す
+    ///
+    /// loがhiより大きい場合や、端点がNaNの場合はNone。
+    pub fn new(lo: f64, hi: f64) -> Option<Self> {
+        (lo <= hi).then_some(Interval { lo, hi })
+    }
+
+    /// 1点のみからなる区間`[x .. x]`を返す
+    pub fn point(x: f64) -> Self {
+        Interval { lo: x, hi: x }
+    }
+
+    pub fn lo(self) -> f64 {
+        self.lo
+    }
+
+    pub fn hi(self) -> f64 {
+        self.hi
+    }
+
+    /// 両端をfloatで文字列に変換し、`[1.0 .. 2.0]`のように表した文字列を返す
+    pub fn to_string_with(self, float: impl Fn(f64) -> String) -> String {
+        format!("[{} .. {}]", float(self.lo), float(self.hi))
+    }
+
+    /// 区間の中点
+    pub fn mid(self) -> f64 {
+        self.lo + (self.hi - self.lo) / 2.0
+    }
+
+    pub fn contains(self, x: f64) -> bool {
+        self.lo <= x && x <= self.hi
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.lo == 0.0 && self.hi == 0.0
+    }
+
+    pub(crate) fn add(self, rhs: Interval) -> Interval {
+        Interval {
+            lo: self.lo + rhs.lo,
+            hi: self.hi + rhs.hi,
+        }
+    }
+
+    pub(crate) fn sub(self, rhs: Interval) -> Interval {
+        Interval {
+            lo: self.lo - rhs.hi,
+            hi: self.hi - rhs.lo,
+        }
+    }
+
+    /// 乗算
+    ///
+    /// 端点同士の4つの積の最小値と最大値を端点とする。
+    pub(crate) fn mul(self, rhs: Interval) -> Interval {
+        let products = [
+            self.lo * rhs.lo,
+            self.lo * rhs.hi,
+            self.hi * rhs.lo,
+            self.hi * rhs.hi,
+        ];
+        hull(products)
+    }
+
+    /// 除算
+    ///
+    /// 除数の区間が0を含む場合はNone。
+    pub(crate) fn div(self, rhs: Interval) -> Option<Interval> {
+        if rhs.contains(0.0) {
+            return None;
+        }
+        Some(self.mul(Interval {
+            lo: 1.0 / rhs.hi,
+            hi: 1.0 / rhs.lo,
+        }))
+    }
+
+    pub(crate) fn neg(self) -> Interval {
+        Interval {
+            lo: -self.hi,
+            hi: -self.lo,
+        }
+    }
+
+    pub fn abs(self) -> Interval {
+        if self.lo >= 0.0 {
+            self
+        } else if self.hi <= 0.0 {
+            self.neg()
+        } else {
+            Interval {
+                lo: 0.0,
+                hi: self.hi.max(-self.lo),
+            }
+        }
+    }
+
+    /// 整数乗
+    ///
+    /// 指数が負で、区間が0を含む場合はNone。
+    pub fn powi(self, n: i32) -> Option<Interval> {
+        if n < 0 {
+            return Interval::point(1.0).div(self.powi(n.checked_neg()?)?);
+        }
+        // 偶数乗は、絶対値の区間で単調増加となる
+        let base = if n % 2 == 0 { self.abs() } else { self };
+        Some(Interval {
+            lo: base.lo.powi(n),
+            hi: base.hi.powi(n),
+        })
+    }
+
+    /// 単調増加の関数fを端点に適用する
+    ///
+    /// `sqrt`や`ln`の定義域外の端点はNaNとなる。
+    pub fn map_increasing(self, f: fn(f64) -> f64) -> Interval {
+        Interval {
+            lo: f(self.lo),
+            hi: f(self.hi),
+        }
+    }
+
+    pub fn sin(self) -> Interval {
+        self.periodic(f64::sin, PI / 2.0, -PI / 2.0)
+    }
+
+    pub fn cos(self) -> Interval {
+        self.periodic(f64::cos, 0.0, PI)
+    }
+
+    /// 周期2πの関数fを適用する
+    ///
+    /// 区間がmax_at+2πkを含む場合は上限を1、min_at+2πkを含む場合は下限を-1とし、
+    /// それ以外は端点の値から求める。
+    fn periodic(self, f: fn(f64) -> f64, max_at: f64, min_at: f64) -> Interval {
+        if self.hi - self.lo >= TAU {
+            return Interval { lo: -1.0, hi: 1.0 };
+        }
+        let contains_period = |x: f64| {
+            let k = ((self.lo - x) / TAU).ceil();
+            self.contains(x + k * TAU)
+        };
+        let (a, b) = (f(self.lo), f(self.hi));
+        Interval {
+            lo: if contains_period(min_at) {
+                -1.0
+            } else {
+                a.min(b)
+            },
+            hi: if contains_period(max_at) {
+                1.0
+            } else {
+                a.max(b)
+            },
+        }
+    }
+
+    /// 2つの区間の値の大小を比較し、その順序がpredを満たすかを返す
+    ///
+    /// 区間内のすべての値の組について満たす場合はtrue、いずれの組についても満たさない場合はfalse、
+    /// 組によって異なる場合はNone。
+    pub fn compare(self, rhs: Interval, pred: fn(Ordering) -> bool) -> Option<bool> {
+        // それぞれの順序となる値の組が存在するか
+        let possible = [
+            (Ordering::Less, self.lo < rhs.hi),
+            (Ordering::Equal, self.lo <= rhs.hi && rhs.lo <= self.hi),
+            (Ordering::Greater, self.hi > rhs.lo),
+        ];
+        let mut results = possible
+            .iter()
+            .filter(|(_, possible)| *possible)
+            .map(|(ord, _)| pred(*ord));
+        let first = results.next()?;
+        results.all(|r| r == first).then_some(first)
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_string_with(|x| format!("{x:?}")))
+    }
+}
+
+/// 値をすべて含む最小の区間を返す
+fn hull(xs: [f64; 4]) -> Interval {
+    Interval {
+        lo: xs.into_iter().fold(f64::INFINITY, f64::min),
+        hi: xs.into_iter().fold(f64::NEG_INFINITY, f64::max),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn iv(lo: f64, hi: f64) -> Interval {
+        Interval::new(lo, hi).unwrap()
+    }
+
+    #[test]
+    fn test_arith() {
+        assert_eq!(iv(1.0, 2.0).add(iv(-1.0, 3.0)), iv(0.0, 5.0));
+        assert_eq!(iv(1.0, 2.0).sub(iv(-1.0, 3.0)), iv(-2.0, 3.0));
+        assert_eq!(iv(-2.0, 3.0).mul(iv(-4.0, 1.0)), iv(-12.0, 8.0));
+        assert_eq!(iv(1.0, 2.0).div(iv(2.0, 4.0)), Some(iv(0.25, 1.0)));
+        assert_eq!(iv(1.0, 2.0).div(iv(-1.0, 1.0)), None);
+        assert_eq!(iv(-3.0, 2.0).powi(2), Some(iv(0.0, 9.0)));
+        assert_eq!(iv(-3.0, 2.0).powi(3), Some(iv(-27.0, 8.0)));
+        assert_eq!(iv(2.0, 4.0).powi(-1), Some(iv(0.25, 0.5)));
+        assert_eq!(iv(-3.0, 2.0).abs(), iv(0.0, 3.0));
+        assert_eq!(Interval::new(2.0, 1.0), None);
+    }
+
+    #[test]
+    fn test_trig() {
+        assert_eq!(iv(0.0, PI).cos(), iv(-1.0, 1.0));
+        assert_eq!(iv(-1.0, 1.0).cos(), iv(1.0f64.cos(), 1.0));
+        assert_eq!(iv(0.0, 10.0).sin(), iv(-1.0, 1.0));
+        let s = iv(0.0, 1.0).sin();
+        assert_eq!((s.lo, s.hi), (0.0, 1.0f64.sin()));
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(
+            iv(1.0, 2.0).compare(iv(3.0, 4.0), Ordering::is_lt),
+            Some(true)
+        );
+        assert_eq!(
+            iv(1.0, 3.0).compare(iv(3.0, 4.0), Ordering::is_le),
+            Some(true)
+        );
+        assert_eq!(iv(1.0, 3.0).compare(iv(3.0, 4.0), Ordering::is_lt), None);
+        assert_eq!(
+            iv(5.0, 6.0).compare(iv(3.0, 4.0), Ordering::is_le),
+            Some(false)
+        );
+        assert_eq!(
+            Interval::point(1.0).compare(Interval::point(1.0), Ordering::is_eq),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(iv(0.5, 2.0).to_string(), "[0.5 .. 2.0]");
+    }
+}
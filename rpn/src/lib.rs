@@ -0,0 +1,79 @@
+//! # 数式を評価する電卓のクレート
+//!
+//! 数式のパースと評価を行う。`rpn`コマンド（`cli`モジュール）のREPLは、このクレートを利用する薄いラッパーである。
+//! 前置記法、後置記法、中置記法の数式を抽象構文木（Expr）に変換し、
+//! 変数や関数を保持する環境（Env）のもとで値（Value）に評価する。
+//!
+//! ## 利用例
+//!
+//! ```
+//! use rpn::{Env, Value};
+//!
+//! let mut env = Env::new();
+//! let e = rpn::parse("x = 3 * (1 + 2)").unwrap();
+//! assert_eq!(rpn::eval(&e, &mut env), Ok(Value::Int(9)));
+//!
+//! let e = rpn::parse("x ^ 2").unwrap();
+//! assert_eq!(rpn::eval(&e, &mut env), Ok(Value::Int(81)));
+//!
+//! assert!(rpn::parse("1 +").is_err());
+//! ```
+//!
+//! `lisp`モジュールは、同じ値と環境を用いてs式を評価する小さなインタプリタである。
+//!
+//! `serde`フィーチャを有効にすると、ExprやValueなどがserdeでシリアライズ、デシリアライズできる。
+pub mod builtin;
+pub mod cli;
+pub mod decimal;
+pub mod diff;
+pub mod env;
+pub mod eval;
+pub mod interval;
+pub mod lisp;
+pub mod parser;
+pub mod simplify;
+pub mod stack;
+pub mod unit;
+pub mod value;
+pub mod wasm;
+
+pub use helper::rng;
+
+pub use env::Env;
+pub use eval::{eval, EvalError};
+pub use parser::{Expr, Notation, ParseError, ParseErrorKind, Stmt};
+pub use value::Value;
+
+/// 中置記法の式をパース
+///
+/// 行頭に`x = `と記述した場合は、変数への代入の式となる。
+/// `fn f(x) = x * 2`のような関数定義は式ではないため、ParseErrorKind::NotAnExpressionのエラーとなる。
+/// 記法や式のネストの深さの上限を指定する場合は、parser::parseを用いる。
+pub fn parse(c: &str) -> Result<Expr, ParseError<'_>> {
+    match parser::parse(c, Notation::Infix, parser::DEFAULT_MAX_DEPTH)? {
+        Stmt::Expr(e) => Ok(e),
+        Stmt::FnDef(..) => Err(ParseError {
+            input: c.trim_start(),
+            kind: ParseErrorKind::NotAnExpression,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!(matches!(parse("x = 1 + 2"), Ok(Expr::Assign(..))));
+
+        // 関数定義は、先頭の`fn`を指すエラーとなる
+        let c = "  fn f(x) = x * 2";
+        let e = parse(c).unwrap_err();
+        assert_eq!(e.kind, ParseErrorKind::NotAnExpression);
+        assert_eq!(
+            e.diagnostic(c),
+            "ParseError: expected an expression, not a statement\n    fn f(x) = x * 2\n    ^^"
+        );
+    }
+}
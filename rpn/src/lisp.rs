@@ -0,0 +1,666 @@
+//! 電卓の値と環境を用いる、s式のインタプリタ
+//!
+//! 数値と真偽値は電卓の値（Value）として扱い、算術演算や比較、組み込み関数の呼び出しは
+//! 電卓と同じ実装で行う。`pi`などの定数や`ans`、`:set`で変更できる設定、乱数生成器は
+//! 電卓の環境（Env）のものを用いる。
+//!
+//! 特殊形式として、`quote`（`'x`）、`if`、`define`、`lambda`、`let`、`begin`に対応する。
+//! 組み込みの手続きは、`+`、`-`、`*`、`/`、`%`、`=`、`<`、`>`、`<=`、`>=`、`not`と、
+//! リストを扱う`list`、`cons`、`car`、`cdr`、`null?`、および電卓の組み込み関数である。
+//! 偽となるのは`false`のみで、空リストを含む他の値はすべて真となる。
+//!
+//! ```
+//! use rpn::lisp::Interp;
+//!
+//! let mut interp = Interp::new();
+//! interp.eval_str("(define (fact n) (if (= n 0) 1 (* n (fact (- n 1)))))").unwrap();
+//! let v = interp.eval_str("(fact 20)").unwrap().unwrap();
+//! assert_eq!(v.to_string(), "2432902008176640000");
+//! ```
+mod reader;
+
+pub use reader::read;
+
+use crate::{
+    builtin,
+    env::{Env, MAX_CALL_DEPTH},
+    eval::EvalError,
+    value::Value,
+};
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+/// 組み込みの手続きの名前（電卓の組み込み関数を除く）
+const PRIMITIVES: [&str; 16] = [
+    "+", "-", "*", "/", "%", "=", "<", ">", "<=", ">=", "not", "list", "cons", "car", "cdr",
+    "null?",
+];
+
+/// s式の読み込みや評価のエラーを表す型
+#[derive(Debug, PartialEq)]
+pub enum LispError {
+    Eval(EvalError),       // 電卓の値の演算や、変数の参照のエラー
+    UnexpectedEof,         // 閉じ括弧がないまま入力が終わった
+    UnexpectedParen,       // 対応する開き括弧がない閉じ括弧
+    TooDeep,               // リストのネストが深すぎる
+    BadForm(&'static str), // 特殊形式の構文の誤り
+    NotProcedure(String),  // 呼び出そうとした値が手続きでない
+}
+
+impl Display for LispError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LispError::Eval(e) => write!(f, "{e}"),
+            LispError::UnexpectedEof => write!(f, "LispError: missing ')' at end of input"),
+            LispError::UnexpectedParen => write!(f, "LispError: unexpected ')'"),
+            LispError::TooDeep => write!(f, "LispError: expression nested too deeply"),
+            LispError::BadForm(form) => write!(f, "LispError: malformed {form}"),
+            LispError::NotProcedure(s) => write!(f, "LispError: {s} is not a procedure"),
+        }
+    }
+}
+
+impl Error for LispError {}
+
+impl From<EvalError> for LispError {
+    fn from(e: EvalError) -> Self {
+        LispError::Eval(e)
+    }
+}
+
+/// s式、およびその評価結果を表す型
+#[derive(Debug, Clone)]
+pub enum Datum {
+    Value(Value),            // 数値や真偽値
+    Symbol(String),          // 変数名や特殊形式の名前
+    List(Vec<Datum>),        // 式やデータのリスト
+    Lambda(Rc<Lambda>),      // ユーザ定義の手続き
+    Primitive(&'static str), // 組み込みの手続き
+}
+
+impl Display for Datum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Datum::Value(v) => write!(f, "{v}"),
+            Datum::Symbol(s) => write!(f, "{s}"),
+            Datum::List(xs) => {
+                write!(f, "(")?;
+                for (i, x) in xs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{x}")?;
+                }
+                write!(f, ")")
+            }
+            Datum::Lambda(l) => match &l.name {
+                Some(name) => write!(f, "#<lambda {name}>"),
+                None => write!(f, "#<lambda>"),
+            },
+            Datum::Primitive(name) => write!(f, "#<builtin {name}>"),
+        }
+    }
+}
+
+impl Datum {
+    /// 条件として真とみなすか
+    fn is_true(&self) -> bool {
+        !matches!(self, Datum::Value(Value::Bool(false)))
+    }
+}
+
+/// 電卓の値をs式に変換
+///
+/// 電卓のリストは、要素を変換したリストとする。
+fn from_value(v: Value) -> Datum {
+    match v {
+        Value::List(xs) => Datum::List(xs.into_iter().map(from_value).collect()),
+        v => Datum::Value(v),
+    }
+}
+
+/// s式を電卓の値に変換
+///
+/// 値のみからなるリストは電卓のリストとする。シンボルや手続きの場合はエラー。
+fn to_value(d: &Datum) -> Result<Value, EvalError> {
+    match d {
+        Datum::Value(v) => Ok(v.clone()),
+        Datum::List(xs) => xs
+            .iter()
+            .map(to_value)
+            .collect::<Result<_, _>>()
+            .map(Value::List),
+        _ => Err(EvalError::NotNumber),
+    }
+}
+
+/// ユーザ定義の手続き
+pub struct Lambda {
+    name: Option<String>, // `(define (f ...) ...)`で定義した場合の名前
+    params: Vec<String>,
+    body: Vec<Datum>,
+    scope: Rc<Scope>, // 定義時のスコープ
+}
+
+impl fmt::Debug for Lambda {
+    // スコープは自身を含むことがあるため、表示しない
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lambda")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .field("body", &self.body)
+            .finish_non_exhaustive()
+    }
+}
+
+/// 変数のスコープ
+///
+/// 手続きの呼び出しや`let`ごとに、定義時のスコープを親とするスコープを生成する。
+#[derive(Default)]
+struct Scope {
+    vars: RefCell<HashMap<String, Datum>>,
+    parent: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    /// parentを親とする、変数varsを持つスコープを生成
+    fn child(parent: &Rc<Scope>, vars: HashMap<String, Datum>) -> Rc<Scope> {
+        Rc::new(Scope {
+            vars: RefCell::new(vars),
+            parent: Some(Rc::clone(parent)),
+        })
+    }
+
+    /// 内側のスコープから順に変数を探す
+    fn get(&self, name: &str) -> Option<Datum> {
+        match self.vars.borrow().get(name) {
+            Some(d) => Some(d.clone()),
+            None => self.parent.as_ref()?.get(name),
+        }
+    }
+
+    /// このスコープに変数を定義
+    fn define(&self, name: &str, d: Datum) {
+        self.vars.borrow_mut().insert(name.to_string(), d);
+    }
+}
+
+/// s式のインタプリタ
+///
+/// 入力をまたいで、定義した変数と手続きを保持する。
+pub struct Interp {
+    pub env: Env, // 定数、評価結果、設定、乱数生成器を保持する電卓の環境
+    global: Rc<Scope>,
+    depth: usize, // 評価中の式のネストの深さ
+}
+
+impl Default for Interp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interp {
+    pub fn new() -> Self {
+        Interp {
+            env: Env::new(),
+            global: Rc::new(Scope::default()),
+            depth: 0,
+        }
+    }
+
+    /// テキストsrcのすべての式を順に評価し、最後の式の値を返す
+    ///
+    /// 式がない場合はNoneを返す。電卓の値となる結果は、`ans`で参照できるよう評価結果に追加する。
+    pub fn eval_str(&mut self, src: &str) -> Result<Option<Datum>, LispError> {
+        let mut last = None;
+        for x in read(src)? {
+            last = Some(self.eval(&x)?);
+        }
+        Ok(last)
+    }
+
+    /// 大域スコープで式を評価
+    ///
+    /// 電卓の値となる結果は、`ans`で参照できるよう評価結果に追加する。
+    pub fn eval(&mut self, x: &Datum) -> Result<Datum, LispError> {
+        let global = Rc::clone(&self.global);
+        let v = self.eval_in(x.clone(), global)?;
+        if let Ok(value) = to_value(&v) {
+            self.env.push_result(value);
+        }
+        Ok(v)
+    }
+
+    /// スコープscopeで式を評価
+    ///
+    /// ネストの深さが電卓の関数呼び出しと同じ上限MAX_CALL_DEPTHを超える場合はエラー。
+    /// 末尾位置の式の評価はネストに数えないため、末尾再帰の手続きは深さに関わらず実行できる。
+    fn eval_in(&mut self, x: Datum, scope: Rc<Scope>) -> Result<Datum, LispError> {
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(EvalError::RecursionLimit.into());
+        }
+        self.depth += 1;
+        let result = self.eval_tail(x, scope);
+        self.depth -= 1;
+        result
+    }
+
+    /// スコープscopeで式を評価する本体
+    ///
+    /// `if`の分岐や手続きの本体の最後の式など、末尾位置の式は再帰せずにループで評価する。
+    fn eval_tail(&mut self, mut x: Datum, mut scope: Rc<Scope>) -> Result<Datum, LispError> {
+        loop {
+            let items = match x {
+                Datum::Symbol(name) => return self.lookup(&name, &scope),
+                Datum::List(items) if !items.is_empty() => items,
+                x => return Ok(x),
+            };
+            let form = match &items[0] {
+                Datum::Symbol(s) => s.as_str(),
+                _ => "",
+            };
+
+            match form {
+                "quote" => match items.as_slice() {
+                    [_, q] => return Ok(q.clone()),
+                    _ => return Err(LispError::BadForm("quote")),
+                },
+                "if" => {
+                    let (cond, then, otherwise) = match items.as_slice() {
+                        [_, c, t] => (c, t, None),
+                        [_, c, t, e] => (c, t, Some(e)),
+                        _ => return Err(LispError::BadForm("if")),
+                    };
+                    x = if self.eval_in(cond.clone(), Rc::clone(&scope))?.is_true() {
+                        then.clone()
+                    } else {
+                        match otherwise {
+                            Some(e) => e.clone(),
+                            None => return Ok(Datum::Value(Value::Bool(false))),
+                        }
+                    };
+                }
+                "define" => return self.define(&items, &scope),
+                "lambda" => {
+                    let [_, Datum::List(params), ..] = items.as_slice() else {
+                        return Err(LispError::BadForm("lambda"));
+                    };
+                    return make_lambda(None, params, &items[2..], &scope, "lambda");
+                }
+                "let" => {
+                    let [_, Datum::List(bindings), body @ ..] = items.as_slice() else {
+                        return Err(LispError::BadForm("let"));
+                    };
+                    let mut vars = HashMap::new();
+                    for binding in bindings {
+                        let [Datum::Symbol(name), e] = binding_pair(binding)? else {
+                            return Err(LispError::BadForm("let"));
+                        };
+                        vars.insert(name.clone(), self.eval_in(e.clone(), Rc::clone(&scope))?);
+                    }
+                    scope = Scope::child(&scope, vars);
+                    x = self.eval_body(body, &scope, "let")?;
+                }
+                "begin" => x = self.eval_body(&items[1..], &scope, "begin")?,
+                _ => {
+                    let f = self.eval_in(items[0].clone(), Rc::clone(&scope))?;
+                    let args = items[1..]
+                        .iter()
+                        .map(|e| self.eval_in(e.clone(), Rc::clone(&scope)))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    match f {
+                        Datum::Lambda(l) => {
+                            let name = l.name.as_deref().unwrap_or("lambda");
+                            if args.len() != l.params.len() {
+                                let e = EvalError::WrongArity(
+                                    name.to_string(),
+                                    l.params.len(),
+                                    args.len(),
+                                );
+                                return Err(e.into());
+                            }
+                            let vars = l.params.iter().cloned().zip(args).collect();
+                            scope = Scope::child(&l.scope, vars);
+                            x = self.eval_body(&l.body, &scope, "lambda")?;
+                        }
+                        Datum::Primitive(name) => return self.apply(name, args),
+                        f => return Err(LispError::NotProcedure(f.to_string())),
+                    }
+                }
+            }
+        }
+    }
+
+    /// 本体bodyの最後以外の式を評価し、末尾位置となる最後の式を返す
+    ///
+    /// 本体が空の場合は、特殊形式formの構文の誤りとする。
+    fn eval_body(
+        &mut self,
+        body: &[Datum],
+        scope: &Rc<Scope>,
+        form: &'static str,
+    ) -> Result<Datum, LispError> {
+        let (last, init) = body.split_last().ok_or(LispError::BadForm(form))?;
+        for e in init {
+            self.eval_in(e.clone(), Rc::clone(scope))?;
+        }
+        Ok(last.clone())
+    }
+
+    /// `(define name expr)`、`(define (name params...) body...)`を評価し、定義した名前を返す
+    fn define(&mut self, items: &[Datum], scope: &Rc<Scope>) -> Result<Datum, LispError> {
+        let (name, d) = match items {
+            [_, Datum::Symbol(name), e] => (name, self.eval_in(e.clone(), Rc::clone(scope))?),
+            [_, Datum::List(head), body @ ..] => match head.split_first() {
+                Some((Datum::Symbol(name), params)) => {
+                    let f = make_lambda(Some(name), params, body, scope, "define")?;
+                    (name, f)
+                }
+                _ => return Err(LispError::BadForm("define")),
+            },
+            _ => return Err(LispError::BadForm("define")),
+        };
+        scope.define(name, d);
+        Ok(Datum::Symbol(name.clone()))
+    }
+
+    /// 変数nameの値を返す
+    ///
+    /// スコープで定義された変数、電卓の定数と評価結果、組み込みの手続きの順に探す。
+    fn lookup(&self, name: &str, scope: &Scope) -> Result<Datum, LispError> {
+        if let Some(d) = scope.get(name) {
+            return Ok(d);
+        }
+        if let Some(v) = self.env.get(name) {
+            return Ok(from_value(v));
+        }
+        let primitive = PRIMITIVES
+            .into_iter()
+            .chain(builtin::names())
+            .find(|p| *p == name);
+        if let Some(p) = primitive {
+            return Ok(Datum::Primitive(p));
+        }
+        let similar = self.env.similar_var(name);
+        Err(EvalError::UndefinedVariable(name.to_string(), similar).into())
+    }
+
+    /// 組み込みの手続きnameを引数argsに適用
+    fn apply(&mut self, name: &'static str, args: Vec<Datum>) -> Result<Datum, LispError> {
+        let value = match name {
+            "list" => return Ok(Datum::List(args)),
+            "cons" => {
+                let [x, xs] = arity::<_, 2>(name, args)?;
+                let Datum::List(mut xs) = xs else {
+                    return Err(EvalError::NotList.into());
+                };
+                xs.insert(0, x);
+                return Ok(Datum::List(xs));
+            }
+            "car" | "cdr" => {
+                let [xs] = arity::<_, 1>(name, args)?;
+                let Datum::List(mut xs) = xs else {
+                    return Err(EvalError::NotList.into());
+                };
+                if xs.is_empty() {
+                    return Err(EvalError::EmptyList.into());
+                }
+                let head = xs.remove(0);
+                return Ok(if name == "car" { head } else { Datum::List(xs) });
+            }
+            "null?" => {
+                let [x] = arity::<_, 1>(name, args)?;
+                Value::Bool(matches!(x, Datum::List(xs) if xs.is_empty()))
+            }
+            "not" => {
+                let [x] = arity::<_, 1>(name, args)?;
+                Value::Bool(!x.is_true())
+            }
+            _ => {
+                let args = args.iter().map(to_value).collect::<Result<Vec<_>, _>>()?;
+                self.apply_value(name, args)?
+            }
+        };
+        Ok(from_value(value))
+    }
+
+    /// 電卓の値を引数とする組み込みの手続きnameを、引数argsに適用
+    fn apply_value(&mut self, name: &'static str, args: Vec<Value>) -> Result<Value, EvalError> {
+        let rounding = self.env.settings.rounding;
+        let mut args = args.into_iter();
+        match name {
+            "+" => args.try_fold(Value::Int(0), Value::add),
+            "*" => args.try_fold(Value::Int(1), Value::mul),
+            "-" | "/" => {
+                let first = args
+                    .next()
+                    .ok_or_else(|| EvalError::WrongArity(name.to_string(), 1, 0))?;
+                let op = |a: Value, b: Value| match name {
+                    "-" => a.sub(b),
+                    _ => a.div(b, rounding),
+                };
+                // 引数が1つの場合は、符号の反転と逆数とする
+                if args.len() == 0 {
+                    let identity = if name == "-" { 0 } else { 1 };
+                    return op(Value::Int(identity), first);
+                }
+                args.try_fold(first, op)
+            }
+            "%" => {
+                let [a, b] = arity::<_, 2>(name, args.collect())?;
+                a.rem(b)
+            }
+            "=" | "<" | ">" | "<=" | ">=" => {
+                let args: Vec<Value> = args.collect();
+                if args.is_empty() {
+                    return Err(EvalError::WrongArity(name.to_string(), 1, 0));
+                }
+                // 隣り合う引数の組がすべて条件を満たす場合に真とする
+                for pair in args.windows(2) {
+                    let holds = match name {
+                        "=" => pair[0].equals(&pair[1])?,
+                        "<" => pair[0].compare(&pair[1], Ordering::is_lt)?.to_bool()?,
+                        ">" => pair[0].compare(&pair[1], Ordering::is_gt)?.to_bool()?,
+                        "<=" => pair[0].compare(&pair[1], Ordering::is_le)?.to_bool()?,
+                        _ => pair[0].compare(&pair[1], Ordering::is_ge)?.to_bool()?,
+                    };
+                    if !holds {
+                        return Ok(Value::Bool(false));
+                    }
+                }
+                Ok(Value::Bool(true))
+            }
+            _ => builtin::call(name, &args.collect::<Vec<_>>(), &mut self.env.rng),
+        }
+    }
+}
+
+/// 引数argsがN個であることを確かめ、配列として返す
+fn arity<T, const N: usize>(name: &str, args: Vec<T>) -> Result<[T; N], EvalError> {
+    let len = args.len();
+    args.try_into()
+        .map_err(|_| EvalError::WrongArity(name.to_string(), N, len))
+}
+
+/// `let`の束縛`(name expr)`を要素の配列として返す
+fn binding_pair(binding: &Datum) -> Result<&[Datum; 2], LispError> {
+    match binding {
+        Datum::List(pair) => pair
+            .as_slice()
+            .try_into()
+            .map_err(|_| LispError::BadForm("let")),
+        _ => Err(LispError::BadForm("let")),
+    }
+}
+
+/// 仮引数paramsと本体bodyから、スコープscopeを定義時のスコープとする手続きを生成
+///
+/// 仮引数がシンボルでない場合や、本体が空の場合は、特殊形式formの構文の誤りとする。
+fn make_lambda(
+    name: Option<&String>,
+    params: &[Datum],
+    body: &[Datum],
+    scope: &Rc<Scope>,
+    form: &'static str,
+) -> Result<Datum, LispError> {
+    let params = params
+        .iter()
+        .map(|p| match p {
+            Datum::Symbol(s) => Ok(s.clone()),
+            _ => Err(LispError::BadForm(form)),
+        })
+        .collect::<Result<_, _>>()?;
+    if body.is_empty() {
+        return Err(LispError::BadForm(form));
+    }
+    Ok(Datum::Lambda(Rc::new(Lambda {
+        name: name.cloned(),
+        params,
+        body: body.to_vec(),
+        scope: Rc::clone(scope),
+    })))
+}
+
+/// トップレベルの式が、表示する値を持たない定義であるかを判定
+pub fn is_definition(x: &Datum) -> bool {
+    matches!(x, Datum::List(items) if matches!(items.first(), Some(Datum::Symbol(s)) if s == "define"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 式を順に評価し、最後の式の値を表示した文字列を返す
+    fn run(interp: &mut Interp, src: &str) -> Result<String, LispError> {
+        Ok(interp
+            .eval_str(src)?
+            .map(|d| d.to_string())
+            .unwrap_or_default())
+    }
+
+    fn lisp(src: &str) -> Result<String, LispError> {
+        run(&mut Interp::new(), src)
+    }
+
+    #[test]
+    fn test_arith() {
+        assert_eq!(lisp("(+ 1 2 3)"), Ok("6".to_string()));
+        assert_eq!(lisp("(+)"), Ok("0".to_string()));
+        assert_eq!(lisp("(- 10 1 2)"), Ok("7".to_string()));
+        assert_eq!(lisp("(- 5)"), Ok("-5".to_string()));
+        assert_eq!(lisp("(/ 7 2)"), Ok("3".to_string()));
+        assert_eq!(lisp("(/ 2.0)"), Ok("0.5".to_string()));
+        assert_eq!(lisp("(* 1.5 (% 7 4))"), Ok("4.5".to_string()));
+        assert_eq!(lisp("(sqrt 16)"), Ok("4.0".to_string()));
+        assert_eq!(lisp("(sum (list 1 2 3))"), Ok("6".to_string()));
+        assert_eq!(lisp("(* 2 pi)"), lisp("tau"));
+        assert_eq!(lisp("(< 1 2 3)"), Ok("true".to_string()));
+        assert_eq!(lisp("(>= 3 3 4)"), Ok("false".to_string()));
+        assert_eq!(lisp("(= 1 1.0)"), Ok("true".to_string()));
+        assert_eq!(
+            lisp("(/ 1 0)"),
+            Err(LispError::Eval(EvalError::DivideByZero))
+        );
+        assert_eq!(lisp("(+ 1 'a)"), Err(LispError::Eval(EvalError::NotNumber)));
+    }
+
+    #[test]
+    fn test_special_forms() {
+        assert_eq!(lisp("'(1 (a b))"), Ok("(1 (a b))".to_string()));
+        assert_eq!(lisp("(if (< 1 2) 'yes 'no)"), Ok("yes".to_string()));
+        assert_eq!(lisp("(if '() 1 2)"), Ok("1".to_string()));
+        assert_eq!(lisp("(if false 1)"), Ok("false".to_string()));
+        assert_eq!(lisp("(let ((x 2) (y 3)) (* x y))"), Ok("6".to_string()));
+        assert_eq!(lisp("(begin (define x 1) (+ x 1))"), Ok("2".to_string()));
+        assert_eq!(lisp("((lambda (x) (* x x)) 7)"), Ok("49".to_string()));
+        assert_eq!(lisp("(define x 1)"), Ok("x".to_string()));
+        assert_eq!(lisp("(if)"), Err(LispError::BadForm("if")));
+        assert_eq!(lisp("(lambda (1) 1)"), Err(LispError::BadForm("lambda")));
+        assert_eq!(lisp("(let ((x)) x)"), Err(LispError::BadForm("let")));
+        assert_eq!(lisp("(1 2)"), Err(LispError::NotProcedure("1".to_string())));
+    }
+
+    #[test]
+    fn test_closure() {
+        let mut interp = Interp::new();
+        run(&mut interp, "(define (adder n) (lambda (x) (+ x n)))").unwrap();
+        run(&mut interp, "(define add2 (adder 2))").unwrap();
+        assert_eq!(run(&mut interp, "(add2 40)"), Ok("42".to_string()));
+        assert_eq!(run(&mut interp, "add2"), Ok("#<lambda>".to_string()));
+        assert_eq!(run(&mut interp, "adder"), Ok("#<lambda adder>".to_string()));
+        assert_eq!(
+            run(&mut interp, "(adder 1 2)"),
+            Err(LispError::Eval(EvalError::WrongArity(
+                "adder".to_string(),
+                1,
+                2
+            )))
+        );
+        // 評価結果は、電卓と同様にansで参照できる
+        assert_eq!(run(&mut interp, "(+ ans 1)"), Ok("43".to_string()));
+    }
+
+    #[test]
+    fn test_list() {
+        assert_eq!(lisp("(cons 1 (list 2 3))"), Ok("(1 2 3)".to_string()));
+        assert_eq!(lisp("(car '(a b))"), Ok("a".to_string()));
+        assert_eq!(lisp("(cdr '(a b))"), Ok("(b)".to_string()));
+        assert_eq!(lisp("(null? (cdr '(a)))"), Ok("true".to_string()));
+        assert_eq!(lisp("(* 2 '(1 2))"), Ok("(2 4)".to_string()));
+        assert_eq!(
+            lisp("(car '())"),
+            Err(LispError::Eval(EvalError::EmptyList))
+        );
+        assert_eq!(lisp("(cons 1 2)"), Err(LispError::Eval(EvalError::NotList)));
+        assert_eq!(
+            lisp(
+                "(define (map f xs) (if (null? xs) '() (cons (f (car xs)) (map f (cdr xs)))))
+                 (map (lambda (x) (* x x)) '(1 2 3))"
+            ),
+            Ok("(1 4 9)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_recursion() {
+        let mut interp = Interp::new();
+        run(
+            &mut interp,
+            "(define (loop n acc) (if (= n 0) acc (loop (- n 1) (+ acc 1))))
+             (define (depth n) (if (= n 0) 0 (+ 1 (depth (- n 1)))))",
+        )
+        .unwrap();
+        // 末尾再帰はネストに数えない
+        assert_eq!(run(&mut interp, "(loop 10000 0)"), Ok("10000".to_string()));
+        assert_eq!(run(&mut interp, "(depth 100)"), Ok("100".to_string()));
+        assert_eq!(
+            run(&mut interp, "(depth 100000)"),
+            Err(LispError::Eval(EvalError::RecursionLimit))
+        );
+    }
+
+    #[test]
+    fn test_undefined() {
+        assert_eq!(
+            lisp("(+ x 1)"),
+            Err(LispError::Eval(EvalError::UndefinedVariable(
+                "x".to_string(),
+                None
+            )))
+        );
+        assert_eq!(
+            lisp("(+ pj 1)"),
+            Err(LispError::Eval(EvalError::UndefinedVariable(
+                "pj".to_string(),
+                Some("pi".to_string())
+            )))
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! s式のテキストを読み込み、Datumに変換
+//!
+//! `(`と`)`で囲んだ要素の並びをリストとし、`'x`は`(quote x)`として読み込む。
+//! `;`から行末まではコメントとして無視する。
+//!
+//! 数値として読める要素は電卓の値（整数、多倍長整数、浮動小数点数）に、
+//! `true`、`#t`、`false`、`#f`は真偽値に、それ以外はシンボルとなる。
+use super::{Datum, LispError};
+use crate::{parser::DEFAULT_MAX_DEPTH, value::Value};
+use num_bigint::BigInt;
+use std::mem;
+
+/// テキストsrcに含まれるすべての式を読み込む
+///
+/// リストのネストの深さがDEFAULT_MAX_DEPTHを超える場合はエラー。
+/// 深いネストでもスタックを使い果たさないよう、再帰せずに読み込む。
+pub fn read(src: &str) -> Result<Vec<Datum>, LispError> {
+    // 開いているリストごとに、それまでの外側のリストの要素と、`(`の前の`'`の数を積む
+    let mut frames: Vec<(Vec<Datum>, usize)> = Vec::new();
+    let mut items = Vec::new();
+    let mut quotes = 0;
+    for token in tokenize(src) {
+        let datum = match token {
+            "(" => {
+                if frames.len() >= DEFAULT_MAX_DEPTH {
+                    return Err(LispError::TooDeep);
+                }
+                frames.push((mem::take(&mut items), quotes));
+                quotes = 0;
+                continue;
+            }
+            ")" => {
+                let (outer, q) = frames.pop().ok_or(LispError::UnexpectedParen)?;
+                if quotes > 0 {
+                    return Err(LispError::UnexpectedParen);
+                }
+                quotes = q;
+                Datum::List(mem::replace(&mut items, outer))
+            }
+            "'" => {
+                quotes += 1;
+                continue;
+            }
+            atom => parse_atom(atom),
+        };
+        let datum = (0..quotes).fold(datum, |d, _| {
+            Datum::List(vec![Datum::Symbol("quote".to_string()), d])
+        });
+        quotes = 0;
+        items.push(datum);
+    }
+
+    if !frames.is_empty() || quotes > 0 {
+        return Err(LispError::UnexpectedEof);
+    }
+    Ok(items)
+}
+
+/// テキストを`(`、`)`、`'`と、それ以外の要素に分割する
+fn tokenize(src: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = src;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c == ';' {
+            rest = rest.find('\n').map_or("", |i| &rest[i..]);
+        } else if matches!(c, '(' | ')' | '\'') {
+            tokens.push(&rest[..1]);
+            rest = &rest[1..];
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '\'' | ';'))
+                .unwrap_or(rest.len());
+            tokens.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+    }
+    tokens
+}
+
+/// 括弧以外の要素を、値かシンボルに変換する
+fn parse_atom(s: &str) -> Datum {
+    match s {
+        "true" | "#t" => return Datum::Value(Value::Bool(true)),
+        "false" | "#f" => return Datum::Value(Value::Bool(false)),
+        _ => {}
+    }
+
+    // `+`や`-`のみ、`inf`や`nan`などは数値ではなくシンボルとする
+    let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+    let digits = digits.strip_prefix('.').unwrap_or(digits);
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return Datum::Symbol(s.to_string());
+    }
+    if let Ok(n) = s.parse::<i64>() {
+        Datum::Value(Value::Int(n))
+    } else if let Ok(n) = s.parse::<BigInt>() {
+        Datum::Value(Value::Big(n))
+    } else if let Ok(x) = s.parse::<f64>() {
+        Datum::Value(Value::Float(x))
+    } else {
+        Datum::Symbol(s.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 読み込んだ式を表示した文字列
+    fn show(src: &str) -> Result<Vec<String>, LispError> {
+        Ok(read(src)?.iter().map(|d| d.to_string()).collect())
+    }
+
+    #[test]
+    fn test_read() {
+        assert_eq!(
+            show("(+ 1 2.5) ; comment\n'(a b) x"),
+            Ok(vec![
+                "(+ 1 2.5)".to_string(),
+                "(quote (a b))".to_string(),
+                "x".to_string(),
+            ])
+        );
+        assert_eq!(
+            show("(define (f) '()) #t -3 -x 1e3 99999999999999999999"),
+            Ok(vec![
+                "(define (f) (quote ()))".to_string(),
+                "true".to_string(),
+                "-3".to_string(),
+                "-x".to_string(),
+                "1000.0".to_string(),
+                "99999999999999999999".to_string(),
+            ])
+        );
+        assert_eq!(read("''a").unwrap()[0].to_string(), "(quote (quote a))");
+        assert_eq!(read("").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_read_error() {
+        assert_eq!(read("(+ 1").unwrap_err(), LispError::UnexpectedEof);
+        assert_eq!(read("'").unwrap_err(), LispError::UnexpectedEof);
+        assert_eq!(read("1)").unwrap_err(), LispError::UnexpectedParen);
+        assert_eq!(read("(')").unwrap_err(), LispError::UnexpectedParen);
+        assert_eq!(read(&"(".repeat(100_000)).unwrap_err(), LispError::TooDeep);
+    }
+}
@@ -0,0 +1,1327 @@
+//! 数式をパースし、抽象構文木に変換
+//!
+//! 前置記法（`+ 1 2`）、後置記法（`1 2 +`）、中置記法（`1 + 2`）に対応し、
+//! いずれも同じExprに変換する。
+//!
+//! 行の先頭に`x = `と記述すると、記法に関わらず、続く式の値を変数xに代入する。
+//! 同様に、`fn f(x, y) = `と記述すると、続く式を本体とする関数fを定義する。
+//!
+//! 1行に`;`で区切って複数の式を記述できる。例: `x = 3; y = 4; x*x + y*y`
+//! `#`から行末まではコメントとして無視する。
+//!
+//! `[1, 2, 3]`のように、`[]`で囲んで`,`で区切った式はリストとなる。
+//! 各要素は、それぞれの記法の式として記述する。
+//!
+//! Exprは中置記法のほか、`to_latex`でLaTeXの数式として、`to_dot`でGraphvizのDOT言語の木として表示できる。
+//!
+//! スタックモードの入力行は、式ではなくスタックへの操作の並びとしてstackモジュールでパースする。
+mod infix;
+mod latex;
+mod postfix;
+mod prefix;
+pub mod stack;
+
+use crate::unit;
+use helper::lexer::{self, Span, Token, TokenKind};
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, digit1, one_of, satisfy},
+    combinator::{cut, map, map_res, not, opt, recognize, verify},
+    error::ErrorKind,
+    multi::{many0, separated_list0},
+    sequence::{delimited, pair, preceded, terminated, tuple},
+    IResult,
+};
+use num_bigint::BigInt;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    str::FromStr,
+};
+use tracing::instrument;
+
+/// パース結果を表すための型
+type PResult<'a, T> = IResult<&'a str, T, ParseError<'a>>;
+
+/// 数式の記法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Notation {
+    Prefix,
+    Postfix,
+    Infix,
+}
+
+/// 1行の入力を表す型
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Stmt {
+    Expr(Expr),
+    FnDef(String, Vec<String>, Expr), // 関数名、引数名、本体
+}
+
+/// 抽象構文木を表現するための型
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Expr {
+    Num(i64),
+    BigNum(BigInt), // i64の範囲を超える整数のリテラル
+    Float(f64),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Rem(Box<Expr>, Box<Expr>),
+    Pow(Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+    Fact(Box<Expr>), // 階乗
+    BitAnd(Box<Expr>, Box<Expr>),
+    BitOr(Box<Expr>, Box<Expr>),
+    BitXor(Box<Expr>, Box<Expr>),
+    Shl(Box<Expr>, Box<Expr>),
+    Shr(Box<Expr>, Box<Expr>),
+    BitNot(Box<Expr>),
+    Bool(bool),
+    Eq(Box<Expr>, Box<Expr>),
+    Ne(Box<Expr>, Box<Expr>),
+    Lt(Box<Expr>, Box<Expr>),
+    Le(Box<Expr>, Box<Expr>),
+    Gt(Box<Expr>, Box<Expr>),
+    Ge(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>), // 条件、真の場合の式、偽の場合の式
+    Var(String),                         // 変数の参照
+    Assign(String, Box<Expr>),           // 変数への代入
+    History(usize),                      // `$1`のような、n番目の評価結果の参照
+    Unit(String),                        // `km/h`のような単位
+    Call(String, Vec<Expr>),             // 関数呼び出し
+    List(Vec<Expr>),                     // リスト
+    Index(Box<Expr>, Box<Expr>),         // リストの要素の参照
+}
+
+/// パースエラーの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    ExpectedNumber,                // 数値がない
+    ExpectedExpr,                  // 数値または演算子がない
+    ExpectedOperator,              // 演算子がない
+    ExpectedChar(char),            // 閉じ括弧などの文字がない
+    ExpectedKeyword(&'static str), // thenなどのキーワードがない
+    TrailingInput,                 // 式の後に余分な入力がある
+    MissingOperand,                // 演算子の被演算子が足りない
+    OutOfRange,                    // 数値が範囲外
+    InvalidSeparator,              // 数字の区切りの`_`の位置が誤っている
+    TooDeep,                       // ネストが深すぎる
+    NotAnExpression,               // 関数定義など、式でない文
+    DuplicateParam,                // 関数の引数名が重複している
+    Nom(ErrorKind),                // その他のnomのエラー
+}
+
+/// パースエラーを表すための型
+///
+/// inputはエラーが発生した位置以降の入力。
+/// 識別子、数値、二項演算子はhelper::lexerの字句解析器で読み込み、エラーの位置も字句の範囲で示す。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError<'a> {
+    pub input: &'a str,
+    pub kind: ParseErrorKind,
+}
+
+impl<'a> ParseError<'a> {
+    fn new(input: &'a str, kind: ParseErrorKind) -> Self {
+        ParseError { input, kind }
+    }
+
+    /// 入力行lineにおけるエラーの位置（バイト単位）を返す
+    ///
+    /// `;`で区切られた式のエラーも行全体での位置となるよう、
+    /// 残りの入力の長さではなく、lineの先頭からのオフセットで求める。
+    pub fn position(&self, line: &str) -> usize {
+        let offset = (self.input.as_ptr() as usize).wrapping_sub(line.as_ptr() as usize);
+        if offset <= line.len() {
+            offset
+        } else {
+            line.len() - self.input.len()
+        }
+    }
+
+    /// 入力行lineにおけるエラーの範囲を返す
+    ///
+    /// エラーの位置から始まる字句の範囲とする。区切りの`_`の誤りは、その1文字を範囲とする。
+    pub fn span(&self, line: &str) -> Span {
+        let start = self.position(line);
+        let len = match (self.kind, lexer::scan(&line[start..])) {
+            (ParseErrorKind::InvalidSeparator, _) => 1,
+            (_, Ok(Some(token))) => token.span.end,
+            (_, Ok(None)) => 0,
+            (_, Err(e)) => e.span.end,
+        };
+        Span::new(start, start + len)
+    }
+
+    /// 入力行lineが途中で終わっているためのエラーかを判定
+    ///
+    /// 閉じ括弧や被演算子がないまま行末に達した場合など、続きを入力すれば
+    /// パースできる可能性がある場合にtrueとなる。
+    pub fn is_incomplete(&self, line: &str) -> bool {
+        let at_end = line[self.position(line)..].trim().is_empty();
+        at_end
+            && matches!(
+                self.kind,
+                ParseErrorKind::ExpectedNumber
+                    | ParseErrorKind::ExpectedExpr
+                    | ParseErrorKind::ExpectedOperator
+                    | ParseErrorKind::ExpectedChar(_)
+                    | ParseErrorKind::ExpectedKeyword(_)
+            )
+    }
+
+    /// 入力行と、エラーの範囲を指すキャレットを含む診断メッセージを返す
+    ///
+    /// ```text
+    /// ParseError: expected an operator
+    ///   1 + foo bar
+    ///           ^^^
+    /// ```
+    pub fn diagnostic(&self, line: &str) -> String {
+        self.span(line).diagnostic(line, self)
+    }
+}
+
+impl Display for ParseError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ParseErrorKind::ExpectedNumber => write!(f, "ParseError: expected a number"),
+            ParseErrorKind::ExpectedExpr => {
+                write!(f, "ParseError: expected a number or an operator")
+            }
+            ParseErrorKind::ExpectedOperator => write!(f, "ParseError: expected an operator"),
+            ParseErrorKind::ExpectedChar(c) => write!(f, "ParseError: expected '{c}'"),
+            ParseErrorKind::ExpectedKeyword(k) => write!(f, "ParseError: expected '{k}'"),
+            ParseErrorKind::TrailingInput => {
+                write!(f, "ParseError: unexpected input after the expression")
+            }
+            ParseErrorKind::MissingOperand => {
+                write!(f, "ParseError: missing operand for the operator")
+            }
+            ParseErrorKind::OutOfRange => write!(f, "ParseError: number out of range"),
+            ParseErrorKind::InvalidSeparator => {
+                write!(f, "ParseError: misplaced digit separator '_'")
+            }
+            ParseErrorKind::TooDeep => write!(f, "ParseError: expression nested too deeply"),
+            ParseErrorKind::DuplicateParam => write!(f, "ParseError: duplicate parameter name"),
+            ParseErrorKind::NotAnExpression => {
+                write!(f, "ParseError: expected an expression, not a statement")
+            }
+            ParseErrorKind::Nom(kind) => {
+                write!(f, "ParseError: unexpected input ({})", kind.description())
+            }
+        }
+    }
+}
+
+impl Error for ParseError<'_> {}
+
+/// nomのパーサで利用するための実装
+impl<'a> nom::error::ParseError<&'a str> for ParseError<'a> {
+    fn from_error_kind(input: &'a str, kind: ErrorKind) -> Self {
+        ParseError::new(input, ParseErrorKind::Nom(kind))
+    }
+
+    fn append(_input: &'a str, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    fn from_char(input: &'a str, c: char) -> Self {
+        ParseError::new(input, ParseErrorKind::ExpectedChar(c))
+    }
+
+    /// altで全ての候補が失敗した場合は、最も先まで読み進めた候補のエラーを採用する
+    fn or(self, other: Self) -> Self {
+        if other.input.len() < self.input.len() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// map_resで利用するための実装
+impl<'a, E> nom::error::FromExternalError<&'a str, E> for ParseError<'a> {
+    fn from_external_error(input: &'a str, kind: ErrorKind, _e: E) -> Self {
+        ParseError::new(input, ParseErrorKind::Nom(kind))
+    }
+}
+
+/// 回復可能なエラーをNoneに変換する
+///
+/// 回復不能なエラーはそのまま返す。
+fn optional<'a, T>(
+    result: PResult<'a, T>,
+) -> Result<Option<(&'a str, T)>, nom::Err<ParseError<'a>>> {
+    match result {
+        Ok(r) => Ok(Some(r)),
+        Err(nom::Err::Error(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// 回復不能なパースエラーを生成
+fn fail(c: &str, kind: ParseErrorKind) -> nom::Err<ParseError<'_>> {
+    nom::Err::Failure(ParseError::new(c, kind))
+}
+
+/// 入力cの先頭で発生したエラーを、kindの種類のエラーに置き換える
+///
+/// cより先で発生したエラーや、回復不能なエラーはそのまま返す。
+fn expect<'a, T>(c: &'a str, kind: ParseErrorKind, result: PResult<'a, T>) -> PResult<'a, T> {
+    match result {
+        Err(nom::Err::Error(e)) if e.input.len() == c.len() => {
+            Err(nom::Err::Error(ParseError::new(c, kind)))
+        }
+        _ => result,
+    }
+}
+
+impl FromStr for Notation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prefix" => Ok(Notation::Prefix),
+            "rpn" | "postfix" => Ok(Notation::Postfix),
+            "infix" => Ok(Notation::Infix),
+            _ => Err(format!(
+                "unknown notation '{s}' (expected rpn, infix or prefix)"
+            )),
+        }
+    }
+}
+
+impl Display for Notation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Notation::Prefix => write!(f, "prefix"),
+            Notation::Postfix => write!(f, "rpn"),
+            Notation::Infix => write!(f, "infix"),
+        }
+    }
+}
+
+/// 括弧などのネストの深さの上限のデフォルト値
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// 括弧などのネストの深さの上限として指定できる最大値
+///
+/// 式を再帰的にパースや評価する関数は、ネスト1段ごとにスタックを使う。
+/// デバッグビルドでもメインスレッドの8MiBのスタックに余裕を持って収まる値とする。
+pub const MAX_MAX_DEPTH: usize = 512;
+
+/// 指定された記法で、`;`で区切られた1行の入力をパース
+///
+/// `#`以降のコメントと、空の式は無視する。
+pub fn parse_line(
+    c: &str,
+    notation: Notation,
+    max_depth: usize,
+) -> Result<Vec<Stmt>, ParseError<'_>> {
+    strip_comment(c)
+        .split(';')
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| parse(s, notation, max_depth))
+        .collect()
+}
+
+/// 行から`#`以降のコメントを除く
+pub fn strip_comment(c: &str) -> &str {
+    c.split('#').next().unwrap_or_default()
+}
+
+/// 指定された記法で1つの式をパース
+///
+/// 式の後には空白以外の入力があってはならず、残った入力がある場合は
+/// その位置を示すエラーとなる。
+/// max_depthは式のネストの深さの上限で、深すぎる式はParseErrorKind::TooDeepのエラーとなる。
+/// これにより、パースした式を再帰的に処理する評価器などでスタックが溢れないようにする。
+#[instrument(level = "debug", skip(max_depth))]
+pub fn parse(c: &str, notation: Notation, max_depth: usize) -> Result<Stmt, ParseError<'_>> {
+    let (body, head) = match parse_fn_head(c) {
+        Ok((body, head)) => (body, Some(head)),
+        Err(nom::Err::Failure(e)) => return Err(e),
+        Err(_) => (c, None),
+    };
+    let (body, name) = match parse_assign(body) {
+        Ok((body, name)) if head.is_none() => (body, Some(name)),
+        _ => (body, None),
+    };
+
+    let e = parse_expr(body, notation, max_depth)?;
+    match (head, name) {
+        (Some((name, params)), _) => Ok(Stmt::FnDef(name.to_string(), params, e)),
+        (None, Some(name)) => Ok(Stmt::Expr(Expr::Assign(name.to_string(), Box::new(e)))),
+        (None, None) => Ok(Stmt::Expr(e)),
+    }
+}
+
+/// 指定された記法で、代入や関数定義を含まない1つの式をパース
+///
+/// parseと同様に、式の後に残った入力がある場合はエラーとなる。
+pub fn parse_expr(c: &str, notation: Notation, max_depth: usize) -> Result<Expr, ParseError<'_>> {
+    let result = match notation {
+        Notation::Prefix => prefix::parse_expr(c, max_depth),
+        Notation::Postfix => postfix::parse_expr(c, max_depth),
+        Notation::Infix => infix::parse_expr(c, max_depth),
+    };
+
+    let (c1, e) = match result {
+        Ok(r) => r,
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => return Err(e),
+        Err(nom::Err::Incomplete(_)) => {
+            return Err(ParseError::new(c, ParseErrorKind::Nom(ErrorKind::Complete)))
+        }
+    };
+
+    let c1 = c1.trim_start_matches(' ');
+    if c1.is_empty() {
+        Ok(e)
+    } else if notation == Notation::Infix {
+        // 中置記法では、式の後には演算子が続くはず
+        Err(ParseError::new(c1, ParseErrorKind::ExpectedOperator))
+    } else {
+        Err(ParseError::new(c1, ParseErrorKind::TrailingInput))
+    }
+}
+
+/// 代入先の変数名と`=`をパース
+fn parse_assign(c: &str) -> PResult<'_, &str> {
+    let (c, _) = many0(char(' '))(c)?;
+    let (c, name) = parse_ident(c)?;
+    let (c, _) = many0(char(' '))(c)?;
+    let (c, _) = terminated(char('='), not(char('=')))(c)?;
+    Ok((c, name))
+}
+
+/// `fn f(x, y) =`のような関数定義の、本体より前の部分をパース
+///
+/// 関数名と引数名を返す。`fn`以降でのエラーは回復不能なエラーとなる。
+/// `fn f(x, x) =`のように同じ引数名が2回以上現れる場合は、2回目の位置を示すエラーとする。
+fn parse_fn_head(c: &str) -> PResult<'_, (&str, Vec<String>)> {
+    let ws = || many0(char(' '));
+    let (c, _) = preceded(ws(), keyword("fn"))(c)?;
+    let (rest, (name, params)) = cut(pair(
+        preceded(ws(), parse_ident),
+        delimited(
+            pair(ws(), char('(')),
+            separated_list0(pair(ws(), char(',')), preceded(ws(), parse_ident)),
+            pair(ws(), char(')')),
+        ),
+    ))(c)?;
+    for (i, param) in params.iter().enumerate() {
+        if params[..i].contains(param) {
+            // 引数名は入力の一部なので、その位置以降の入力をエラーの位置とする
+            let offset = param.as_ptr() as usize - c.as_ptr() as usize;
+            return Err(nom::Err::Failure(ParseError::new(
+                &c[offset..],
+                ParseErrorKind::DuplicateParam,
+            )));
+        }
+    }
+    let (rest, _) = cut(pair(ws(), char('=')))(rest)?;
+    Ok((
+        rest,
+        (name, params.into_iter().map(str::to_string).collect()),
+    ))
+}
+
+/// キーワードとして予約された識別子
+const KEYWORDS: [&str; 9] = [
+    "if",
+    "then",
+    "else",
+    "true",
+    "false",
+    "neg",
+    "factorial",
+    "xor",
+    "fn",
+];
+
+/// 入力cの先頭の字句を読み込み、kindの種類でvalidを満たす場合はその文字列を返す
+///
+/// 字句解析のエラーや、条件を満たさない字句の場合は、cの位置の回復可能なエラーとなる。
+fn token<'a>(c: &'a str, kind: TokenKind, valid: impl Fn(&str) -> bool) -> PResult<'a, &'a str> {
+    match lexer::scan(c) {
+        Ok(Some(Token { kind: k, text, .. })) if k == kind && valid(text) => {
+            Ok((&c[text.len()..], text))
+        }
+        _ => Err(nom::Err::Error(ParseError::new(
+            c,
+            ParseErrorKind::Nom(ErrorKind::Verify),
+        ))),
+    }
+}
+
+/// 識別子をパース
+///
+/// 識別子は英字または`_`で始まり、英数字または`_`が続く。キーワードは識別子とならない。
+fn parse_ident(c: &str) -> PResult<'_, &str> {
+    token(c, TokenKind::Ident, |ident| !KEYWORDS.contains(&ident))
+}
+
+/// 変数の参照、または`$1`のような評価結果の履歴の参照をパース
+fn parse_var(c: &str) -> PResult<'_, Expr> {
+    alt((
+        map_res(preceded(char('$'), digit1), |n: &str| {
+            n.parse().map(Expr::History)
+        }),
+        map(parse_ident, |name| Expr::Var(name.to_string())),
+    ))(c)
+}
+
+/// `max(1, 2)`のような関数呼び出しをパース
+///
+/// 各引数はargでパースする。`(`以降でのエラーは回復不能なエラーとなる。
+fn parse_call<'a>(c: &'a str, arg: impl FnMut(&'a str) -> PResult<'a, Expr>) -> PResult<'a, Expr> {
+    let (c, name) = parse_ident(c)?;
+    let (c, _) = char('(')(c)?;
+    let (c, args) = parse_elems(c, arg)?;
+    let (c, _) = many0(char(' '))(c)?;
+    let (c, _) = cut(char(')'))(c)?;
+    Ok((c, Expr::Call(name.to_string(), args)))
+}
+
+/// 関数の引数やリストの要素のような、`,`で区切られた0個以上の式をパース
+///
+/// 各式はelemでパースする。`,`の後に式がない場合は回復不能なエラーとなる。
+/// これにより、`max(1,`のように`,`で終わる入力は、`,`ではなく行末の位置を示すエラーとなり、
+/// ParseError::is_incompleteで途中の入力として扱える。
+fn parse_elems<'a>(
+    c: &'a str,
+    mut elem: impl FnMut(&'a str) -> PResult<'a, Expr>,
+) -> PResult<'a, Vec<Expr>> {
+    let (mut c, first) = match elem(c) {
+        Ok(r) => r,
+        Err(nom::Err::Error(_)) => return Ok((c, Vec::new())),
+        Err(e) => return Err(e),
+    };
+    let mut elems = vec![first];
+    while let Ok((rest, _)) = preceded(many0(char(' ')), char::<_, ParseError>(','))(c) {
+        let (rest, e) = cut(&mut elem)(rest)?;
+        elems.push(e);
+        c = rest;
+    }
+    Ok((c, elems))
+}
+
+/// `[1, 2, 3]`のようなリストをパース
+///
+/// 各要素はelemでパースする。`[`以降でのエラーは回復不能なエラーとなる。
+/// `[0.9 .. 1.1]`のように2つの式を`..`で区切った場合は、区間`interval(0.9, 1.1)`とする。
+fn parse_list<'a>(
+    c: &'a str,
+    mut elem: impl FnMut(&'a str) -> PResult<'a, Expr>,
+) -> PResult<'a, Expr> {
+    let (c, _) = char('[')(c)?;
+    let (c, mut elems) = parse_elems(c, &mut elem)?;
+    let (c, _) = many0(char(' '))(c)?;
+
+    if let (1, Ok((c, _))) = (elems.len(), tag::<_, _, ParseError>("..")(c)) {
+        let (c, hi) = cut(elem)(c)?;
+        let (c, _) = many0(char(' '))(c)?;
+        let (c, _) = cut(char(']'))(c)?;
+        elems.push(hi);
+        return Ok((c, Expr::Call("interval".to_string(), elems)));
+    }
+
+    let (c, _) = cut(char(']'))(c)?;
+    Ok((c, Expr::List(elems)))
+}
+
+/// 数値をパース
+///
+/// `3.14`や`1e-3`のように小数点や指数を含む場合は浮動小数点数、それ以外は整数とする。
+/// 整数は`0xFF`、`0b1010`、`0o755`のように16進数、2進数、8進数でも記述できる。
+/// また、`1_000_000`のように数字の間に`_`を区切りとして記述できる。
+/// i64の範囲を超える整数は多倍長整数のリテラルとし、評価時に整数の幅の設定に従って扱う。
+/// `4i`や`2.5i`のように10進数の直後に`i`を記述すると、虚数単位`i`との積とする。
+/// `3 km`や`90 km/h`のように数値の後に単位を記述すると、数値と単位の積とする。
+fn parse_num(c: &str) -> PResult<'_, Expr> {
+    let (c1, var) = recognize(unsigned_num)(c)?;
+    let (c1, e) = to_num(c, c1, var)?;
+    let (c1, e) = imaginary(c1, var, e)?;
+    with_unit(c1, e, true)
+}
+
+/// 符号付きの数値をパース
+///
+/// `-5`のように、数字の直前に空白を挟まず`-`がある場合は負の数とする。
+/// `- 5`のように空白を挟んだ場合は、減算の演算子とみなされる。
+/// 単位は、空白で区切ると別の式となるため、`3km`のように数値の直後に記述する。
+fn parse_signed_num(c: &str) -> PResult<'_, Expr> {
+    let (c1, var) = recognize(pair(opt(char('-')), unsigned_num))(c)?;
+    let (c1, e) = to_num(c, c1, var)?;
+    let (c1, e) = imaginary(c1, var, e)?;
+    with_unit(c1, e, false)
+}
+
+/// 数値eの後に単位がある場合は、数値eと単位の積とする
+///
+/// spacedがtrueの場合は、数値と単位の間に空白を挟んでもよい。
+/// 単位は`km`のような名前を`*`や`/`で組み合わせたもので、各名前には`^2`のように指数を付けられる。
+/// 単位の表にない名前は単位とみなさない。
+fn with_unit(c: &str, e: Expr, spaced: bool) -> PResult<'_, Expr> {
+    let term = || {
+        recognize(pair(
+            verify(parse_ident, |name: &str| unit::is_unit(name)),
+            opt(tuple((char('^'), opt(char('-')), digit1))),
+        ))
+    };
+    let (c1, _) = if spaced {
+        many0(char(' '))(c)?
+    } else {
+        (c, vec![])
+    };
+    match recognize(pair(term(), many0(pair(one_of("*/"), term()))))(c1) {
+        Ok((c1, name)) => Ok((
+            c1,
+            Expr::Mul(Box::new(e), Box::new(Expr::Unit(name.to_string()))),
+        )),
+        Err(_) => Ok((c, e)),
+    }
+}
+
+/// 10進数の数値の直後に`i`がある場合は、数値eと虚数単位`i`の積とする
+///
+/// varはパースした数値の文字列。`2if`のように、`i`に続けて識別子の文字がある場合は積としない。
+fn imaginary<'a>(c: &'a str, var: &str, e: Expr) -> PResult<'a, Expr> {
+    if split_radix(var).is_some() {
+        return Ok((c, e));
+    }
+    let suffix: PResult<'_, char> =
+        terminated(char('i'), not(satisfy(|c| c.is_alphanumeric() || c == '_')))(c);
+    match suffix {
+        Ok((c, _)) => Ok((
+            c,
+            Expr::Mul(Box::new(e), Box::new(Expr::Var("i".to_string()))),
+        )),
+        Err(_) => Ok((c, e)),
+    }
+}
+
+/// 符号なしの数値を読み込む
+///
+/// 接頭辞付きの16進数、2進数、8進数と、10進数の整数または浮動小数点数を、字句解析器の数値として読み込む。
+/// 先頭は数字に限る（`_1`は数値とみなさない）。
+fn unsigned_num(c: &str) -> PResult<'_, ()> {
+    let (c, _) = token(c, TokenKind::Number, |_| true)?;
+    Ok((c, ()))
+}
+
+/// パースした数値の文字列varをExprに変換
+///
+/// cはパース前、c1はパース後の入力。
+/// 区切りの`_`の位置が誤っている場合は、その位置を示すエラーとなる。
+fn to_num<'a>(c: &'a str, c1: &'a str, var: &str) -> PResult<'a, Expr> {
+    if let Some(pos) = invalid_separator(var) {
+        return Err(fail(&c[pos..], ParseErrorKind::InvalidSeparator));
+    }
+    let var = &var.replace('_', "");
+
+    if let Some((radix, digits)) = split_radix(var) {
+        // 符号を付けてから変換することで、i64::MINも表現できるようにする
+        let sign = if var.starts_with('-') { "-" } else { "" };
+        if let Ok(n) = i64::from_str_radix(&format!("{sign}{digits}"), radix) {
+            return Ok((c1, Expr::Num(n)));
+        }
+        if let Some(n) = BigInt::parse_bytes(format!("{sign}{digits}").as_bytes(), radix) {
+            return Ok((c1, Expr::BigNum(n)));
+        }
+    } else if var.contains(['.', 'e', 'E']) {
+        if let Ok(x) = var.parse::<f64>() {
+            return Ok((c1, Expr::Float(x)));
+        }
+    } else if let Ok(n) = var.parse::<i64>() {
+        return Ok((c1, Expr::Num(n)));
+    } else if let Ok(n) = var.parse::<BigInt>() {
+        return Ok((c1, Expr::BigNum(n)));
+    }
+
+    Err(fail(c, ParseErrorKind::OutOfRange))
+}
+
+/// 区切りの`_`が数字の間以外にある場合、その位置を返す
+///
+/// `1__0`、`1_`、`1_.5`、`0x_FF`などは誤りとする。
+fn invalid_separator(var: &str) -> Option<usize> {
+    let bytes = var.as_bytes();
+    let is_digit = |i: usize| bytes.get(i).is_some_and(|b| b.is_ascii_hexdigit());
+    let hex = split_radix(var).is_some_and(|(radix, _)| radix == 16);
+
+    bytes.iter().enumerate().find_map(|(i, &b)| {
+        if b != b'_' {
+            return None;
+        }
+        // 10進数では`e`は指数を表すため、数字とはみなさない
+        let digit = |i: usize| is_digit(i) && (hex || bytes[i].is_ascii_digit());
+        if i > 0 && digit(i - 1) && digit(i + 1) && !is_radix_prefix(&var[..i]) {
+            None
+        } else {
+            Some(i)
+        }
+    })
+}
+
+/// 文字列が`0x`などの基数の接頭辞で終わるか判定
+fn is_radix_prefix(s: &str) -> bool {
+    let s = s.strip_prefix('-').unwrap_or(s);
+    s.len() == 2 && split_radix(s).is_some()
+}
+
+/// 数値の文字列から基数の接頭辞を取り除き、基数と数字部分を返す
+///
+/// 接頭辞がない場合はNoneを返す。
+fn split_radix(var: &str) -> Option<(u32, &str)> {
+    let var = var.strip_prefix('-').unwrap_or(var);
+    let radix = match var.get(..2)? {
+        "0x" | "0X" => 16,
+        "0b" | "0B" => 2,
+        "0o" | "0O" => 8,
+        _ => return None,
+    };
+    Some((radix, &var[2..]))
+}
+
+/// キーワードをパース
+///
+/// `iffy`のように、キーワードの直後に英数字が続く場合はキーワードとみなさない。
+fn keyword<'a>(word: &'static str) -> impl FnMut(&'a str) -> PResult<'a, &'a str> {
+    terminated(tag(word), not(satisfy(|c| c.is_alphanumeric() || c == '_')))
+}
+
+/// 空白に続くキーワードをパース
+///
+/// キーワードがない場合は、それを示すエラーとなる。
+fn expect_keyword<'a>(c: &'a str, word: &'static str) -> PResult<'a, &'a str> {
+    let (c, _) = many0(char(' '))(c)?;
+    expect(c, ParseErrorKind::ExpectedKeyword(word), keyword(word)(c))
+}
+
+/// 真偽値をパース
+fn parse_bool(c: &str) -> PResult<'_, Expr> {
+    alt((
+        map(keyword("true"), |_| Expr::Bool(true)),
+        map(keyword("false"), |_| Expr::Bool(false)),
+    ))(c)
+}
+
+/// 条件式を生成
+fn if_expr(cond: Expr, then: Expr, els: Expr) -> Expr {
+    Expr::If(Box::new(cond), Box::new(then), Box::new(els))
+}
+
+/// 単項演算子をパース
+///
+/// 前置記法と後置記法では、`-`は減算を表すため、単項マイナスは`neg`と記述する。
+/// `~`はビット反転、`!`は論理否定を表す。
+fn parse_unary(c: &str) -> PResult<'_, &str> {
+    alt((
+        keyword("neg"),
+        keyword("factorial"),
+        tag("~"),
+        terminated(tag("!"), not(char('='))),
+    ))(c)
+}
+
+/// 記号の二項演算子
+const BINARY_OPERATORS: [&str; 18] = [
+    "+", "-", "*", "/", "%", "^", "&", "|", "<<", ">>", "==", "!=", "<", "<=", ">", ">=", "&&",
+    "||",
+];
+
+/// 二項演算子をパース
+///
+/// 記号の演算子は字句解析器で最長一致で読み込むため、`<=`は`<`と`=`に分かれない。
+fn parse_operator(c: &str) -> PResult<'_, &str> {
+    alt((keyword("xor"), |c| {
+        token(c, TokenKind::Op, |op| BINARY_OPERATORS.contains(&op))
+    }))(c)
+}
+
+/// 単項演算子と式から単項演算の式を生成
+fn unary(op: &str, e: Expr) -> Option<Expr> {
+    match op {
+        "neg" | "-" => Some(Expr::Neg(Box::new(e))),
+        "factorial" => Some(Expr::Fact(Box::new(e))),
+        "~" => Some(Expr::BitNot(Box::new(e))),
+        "!" => Some(Expr::Not(Box::new(e))),
+        _ => None,
+    }
+}
+
+/// 演算子と2つの式から二項演算の式を生成
+fn binary(op: &str, e1: Expr, e2: Expr) -> Option<Expr> {
+    let (e1, e2) = (Box::new(e1), Box::new(e2));
+    match op {
+        "+" => Some(Expr::Add(e1, e2)),
+        "-" => Some(Expr::Sub(e1, e2)),
+        "*" => Some(Expr::Mul(e1, e2)),
+        "/" => Some(Expr::Div(e1, e2)),
+        "%" => Some(Expr::Rem(e1, e2)),
+        "^" => Some(Expr::Pow(e1, e2)),
+        "&" => Some(Expr::BitAnd(e1, e2)),
+        "|" => Some(Expr::BitOr(e1, e2)),
+        "xor" => Some(Expr::BitXor(e1, e2)),
+        "<<" => Some(Expr::Shl(e1, e2)),
+        ">>" => Some(Expr::Shr(e1, e2)),
+        "==" => Some(Expr::Eq(e1, e2)),
+        "!=" => Some(Expr::Ne(e1, e2)),
+        "<" => Some(Expr::Lt(e1, e2)),
+        "<=" => Some(Expr::Le(e1, e2)),
+        ">" => Some(Expr::Gt(e1, e2)),
+        ">=" => Some(Expr::Ge(e1, e2)),
+        "&&" => Some(Expr::And(e1, e2)),
+        "||" => Some(Expr::Or(e1, e2)),
+        _ => None,
+    }
+}
+
+/// 二項演算の式を、演算子と2つの式に分解
+///
+/// binaryの逆で、二項演算でない式の場合はNone。
+fn split_binary(e: &Expr) -> Option<(&'static str, &Expr, &Expr)> {
+    let (op, e1, e2) = match e {
+        Expr::Add(e1, e2) => ("+", e1, e2),
+        Expr::Sub(e1, e2) => ("-", e1, e2),
+        Expr::Mul(e1, e2) => ("*", e1, e2),
+        Expr::Div(e1, e2) => ("/", e1, e2),
+        Expr::Rem(e1, e2) => ("%", e1, e2),
+        Expr::Pow(e1, e2) => ("^", e1, e2),
+        Expr::BitAnd(e1, e2) => ("&", e1, e2),
+        Expr::BitOr(e1, e2) => ("|", e1, e2),
+        Expr::BitXor(e1, e2) => ("xor", e1, e2),
+        Expr::Shl(e1, e2) => ("<<", e1, e2),
+        Expr::Shr(e1, e2) => (">>", e1, e2),
+        Expr::Eq(e1, e2) => ("==", e1, e2),
+        Expr::Ne(e1, e2) => ("!=", e1, e2),
+        Expr::Lt(e1, e2) => ("<", e1, e2),
+        Expr::Le(e1, e2) => ("<=", e1, e2),
+        Expr::Gt(e1, e2) => (">", e1, e2),
+        Expr::Ge(e1, e2) => (">=", e1, e2),
+        Expr::And(e1, e2) => ("&&", e1, e2),
+        Expr::Or(e1, e2) => ("||", e1, e2),
+        _ => return None,
+    };
+    Some((op, e1, e2))
+}
+
+impl Expr {
+    /// 節点の名前と子の式を返す
+    ///
+    /// 定数や変数などの葉の場合は、子の式をNoneとする。
+    fn node(&self) -> (String, Option<Vec<&Expr>>) {
+        if let Some((op, e1, e2)) = split_binary(self) {
+            return (op.to_string(), Some(vec![e1, e2]));
+        }
+
+        match self {
+            Expr::Num(n) => (n.to_string(), None),
+            Expr::BigNum(n) => (n.to_string(), None),
+            Expr::Float(x) => (format!("{x:?}"), None),
+            Expr::Bool(b) => (b.to_string(), None),
+            Expr::Var(name) => (name.clone(), None),
+            Expr::History(n) => (format!("${n}"), None),
+            Expr::Unit(name) => (name.clone(), None),
+            Expr::Neg(e) => ("neg".to_string(), Some(vec![e])),
+            Expr::Fact(e) => ("factorial".to_string(), Some(vec![e])),
+            Expr::BitNot(e) => ("~".to_string(), Some(vec![e])),
+            Expr::Not(e) => ("!".to_string(), Some(vec![e])),
+            Expr::If(cond, then, els) => ("if".to_string(), Some(vec![cond, then, els])),
+            Expr::Assign(name, e) => (format!("= {name}"), Some(vec![e])),
+            Expr::Call(name, args) => (name.clone(), Some(args.iter().collect())),
+            Expr::List(es) => ("list".to_string(), Some(es.iter().collect())),
+            Expr::Index(e, i) => ("index".to_string(), Some(vec![e, i])),
+            _ => unreachable!("binary operators are handled above"),
+        }
+    }
+
+    /// 子の式を返す
+    pub fn children(&self) -> Vec<&Expr> {
+        self.node().1.unwrap_or_default()
+    }
+
+    /// 式の木の深さを返す
+    ///
+    /// 定数や変数などの葉のみの式の深さを1とする。
+    pub fn depth(&self) -> usize {
+        1 + self.children().iter().map(|e| e.depth()).max().unwrap_or(0)
+    }
+
+    /// S式で表した文字列を返す
+    ///
+    /// 例: `1 + 2 * 3` は `(+ 1 (* 2 3))` となる。
+    pub fn to_sexp(&self) -> String {
+        match self.node() {
+            (label, None) => label,
+            (label, Some(children)) => {
+                let mut s = format!("({label}");
+                for e in children {
+                    s.push(' ');
+                    s.push_str(&e.to_sexp());
+                }
+                s.push(')');
+                s
+            }
+        }
+    }
+
+    /// 字下げした木で表した文字列を返す
+    ///
+    /// 各節点を1行に表示し、子の節点を罫線でつなぐ。
+    pub fn to_tree(&self) -> String {
+        let mut s = String::new();
+        self.write_tree(&mut s, "", "");
+        s
+    }
+
+    /// 木の各行をsに追加する
+    ///
+    /// headはこの節点の行の前に、restは子の節点の行の前に付ける文字列。
+    fn write_tree(&self, s: &mut String, head: &str, rest: &str) {
+        let (label, children) = self.node();
+        s.push_str(head);
+        s.push_str(&label);
+        s.push('\n');
+
+        let children = children.unwrap_or_default();
+        for (i, e) in children.iter().enumerate() {
+            if i + 1 < children.len() {
+                e.write_tree(s, &format!("{rest}├── "), &format!("{rest}│   "));
+            } else {
+                e.write_tree(s, &format!("{rest}└── "), &format!("{rest}    "));
+            }
+        }
+    }
+
+    /// GraphvizのDOT言語で表した有向グラフを返す
+    ///
+    /// 各節点に`n0`から順に番号を付け、親から子へ辺を引く。子の順序は左から右となる。
+    pub fn to_dot(&self) -> String {
+        let mut s = String::from("digraph ast {\n    node [shape=box];\n");
+        self.write_dot(&mut s, &mut 0);
+        s.push_str("}\n");
+        s
+    }
+
+    /// この節点と子の節点、辺をsに追加し、この節点の番号を返す
+    ///
+    /// nextは次に付ける番号。
+    fn write_dot(&self, s: &mut String, next: &mut usize) -> usize {
+        let (label, children) = self.node();
+        let id = *next;
+        *next += 1;
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        s.push_str(&format!("    n{id} [label=\"{label}\"];\n"));
+        for e in children.unwrap_or_default() {
+            let child = e.write_dot(s, next);
+            s.push_str(&format!("    n{id} -> n{child};\n"));
+        }
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 式の入力をパース
+    fn parse_expr(c: &str, notation: Notation) -> Result<Expr, ParseError<'_>> {
+        match parse(c, notation, DEFAULT_MAX_DEPTH)? {
+            Stmt::Expr(e) => Ok(e),
+            s => panic!("not an expression: {s:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_num() {
+        assert_eq!(parse_num("42"), Ok(("", Expr::Num(42))));
+        assert_eq!(parse_num("2.5"), Ok(("", Expr::Float(2.5))));
+        assert_eq!(parse_num("0xFF"), Ok(("", Expr::Num(255))));
+        assert_eq!(parse_num("0b1010"), Ok(("", Expr::Num(10))));
+        assert_eq!(parse_num("0o755"), Ok(("", Expr::Num(493))));
+        assert_eq!(parse_signed_num("-0x10"), Ok(("", Expr::Num(-16))));
+        assert_eq!(
+            parse_signed_num("-0x8000000000000000"),
+            Ok(("", Expr::Num(i64::MIN)))
+        );
+
+        // i64の範囲を超える整数は多倍長整数のリテラルとする
+        let big = |s: &str| Expr::BigNum(s.parse().unwrap());
+        assert_eq!(
+            parse_num("123456789012345678901234567890"),
+            Ok(("", big("123456789012345678901234567890")))
+        );
+        assert_eq!(
+            parse_num("0x8000000000000000"),
+            Ok(("", big("9223372036854775808")))
+        );
+        assert_eq!(
+            parse_signed_num("-0x8000000000000001"),
+            Ok(("", big("-9223372036854775809")))
+        );
+
+        // 10進数の直後の`i`は、虚数単位との積とする
+        let imag = |e| Expr::Mul(Box::new(e), Box::new(Expr::Var("i".to_string())));
+        assert_eq!(parse_num("4i"), Ok(("", imag(Expr::Num(4)))));
+        assert_eq!(parse_signed_num("-2.5i"), Ok(("", imag(Expr::Float(-2.5)))));
+        assert_eq!(parse_num("2if"), Ok(("if", Expr::Num(2))));
+        assert_eq!(parse_num("0x1i"), Ok(("i", Expr::Num(1))));
+
+        // 数値の後の単位は、単位との積とする。単位の表にない名前は単位としない
+        let unit = |e, u: &str| Expr::Mul(Box::new(e), Box::new(Expr::Unit(u.to_string())));
+        assert_eq!(parse_num("3 km"), Ok(("", unit(Expr::Num(3), "km"))));
+        assert_eq!(
+            parse_num("90 km/h*x"),
+            Ok(("*x", unit(Expr::Num(90), "km/h")))
+        );
+        assert_eq!(parse_num("2 m/s^2"), Ok(("", unit(Expr::Num(2), "m/s^2"))));
+        assert_eq!(parse_num("2 x"), Ok((" x", Expr::Num(2))));
+        assert_eq!(parse_num("2 mx"), Ok((" mx", Expr::Num(2))));
+        assert_eq!(
+            parse_signed_num("-3km"),
+            Ok(("", unit(Expr::Num(-3), "km")))
+        );
+        assert_eq!(parse_signed_num("3 km"), Ok((" km", Expr::Num(3))));
+
+        // 接頭辞の後に数字がない場合は、0までを数値とみなす
+        assert_eq!(parse_num("0x"), Ok(("x", Expr::Num(0))));
+    }
+
+    #[test]
+    fn test_trailing_input() {
+        let parse = |c| parse_expr(c, Notation::Prefix);
+        assert_eq!(
+            parse("+ 1 2  "),
+            Ok(Expr::Add(Box::new(Expr::Num(1)), Box::new(Expr::Num(2))))
+        );
+
+        // 式の後に残った入力は、その位置を示すエラー
+        let c = "+ 1 2 garbage";
+        assert_eq!(
+            parse(c),
+            Err(ParseError::new(&c[6..], ParseErrorKind::TrailingInput))
+        );
+        let c = "(1 + 2))";
+        assert_eq!(
+            super::parse(c, Notation::Infix, DEFAULT_MAX_DEPTH),
+            Err(ParseError::new(&c[7..], ParseErrorKind::ExpectedOperator))
+        );
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let diagnostic = |c, notation| {
+            parse(c, notation, DEFAULT_MAX_DEPTH)
+                .unwrap_err()
+                .diagnostic(c)
+        };
+
+        assert_eq!(
+            diagnostic("1 + *", Notation::Infix),
+            "ParseError: expected a number\n  1 + *\n      ^"
+        );
+        assert_eq!(
+            diagnostic("(1 + 2", Notation::Infix),
+            "ParseError: expected ')'\n  (1 + 2\n        ^"
+        );
+        assert_eq!(
+            diagnostic("if true then 1", Notation::Infix),
+            "ParseError: expected 'else'\n  if true then 1\n                ^"
+        );
+        assert_eq!(
+            diagnostic("+ 1", Notation::Prefix),
+            "ParseError: expected a number or an operator\n  + 1\n     ^"
+        );
+        assert_eq!(
+            diagnostic("1 2", Notation::Postfix),
+            "ParseError: expected an operator\n  1 2\n     ^"
+        );
+        assert_eq!(
+            diagnostic("1 +", Notation::Postfix),
+            "ParseError: missing operand for the operator\n  1 +\n    ^"
+        );
+
+        // エラーの位置から始まる字句全体を指す
+        assert_eq!(
+            diagnostic("1 + foo bar", Notation::Infix),
+            "ParseError: expected an operator\n  1 + foo bar\n          ^^^"
+        );
+        assert_eq!(
+            diagnostic("1 + 2 0x1F", Notation::Infix),
+            "ParseError: expected an operator\n  1 + 2 0x1F\n        ^^^^"
+        );
+        assert_eq!(
+            diagnostic("1_000__0", Notation::Infix),
+            "ParseError: misplaced digit separator '_'\n  1_000__0\n       ^"
+        );
+    }
+
+    #[test]
+    fn test_incomplete() {
+        let incomplete = |c, notation| {
+            parse_line(c, notation, DEFAULT_MAX_DEPTH)
+                .err()
+                .is_some_and(|e| e.is_incomplete(c))
+        };
+
+        assert!(incomplete("(1 + 2", Notation::Infix));
+        assert!(incomplete("1 *  ", Notation::Infix));
+        assert!(incomplete("x = 1; max(1, 2", Notation::Infix));
+        assert!(incomplete("if x then 1", Notation::Infix));
+        assert!(incomplete("+ 1", Notation::Prefix));
+        assert!(incomplete("1 2", Notation::Postfix));
+        assert!(incomplete("[1, 2", Notation::Postfix));
+        assert!(incomplete("max(1,", Notation::Infix));
+        assert!(incomplete("[1, ", Notation::Infix));
+        assert!(incomplete("max(1,", Notation::Prefix));
+
+        // 行の途中のエラーは、続きを入力してもパースできない
+        assert!(!incomplete("1 + 2)", Notation::Infix));
+        assert!(!incomplete("(1 +; 2", Notation::Infix));
+        assert!(!incomplete("1 +", Notation::Postfix));
+        assert!(!incomplete("1 + 2", Notation::Infix));
+        assert!(!incomplete("max(1, )", Notation::Infix));
+        assert!(!incomplete("[1, ]", Notation::Infix));
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let parse = |c| parse_line(c, Notation::Infix, DEFAULT_MAX_DEPTH);
+
+        assert_eq!(
+            parse("x = 3; ; x + 1;"),
+            Ok(vec![
+                Stmt::Expr(Expr::Assign("x".to_string(), Box::new(Expr::Num(3)))),
+                Stmt::Expr(Expr::Add(
+                    Box::new(Expr::Var("x".to_string())),
+                    Box::new(Expr::Num(1))
+                )),
+            ])
+        );
+        assert_eq!(parse("  "), Ok(vec![]));
+
+        // コメント
+        assert_eq!(parse("# only"), Ok(vec![]));
+        assert_eq!(
+            parse("1 + 2 # note"),
+            Ok(vec![Stmt::Expr(Expr::Add(
+                Box::new(Expr::Num(1)),
+                Box::new(Expr::Num(2))
+            ))])
+        );
+        assert_eq!(
+            parse("1 + 2 # 3; 4"),
+            Ok(vec![Stmt::Expr(Expr::Add(
+                Box::new(Expr::Num(1)),
+                Box::new(Expr::Num(2))
+            ))])
+        );
+
+        // エラーの位置は行全体での位置
+        let c = "1; 2 +; 3";
+        let e = parse(c).unwrap_err();
+        assert_eq!(e.position(c), 6);
+    }
+
+    #[test]
+    fn test_parse_assign() {
+        let parse = parse_expr;
+        let var = |name: &str| Box::new(Expr::Var(name.to_string()));
+
+        assert_eq!(
+            parse("x = y + 1", Notation::Infix),
+            Ok(Expr::Assign(
+                "x".to_string(),
+                Box::new(Expr::Add(var("y"), Box::new(Expr::Num(1))))
+            ))
+        );
+        assert_eq!(
+            parse(" _a1 = 1 x_ +", Notation::Postfix),
+            Ok(Expr::Assign(
+                "_a1".to_string(),
+                Box::new(Expr::Add(Box::new(Expr::Num(1)), var("x_")))
+            ))
+        );
+
+        assert_eq!(
+            parse("$2 * ans", Notation::Infix),
+            Ok(Expr::Mul(Box::new(Expr::History(2)), var("ans")))
+        );
+
+        // `==`は比較演算子
+        assert_eq!(
+            parse("x == 1", Notation::Infix),
+            Ok(Expr::Eq(var("x"), Box::new(Expr::Num(1))))
+        );
+        // キーワードは変数名にできない
+        assert!(parse("if = 1", Notation::Infix).is_err());
+        assert!(parse("x = ", Notation::Infix).is_err());
+    }
+
+    #[test]
+    fn test_parse_fn_def() {
+        let parse = |c, notation| parse(c, notation, DEFAULT_MAX_DEPTH);
+        let var = |name: &str| Box::new(Expr::Var(name.to_string()));
+
+        assert_eq!(
+            parse("fn f(x, y) = x * y", Notation::Infix),
+            Ok(Stmt::FnDef(
+                "f".to_string(),
+                vec!["x".to_string(), "y".to_string()],
+                Expr::Mul(var("x"), var("y"))
+            ))
+        );
+        assert_eq!(
+            parse("fn one() = 1", Notation::Postfix),
+            Ok(Stmt::FnDef("one".to_string(), vec![], Expr::Num(1)))
+        );
+
+        let c = "fn f(x) 1";
+        assert_eq!(
+            parse(c, Notation::Infix),
+            Err(ParseError::new(&c[8..], ParseErrorKind::ExpectedChar('=')))
+        );
+        assert!(parse("fn f(1) = 1", Notation::Infix).is_err());
+        assert!(parse("fn = 1", Notation::Infix).is_err());
+
+        // 引数名の重複は、2回目の引数名の位置を示すエラー
+        let c = "fn f(x, y, x) = x";
+        assert_eq!(
+            parse(c, Notation::Infix),
+            Err(ParseError::new(&c[11..], ParseErrorKind::DuplicateParam))
+        );
+        assert_eq!(
+            parse(c, Notation::Infix).unwrap_err().diagnostic(c),
+            "ParseError: duplicate parameter name\n  fn f(x, y, x) = x\n             ^"
+        );
+        assert!(parse("fn f(x, xy) = x", Notation::Infix).is_ok());
+    }
+
+    #[test]
+    fn test_digit_separator() {
+        assert_eq!(parse_num("1_000_000"), Ok(("", Expr::Num(1_000_000))));
+        assert_eq!(parse_num("0xFF_FF"), Ok(("", Expr::Num(0xFFFF))));
+        assert_eq!(parse_num("1_0.2_5e1_0"), Ok(("", Expr::Float(10.25e10))));
+        assert_eq!(parse_signed_num("-1_0"), Ok(("", Expr::Num(-10))));
+
+        // 数字の間以外にある区切りは、その位置を示すエラー
+        let fail = |input| fail(input, ParseErrorKind::InvalidSeparator);
+        let c = "1__0";
+        assert_eq!(parse_num(c), Err(fail(&c[1..])));
+        let c = "10_";
+        assert_eq!(parse_num(c), Err(fail(&c[2..])));
+        let c = "1_.5";
+        assert_eq!(parse_num(c), Err(fail(&c[1..])));
+        let c = "1_e5";
+        assert_eq!(parse_num(c), Err(fail(&c[1..])));
+        let c = "0x_FF";
+        assert_eq!(parse_num(c), Err(fail(&c[2..])));
+
+        // 先頭の`_`は数値ではない
+        assert!(parse_num("_1").is_err());
+    }
+
+    #[test]
+    fn test_print_ast() {
+        let e = parse_expr("x = -1 + max(2, [a]) * 3!", Notation::Infix).unwrap();
+        assert_eq!(
+            e.to_sexp(),
+            "(= x (+ (neg 1) (* (max 2 (list a)) (factorial 3))))"
+        );
+        assert_eq!(
+            e.to_tree(),
+            "\
+= x
+└── +
+    ├── neg
+    │   └── 1
+    └── *
+        ├── max
+        │   ├── 2
+        │   └── list
+        │       └── a
+        └── factorial
+            └── 3
+"
+        );
+
+        let e = parse_expr("if p then f() else xs[0]", Notation::Infix).unwrap();
+        assert_eq!(e.to_sexp(), "(if p (f) (index xs 0))");
+
+        let e = parse_expr("-x * 2", Notation::Infix).unwrap();
+        assert_eq!(
+            e.to_dot(),
+            "\
+digraph ast {
+    node [shape=box];
+    n0 [label=\"*\"];
+    n1 [label=\"neg\"];
+    n2 [label=\"x\"];
+    n1 -> n2;
+    n0 -> n1;
+    n3 [label=\"2\"];
+    n0 -> n3;
+}
+"
+        );
+
+        // ラベルの`"`と`\`はエスケープする
+        let e = Expr::Call("f".to_string(), vec![Expr::Var("a\"b\\c".to_string())]);
+        assert_eq!(
+            e.to_dot(),
+            r#"digraph ast {
+    node [shape=box];
+    n0 [label="f"];
+    n1 [label="a\"b\\c"];
+    n0 -> n1;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let too_deep = |c: &str, notation| {
+            matches!(
+                parse(c, notation, 64),
+                Err(ParseError {
+                    kind: ParseErrorKind::TooDeep,
+                    ..
+                })
+            )
+        };
+
+        // 括弧のない左結合の演算子の連続も、木が深くなるためエラー
+        let infix = format!("1{}", " + 1".repeat(100_000));
+        assert!(too_deep(&infix, Notation::Infix));
+        let prefix = format!("{}1", "+ 1 ".repeat(100_000));
+        assert!(too_deep(&prefix, Notation::Prefix));
+        let postfix = format!("1{}", " 1 +".repeat(100_000));
+        assert!(too_deep(&postfix, Notation::Postfix));
+        let call = format!("{}1{}", "abs(".repeat(100), ")".repeat(100));
+        assert!(too_deep(&call, Notation::Postfix));
+
+        // 上限以内の深さであればパースできる
+        let infix = format!("1{}", " + 1".repeat(62));
+        match parse(&infix, Notation::Infix, 64) {
+            Ok(Stmt::Expr(e)) => assert_eq!(e.depth(), 63),
+            r => panic!("unexpected result: {r:?}"),
+        }
+        let prefix = format!("{}1", "+ 1 ".repeat(62));
+        assert!(parse(&prefix, Notation::Prefix, 64).is_ok());
+        let postfix = format!("1{}", " 1 +".repeat(62));
+        assert!(parse(&postfix, Notation::Postfix, 64).is_ok());
+    }
+}
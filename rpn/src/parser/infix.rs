@@ -0,0 +1,492 @@
+//! 中置記法の数式をパース
+//!
+//! 演算子の優先順位はPratt構文解析法で扱う。
+//! 例: `1 + 2 * 3` は 1 + (2 * 3) を表す。
+//!
+//! 式の先頭や演算子の直後にある`-`は単項マイナス、`~`はビット反転、`!`は論理否定となる。
+//! 単項演算子はべき乗より優先順位が低く、`-2 ^ 2` は -(2 ^ 2) を表す。
+//! 式の後の`!`は階乗で、どの演算子よりも優先順位が高い。例: `-2 ^ 3!` は -(2 ^ (3!)) を表す。
+//! 同様に、式の後の`[i]`はリストのi番目（0始まり）の要素を表す。例: `xs[0] + 1`
+//!
+//! 二項演算子の優先順位は、低い順に`||`、`&&`、比較演算子、`|`、`xor`、`&`、
+//! `<<`と`>>`、`+`と`-`、`*`と`/`と`%`、`^`となる。
+//! 例: `1 | 2 + 3` は 1 | (2 + 3) を、`1 < 2 && 3 < 4` は (1 < 2) && (3 < 4) を表す。
+//!
+//! `(1 + 2) * 3`のように括弧でまとめた式は、優先順位に関わらず先に評価される。
+//! 深くネストした入力でスタックが溢れないよう、再帰の深さと式の木の深さはmax_depthまでに制限する。
+//! `1 + 1 + 1 + …`のような左結合の演算子の連なりは、再帰せずに深い木となるため、木の深さで制限する。
+//!
+//! 条件式は`if 条件 then 式 else 式`と記述する。elseの後の式は可能な限り長く読み込むため、
+//! `if c then 1 else 2 + 3` は if c then 1 else (2 + 3) を表す。
+//!
+//! Exprは中置記法で表示でき、表示した文字列をパースすると同じ構造の式に戻る。
+//! ただし、負の数の定数は単項マイナスの式に戻る。
+use super::{
+    binary, expect, expect_keyword, fail, if_expr, keyword, optional, parse_bool, parse_call,
+    parse_list, parse_num, parse_operator, parse_var, split_binary, Expr, PResult, ParseError,
+    ParseErrorKind,
+};
+use nom::{
+    bytes::complete::tag,
+    character::complete::{char, one_of},
+    combinator::not,
+    multi::many0,
+    sequence::terminated,
+};
+use num_traits::{Signed, ToPrimitive};
+use std::fmt::{self, Display};
+
+/// 単項演算子の右結合力
+pub(super) const UNARY_BP: u8 = 19;
+
+/// 中置記法の式をパース
+///
+/// 括弧や演算子のネストや、式の木の深さがmax_depthを超える場合は、
+/// ParseErrorKind::TooDeepのエラーとなる。
+pub fn parse_expr(c: &str, max_depth: usize) -> PResult<'_, Expr> {
+    parse_bp(c, 0, max_depth)
+}
+
+/// 結合力がmin_bp以上の演算子を含む式をパース
+///
+/// 左辺を読み込んだ後、結合力がmin_bp以上の演算子が続く限り、
+/// 右辺をその演算子の右結合力で再帰的にパースして左辺と結合する。
+/// depthは残りの再帰可能な深さ。
+fn parse_bp(c: &str, min_bp: u8, depth: usize) -> PResult<'_, Expr> {
+    let (c, _) = many0(char(' '))(c)?;
+    if depth == 0 {
+        return Err(fail(c, ParseErrorKind::TooDeep));
+    }
+
+    let (mut c, mut lhs) = if let Ok((c, op)) = one_of::<_, _, ParseError>("-~!")(c) {
+        let (c, e) = parse_bp(c, UNARY_BP, depth - 1)?;
+        match op {
+            '-' => (c, neg(e)),
+            '~' => (c, Expr::BitNot(Box::new(e))),
+            _ => (c, Expr::Not(Box::new(e))),
+        }
+    } else if let Ok((c, _)) = char::<_, ParseError>('(')(c) {
+        let (c, e) = parse_bp(c, 0, depth - 1)?;
+        let (c, _) = many0(char(' '))(c)?;
+        let (c, _) = char(')')(c)?;
+        (c, e)
+    } else if let Ok((c, _)) = keyword("if")(c) {
+        let (c, cond) = parse_bp(c, 0, depth - 1)?;
+        let (c, _) = expect_keyword(c, "then")?;
+        let (c, then) = parse_bp(c, 0, depth - 1)?;
+        let (c, _) = expect_keyword(c, "else")?;
+        let (c, els) = parse_bp(c, 0, depth - 1)?;
+        (c, if_expr(cond, then, els))
+    } else if let Ok(r) = parse_bool(c) {
+        r
+    } else if let Some(r) = optional(parse_call(c, |c| parse_bp(c, 0, depth - 1)))? {
+        r
+    } else if let Some(r) = optional(parse_list(c, |c| parse_bp(c, 0, depth - 1)))? {
+        r
+    } else if let Ok(r) = parse_var(c) {
+        r
+    } else {
+        expect(c, ParseErrorKind::ExpectedNumber, parse_num(c))?
+    };
+
+    // `1 + 1 + 1 + …`は再帰せずに左辺を包んでいくため、左辺の木の深さを追跡して制限する
+    let mut lhs_depth = lhs.depth();
+    loop {
+        let (c1, _) = many0(char(' '))(c)?;
+
+        // 後置の階乗演算子は、`!=`と区別する
+        let (c2, rhs_depth) =
+            if let Ok((c2, _)) = terminated(tag::<_, _, ParseError>("!"), not(char('=')))(c1) {
+                lhs = Expr::Fact(Box::new(lhs));
+                (c2, 0)
+            } else if let Ok((c2, _)) = char::<_, ParseError>('[')(c1) {
+                let (c2, index) = parse_bp(c2, 0, depth - 1)?;
+                let (c2, _) = many0(char(' '))(c2)?;
+                let (c2, _) = char(']')(c2)?;
+                let index_depth = index.depth();
+                lhs = Expr::Index(Box::new(lhs), Box::new(index));
+                (c2, index_depth)
+            } else if let Ok((c2, op)) = parse_operator(c1) {
+                let (l_bp, r_bp) = binding_power(op);
+                if l_bp < min_bp {
+                    break;
+                }
+                let (c3, rhs) = parse_bp(c2, r_bp, depth - 1)?;
+                let rhs_depth = rhs.depth();
+                lhs = binary(op, lhs, rhs).expect("operator is a binary operator");
+                (c3, rhs_depth)
+            } else {
+                break;
+            };
+
+        lhs_depth = lhs_depth.max(rhs_depth) + 1;
+        if lhs_depth > depth {
+            return Err(fail(c1, ParseErrorKind::TooDeep));
+        }
+        c = c2;
+    }
+
+    Ok((c, lhs))
+}
+
+/// 単項演算子`-`を式eに適用した式を返す
+///
+/// 多倍長整数のリテラルには符号を含め、`-9223372036854775808`のようにi64の範囲に収まる場合は負の整数とする。
+/// 符号を付ける前の2^63はi64の範囲を超えるため、評価してから符号を反転するとオーバーフローとなる。
+fn neg(e: Expr) -> Expr {
+    match e {
+        Expr::BigNum(n) => {
+            let n = -n;
+            n.to_i64().map_or(Expr::BigNum(n), Expr::Num)
+        }
+        e => Expr::Neg(Box::new(e)),
+    }
+}
+
+/// 二項演算子の(左結合力, 右結合力)を返す
+///
+/// 値が大きいほど優先順位が高い。左結合力 < 右結合力 の場合は左結合となり、
+/// 左結合力 > 右結合力 の場合は右結合となる。
+pub(super) fn binding_power(op: &str) -> (u8, u8) {
+    match op {
+        "||" => (1, 2),
+        "&&" => (3, 4),
+        "==" | "!=" | "<" | "<=" | ">" | ">=" => (5, 6),
+        "|" => (7, 8),
+        "xor" => (9, 10),
+        "&" => (11, 12),
+        "<<" | ">>" => (13, 14),
+        "+" | "-" => (15, 16),
+        "^" => (20, 19),
+        _ => (17, 18),
+    }
+}
+
+/// 中置記法で表示
+///
+/// 演算子の優先順位と結合性から、必要な箇所にのみ括弧を付ける。
+/// 数値と単位の積は、パースし直せるよう`3 km`のように数値の後に単位を記述する。
+impl Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Expr::Mul(e1, e2) = self {
+            if let (Expr::Num(_) | Expr::BigNum(_) | Expr::Float(_), Expr::Unit(name)) =
+                (&**e1, &**e2)
+            {
+                return write!(f, "{e1} {name}");
+            }
+        }
+
+        if let Some((op, e1, e2)) = split_binary(self) {
+            let (l_bp, r_bp) = binding_power(op);
+            // 左辺は、その演算子の右結合力がl_bp以下の場合に括弧が必要
+            let paren1 = match split_binary(e1) {
+                Some((op1, _, _)) => binding_power(op1).1 <= l_bp,
+                None => is_prefix(e1) && l_bp >= UNARY_BP || matches!(e1, Expr::If(..)),
+            };
+            // 右辺は、その演算子の左結合力がr_bp未満の場合に括弧が必要
+            let paren2 = match split_binary(e2) {
+                Some((op2, _, _)) => binding_power(op2).0 < r_bp,
+                None => matches!(e2, Expr::If(..)),
+            };
+            write_operand(f, e1, paren1)?;
+            write!(f, " {op} ")?;
+            return write_operand(f, e2, paren2);
+        }
+
+        match self {
+            Expr::Num(n) => write!(f, "{n}"),
+            Expr::BigNum(n) => write!(f, "{n}"),
+            // 整数と区別するため、常に小数点または指数を付ける
+            Expr::Float(x) => write!(f, "{x:?}"),
+            Expr::Bool(b) => write!(f, "{b}"),
+            Expr::Var(name) => write!(f, "{name}"),
+            Expr::History(n) => write!(f, "${n}"),
+            Expr::Unit(name) => write!(f, "{name}"),
+            Expr::Assign(name, e) => write!(f, "{name} = {e}"),
+            Expr::Call(name, args) => {
+                write!(f, "{name}(")?;
+                write_list(f, args)?;
+                write!(f, ")")
+            }
+            Expr::List(es) => {
+                write!(f, "[")?;
+                write_list(f, es)?;
+                write!(f, "]")
+            }
+            Expr::Neg(e) | Expr::BitNot(e) | Expr::Not(e) => {
+                let op = match self {
+                    Expr::Neg(_) => "-",
+                    Expr::BitNot(_) => "~",
+                    _ => "!",
+                };
+                // 被演算子は、単項演算子より優先順位が高い演算のみ括弧なしで書ける
+                let paren = match split_binary(e) {
+                    Some((op, _, _)) => binding_power(op).0 < UNARY_BP,
+                    None => matches!(**e, Expr::If(..)),
+                };
+                write!(f, "{op}")?;
+                write_operand(f, e, paren)
+            }
+            Expr::Fact(e) => {
+                write_operand(f, e, !is_atom(e))?;
+                write!(f, "!")
+            }
+            Expr::Index(e, i) => {
+                write_operand(f, e, !is_atom(e))?;
+                write!(f, "[{i}]")
+            }
+            Expr::If(cond, then, els) => write!(f, "if {cond} then {then} else {els}"),
+            _ => unreachable!("binary operators are handled above"),
+        }
+    }
+}
+
+/// 式を表示し、parenがtrueの場合は括弧で囲む
+fn write_operand(f: &mut fmt::Formatter<'_>, e: &Expr, paren: bool) -> fmt::Result {
+    if paren {
+        write!(f, "({e})")
+    } else {
+        write!(f, "{e}")
+    }
+}
+
+/// 式を`, `で区切って表示
+fn write_list(f: &mut fmt::Formatter<'_>, es: &[Expr]) -> fmt::Result {
+    for (i, e) in es.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{e}")?;
+    }
+    Ok(())
+}
+
+/// 単項演算子で始まる式として表示されるか
+pub(super) fn is_prefix(e: &Expr) -> bool {
+    match e {
+        Expr::Num(n) => *n < 0,
+        Expr::BigNum(n) => n.is_negative(),
+        Expr::Float(x) => x.is_sign_negative(),
+        Expr::Neg(_) | Expr::BitNot(_) | Expr::Not(_) => true,
+        _ => false,
+    }
+}
+
+/// 後置演算子の被演算子として、括弧なしで表示できるか
+pub(super) fn is_atom(e: &Expr) -> bool {
+    match e {
+        Expr::Num(_) | Expr::BigNum(_) | Expr::Float(_) => !is_prefix(e),
+        Expr::Bool(_)
+        | Expr::Var(_)
+        | Expr::History(_)
+        | Expr::Unit(_)
+        | Expr::Call(..)
+        | Expr::List(_)
+        | Expr::Fact(_)
+        | Expr::Index(..) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(n: i64) -> Box<Expr> {
+        Box::new(Expr::Num(n))
+    }
+
+    fn parse_expr(c: &str) -> PResult<'_, Expr> {
+        super::parse_expr(c, 64)
+    }
+
+    #[test]
+    fn test_parse_infix() {
+        assert_eq!(
+            parse_expr("1 + 2 * 3").unwrap().1,
+            Expr::Add(num(1), Box::new(Expr::Mul(num(2), num(3))))
+        );
+        assert_eq!(
+            parse_expr("1*2+3").unwrap().1,
+            Expr::Add(Box::new(Expr::Mul(num(1), num(2))), num(3))
+        );
+
+        // 同じ優先順位の演算子は左結合
+        assert_eq!(
+            parse_expr("5 - 2 - 1").unwrap().1,
+            Expr::Sub(Box::new(Expr::Sub(num(5), num(2))), num(1))
+        );
+
+        // べき乗は右結合で、乗算より優先順位が高い
+        assert_eq!(
+            parse_expr("2 ^ 3 ^ 2").unwrap().1,
+            Expr::Pow(num(2), Box::new(Expr::Pow(num(3), num(2))))
+        );
+        assert_eq!(
+            parse_expr("2 * 3 ^ 2").unwrap().1,
+            Expr::Mul(num(2), Box::new(Expr::Pow(num(3), num(2))))
+        );
+
+        // 単項マイナス
+        assert_eq!(
+            parse_expr("-2 ^ 2").unwrap().1,
+            Expr::Neg(Box::new(Expr::Pow(num(2), num(2))))
+        );
+        assert_eq!(
+            parse_expr("1 - -2 * 3").unwrap().1,
+            Expr::Sub(
+                num(1),
+                Box::new(Expr::Mul(Box::new(Expr::Neg(num(2))), num(3)))
+            )
+        );
+
+        // 階乗
+        assert_eq!(
+            parse_expr("-2 ^ 3!").unwrap().1,
+            Expr::Neg(Box::new(Expr::Pow(num(2), Box::new(Expr::Fact(num(3))))))
+        );
+        assert_eq!(
+            parse_expr("3!! != 1").unwrap().1,
+            Expr::Ne(Box::new(Expr::Fact(Box::new(Expr::Fact(num(3))))), num(1))
+        );
+
+        assert!(parse_expr("+ 1").is_err());
+        assert!(parse_expr("1 +").is_err());
+    }
+
+    #[test]
+    fn test_parse_bitwise() {
+        // ビット演算子は算術演算子より優先順位が低い
+        assert_eq!(
+            parse_expr("1 | 2 + 3").unwrap().1,
+            Expr::BitOr(num(1), Box::new(Expr::Add(num(2), num(3))))
+        );
+        assert_eq!(
+            parse_expr("1 << 2 * 3").unwrap().1,
+            Expr::Shl(num(1), Box::new(Expr::Mul(num(2), num(3))))
+        );
+
+        // 優先順位は低い順に |, xor, &, シフト
+        assert_eq!(
+            parse_expr("1 | 2 xor 3 & 4 >> 5").unwrap().1,
+            Expr::BitOr(
+                num(1),
+                Box::new(Expr::BitXor(
+                    num(2),
+                    Box::new(Expr::BitAnd(num(3), Box::new(Expr::Shr(num(4), num(5)))))
+                ))
+            )
+        );
+
+        assert_eq!(
+            parse_expr("~1 & 2").unwrap().1,
+            Expr::BitAnd(Box::new(Expr::BitNot(num(1))), num(2))
+        );
+    }
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(
+            parse_expr("[1, 2 * 3] + []").unwrap().1,
+            Expr::Add(
+                Box::new(Expr::List(vec![Expr::Num(1), Expr::Mul(num(2), num(3))])),
+                Box::new(Expr::List(vec![]))
+            )
+        );
+
+        // 要素の参照は単項演算子より優先順位が高い
+        assert_eq!(
+            parse_expr("-xs[1 + 1]").unwrap().1,
+            Expr::Neg(Box::new(Expr::Index(
+                Box::new(Expr::Var("xs".to_string())),
+                Box::new(Expr::Add(num(1), num(1)))
+            )))
+        );
+
+        // `..`で区切った2つの式は区間
+        assert_eq!(
+            parse_expr("[1..x + 1]").unwrap().1,
+            Expr::Call(
+                "interval".to_string(),
+                vec![
+                    Expr::Num(1),
+                    Expr::Add(Box::new(Expr::Var("x".to_string())), num(1))
+                ]
+            )
+        );
+
+        assert!(parse_expr("[1, 2").is_err());
+        assert!(parse_expr("xs[1").is_err());
+        assert!(parse_expr("[1 .. 2, 3]").is_err());
+    }
+
+    #[test]
+    fn test_parse_paren() {
+        assert_eq!(
+            parse_expr("(1 + 2) * 3").unwrap().1,
+            Expr::Mul(Box::new(Expr::Add(num(1), num(2))), num(3))
+        );
+        assert_eq!(
+            parse_expr("2 ^ ( 1 - 1 )").unwrap().1,
+            Expr::Pow(num(2), Box::new(Expr::Sub(num(1), num(1))))
+        );
+        assert_eq!(parse_expr("-((3))").unwrap().1, Expr::Neg(num(3)));
+
+        // 整数のリテラルには符号を含め、i64に収まる場合は負の整数とする
+        assert_eq!(
+            parse_expr("-9223372036854775808").unwrap().1,
+            Expr::Num(i64::MIN)
+        );
+        assert_eq!(
+            parse_expr("-9223372036854775809").unwrap().1,
+            Expr::BigNum("-9223372036854775809".parse().unwrap())
+        );
+
+        assert!(parse_expr("(1 + 2").is_err());
+        assert!(parse_expr("()").is_err());
+
+        // 深すぎるネストはスタックを溢れさせずにエラーとなる
+        let deep = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert!(matches!(
+            parse_expr(&deep),
+            Err(nom::Err::Failure(e)) if e.kind == ParseErrorKind::TooDeep
+        ));
+        let deep = format!("{}1", "-".repeat(100_000));
+        assert!(parse_expr(&deep).is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let cases = [
+            ("1 + 2 * 3", "1 + 2 * 3"),
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+            ("1 - (2 - 3)", "1 - (2 - 3)"),
+            ("(1 - 2) - 3", "1 - 2 - 3"),
+            ("2 ^ 3 ^ 2", "2 ^ 3 ^ 2"),
+            ("(2^3)^2", "(2 ^ 3) ^ 2"),
+            ("-2 ^ 2", "-2 ^ 2"),
+            ("(-2) ^ 2", "(-2) ^ 2"),
+            ("-(1 + x)!", "-(1 + x)!"),
+            ("(-x)!", "(-x)!"),
+            ("!(a && b) || c", "!(a && b) || c"),
+            ("1 | 2 xor 3 & 4", "1 | 2 xor 3 & 4"),
+            (
+                "(1 + if c then 2 else 3) * 4",
+                "(1 + (if c then 2 else 3)) * 4",
+            ),
+            ("if a then b else c + 1", "if a then b else c + 1"),
+            ("max(x, [1.0, $1])[0]", "max(x, [1.0, $1])[0]"),
+            ("2.5e-10 + true", "2.5e-10 + true"),
+            ("x * 3 km", "x * (3 km)"),
+            ("90.0 km/h * 2", "90.0 km/h * 2"),
+            ("-3 m/s^2", "-(3 m/s^2)"),
+        ];
+
+        for (c, expected) in cases {
+            let e = parse_expr(c).unwrap().1;
+            assert_eq!(e.to_string(), expected);
+            // 表示した文字列をパースすると、同じ式に戻る
+            assert_eq!(parse_expr(expected).unwrap().1, e);
+        }
+    }
+}
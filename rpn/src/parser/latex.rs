@@ -0,0 +1,237 @@
+//! 数式をLaTeXで表示
+//!
+//! 中置記法の表示と同様に、演算子の優先順位と結合性から必要な箇所にのみ括弧を付ける。
+//! 除算は`\frac`で、べき乗は指数を`^{}`で囲んで表すため、分子、分母、指数には括弧を付けない。
+//! 例: `(1 + x) / 2 ^ (n - 1)` は `\frac{1 + x}{2^{n - 1}}` となる。
+//!
+//! `pi`などのギリシャ文字の名前の変数は`\pi`のように、2文字以上の名前の変数は斜体で表す。
+//! `sqrt`と`abs`は根号と絶対値の記号で、`sin`などLaTeXにコマンドがある関数はそのコマンドで表す。
+use super::{
+    infix::{binding_power, is_atom, is_prefix, UNARY_BP},
+    split_binary, Expr,
+};
+
+/// `\alpha`のように表すギリシャ文字の名前
+const GREEK: [&str; 23] = [
+    "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa",
+    "lambda", "mu", "nu", "xi", "pi", "rho", "sigma", "tau", "upsilon", "phi", "chi", "psi",
+    "omega",
+];
+
+/// `\sin`のようにLaTeXにコマンドがある関数
+const FUNCTIONS: [&str; 10] = [
+    "sin", "cos", "tan", "ln", "log", "exp", "min", "max", "gcd", "arg",
+];
+
+impl Expr {
+    /// LaTeXの数式で表した文字列を返す
+    ///
+    /// 例: `sqrt(b ^ 2 - 4 * a * c)` は `\sqrt{b^{2} - 4 \cdot a \cdot c}` となる。
+    pub fn to_latex(&self) -> String {
+        if let Some((op, e1, e2)) = split_binary(self) {
+            return match (op, e2) {
+                ("/", _) => format!("\\frac{{{}}}{{{}}}", e1.to_latex(), e2.to_latex()),
+                ("^", _) => format!("{}^{{{}}}", operand(e1, !is_base(e1)), e2.to_latex()),
+                // 単位は、値との間に狭い空白を入れて表す
+                ("*", Expr::Unit(_)) => binary(op, e1, e2, "\\,"),
+                _ => binary(op, e1, e2, &format!(" {} ", symbol(op))),
+            };
+        }
+
+        match self {
+            Expr::Num(n) => n.to_string(),
+            Expr::BigNum(n) => n.to_string(),
+            Expr::Float(x) => float(*x),
+            Expr::Bool(b) => format!("\\mathrm{{{b}}}"),
+            Expr::Var(name) => var(name),
+            Expr::History(n) => format!("\\${n}"),
+            Expr::Unit(name) => format!("\\mathrm{{{name}}}"),
+            Expr::Assign(name, e) => format!("{} = {}", var(name), e.to_latex()),
+            Expr::Call(name, args) => match (name.as_str(), args.as_slice()) {
+                ("sqrt", [e]) => format!("\\sqrt{{{}}}", e.to_latex()),
+                ("abs", [e]) => format!("\\left|{}\\right|", e.to_latex()),
+                ("interval", [_, _]) => format!("\\left[{}\\right]", list(args)),
+                (name, _) if FUNCTIONS.contains(&name) => {
+                    format!("\\{name}\\left({}\\right)", list(args))
+                }
+                (name, _) => format!(
+                    "\\operatorname{{{}}}\\left({}\\right)",
+                    escape(name),
+                    list(args)
+                ),
+            },
+            Expr::List(es) => format!("\\left[{}\\right]", list(es)),
+            Expr::Neg(e) | Expr::BitNot(e) | Expr::Not(e) => {
+                let op = match self {
+                    Expr::Neg(_) => "-",
+                    Expr::BitNot(_) => "\\sim ",
+                    _ => "\\lnot ",
+                };
+                // 被演算子は、単項演算子より優先順位が高い演算と分数のみ括弧なしで書ける
+                let paren = match split_binary(e) {
+                    Some(("/", _, _)) => false,
+                    Some((op, _, _)) => binding_power(op).0 < UNARY_BP,
+                    None => matches!(**e, Expr::If(..)),
+                };
+                format!("{op}{}", operand(e, paren))
+            }
+            Expr::Fact(e) => format!("{}!", operand(e, !is_base(e))),
+            Expr::Index(e, i) => format!("{{{}}}_{{{}}}", operand(e, !is_base(e)), i.to_latex()),
+            Expr::If(cond, then, els) => format!(
+                "\\begin{{cases}} {} & \\text{{if }} {} \\\\ {} & \\text{{otherwise}} \\end{{cases}}",
+                then.to_latex(),
+                cond.to_latex(),
+                els.to_latex()
+            ),
+            _ => unreachable!("binary operators are handled above"),
+        }
+    }
+}
+
+/// 二項演算の式を、被演算子の間にsymbolを入れて表示
+fn binary(op: &str, e1: &Expr, e2: &Expr, symbol: &str) -> String {
+    let (l_bp, r_bp) = binding_power(op);
+    // 分数は`\frac`でまとまるため、括弧は不要
+    let paren1 = match split_binary(e1) {
+        Some(("/", _, _)) => false,
+        Some((op1, _, _)) => binding_power(op1).1 <= l_bp,
+        None => is_prefix(e1) && l_bp >= UNARY_BP || matches!(e1, Expr::If(..)),
+    };
+    let paren2 = match split_binary(e2) {
+        Some(("/", _, _)) => false,
+        Some((op2, _, _)) => binding_power(op2).0 < r_bp,
+        None => matches!(e2, Expr::If(..)),
+    };
+    format!("{}{symbol}{}", operand(e1, paren1), operand(e2, paren2))
+}
+
+/// 二項演算子のLaTeXの記号
+fn symbol(op: &str) -> &'static str {
+    match op {
+        "+" => "+",
+        "-" => "-",
+        "*" => "\\cdot",
+        "%" => "\\bmod",
+        "&" => "\\mathbin{\\&}",
+        "|" => "\\mathbin{|}",
+        "xor" => "\\oplus",
+        "<<" => "\\ll",
+        ">>" => "\\gg",
+        "==" => "=",
+        "!=" => "\\neq",
+        "<" => "<",
+        "<=" => "\\leq",
+        ">" => ">",
+        ">=" => "\\geq",
+        "&&" => "\\land",
+        "||" => "\\lor",
+        _ => unreachable!("unknown operator '{op}'"),
+    }
+}
+
+/// 式を表示し、parenがtrueの場合は括弧で囲む
+fn operand(e: &Expr, paren: bool) -> String {
+    if paren {
+        format!("\\left({}\\right)", e.to_latex())
+    } else {
+        e.to_latex()
+    }
+}
+
+/// 式を`, `で区切って表示
+fn list(es: &[Expr]) -> String {
+    es.iter()
+        .map(|e| e.to_latex())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// べき乗の底や後置の演算子の被演算子として、括弧なしで表示できるか
+fn is_base(e: &Expr) -> bool {
+    match e {
+        // 指数表記の浮動小数点数は`\times`を含む
+        Expr::Float(x) => is_atom(e) && !format!("{x:?}").contains('e'),
+        _ => is_atom(e),
+    }
+}
+
+/// 浮動小数点数を表示
+///
+/// 指数表記の場合は`2.5 \times 10^{-10}`のように表す。
+fn float(x: f64) -> String {
+    if x.is_nan() {
+        return "\\mathrm{NaN}".to_string();
+    }
+    if x.is_infinite() {
+        return if x > 0.0 { "\\infty" } else { "-\\infty" }.to_string();
+    }
+    let s = format!("{x:?}");
+    match s.split_once('e') {
+        Some((m, e)) => format!("{m} \\times 10^{{{e}}}"),
+        None => s,
+    }
+}
+
+/// 変数名を表示
+fn var(name: &str) -> String {
+    if GREEK.contains(&name) {
+        format!("\\{name}")
+    } else if name.chars().count() == 1 {
+        name.to_string()
+    } else {
+        format!("\\mathit{{{}}}", escape(name))
+    }
+}
+
+/// 名前の`_`をエスケープ
+fn escape(name: &str) -> String {
+    name.replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_expr, Notation, DEFAULT_MAX_DEPTH};
+
+    #[test]
+    fn test_to_latex() {
+        let cases = [
+            ("1 + 2 * 3", r"1 + 2 \cdot 3"),
+            ("(1 + 2) * 3", r"\left(1 + 2\right) \cdot 3"),
+            ("1 - (2 - 3)", r"1 - \left(2 - 3\right)"),
+            ("(1 + x) / 2 ^ (n - 1)", r"\frac{1 + x}{2^{n - 1}}"),
+            ("(a / b) ^ 2 * c", r"\left(\frac{a}{b}\right)^{2} \cdot c"),
+            ("2 ^ 3 ^ 2", r"2^{3^{2}}"),
+            ("(2 ^ 3) ^ 2", r"\left(2^{3}\right)^{2}"),
+            ("-2 ^ 2 - -(1 + x)!", r"-2^{2} - -\left(1 + x\right)!"),
+            (
+                "sqrt(b ^ 2 - 4 * a * c)",
+                r"\sqrt{b^{2} - 4 \cdot a \cdot c}",
+            ),
+            (
+                "abs(x) <= pi && !big_x",
+                r"\left|x\right| \leq \pi \land \lnot \mathit{big\_x}",
+            ),
+            (
+                "sin(theta) + max(x, 2) % rand_int(6)",
+                r"\sin\left(\theta\right) + \max\left(x, 2\right) \bmod \operatorname{rand\_int}\left(6\right)",
+            ),
+            (
+                "xs[0] ^ 2 + [1 .. 2]",
+                r"{\mathit{xs}}_{0}^{2} + \left[1, 2\right]",
+            ),
+            (
+                "2.5e-10 ^ 2 + 3 km",
+                r"\left(2.5 \times 10^{-10}\right)^{2} + 3\,\mathrm{km}",
+            ),
+            (
+                "(if x > 0 then x else -x) * 2",
+                r"\left(\begin{cases} x & \text{if } x > 0 \\ -x & \text{otherwise} \end{cases}\right) \cdot 2",
+            ),
+        ];
+
+        for (c, expected) in cases {
+            let e = parse_expr(c, Notation::Infix, DEFAULT_MAX_DEPTH).unwrap();
+            assert_eq!(e.to_latex(), expected, "{c}");
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! 後置記法（逆ポーランド記法）の数式をパース
+//!
+//! 例: `1 2 + 3 *` は (1 + 2) * 3 を表す。
+//! 負の数は`-5`、単項マイナスは`5 neg`、ビット反転は`5 ~`、階乗は`5 factorial`と記述する。
+//! 条件式は`条件 真の場合の式 偽の場合の式 if`と記述する。例: `1 2 < 10 20 if`
+//!
+//! 数値はスタックに積み、演算子はスタックから2つの式を取り出して
+//! 二項演算の式を生成し、その結果をスタックに積む。
+//! 入力を読み終えた時点でスタックに1つだけ残った式が結果となる。
+//! 関数呼び出しの引数やリストの要素の中では、`,`、`)`、`]`、区間の`..`の直前までを1つの式とする。
+//!
+//! スタックに積む式ごとに木の深さを記録し、max_depthを超える式は生成しない。
+use super::{
+    binary, expect, fail, if_expr, keyword, optional, parse_bool, parse_call, parse_list,
+    parse_operator, parse_signed_num, parse_unary, parse_var, unary, Expr, PResult, ParseError,
+    ParseErrorKind,
+};
+use nom::{branch::alt, character::complete::char, error::ErrorKind, multi::many0};
+
+/// 後置記法の式をパース
+///
+/// 生成する式の木の深さがmax_depthを超える場合は、ParseErrorKind::TooDeepのエラーとなる。
+pub fn parse_expr(c: &str, max_depth: usize) -> PResult<'_, Expr> {
+    if max_depth == 0 {
+        return Err(fail(c, ParseErrorKind::TooDeep));
+    }
+    // 関数呼び出しの引数やリストの要素は、1段深い式となる
+    let parse_inner = |c| parse_expr(c, max_depth - 1);
+
+    // 式と、その木の深さの組を積む
+    let mut stack: Vec<(Expr, usize)> = Vec::new();
+    let mut c = c;
+
+    // 部分式の深さから式の深さを求め、上限を超える場合はエラー
+    let nest = |c, depths: &[usize]| {
+        let depth = depths.iter().max().unwrap_or(&0) + 1;
+        if depth > max_depth {
+            Err(fail(c, ParseErrorKind::TooDeep))
+        } else {
+            Ok(depth)
+        }
+    };
+
+    loop {
+        let (c1, _) = many0(char(' '))(c)?;
+        c = c1;
+        if c.is_empty() || c.starts_with([',', ')', ']']) || c.starts_with("..") {
+            break;
+        }
+
+        match parse_signed_num(c) {
+            Ok((c1, e)) => {
+                stack.push((e, 1));
+                c = c1;
+                continue;
+            }
+            // 範囲外の数値などはエラー
+            Err(e @ nom::Err::Failure(_)) => return Err(e),
+            Err(_) => (),
+        }
+
+        if let Some((c1, e)) = optional(parse_call(c, parse_inner))? {
+            let depth = e.depth();
+            stack.push((e, depth));
+            c = c1;
+            continue;
+        }
+
+        if let Some((c1, e)) = optional(parse_list(c, parse_inner))? {
+            let depth = e.depth();
+            stack.push((e, depth));
+            c = c1;
+            continue;
+        }
+
+        if let Ok((c1, e)) = alt((parse_bool, parse_var))(c) {
+            stack.push((e, 1));
+            c = c1;
+            continue;
+        }
+
+        if let Ok((c1, _)) = keyword("if")(c) {
+            let (Some((els, d3)), Some((then, d2)), Some((cond, d1))) =
+                (stack.pop(), stack.pop(), stack.pop())
+            else {
+                return Err(fail(c, ParseErrorKind::MissingOperand));
+            };
+            let depth = nest(c, &[d1, d2, d3])?;
+            stack.push((if_expr(cond, then, els), depth));
+            c = c1;
+            continue;
+        }
+
+        if let Ok((c1, op)) = parse_unary(c) {
+            let Some((e, d)) = stack.pop() else {
+                return Err(fail(c, ParseErrorKind::MissingOperand));
+            };
+            let depth = nest(c, &[d])?;
+            match unary(op, e) {
+                Some(e) => stack.push((e, depth)),
+                None => return Err(fail(c, ParseErrorKind::Nom(ErrorKind::Fail))),
+            }
+            c = c1;
+            continue;
+        }
+
+        let (c1, op) = expect(c, ParseErrorKind::ExpectedExpr, parse_operator(c))?;
+
+        // 右辺、左辺の順にスタックから取り出す
+        let (Some((e2, d2)), Some((e1, d1))) = (stack.pop(), stack.pop()) else {
+            // "1 +"のように、演算子の被演算子が足りない場合はエラー
+            return Err(fail(c, ParseErrorKind::MissingOperand));
+        };
+
+        let depth = nest(c, &[d1, d2])?;
+        match binary(op, e1, e2) {
+            Some(e) => stack.push((e, depth)),
+            None => return Err(fail(c, ParseErrorKind::Nom(ErrorKind::Fail))),
+        }
+        c = c1;
+    }
+
+    // "1 2"のように、式が1つにまとまらない場合はエラー
+    match (stack.pop(), stack.is_empty()) {
+        (Some((e, _)), true) => Ok((c, e)),
+        (Some(_), false) => Err(fail(c, ParseErrorKind::ExpectedOperator)),
+        // 引数のない関数呼び出しや空のリストのため、回復可能なエラーとする
+        (None, _) => Err(nom::Err::Error(ParseError::new(
+            c,
+            ParseErrorKind::ExpectedExpr,
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DEFAULT_MAX_DEPTH;
+
+    #[test]
+    fn test_parse_postfix() {
+        // 正常系
+        assert_eq!(
+            parse_expr("12", DEFAULT_MAX_DEPTH).unwrap().1,
+            Expr::Num(12)
+        );
+        assert_eq!(
+            parse_expr("1 2 + 3 *", DEFAULT_MAX_DEPTH).unwrap().1,
+            Expr::Mul(
+                Box::new(Expr::Add(Box::new(Expr::Num(1)), Box::new(Expr::Num(2)))),
+                Box::new(Expr::Num(3))
+            )
+        );
+        assert_eq!(
+            parse_expr(" 5 1 2 - - ", DEFAULT_MAX_DEPTH).unwrap().1,
+            Expr::Sub(
+                Box::new(Expr::Num(5)),
+                Box::new(Expr::Sub(Box::new(Expr::Num(1)), Box::new(Expr::Num(2))))
+            )
+        );
+
+        // 異常系
+        assert!(parse_expr("", DEFAULT_MAX_DEPTH).is_err());
+        assert!(parse_expr("1 +", DEFAULT_MAX_DEPTH).is_err());
+        assert!(parse_expr("1 2", DEFAULT_MAX_DEPTH).is_err());
+        assert!(parse_expr("1 2 x", DEFAULT_MAX_DEPTH).is_err());
+        assert!(parse_expr("neg", DEFAULT_MAX_DEPTH).is_err());
+
+        // 負の数と単項マイナス
+        assert_eq!(
+            parse_expr("3 -5 -", DEFAULT_MAX_DEPTH).unwrap().1,
+            Expr::Sub(Box::new(Expr::Num(3)), Box::new(Expr::Num(-5)))
+        );
+        assert_eq!(
+            parse_expr("3 neg", DEFAULT_MAX_DEPTH).unwrap().1,
+            Expr::Neg(Box::new(Expr::Num(3)))
+        );
+
+        // ビット演算
+        assert_eq!(
+            parse_expr("1 2 << ~", DEFAULT_MAX_DEPTH).unwrap().1,
+            Expr::BitNot(Box::new(Expr::Shl(
+                Box::new(Expr::Num(1)),
+                Box::new(Expr::Num(2))
+            )))
+        );
+        // 条件式
+        assert_eq!(
+            parse_expr("true 1 2 if", DEFAULT_MAX_DEPTH).unwrap().1,
+            Expr::If(
+                Box::new(Expr::Bool(true)),
+                Box::new(Expr::Num(1)),
+                Box::new(Expr::Num(2))
+            )
+        );
+        assert!(parse_expr("1 2 if", DEFAULT_MAX_DEPTH).is_err());
+
+        // リスト
+        assert_eq!(
+            parse_expr("[1 2 +, 3] [] +", DEFAULT_MAX_DEPTH).unwrap().1,
+            Expr::Add(
+                Box::new(Expr::List(vec![
+                    Expr::Add(Box::new(Expr::Num(1)), Box::new(Expr::Num(2))),
+                    Expr::Num(3)
+                ])),
+                Box::new(Expr::List(vec![]))
+            )
+        );
+        assert!(parse_expr("[1, 2", DEFAULT_MAX_DEPTH).is_err());
+
+        // 階乗
+        assert_eq!(
+            parse_expr("3 factorial neg", DEFAULT_MAX_DEPTH).unwrap().1,
+            Expr::Neg(Box::new(Expr::Fact(Box::new(Expr::Num(3)))))
+        );
+
+        // 比較演算と論理否定
+        assert_eq!(
+            parse_expr("1 2 != !", DEFAULT_MAX_DEPTH).unwrap().1,
+            Expr::Not(Box::new(Expr::Ne(
+                Box::new(Expr::Num(1)),
+                Box::new(Expr::Num(2))
+            )))
+        );
+        assert_eq!(
+            parse_expr("6 3 xor", DEFAULT_MAX_DEPTH).unwrap().1,
+            Expr::BitXor(Box::new(Expr::Num(6)), Box::new(Expr::Num(3)))
+        );
+    }
+}
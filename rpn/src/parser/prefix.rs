@@ -0,0 +1,69 @@
+//! 前置記法の数式をパース
+//!
+//! 例: `* + 1 2 3` は (1 + 2) * 3 を表す。
+//! 負の数は`-5`、単項マイナスは`neg 5`、ビット反転は`~ 5`、階乗は`factorial 5`と記述する。
+//! 条件式は`if < 1 2 then 10 else 20`のように記述する。
+//! リストの要素は`[+ 1 2, 3]`のように記述する。
+//!
+//! 演算子ごとに再帰してパースするため、深くネストした入力でスタックが溢れないよう、
+//! 再帰の深さはmax_depthまでに制限する。
+use super::{
+    binary, expect, expect_keyword, fail, if_expr, keyword, parse_bool, parse_call, parse_list,
+    parse_operator, parse_signed_num, parse_unary, parse_var, unary, Expr, PResult, ParseErrorKind,
+};
+use nom::{branch::alt, character::complete::char, error::ErrorKind, multi::many0};
+
+/// 前置記法の式をパース
+///
+/// ネストがmax_depthを超える場合は、ParseErrorKind::TooDeepのエラーとなる。
+pub fn parse_expr(c: &str, max_depth: usize) -> PResult<'_, Expr> {
+    let (c, _) = many0(char(' '))(c)?;
+    if max_depth == 0 {
+        return Err(fail(c, ParseErrorKind::TooDeep));
+    }
+
+    // 部分式は、残りの深さを1減らしてパースする
+    let depth = max_depth - 1;
+    let result = alt((
+        parse_signed_num,
+        parse_bool,
+        |c| parse_if(c, depth),
+        |c| parse_unary_op(c, depth),
+        |c| parse_call(c, |c| parse_expr(c, depth)),
+        |c| parse_list(c, |c| parse_expr(c, depth)),
+        parse_var,
+        |c| parse_op(c, depth),
+    ))(c);
+    expect(c, ParseErrorKind::ExpectedExpr, result)
+}
+
+fn parse_if(c: &str, depth: usize) -> PResult<'_, Expr> {
+    let (c, _) = keyword("if")(c)?;
+    let (c, cond) = parse_expr(c, depth)?;
+    let (c, _) = expect_keyword(c, "then")?;
+    let (c, then) = parse_expr(c, depth)?;
+    let (c, _) = expect_keyword(c, "else")?;
+    let (c, els) = parse_expr(c, depth)?;
+    Ok((c, if_expr(cond, then, els)))
+}
+
+fn parse_unary_op(c: &str, depth: usize) -> PResult<'_, Expr> {
+    let (c, op) = parse_unary(c)?;
+    let (c, e) = parse_expr(c, depth)?;
+
+    match unary(op, e) {
+        Some(e) => Ok((c, e)),
+        None => Err(fail(c, ParseErrorKind::Nom(ErrorKind::Fail))),
+    }
+}
+
+fn parse_op(c: &str, depth: usize) -> PResult<'_, Expr> {
+    let (c, op) = parse_operator(c)?;
+    let (c, e1) = parse_expr(c, depth)?;
+    let (c, e2) = parse_expr(c, depth)?;
+
+    match binary(op, e1, e2) {
+        Some(e) => Ok((c, e)),
+        None => Err(fail(c, ParseErrorKind::Nom(ErrorKind::Fail))),
+    }
+}
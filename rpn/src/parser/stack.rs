@@ -0,0 +1,158 @@
+//! スタックモードの入力行をパース
+//!
+//! 入力行は後置記法と同様に空白で区切った要素の並びで、数値や変数、関数呼び出し、リストは
+//! その値をスタックに積む式、演算子はスタックから取り出した値に適用する演算となる。
+//! `dup`、`swap`、`drop`、`clear`はスタックを操作する語とする。
+//! 後置記法とは異なり、入力行が1つの式にまとまる必要はない。
+use super::{
+    binary, keyword, optional, parse_bool, parse_call, parse_list, parse_operator,
+    parse_signed_num, parse_unary, parse_var, postfix, strip_comment, unary, Expr, PResult,
+    ParseError, ParseErrorKind,
+};
+use nom::{branch::alt, error::ErrorKind};
+
+/// スタックを操作する語
+pub const STACK_WORDS: [&str; 4] = ["dup", "swap", "drop", "clear"];
+
+/// 演算の式で、スタックから取り出した値を参照する変数の名前
+///
+/// 二項演算では、先に積んだ値を1つ目の変数とする。
+pub const OPERANDS: [&str; 2] = ["a", "b"];
+
+/// スタックモードの入力行の要素
+#[derive(Debug, Clone, PartialEq)]
+pub enum StackOp {
+    Push(Expr),         // 式の値を積む
+    Apply(Expr, usize), // n個の値を取り出してOPERANDSの変数とし、式の値を積む
+    Word(&'static str), // スタックを操作する語
+}
+
+/// スタックモードの入力行をパース
+///
+/// `#`から行末まではコメントとして無視する。
+pub fn parse_line(c: &str, max_depth: usize) -> Result<Vec<StackOp>, ParseError<'_>> {
+    let code = strip_comment(c);
+    match parse_ops(code, max_depth) {
+        Ok((_, ops)) => Ok(ops),
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(e),
+        Err(nom::Err::Incomplete(_)) => {
+            Err(ParseError::new(c, ParseErrorKind::Nom(ErrorKind::Complete)))
+        }
+    }
+}
+
+fn parse_ops(c: &str, max_depth: usize) -> PResult<'_, Vec<StackOp>> {
+    if max_depth == 0 {
+        return Err(nom::Err::Failure(ParseError::new(
+            c,
+            ParseErrorKind::TooDeep,
+        )));
+    }
+    // 関数呼び出しの引数やリストの要素は、後置記法の式とする
+    let parse_inner = |c| postfix::parse_expr(c, max_depth - 1);
+    let operand = |i: usize| Expr::Var(OPERANDS[i].to_string());
+
+    let mut ops = Vec::new();
+    let mut c = c;
+    loop {
+        c = c.trim_start();
+        if c.is_empty() {
+            return Ok((c, ops));
+        }
+
+        if let Some(&word) = STACK_WORDS.iter().find(|&&w| keyword(w)(c).is_ok()) {
+            ops.push(StackOp::Word(word));
+            c = &c[word.len()..];
+            continue;
+        }
+
+        if let Some((c1, e)) = optional(parse_signed_num(c))? {
+            ops.push(StackOp::Push(e));
+            c = c1;
+            continue;
+        }
+
+        if let Some((c1, e)) = optional(parse_call(c, parse_inner))? {
+            ops.push(StackOp::Push(e));
+            c = c1;
+            continue;
+        }
+
+        if let Some((c1, e)) = optional(parse_list(c, parse_inner))? {
+            ops.push(StackOp::Push(e));
+            c = c1;
+            continue;
+        }
+
+        if let Ok((c1, e)) = alt((parse_bool, parse_var))(c) {
+            ops.push(StackOp::Push(e));
+            c = c1;
+            continue;
+        }
+
+        if let Ok((c1, op)) = parse_unary(c) {
+            let e = unary(op, operand(0)).expect("parse_unary returns a unary operator");
+            ops.push(StackOp::Apply(e, 1));
+            c = c1;
+            continue;
+        }
+
+        let Ok((c1, op)) = parse_operator(c) else {
+            return Err(nom::Err::Failure(ParseError::new(
+                c,
+                ParseErrorKind::ExpectedExpr,
+            )));
+        };
+        let e = binary(op, operand(0), operand(1)).expect("parse_operator returns an operator");
+        ops.push(StackOp::Apply(e, 2));
+        c = c1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::DEFAULT_MAX_DEPTH;
+
+    fn var(i: usize) -> Box<Expr> {
+        Box::new(Expr::Var(OPERANDS[i].to_string()))
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse_line("3 -4 + dup neg  # comment", DEFAULT_MAX_DEPTH),
+            Ok(vec![
+                StackOp::Push(Expr::Num(3)),
+                StackOp::Push(Expr::Num(-4)),
+                StackOp::Apply(Expr::Add(var(0), var(1)), 2),
+                StackOp::Word("dup"),
+                StackOp::Apply(Expr::Neg(var(0)), 1),
+            ])
+        );
+        assert_eq!(
+            parse_line("max(1, 2 3 +) swap x", DEFAULT_MAX_DEPTH),
+            Ok(vec![
+                StackOp::Push(Expr::Call(
+                    "max".to_string(),
+                    vec![
+                        Expr::Num(1),
+                        Expr::Add(Box::new(Expr::Num(2)), Box::new(Expr::Num(3)))
+                    ]
+                )),
+                StackOp::Word("swap"),
+                StackOp::Push(Expr::Var("x".to_string())),
+            ])
+        );
+        // `dupe`は変数とする
+        assert_eq!(
+            parse_line("dupe", DEFAULT_MAX_DEPTH),
+            Ok(vec![StackOp::Push(Expr::Var("dupe".to_string()))])
+        );
+        assert_eq!(parse_line("", DEFAULT_MAX_DEPTH), Ok(vec![]));
+        assert_eq!(
+            parse_line("1 ) 2", DEFAULT_MAX_DEPTH).map_err(|e| e.kind),
+            Err(ParseErrorKind::ExpectedExpr)
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! 式を代数的に簡約する
+//!
+//! 定数の畳み込みに加えて、和の同類項をまとめ（`2*x + 3*x` → `5 * x`、`x - x` → `0`）、
+//! 積の同じ因数を累乗にまとめる（`x * x * 2` → `2 * x ^ 2`）。
+//! 変数は数値を表すものとして扱うため、`x - x`は`x`がリストなどの場合も`0`となる。
+//! `rand()`のように呼び出すごとに値が変わりうる関数の呼び出しを含む項は、同類項としてまとめない。
+use crate::{builtin, eval::optimize, parser::Expr};
+
+/// 式を簡約する関数
+///
+/// 部分式を先に簡約し、その結果に対して和と積の規則を適用する。
+pub fn simplify(e: &Expr) -> Expr {
+    let e = map_children(e, simplify);
+    let e = match &e {
+        Expr::Add(..) | Expr::Sub(..) | Expr::Neg(_) => simplify_sum(&e),
+        Expr::Mul(..) => simplify_product(&e),
+        _ => None,
+    }
+    .unwrap_or(e);
+    optimize(&e)
+}
+
+/// 部分式それぞれにfを適用した式を返す
+fn map_children(e: &Expr, f: impl Fn(&Expr) -> Expr) -> Expr {
+    let g = |e: &Expr| Box::new(f(e));
+    match e {
+        Expr::Num(_)
+        | Expr::BigNum(_)
+        | Expr::Float(_)
+        | Expr::Bool(_)
+        | Expr::Var(_)
+        | Expr::History(_)
+        | Expr::Unit(_) => e.clone(),
+        Expr::Add(a, b) => Expr::Add(g(a), g(b)),
+        Expr::Sub(a, b) => Expr::Sub(g(a), g(b)),
+        Expr::Mul(a, b) => Expr::Mul(g(a), g(b)),
+        Expr::Div(a, b) => Expr::Div(g(a), g(b)),
+        Expr::Rem(a, b) => Expr::Rem(g(a), g(b)),
+        Expr::Pow(a, b) => Expr::Pow(g(a), g(b)),
+        Expr::Neg(a) => Expr::Neg(g(a)),
+        Expr::Fact(a) => Expr::Fact(g(a)),
+        Expr::BitAnd(a, b) => Expr::BitAnd(g(a), g(b)),
+        Expr::BitOr(a, b) => Expr::BitOr(g(a), g(b)),
+        Expr::BitXor(a, b) => Expr::BitXor(g(a), g(b)),
+        Expr::Shl(a, b) => Expr::Shl(g(a), g(b)),
+        Expr::Shr(a, b) => Expr::Shr(g(a), g(b)),
+        Expr::BitNot(a) => Expr::BitNot(g(a)),
+        Expr::Eq(a, b) => Expr::Eq(g(a), g(b)),
+        Expr::Ne(a, b) => Expr::Ne(g(a), g(b)),
+        Expr::Lt(a, b) => Expr::Lt(g(a), g(b)),
+        Expr::Le(a, b) => Expr::Le(g(a), g(b)),
+        Expr::Gt(a, b) => Expr::Gt(g(a), g(b)),
+        Expr::Ge(a, b) => Expr::Ge(g(a), g(b)),
+        Expr::And(a, b) => Expr::And(g(a), g(b)),
+        Expr::Or(a, b) => Expr::Or(g(a), g(b)),
+        Expr::Not(a) => Expr::Not(g(a)),
+        Expr::If(cond, then, els) => Expr::If(g(cond), g(then), g(els)),
+        Expr::Assign(name, a) => Expr::Assign(name.clone(), g(a)),
+        Expr::Call(name, args) => Expr::Call(name.clone(), args.iter().map(f).collect()),
+        Expr::List(es) => Expr::List(es.iter().map(f).collect()),
+        Expr::Index(a, i) => Expr::Index(g(a), g(i)),
+    }
+}
+
+/// 和の同類項をまとめる
+///
+/// 係数の計算がオーバーフローする場合はNone。
+fn simplify_sum(e: &Expr) -> Option<Expr> {
+    let mut terms = Vec::new();
+    let mut constant = 0;
+    collect_terms(e, 1, &mut terms, &mut constant)?;
+
+    let mut sum: Option<Expr> = None;
+    for (c, t) in terms.into_iter().filter(|(c, _)| *c != 0) {
+        sum = Some(match sum {
+            None => scale(c, t),
+            Some(acc) => match c.checked_neg() {
+                Some(c) if c > 0 => Expr::Sub(Box::new(acc), Box::new(scale(c, t))),
+                _ => Expr::Add(Box::new(acc), Box::new(scale(c, t))),
+            },
+        });
+    }
+
+    Some(match (sum, constant) {
+        (None, n) => Expr::Num(n),
+        (Some(acc), 0) => acc,
+        (Some(acc), n) => match n.checked_neg() {
+            Some(n) if n > 0 => Expr::Sub(Box::new(acc), Box::new(Expr::Num(n))),
+            _ => Expr::Add(Box::new(acc), Box::new(Expr::Num(n))),
+        },
+    })
+}
+
+/// 和を、係数と項の組の列と、整数の定数項に分解する
+///
+/// 同じ項が現れた場合は係数を足し合わせる。signは式全体に掛かる符号。
+fn collect_terms(
+    e: &Expr,
+    sign: i64,
+    terms: &mut Vec<(i64, Expr)>,
+    constant: &mut i64,
+) -> Option<()> {
+    match e {
+        Expr::Add(a, b) => {
+            collect_terms(a, sign, terms, constant)?;
+            collect_terms(b, sign, terms, constant)
+        }
+        Expr::Sub(a, b) => {
+            collect_terms(a, sign, terms, constant)?;
+            collect_terms(b, -sign, terms, constant)
+        }
+        Expr::Neg(a) => collect_terms(a, -sign, terms, constant),
+        Expr::Num(n) => {
+            *constant = constant.checked_add(sign.checked_mul(*n)?)?;
+            Some(())
+        }
+        _ => {
+            let (c, t) = split_coefficient(e);
+            let c = sign.checked_mul(c)?;
+            add_term(terms, c, t)
+        }
+    }
+}
+
+/// 項tを係数cで加える
+///
+/// 浮動小数点数の定数や、純粋でない関数の呼び出しを含む項は、同類項としてまとめない。
+fn add_term(terms: &mut Vec<(i64, Expr)>, c: i64, t: &Expr) -> Option<()> {
+    match terms.iter_mut().find(|(_, u)| u == t) {
+        Some((d, _)) if mergeable(t) => *d = d.checked_add(c)?,
+        _ => terms.push((c, t.clone())),
+    }
+    Some(())
+}
+
+/// `3 * x`のような項を、係数と残りの式に分解する
+fn split_coefficient(e: &Expr) -> (i64, &Expr) {
+    match e {
+        Expr::Mul(a, b) => match (&**a, &**b) {
+            (Expr::Num(c), t) | (t, Expr::Num(c)) => (*c, t),
+            _ => (1, e),
+        },
+        _ => (1, e),
+    }
+}
+
+/// 項tに係数cを掛けた式を返す
+fn scale(c: i64, t: Expr) -> Expr {
+    match c {
+        1 => t,
+        -1 => Expr::Neg(Box::new(t)),
+        c => Expr::Mul(Box::new(Expr::Num(c)), Box::new(t)),
+    }
+}
+
+/// 積の同じ因数を累乗にまとめ、整数の係数を先頭に置く
+///
+/// 係数や指数の計算がオーバーフローする場合はNone。
+fn simplify_product(e: &Expr) -> Option<Expr> {
+    let mut factors = Vec::new();
+    let mut coefficient = 1;
+    collect_factors(e, &mut factors, &mut coefficient)?;
+
+    if coefficient == 0 {
+        return Some(Expr::Num(0));
+    }
+
+    let product = factors
+        .into_iter()
+        .map(|(k, t)| match k {
+            1 => t,
+            k => Expr::Pow(Box::new(t), Box::new(Expr::Num(k))),
+        })
+        .reduce(|acc, t| Expr::Mul(Box::new(acc), Box::new(t)));
+
+    Some(match product {
+        Some(p) => scale(coefficient, p),
+        None => Expr::Num(coefficient),
+    })
+}
+
+/// 積を、指数と因数の組の列と、整数の係数に分解する
+///
+/// 同じ因数が現れた場合は指数を足し合わせる。ただし、まとめられない因数は別の因数とする。
+fn collect_factors(e: &Expr, factors: &mut Vec<(i64, Expr)>, coefficient: &mut i64) -> Option<()> {
+    let (k, t) = match e {
+        Expr::Mul(a, b) => {
+            collect_factors(a, factors, coefficient)?;
+            return collect_factors(b, factors, coefficient);
+        }
+        Expr::Neg(a) => {
+            *coefficient = coefficient.checked_neg()?;
+            return collect_factors(a, factors, coefficient);
+        }
+        Expr::Num(n) => {
+            *coefficient = coefficient.checked_mul(*n)?;
+            return Some(());
+        }
+        Expr::Pow(a, b) => match **b {
+            Expr::Num(k) if k > 0 => (k, &**a),
+            _ => (1, e),
+        },
+        _ => (1, e),
+    };
+
+    match factors.iter_mut().find(|(_, u)| u == t) {
+        Some((j, _)) if mergeable(t) => *j = j.checked_add(k)?,
+        _ => factors.push((k, t.clone())),
+    }
+    Some(())
+}
+
+/// 同じ項や因数をまとめられるかを判定
+///
+/// 浮動小数点数の定数と、純粋でない関数の呼び出しを含む式はまとめない。
+fn mergeable(e: &Expr) -> bool {
+    !matches!(e, Expr::Float(_)) && is_pure(e)
+}
+
+/// 式が、評価するごとに同じ値となるかを判定
+///
+/// 純粋な組み込み関数以外の呼び出しは、同じ引数でも値が異なりうるため純粋でないとする。
+fn is_pure(e: &Expr) -> bool {
+    match e {
+        Expr::Call(name, _) if !builtin::is_pure(name) => false,
+        e => e.children().into_iter().all(is_pure),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, Notation, Stmt, DEFAULT_MAX_DEPTH};
+
+    fn simp(c: &str) -> String {
+        match parse(c, Notation::Infix, DEFAULT_MAX_DEPTH).unwrap() {
+            Stmt::Expr(e) => simplify(&e).to_string(),
+            s => panic!("not an expression: {s:?}"),
+        }
+    }
+
+    #[test]
+    fn test_simplify() {
+        assert_eq!(simp("x - x"), "0");
+        assert_eq!(simp("2*x + 3*x"), "5 * x");
+        assert_eq!(simp("x + y - x + 1 + 2"), "y + 3");
+        assert_eq!(simp("x + x - 3 * y + y * 2"), "2 * x - y");
+        assert_eq!(simp("-(x - y) + x"), "y");
+        assert_eq!(simp("1 - x"), "-x + 1");
+        assert_eq!(simp("x * 2 * x * 3"), "6 * x ^ 2");
+        assert_eq!(simp("x * x - x ^ 2"), "0");
+        assert_eq!(simp("x * 0 + y"), "y");
+        assert_eq!(simp("f(a + a) * g(b)"), "f(2 * a) * g(b)");
+        assert_eq!(simp("1.5 + 1.5"), "1.5 + 1.5");
+        assert_eq!(simp("x / x"), "x / x");
+
+        // 乱数などの呼び出しはまとめない
+        assert_eq!(
+            simp("rand_int(1, 6) - rand_int(1, 6)"),
+            "rand_int(1, 6) - rand_int(1, 6)"
+        );
+        assert_eq!(simp("rand() + rand()"), "rand() + rand()");
+        assert_eq!(simp("rand() * rand()"), "rand() * rand()");
+        assert_eq!(
+            simp("2 * sqrt(rand()) - sqrt(rand())"),
+            "2 * sqrt(rand()) - sqrt(rand())"
+        );
+        assert_eq!(simp("f(x) - f(x)"), "f(x) - f(x)");
+        assert_eq!(simp("sqrt(x) + 2 * sqrt(x)"), "3 * sqrt(x)");
+
+        // 係数がオーバーフローする場合はまとめない
+        assert_eq!(
+            simp("9223372036854775807 * x + x"),
+            "9223372036854775807 * x + x"
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! HP電卓のようなスタックモード
+//!
+//! 入力した値は行をまたいで保持するスタックに積み、演算子はスタックから値を取り出して
+//! 演算した結果を積む。REPLでは、各行を実行した後にスタック全体を表示する。
+//! 行の途中でエラーが発生した場合は、その行を実行する前のスタックに戻す。
+//!
+//! スタックを変更した行ごとに変更前のスタックを保存し、`:undo`で元に戻し、`:redo`でやり直せる。
+//! 保存するスタックの数はMAX_UNDOまでとし、それより古いものは破棄する。
+use crate::{
+    env::Env,
+    eval::{self, EvalError},
+    parser::stack::{StackOp, OPERANDS},
+    value::{NumberFormat, Value},
+};
+use std::{collections::VecDeque, mem};
+
+/// 元に戻せる変更の数の上限
+pub const MAX_UNDO: usize = 100;
+
+/// 行をまたいで値を保持するスタック
+#[derive(Debug, Default)]
+pub struct Stack {
+    values: Vec<Value>,         // 末尾がスタックの先頭
+    undo: VecDeque<Vec<Value>>, // 変更前のスタック（末尾が最新）
+    redo: Vec<Vec<Value>>,      // 元に戻す前のスタック
+}
+
+impl Stack {
+    pub fn new() -> Self {
+        Stack::default()
+    }
+
+    /// スタックの値を、底から順に返す
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// 1行分の操作を順に実行する
+    ///
+    /// エラーの場合は、実行前のスタックに戻してエラーを返す。
+    /// スタックが変わった場合は、実行前のスタックを元に戻せるよう保存する。
+    pub fn exec(&mut self, ops: &[StackOp], env: &mut Env) -> Result<(), EvalError> {
+        let saved = self.values.clone();
+        let result = ops.iter().try_for_each(|op| self.step(op, env));
+        if result.is_err() {
+            self.values = saved;
+        } else if self.values != saved {
+            self.save(saved);
+        }
+        result
+    }
+
+    /// 値を積む
+    pub fn push(&mut self, v: Value) {
+        self.save(self.values.clone());
+        self.values.push(v);
+    }
+
+    /// スタックの先頭の値を返す
+    pub fn top(&self) -> Option<&Value> {
+        self.values.last()
+    }
+
+    /// 変更前のスタックを、元に戻せるよう保存する
+    fn save(&mut self, saved: Vec<Value>) {
+        if self.undo.len() == MAX_UNDO {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(saved);
+        self.redo.clear();
+    }
+
+    /// 直前の変更を元に戻す
+    ///
+    /// 元に戻せる変更がない場合はfalse。
+    pub fn undo(&mut self) -> bool {
+        let Some(prev) = self.undo.pop_back() else {
+            return false;
+        };
+        self.redo.push(mem::replace(&mut self.values, prev));
+        true
+    }
+
+    /// 元に戻した変更をやり直す
+    ///
+    /// やり直せる変更がない場合はfalse。
+    pub fn redo(&mut self) -> bool {
+        let Some(next) = self.redo.pop() else {
+            return false;
+        };
+        self.undo.push_back(mem::replace(&mut self.values, next));
+        true
+    }
+
+    fn step(&mut self, op: &StackOp, env: &mut Env) -> Result<(), EvalError> {
+        match op {
+            StackOp::Push(e) => {
+                let v = eval::eval(e, env)?;
+                self.values.push(v);
+            }
+            StackOp::Apply(e, n) => {
+                let args = self.pop(*n)?;
+                let frame = OPERANDS.iter().map(|s| s.to_string()).zip(args).collect();
+                env.push_frame(frame)?;
+                let v = eval::eval(e, env);
+                env.pop_frame();
+                self.values.push(v?);
+            }
+            StackOp::Word("dup") => {
+                let v = self.pop(1)?.remove(0);
+                self.values.extend([v.clone(), v]);
+            }
+            StackOp::Word("swap") => {
+                let mut xs = self.pop(2)?;
+                xs.reverse();
+                self.values.extend(xs);
+            }
+            StackOp::Word("drop") => {
+                self.pop(1)?;
+            }
+            StackOp::Word("clear") => self.values.clear(),
+            StackOp::Word(word) => unreachable!("unknown stack word '{word}'"),
+        }
+        Ok(())
+    }
+
+    /// スタックの先頭からn個の値を取り出し、積んだ順に返す
+    fn pop(&mut self, n: usize) -> Result<Vec<Value>, EvalError> {
+        let len = self.values.len();
+        if len < n {
+            return Err(EvalError::StackUnderflow);
+        }
+        Ok(self.values.split_off(len - n))
+    }
+
+    /// `2: 3`、`1: 7`のように、先頭を1として番号を付けたスタックの値を底から順に表示する文字列
+    ///
+    /// 値はformatの形式で表す。スタックが空の場合は`(empty)`とする。
+    pub fn to_string_with(&self, format: &NumberFormat) -> String {
+        if self.values.is_empty() {
+            return "(empty)".to_string();
+        }
+        let len = self.values.len();
+        let width = len.to_string().len();
+        self.values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("{:>width$}: {}", len - i, v.to_string_with(format)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{stack::parse_line, DEFAULT_MAX_DEPTH};
+
+    fn exec(stack: &mut Stack, env: &mut Env, c: &str) -> Result<(), EvalError> {
+        stack.exec(&parse_line(c, DEFAULT_MAX_DEPTH).unwrap(), env)
+    }
+
+    #[test]
+    fn test_stack() {
+        let mut env = Env::new();
+        let mut stack = Stack::new();
+        exec(&mut stack, &mut env, "3 4").unwrap();
+        exec(&mut stack, &mut env, "+ dup *").unwrap();
+        assert_eq!(stack.values, [Value::Int(49)]);
+
+        exec(&mut stack, &mut env, "10 swap - 2 neg").unwrap();
+        assert_eq!(stack.values, [Value::Int(-39), Value::Int(-2)]);
+        assert_eq!(
+            stack.to_string_with(&NumberFormat::default()),
+            "2: -39\n1: -2"
+        );
+
+        // エラーの場合は、行を実行する前のスタックに戻す
+        assert_eq!(
+            exec(&mut stack, &mut env, "drop drop +"),
+            Err(EvalError::StackUnderflow)
+        );
+        assert_eq!(stack.values.len(), 2);
+        assert_eq!(
+            exec(&mut stack, &mut env, "1 0 /"),
+            Err(EvalError::DivideByZero)
+        );
+        assert_eq!(stack.values.len(), 2);
+
+        // 変数a、bは演算の被演算子とは別に参照できる
+        env.set("a", Value::Int(5)).unwrap();
+        exec(&mut stack, &mut env, "clear a 1 -").unwrap();
+        assert_eq!(stack.values, [Value::Int(4)]);
+        exec(&mut stack, &mut env, "clear").unwrap();
+        assert_eq!(stack.to_string_with(&NumberFormat::default()), "(empty)");
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut env = Env::new();
+        let mut stack = Stack::new();
+        assert!(!stack.undo());
+        exec(&mut stack, &mut env, "1 2").unwrap();
+        exec(&mut stack, &mut env, "+").unwrap();
+        // 失敗した行や、スタックが変わらない行は保存しない
+        exec(&mut stack, &mut env, "+").unwrap_err();
+        exec(&mut stack, &mut env, "").unwrap();
+
+        assert!(stack.undo());
+        assert_eq!(stack.values, [Value::Int(1), Value::Int(2)]);
+        assert!(stack.undo());
+        assert!(stack.values.is_empty());
+        assert!(!stack.undo());
+        assert!(stack.redo());
+        assert!(stack.redo());
+        assert_eq!(stack.values, [Value::Int(3)]);
+        assert!(!stack.redo());
+
+        // 新たに変更すると、やり直せる変更は破棄する
+        assert!(stack.undo());
+        exec(&mut stack, &mut env, "drop").unwrap();
+        assert!(!stack.redo());
+
+        for _ in 0..MAX_UNDO + 10 {
+            exec(&mut stack, &mut env, "1").unwrap();
+        }
+        let mut n = 0;
+        while stack.undo() {
+            n += 1;
+        }
+        assert_eq!(n, MAX_UNDO);
+    }
+}
@@ -0,0 +1,274 @@
+//! 単位付きの量
+//!
+//! `3 km`や`90 km/h`のように、数値の後に記述した単位との積を量とする。
+//! 量は、長さ（m）、質量（kg）、時間（s）の基本単位での値と、各基本単位の指数の組（次元）で保持する。
+//! 加減算や比較は次元が同じ量同士でのみ行え、乗除算では次元の指数を加減する。
+//!
+//! 表示の際は、次元が同じ単位のうち、値が1以上となる最も大きな単位に換算する。
+//! 次元が同じ単位がない場合は、基本単位を組み合わせて表示する。
+use std::fmt::{self, Display};
+
+/// 基本単位の名前
+const BASE_UNITS: [&str; 3] = ["m", "kg", "s"];
+
+/// 次元（基本単位ごとの指数）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dim(pub [i8; 3]); // m、kg、sの指数
+
+const LENGTH: Dim = Dim([1, 0, 0]);
+const MASS: Dim = Dim([0, 1, 0]);
+const TIME: Dim = Dim([0, 0, 1]);
+
+/// 単位の名前、基本単位での大きさ、次元
+const UNITS: [(&str, f64, Dim); 14] = [
+    ("mm", 1e-3, LENGTH),
+    ("cm", 1e-2, LENGTH),
+    ("m", 1.0, LENGTH),
+    ("km", 1e3, LENGTH),
+    ("g", 1e-3, MASS),
+    ("kg", 1.0, MASS),
+    ("t", 1e3, MASS),
+    ("ms", 1e-3, TIME),
+    ("s", 1.0, TIME),
+    ("min", 60.0, TIME),
+    ("h", 3600.0, TIME),
+    ("Hz", 1.0, Dim([0, 0, -1])),
+    ("N", 1.0, Dim([1, 1, -2])),
+    ("J", 1.0, Dim([2, 1, -2])),
+];
+
+impl Dim {
+    /// 無次元かを判定
+    pub fn is_none(self) -> bool {
+        self.0 == [0; 3]
+    }
+
+    /// 積の次元を返す
+    ///
+    /// 指数がi8の範囲を超える場合はNone。
+    pub(crate) fn mul(self, rhs: Dim) -> Option<Dim> {
+        self.zip(rhs, i8::checked_add)
+    }
+
+    /// 商の次元を返す
+    pub(crate) fn div(self, rhs: Dim) -> Option<Dim> {
+        self.zip(rhs, i8::checked_sub)
+    }
+
+    /// n乗の次元を返す
+    pub fn powi(self, n: i32) -> Option<Dim> {
+        let n = i8::try_from(n).ok()?;
+        self.zip(Dim::default(), |a, _| a.checked_mul(n))
+    }
+
+    fn zip(self, rhs: Dim, f: impl Fn(i8, i8) -> Option<i8>) -> Option<Dim> {
+        let mut d = [0; 3];
+        for (i, x) in d.iter_mut().enumerate() {
+            *x = f(self.0[i], rhs.0[i])?;
+        }
+        Some(Dim(d))
+    }
+}
+
+/// `kg*m/s^2`のように、基本単位を組み合わせて表示する
+impl Display for Dim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let term = |name: &str, n: i8| match n.abs() {
+            1 => name.to_string(),
+            n => format!("{name}^{n}"),
+        };
+        // 質量、長さ、時間の順に表示する
+        let order = [1, 0, 2];
+        let numer: Vec<_> = order
+            .iter()
+            .filter(|&&i| self.0[i] > 0)
+            .map(|&i| term(BASE_UNITS[i], self.0[i]))
+            .collect();
+        if numer.is_empty() {
+            write!(f, "1")?;
+        } else {
+            write!(f, "{}", numer.join("*"))?;
+        }
+        for &i in order.iter().filter(|&&i| self.0[i] < 0) {
+            write!(f, "/{}", term(BASE_UNITS[i], self.0[i]))?;
+        }
+        Ok(())
+    }
+}
+
+/// 単位付きの量
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quantity {
+    pub value: f64, // 基本単位での値
+    pub dim: Dim,
+}
+
+impl Quantity {
+    /// 加算
+    ///
+    /// 次元が異なる場合はNone。
+    pub(crate) fn add(self, rhs: Quantity) -> Option<Quantity> {
+        (self.dim == rhs.dim).then_some(Quantity {
+            value: self.value + rhs.value,
+            dim: self.dim,
+        })
+    }
+
+    pub(crate) fn sub(self, rhs: Quantity) -> Option<Quantity> {
+        self.add(rhs.neg())
+    }
+
+    /// 乗算
+    ///
+    /// 次元の指数がi8の範囲を超える場合はNone。
+    pub(crate) fn mul(self, rhs: Quantity) -> Option<Quantity> {
+        Some(Quantity {
+            value: self.value * rhs.value,
+            dim: self.dim.mul(rhs.dim)?,
+        })
+    }
+
+    pub(crate) fn div(self, rhs: Quantity) -> Option<Quantity> {
+        Some(Quantity {
+            value: self.value / rhs.value,
+            dim: self.dim.div(rhs.dim)?,
+        })
+    }
+
+    pub(crate) fn neg(self) -> Quantity {
+        Quantity {
+            value: -self.value,
+            dim: self.dim,
+        }
+    }
+
+    pub fn powi(self, n: i32) -> Option<Quantity> {
+        Some(Quantity {
+            value: self.value.powi(n),
+            dim: self.dim.powi(n)?,
+        })
+    }
+
+    /// 数値をfloatで文字列に変換し、表示に用いる単位を付けた文字列を返す
+    pub fn to_string_with(self, float: impl Fn(f64) -> String) -> String {
+        match self.display_unit() {
+            Some((name, size)) => format!("{} {name}", float(self.value / size)),
+            None => format!("{} {}", float(self.value), self.dim),
+        }
+    }
+
+    /// 表示に用いる単位の名前と大きさを返す
+    ///
+    /// 次元が同じ単位がない場合はNone。
+    fn display_unit(self) -> Option<(&'static str, f64)> {
+        let mut units = UNITS
+            .iter()
+            .filter(|(_, _, dim)| *dim == self.dim)
+            .map(|&(name, size, _)| (name, size));
+        let smallest = units.clone().next()?;
+        if self.value == 0.0 || !self.value.is_finite() {
+            return Some(units.find(|&(_, size)| size == 1.0).unwrap_or(smallest));
+        }
+        // UNITSは次元ごとに大きさの昇順に並べている
+        Some(
+            units
+                .rfind(|&(_, size)| self.value.abs() >= size)
+                .unwrap_or(smallest),
+        )
+    }
+}
+
+impl Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_string_with(|x| format!("{x:?}")))
+    }
+}
+
+/// `km`や`km/h`、`m/s^2`のような単位を、その単位での1に相当する量に変換する
+///
+/// 単位は`*`や`/`で組み合わせることができ、各単位には`^2`のように整数の指数を付けられる。
+/// 未知の単位を含む場合はNone。
+pub fn parse(c: &str) -> Option<Quantity> {
+    let mut q = Quantity {
+        value: 1.0,
+        dim: Dim::default(),
+    };
+    let mut div = false;
+    let mut rest = c;
+    loop {
+        let end = rest.find(['*', '/']).unwrap_or(rest.len());
+        let (term, next) = rest.split_at(end);
+        let (name, n) = match term.split_once('^') {
+            Some((name, n)) => (name, n.parse().ok()?),
+            None => (term, 1),
+        };
+        let &(_, size, dim) = UNITS.iter().find(|(u, _, _)| *u == name)?;
+        let unit = Quantity { value: size, dim }.powi(n)?;
+        q = if div { q.div(unit)? } else { q.mul(unit)? };
+
+        let mut chars = next.chars();
+        match chars.next() {
+            Some(op) => {
+                div = op == '/';
+                rest = chars.as_str();
+            }
+            None => return Some(q),
+        }
+    }
+}
+
+/// 単位の名前かを判定
+pub fn is_unit(name: &str) -> bool {
+    UNITS.iter().any(|(u, _, _)| *u == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn q(value: f64, unit: &str) -> Quantity {
+        let u = parse(unit).unwrap();
+        Quantity {
+            value: value * u.value,
+            dim: u.dim,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("km").unwrap().value, 1000.0);
+        let speed = parse("km/h").unwrap();
+        assert_eq!(speed.dim, Dim([1, 0, -1]));
+        assert_eq!(parse("m/s^2").unwrap().dim, Dim([1, 0, -2]));
+        assert_eq!(parse("kg*m/s^2").unwrap().dim, parse("N").unwrap().dim);
+        assert_eq!(parse("m^-1").unwrap().dim, Dim([-1, 0, 0]));
+        assert_eq!(parse("foo"), None);
+        assert_eq!(parse("m/"), None);
+    }
+
+    #[test]
+    fn test_arith() {
+        assert_eq!(q(3.0, "km").add(q(200.0, "m")), Some(q(3200.0, "m")));
+        assert_eq!(q(1.0, "m").add(q(1.0, "s")), None);
+        let d = q(90.0, "km/h").mul(q(2.0, "h")).unwrap();
+        assert_eq!(d.dim, LENGTH);
+        assert_eq!(d.value, 180_000.0);
+        assert_eq!(q(2.0, "m").powi(3).unwrap().dim, Dim([3, 0, 0]));
+        assert_eq!(q(2.0, "m").powi(200), None);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(q(3200.0, "m").to_string(), "3.2 km");
+        assert_eq!(q(500.0, "g").to_string(), "500.0 g");
+        assert_eq!(q(90.0, "s").to_string(), "1.5 min");
+        assert_eq!(q(0.0, "km").to_string(), "0.0 m");
+        assert_eq!(q(25.0, "m/s").to_string(), "25.0 m/s");
+        assert_eq!(q(2.0, "m^2").to_string(), "2.0 m^2");
+        assert_eq!(q(3.0, "kg*m/s^2").to_string(), "3.0 N");
+        assert_eq!(q(4.0, "kg/s").to_string(), "4.0 kg/s");
+        assert_eq!(q(4.0, "m^-1").to_string(), "4.0 1/m");
+    }
+}
@@ -0,0 +1,1112 @@
+//! 評価結果の値と、その演算
+//!
+//! 整数同士の演算結果は整数となり、いずれかが浮動小数点数の場合は
+//! もう一方も浮動小数点数に変換してから演算する。
+//! 真偽値は比較演算と論理演算の結果で、算術演算には利用できない。
+//!
+//! 多倍長整数はi64の範囲を超えても正確に計算できる整数で、
+//! 多倍長整数とi64の整数の演算結果は多倍長整数となる。
+//! 有理数は既約分数として正確に計算し、整数と有理数の演算結果は有理数となる。
+//! 10進小数と整数の演算結果は10進小数となり、10進小数と有理数の演算結果は有理数となる。
+//!
+//! 複素数は実部と虚部を浮動小数点数で保持し、複素数と実数の演算では実数を複素数に変換する。
+//! 複素数の大小比較や剰余はエラーとなる。
+//!
+//! 区間と実数の演算では、実数を1点のみからなる区間に変換する。区間の剰余はエラーとなり、
+//! 区間の大小比較は、区間内のすべての値で結果が同じでない場合にエラーとなる。
+//!
+//! 単位付きの量と実数の演算では、実数を無次元の量とみなす。次元が異なる量の加減算や比較はエラーとなり、
+//! 乗除算の結果が無次元となる場合は浮動小数点数とする。
+//!
+//! リストとの算術演算やビット演算は要素ごとに行う。リストとスカラーの演算では
+//! スカラーを各要素と演算し、リスト同士の演算では同じ位置の要素同士を演算する。
+use crate::{
+    decimal::{Decimal, Rounding},
+    eval::EvalError,
+    interval::Interval,
+    unit::{Dim, Quantity},
+};
+use helper::SafeArith;
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+/// 多倍長整数の演算結果のビット数の上限
+const MAX_BIG_BITS: u64 = 1 << 20;
+
+/// 整数の幅
+///
+/// I128の場合、整数は多倍長整数で計算し、結果がi128の範囲を超えるとオーバーフローとする。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntWidth {
+    #[default]
+    I64,
+    I128,
+}
+
+impl IntWidth {
+    /// 整数のビット数
+    fn bits(self) -> u32 {
+        match self {
+            IntWidth::I64 => 64,
+            IntWidth::I128 => 128,
+        }
+    }
+
+    /// 多倍長整数をこの幅の整数の範囲に収める
+    ///
+    /// 範囲外の場合はmodeに従い、エラーとするか、ラップアラウンドするか、飽和させる。
+    /// リストの場合は各要素に適用する。多倍長整数以外の値はそのまま返す。
+    pub fn fit(self, v: Value, mode: OverflowMode) -> Result<Value, EvalError> {
+        let n = match v {
+            Value::Big(n) => n,
+            Value::List(xs) => {
+                return xs
+                    .into_iter()
+                    .map(|x| self.fit(x, mode))
+                    .collect::<Result<_, _>>()
+                    .map(Value::List)
+            }
+            v => return Ok(v),
+        };
+
+        let bits = self.bits();
+        let max = (BigInt::one() << (bits - 1)) - 1;
+        let min = -(BigInt::one() << (bits - 1));
+        if min <= n && n <= max {
+            return Ok(Value::Big(n));
+        }
+
+        match mode {
+            OverflowMode::Error => Err(EvalError::Overflow),
+            OverflowMode::Wrap => {
+                // 下位bitsビットを2の補数として解釈する
+                let n = n & ((BigInt::one() << bits) - 1);
+                Ok(Value::Big(if n > max {
+                    n - (BigInt::one() << bits)
+                } else {
+                    n
+                }))
+            }
+            OverflowMode::Saturate => Ok(Value::Big(if n > max { max } else { min })),
+        }
+    }
+}
+
+impl FromStr for IntWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "i64" => Ok(IntWidth::I64),
+            "i128" => Ok(IntWidth::I128),
+            _ => Err(format!(
+                "unknown integer width '{s}' (expected i64 or i128)"
+            )),
+        }
+    }
+}
+
+impl Display for IntWidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntWidth::I64 => write!(f, "i64"),
+            IntWidth::I128 => write!(f, "i128"),
+        }
+    }
+}
+
+/// 整数のオーバーフロー時の動作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowMode {
+    #[default]
+    Error, // エラーとする
+    Wrap,     // ラップアラウンドする
+    Saturate, // 最大値または最小値に飽和させる
+}
+
+impl FromStr for OverflowMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(OverflowMode::Error),
+            "wrap" => Ok(OverflowMode::Wrap),
+            "saturate" => Ok(OverflowMode::Saturate),
+            _ => Err(format!(
+                "unknown overflow mode '{s}' (expected error, wrap or saturate)"
+            )),
+        }
+    }
+}
+
+impl Display for OverflowMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverflowMode::Error => write!(f, "error"),
+            OverflowMode::Wrap => write!(f, "wrap"),
+            OverflowMode::Saturate => write!(f, "saturate"),
+        }
+    }
+}
+
+/// 整数の結果を表示する基数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Radix {
+    #[default]
+    Dec,
+    Hex,
+    Bin,
+    Oct,
+}
+
+impl Radix {
+    /// 整数nを`0xFF`のように接頭辞を付けてこの基数で表す
+    fn format(self, n: &BigInt) -> String {
+        let (prefix, radix) = match self {
+            Radix::Dec => return n.to_string(),
+            Radix::Hex => ("0x", 16),
+            Radix::Bin => ("0b", 2),
+            Radix::Oct => ("0o", 8),
+        };
+        let sign = if n.is_negative() { "-" } else { "" };
+        let digits = n.magnitude().to_str_radix(radix).to_uppercase();
+        format!("{sign}{prefix}{digits}")
+    }
+}
+
+impl FromStr for Radix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dec" => Ok(Radix::Dec),
+            "hex" => Ok(Radix::Hex),
+            "bin" => Ok(Radix::Bin),
+            "oct" => Ok(Radix::Oct),
+            _ => Err(format!(
+                "unknown radix '{s}' (expected dec, hex, bin or oct)"
+            )),
+        }
+    }
+}
+
+impl Display for Radix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Radix::Dec => write!(f, "dec"),
+            Radix::Hex => write!(f, "hex"),
+            Radix::Bin => write!(f, "bin"),
+            Radix::Oct => write!(f, "oct"),
+        }
+    }
+}
+
+/// 数値の結果の表示形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NumberFormat {
+    pub radix: Radix,             // 整数を表す基数
+    pub precision: Option<usize>, // 浮動小数点数の小数点以下の桁数（Noneの場合は値を表せる最短の桁数）
+    pub sci: bool,                // 浮動小数点数を`1.5e3`のような指数表記で表す
+    pub separator: bool,          // 10進数の整数部を3桁ごとに`,`で区切る
+}
+
+impl NumberFormat {
+    /// 整数を表す
+    fn int(&self, n: &BigInt) -> String {
+        match self.radix {
+            Radix::Dec => self.group(n.to_string()),
+            radix => radix.format(n),
+        }
+    }
+
+    /// 浮動小数点数を表す
+    ///
+    /// 桁数や指数表記の指定がない場合は、整数と区別できるよう`3.0`のように表す。
+    fn float(&self, x: f64) -> String {
+        if !x.is_finite() {
+            return format!("{x:?}");
+        }
+        let s = match (self.sci, self.precision) {
+            (false, None) => format!("{x:?}"),
+            (false, Some(p)) => format!("{x:.p$}"),
+            (true, None) => format!("{x:e}"),
+            (true, Some(p)) => format!("{x:.p$e}"),
+        };
+        self.group(s)
+    }
+
+    /// 区切りが有効な場合は、数値の文字列の整数部を3桁ごとに`,`で区切る
+    fn group(&self, s: String) -> String {
+        if !self.separator {
+            return s;
+        }
+        let (sign, rest) = s.split_at(usize::from(s.starts_with('-')));
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (digits, tail) = rest.split_at(end);
+        let mut out = sign.to_string();
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                out.push(',');
+            }
+            out.push(c);
+        }
+        out.push_str(tail);
+        out
+    }
+}
+
+/// 評価結果の値
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    Int(i64),
+    Big(BigInt),             // 多倍長整数
+    Ratio(Box<BigRational>), // 有理数（Valueを小さく保つためBoxに入れる）
+    Decimal(Box<Decimal>),   // 10進小数
+    Float(f64),
+    Complex(Complex64), // 複素数
+    Interval(Interval), // 区間
+    Quantity(Quantity), // 単位付きの量
+    Bool(bool),
+    List(Vec<Value>),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_string_with(&NumberFormat::default()))
+    }
+}
+
+impl Value {
+    /// 整数をradixの基数で表した文字列を返す
+    ///
+    /// 整数以外の値は通常どおり表示し、リストの場合は整数の要素のみをradixの基数で表す。
+    pub fn to_string_radix(&self, radix: Radix) -> String {
+        self.to_string_with(&NumberFormat {
+            radix,
+            ..NumberFormat::default()
+        })
+    }
+
+    /// 数値をformatの形式で表した文字列を返す
+    ///
+    /// 整数はformatの基数で表し、浮動小数点数の桁数と指数表記の指定は、複素数や区間、量の数値にも適用する。
+    /// 10進小数は、整数部の区切りのみを適用する。
+    pub fn to_string_with(&self, format: &NumberFormat) -> String {
+        let float = |x| format.float(x);
+        match self {
+            Value::Int(n) => format.int(&BigInt::from(*n)),
+            Value::Big(n) => format.int(n),
+            // 分母が1の場合は整数として表示する
+            Value::Ratio(r) if r.is_integer() => format.int(r.numer()),
+            Value::Ratio(r) => {
+                let dec = NumberFormat {
+                    radix: Radix::Dec,
+                    ..*format
+                };
+                format!("{}/{}", dec.int(r.numer()), dec.int(r.denom()))
+            }
+            Value::Decimal(d) => format.group(d.to_string()),
+            Value::Float(x) => float(*x),
+            // `3.0+4.0i`のように、浮動小数点数と同じ形式で常に実部と虚部を表示する
+            Value::Complex(z) if z.im.is_sign_negative() => {
+                format!("{}-{}i", float(z.re), float(-z.im))
+            }
+            Value::Complex(z) => format!("{}+{}i", float(z.re), float(z.im)),
+            Value::Interval(x) => x.to_string_with(float),
+            // 次元が同じ単位のうち、適切な大きさの単位に換算して表示する
+            Value::Quantity(q) => q.to_string_with(float),
+            Value::Bool(b) => b.to_string(),
+            Value::List(xs) => {
+                let xs: Vec<_> = xs.iter().map(|x| x.to_string_with(format)).collect();
+                format!("[{}]", xs.join(", "))
+            }
+        }
+    }
+
+    /// 浮動小数点数に変換
+    ///
+    /// 数値でない場合はエラー。
+    pub fn to_f64(&self) -> Result<f64, EvalError> {
+        match self {
+            Value::Int(n) => Ok(*n as f64),
+            Value::Big(n) => Ok(n.to_f64().unwrap_or(f64::NAN)),
+            Value::Ratio(r) => Ok(r.to_f64().unwrap_or(f64::NAN)),
+            Value::Decimal(d) => Ok(d.to_f64()),
+            Value::Float(x) => Ok(*x),
+            Value::Complex(_) | Value::Interval(_) => Err(EvalError::NotReal),
+            Value::Quantity(_) => Err(EvalError::IncompatibleUnits),
+            _ => Err(EvalError::NotNumber),
+        }
+    }
+
+    /// 量の場合はそのまま、実数の場合は無次元の量として返す
+    fn to_quantity(&self) -> Option<Quantity> {
+        match self {
+            Value::Quantity(q) => Some(*q),
+            v => v.to_f64().ok().map(|value| Quantity {
+                value,
+                dim: Dim::default(),
+            }),
+        }
+    }
+
+    /// 数値の場合は複素数として返す
+    pub fn to_complex(&self) -> Option<Complex64> {
+        match self {
+            Value::Complex(z) => Some(*z),
+            v => v.to_f64().ok().map(Complex64::from),
+        }
+    }
+
+    /// 真偽値に変換
+    ///
+    /// 真偽値でない場合はエラー。
+    pub fn to_bool(&self) -> Result<bool, EvalError> {
+        match *self {
+            Value::Bool(b) => Ok(b),
+            _ => Err(EvalError::NotBool),
+        }
+    }
+
+    /// 複素数以外の数値の場合は区間として返す
+    pub fn to_interval(&self) -> Option<Interval> {
+        match self {
+            Value::Interval(x) => Some(*x),
+            v => v.to_f64().ok().map(Interval::point),
+        }
+    }
+
+    /// 複素数以外の数値を区間に変換
+    ///
+    /// リストの場合は各要素を変換する。それ以外の値はそのまま返す。
+    pub fn into_interval(self) -> Value {
+        match self {
+            Value::List(xs) => Value::List(xs.into_iter().map(Value::into_interval).collect()),
+            v => match v.to_interval() {
+                Some(x) => Value::Interval(x),
+                None => v,
+            },
+        }
+    }
+
+    /// 整数を多倍長整数に変換
+    ///
+    /// リストの場合は各要素を変換する。整数以外の値はそのまま返す。
+    pub fn into_big(self) -> Value {
+        match self {
+            Value::Int(n) => Value::Big(n.into()),
+            Value::List(xs) => Value::List(xs.into_iter().map(Value::into_big).collect()),
+            v => v,
+        }
+    }
+
+    /// 整数を有理数に変換
+    ///
+    /// リストの場合は各要素を変換する。整数以外の値はそのまま返す。
+    pub fn into_ratio(self) -> Value {
+        match self {
+            Value::Int(n) => Value::Ratio(Box::new(BigInt::from(n).into())),
+            Value::Big(n) => Value::Ratio(Box::new(n.into())),
+            Value::List(xs) => Value::List(xs.into_iter().map(Value::into_ratio).collect()),
+            v => v,
+        }
+    }
+
+    /// 整数と浮動小数点数を10進小数に変換
+    ///
+    /// リストの場合は各要素を変換する。それ以外の値はそのまま返す。
+    pub fn into_decimal(self) -> Value {
+        match self {
+            Value::Int(n) => Value::Decimal(Box::new(BigInt::from(n).into())),
+            Value::Big(n) => Value::Decimal(Box::new(n.into())),
+            Value::Float(x) => match Decimal::from_f64(x) {
+                Some(d) => Value::Decimal(Box::new(d)),
+                None => Value::Float(x),
+            },
+            Value::List(xs) => Value::List(xs.into_iter().map(Value::into_decimal).collect()),
+            v => v,
+        }
+    }
+
+    /// 整数の場合は多倍長整数として返す
+    fn to_big(&self) -> Option<BigInt> {
+        match self {
+            Value::Int(n) => Some((*n).into()),
+            Value::Big(n) => Some(n.clone()),
+            _ => None,
+        }
+    }
+
+    /// 整数または10進小数の場合は10進小数として返す
+    fn to_decimal(&self) -> Option<Decimal> {
+        match self {
+            Value::Decimal(d) => Some(*d.clone()),
+            v => v.to_big().map(Decimal::from),
+        }
+    }
+
+    /// 整数、有理数、10進小数の場合は有理数として返す
+    fn to_ratio(&self) -> Option<BigRational> {
+        match self {
+            Value::Ratio(r) => Some(*r.clone()),
+            Value::Decimal(d) => {
+                let (numer, denom) = d.to_fraction();
+                Some(BigRational::new(numer, denom))
+            }
+            v => v.to_big().map(BigRational::from),
+        }
+    }
+
+    /// 整数、または整数値の有理数や10進小数の場合は多倍長整数として返す
+    pub fn to_integer(&self) -> Option<BigInt> {
+        match self {
+            Value::Ratio(r) if r.is_integer() => Some(r.to_integer()),
+            Value::Decimal(d) => d.to_integer(),
+            v => v.to_big(),
+        }
+    }
+
+    /// 値が0かを判定
+    fn is_zero(&self) -> bool {
+        match self {
+            Value::Int(n) => *n == 0,
+            Value::Big(n) => n.is_zero(),
+            Value::Ratio(r) => r.is_zero(),
+            Value::Decimal(d) => d.is_zero(),
+            Value::Float(x) => *x == 0.0,
+            Value::Complex(z) => z.is_zero(),
+            Value::Interval(x) => x.is_zero(),
+            Value::Quantity(q) => q.value == 0.0,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn add(self, rhs: Value) -> Result<Value, EvalError> {
+        broadcast(self, rhs, |a, b| {
+            if let Some((a, b)) = quantity_pair(&a, &b)? {
+                return quantity(a.add(b));
+            }
+            if let Some((a, b)) = complex_pair(&a, &b)? {
+                return Ok(Value::Complex(a + b));
+            }
+            if let Some((a, b)) = interval_pair(&a, &b)? {
+                return Ok(Value::Interval(a.add(b)));
+            }
+            arith(
+                a,
+                b,
+                |a, b| a.safe_add(&b).ok_or(EvalError::Overflow),
+                |a, b| Ok(a + b),
+                |a, b| Ok(a.add(&b)),
+                |a, b| Ok(a + b),
+                |a, b| a + b,
+            )
+        })
+    }
+
+    pub(crate) fn sub(self, rhs: Value) -> Result<Value, EvalError> {
+        broadcast(self, rhs, |a, b| {
+            if let Some((a, b)) = quantity_pair(&a, &b)? {
+                return quantity(a.sub(b));
+            }
+            if let Some((a, b)) = complex_pair(&a, &b)? {
+                return Ok(Value::Complex(a - b));
+            }
+            if let Some((a, b)) = interval_pair(&a, &b)? {
+                return Ok(Value::Interval(a.sub(b)));
+            }
+            arith(
+                a,
+                b,
+                |a, b| a.safe_sub(&b).ok_or(EvalError::Overflow),
+                |a, b| Ok(a - b),
+                |a, b| Ok(a.sub(&b)),
+                |a, b| Ok(a - b),
+                |a, b| a - b,
+            )
+        })
+    }
+
+    pub(crate) fn mul(self, rhs: Value) -> Result<Value, EvalError> {
+        broadcast(self, rhs, |a, b| {
+            if let Some((a, b)) = quantity_pair(&a, &b)? {
+                return quantity(a.mul(b));
+            }
+            if let Some((a, b)) = complex_pair(&a, &b)? {
+                return Ok(Value::Complex(a * b));
+            }
+            if let Some((a, b)) = interval_pair(&a, &b)? {
+                return Ok(Value::Interval(a.mul(b)));
+            }
+            arith(
+                a,
+                b,
+                |a, b| a.safe_mul(&b).ok_or(EvalError::Overflow),
+                |a, b| Ok(a * b),
+                |a, b| Ok(a.mul(&b)),
+                |a, b| Ok(a * b),
+                |a, b| a * b,
+            )
+        })
+    }
+
+    /// 除算
+    ///
+    /// 整数同士の場合は0方向に切り捨てる。有理数の場合は切り捨てない。
+    /// 10進小数の場合は、roundingの桁数と丸め方で丸める。
+    pub fn div(self, rhs: Value, rounding: Rounding) -> Result<Value, EvalError> {
+        broadcast(self, rhs, |a, b| {
+            if b.is_zero() {
+                return Err(EvalError::DivideByZero);
+            }
+            if let Some((a, b)) = quantity_pair(&a, &b)? {
+                return quantity(a.div(b));
+            }
+            if let Some((a, b)) = complex_pair(&a, &b)? {
+                return Ok(Value::Complex(a / b));
+            }
+            if let Some((a, b)) = interval_pair(&a, &b)? {
+                return a.div(b).map(Value::Interval).ok_or(EvalError::DivideByZero);
+            }
+            // i64::MIN / -1 はi64の範囲を超える
+            arith(
+                a,
+                b,
+                |a, b| a.checked_div(b).ok_or(EvalError::Overflow),
+                |a, b| Ok(a / b),
+                |a, b| a.div(&b, rounding).ok_or(EvalError::DivideByZero),
+                |a, b| Ok(a / b),
+                |a, b| a / b,
+            )
+        })
+    }
+
+    /// 剰余
+    ///
+    /// 剰余の符号は被除数と同じ（Rustの%演算子と同じ）。複素数や区間、量の剰余はエラー。
+    pub(crate) fn rem(self, rhs: Value) -> Result<Value, EvalError> {
+        broadcast(self, rhs, |a, b| {
+            if quantity_pair(&a, &b)?.is_some() {
+                return Err(EvalError::IncompatibleUnits);
+            }
+            if complex_pair(&a, &b)?.is_some() || interval_pair(&a, &b)?.is_some() {
+                return Err(EvalError::NotReal);
+            }
+            if b.is_zero() {
+                return Err(EvalError::DivideByZero);
+            }
+            arith(
+                a,
+                b,
+                |a, b| a.checked_rem(b).ok_or(EvalError::Overflow),
+                |a, b| Ok(a % b),
+                |a, b| a.rem(&b).ok_or(EvalError::DivideByZero),
+                |a, b| Ok(a % b),
+                |a, b| a % b,
+            )
+        })
+    }
+
+    /// べき乗
+    ///
+    /// 複素数の整数乗は乗算の繰り返しで計算し、`i ^ 2`が正確に`-1+0i`となるようにする。
+    /// 区間や量の指数は整数に限る。量を指数とすることはできない。
+    pub fn pow(self, rhs: Value) -> Result<Value, EvalError> {
+        broadcast(self, rhs, |a, b| {
+            if let Value::Quantity(q) = a {
+                return quantity(q.powi(integer_exponent(&b)?));
+            }
+            pow_number(a, b)
+        })
+    }
+
+    pub(crate) fn bitand(self, rhs: Value) -> Result<Value, EvalError> {
+        broadcast(self, rhs, |a, b| {
+            bitwise(a, b, |a, b| Ok(a & b), |a, b| Ok(a & b))
+        })
+    }
+
+    pub(crate) fn bitor(self, rhs: Value) -> Result<Value, EvalError> {
+        broadcast(self, rhs, |a, b| {
+            bitwise(a, b, |a, b| Ok(a | b), |a, b| Ok(a | b))
+        })
+    }
+
+    pub(crate) fn bitxor(self, rhs: Value) -> Result<Value, EvalError> {
+        broadcast(self, rhs, |a, b| {
+            bitwise(a, b, |a, b| Ok(a ^ b), |a, b| Ok(a ^ b))
+        })
+    }
+
+    /// 左シフト
+    ///
+    /// シフト量が0以上64未満でない場合はエラー。あふれたビットは捨てられる。
+    /// 多倍長整数の場合はビットを捨てず、シフト量はMAX_BIG_BITSまでとする。
+    pub(crate) fn shl(self, rhs: Value) -> Result<Value, EvalError> {
+        broadcast(self, rhs, |a, b| {
+            bitwise(
+                a,
+                b,
+                |a, b| {
+                    a.checked_shl(shift_amount(b)?)
+                        .ok_or(EvalError::InvalidShift)
+                },
+                |a, b| Ok(a << big_shift_amount(&b)?),
+            )
+        })
+    }
+
+    /// 算術右シフト
+    ///
+    /// シフト量が0以上64未満でない場合はエラー。
+    /// 多倍長整数の場合は、シフト量はMAX_BIG_BITSまでとする。
+    pub(crate) fn shr(self, rhs: Value) -> Result<Value, EvalError> {
+        broadcast(self, rhs, |a, b| {
+            bitwise(
+                a,
+                b,
+                |a, b| {
+                    a.checked_shr(shift_amount(b)?)
+                        .ok_or(EvalError::InvalidShift)
+                },
+                |a, b| Ok(a >> big_shift_amount(&b)?),
+            )
+        })
+    }
+
+    pub fn bitnot(self) -> Result<Value, EvalError> {
+        match self {
+            Value::Int(n) => Ok(Value::Int(!n)),
+            Value::Big(n) => Ok(Value::Big(!n)),
+            Value::Ratio(_) | Value::Decimal(_) => self
+                .to_integer()
+                .map(|n| Value::Big(!n))
+                .ok_or(EvalError::NotInteger),
+            Value::List(xs) => map(xs, Value::bitnot),
+            _ => Err(EvalError::NotInteger),
+        }
+    }
+
+    pub(crate) fn neg(self) -> Result<Value, EvalError> {
+        match self {
+            Value::Int(n) => n.checked_neg().map(Value::Int).ok_or(EvalError::Overflow),
+            Value::Big(n) => Ok(Value::Big(-n)),
+            Value::Ratio(r) => Ok(Value::Ratio(Box::new(-*r))),
+            Value::Decimal(d) => Ok(Value::Decimal(Box::new(d.neg()))),
+            Value::Float(x) => Ok(Value::Float(-x)),
+            Value::Complex(z) => Ok(Value::Complex(-z)),
+            Value::Interval(x) => Ok(Value::Interval(x.neg())),
+            Value::Quantity(q) => Ok(Value::Quantity(q.neg())),
+            Value::List(xs) => map(xs, Value::neg),
+            Value::Bool(_) => Err(EvalError::NotNumber),
+        }
+    }
+
+    /// 階乗
+    ///
+    /// 負の数の場合や、i64の範囲を超える場合はエラー。
+    /// 多倍長整数の場合は、結果のビット数がMAX_BIG_BITSを超えるとエラー。
+    pub fn fact(self) -> Result<Value, EvalError> {
+        match self {
+            Value::Int(n) if n < 0 => Err(EvalError::NegativeFactorial),
+            Value::Int(n) => (1..=n)
+                .try_fold(1i64, |acc, k| acc.safe_mul(&k))
+                .map(Value::Int)
+                .ok_or(EvalError::Overflow),
+            Value::Big(n) if n.is_negative() => Err(EvalError::NegativeFactorial),
+            Value::Big(n) => {
+                let n = n.to_u64().ok_or(EvalError::Overflow)?;
+                (1..=n)
+                    .try_fold(BigInt::one(), |acc, k| {
+                        Some(acc * k).filter(|acc| acc.bits() <= MAX_BIG_BITS)
+                    })
+                    .map(Value::Big)
+                    .ok_or(EvalError::Overflow)
+            }
+            Value::Ratio(_) | Value::Decimal(_) => match self.to_integer() {
+                Some(n) => Value::Big(n).fact(),
+                None => Err(EvalError::NotInteger),
+            },
+            Value::List(xs) => map(xs, Value::fact),
+            _ => Err(EvalError::NotInteger),
+        }
+    }
+
+    pub(crate) fn not(self) -> Result<Value, EvalError> {
+        Ok(Value::Bool(!self.to_bool()?))
+    }
+
+    /// 等しいかを判定
+    ///
+    /// 整数と浮動小数点数は数値として比較する。真偽値と数値の比較はエラー。
+    /// 複素数と実数は、実数を複素数に変換して比較する。
+    /// 区間は、区間内のすべての値が等しい場合に等しく、共通部分がない場合に等しくないとする。
+    /// 量は、次元が同じ場合のみ比較できる。
+    /// リスト同士は、長さが同じで全ての要素が等しい場合に等しいとする。
+    pub fn equals(&self, rhs: &Value) -> Result<bool, EvalError> {
+        match (self, rhs) {
+            (Value::Int(a), Value::Int(b)) => Ok(a == b),
+            (Value::Bool(a), Value::Bool(b)) => Ok(a == b),
+            (Value::List(a), Value::List(b)) => {
+                if a.len() != b.len() {
+                    return Ok(false);
+                }
+                for (x, y) in a.iter().zip(b) {
+                    if !x.equals(y)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            (Value::Quantity(_), _) | (_, Value::Quantity(_)) => {
+                self.compare(rhs, Ordering::is_eq)?.to_bool()
+            }
+            (Value::Complex(_), _) | (_, Value::Complex(_)) => {
+                Ok(complex_pair(self, rhs)?.is_some_and(|(a, b)| a == b))
+            }
+            (Value::Interval(_), _) | (_, Value::Interval(_)) => {
+                self.compare(rhs, Ordering::is_eq)?.to_bool()
+            }
+            (a, b) => match (a.to_ratio(), b.to_ratio()) {
+                (Some(a), Some(b)) => Ok(a == b),
+                _ => Ok(a.to_f64()? == b.to_f64()?),
+            },
+        }
+    }
+
+    /// 大小を比較し、その順序がpredを満たすかを返す
+    ///
+    /// NaNとの比較は常に偽となる。
+    /// 区間の場合は、区間内の値の組によって結果が異なる場合はエラー。
+    /// 量の場合は、次元が異なる場合はエラー。
+    pub fn compare(&self, rhs: &Value, pred: fn(Ordering) -> bool) -> Result<Value, EvalError> {
+        if let Some((a, b)) = quantity_pair(self, rhs)? {
+            if a.dim != b.dim {
+                return Err(EvalError::IncompatibleUnits);
+            }
+            return Ok(Value::Bool(a.value.partial_cmp(&b.value).is_some_and(pred)));
+        }
+        if let Some((a, b)) = interval_pair(self, rhs)? {
+            return a
+                .compare(b, pred)
+                .map(Value::Bool)
+                .ok_or(EvalError::AmbiguousComparison);
+        }
+        let ord = match (self, rhs) {
+            (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+            (a, b) => match (a.to_ratio(), b.to_ratio()) {
+                (Some(a), Some(b)) => Some(a.cmp(&b)),
+                _ => a.to_f64()?.partial_cmp(&b.to_f64()?),
+            },
+        };
+        Ok(Value::Bool(ord.is_some_and(pred)))
+    }
+
+    /// リストのi番目（0始まり）の要素を返す
+    ///
+    /// 負のiは末尾から数える。リストでない場合や、範囲外の場合はエラー。
+    pub fn index(self, i: Value) -> Result<Value, EvalError> {
+        let Value::List(mut xs) = self else {
+            return Err(EvalError::NotList);
+        };
+        let i = match i {
+            Value::Int(i) => i,
+            i => i
+                .to_integer()
+                .ok_or(EvalError::NotInteger)?
+                .to_i64()
+                .ok_or(EvalError::Overflow)?,
+        };
+
+        let len = xs.len();
+        let pos = if i < 0 {
+            len.checked_sub(i.unsigned_abs() as usize)
+        } else {
+            Some(i as usize).filter(|&pos| pos < len)
+        };
+        match pos {
+            Some(pos) => Ok(xs.swap_remove(pos)),
+            None => Err(EvalError::IndexOutOfRange(i, len)),
+        }
+    }
+}
+
+/// 二項演算opを、リストの場合は要素ごとに行う
+///
+/// リスト同士の長さが異なる場合はエラー。
+fn broadcast(
+    lhs: Value,
+    rhs: Value,
+    op: impl Fn(Value, Value) -> Result<Value, EvalError> + Copy,
+) -> Result<Value, EvalError> {
+    match (lhs, rhs) {
+        (Value::List(a), Value::List(b)) => {
+            if a.len() != b.len() {
+                return Err(EvalError::LengthMismatch(a.len(), b.len()));
+            }
+            a.into_iter()
+                .zip(b)
+                .map(|(x, y)| broadcast(x, y, op))
+                .collect::<Result<_, _>>()
+                .map(Value::List)
+        }
+        (Value::List(a), b) => a
+            .into_iter()
+            .map(|x| broadcast(x, b.clone(), op))
+            .collect::<Result<_, _>>()
+            .map(Value::List),
+        (a, Value::List(b)) => b
+            .into_iter()
+            .map(|y| broadcast(a.clone(), y, op))
+            .collect::<Result<_, _>>()
+            .map(Value::List),
+        (a, b) => op(a, b),
+    }
+}
+
+/// いずれかが複素数の場合は、両辺を複素数に変換して返す
+///
+/// いずれも複素数でない場合はNone。もう一方が数値でない場合はエラー。
+fn complex_pair(lhs: &Value, rhs: &Value) -> Result<Option<(Complex64, Complex64)>, EvalError> {
+    match (lhs, rhs) {
+        (Value::Complex(_), _) | (_, Value::Complex(_)) => {
+            match (lhs.to_complex(), rhs.to_complex()) {
+                (Some(a), Some(b)) => Ok(Some((a, b))),
+                _ => Err(EvalError::NotNumber),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// いずれかが区間の場合は、両辺を区間に変換して返す
+///
+/// いずれも区間でない場合はNone。もう一方が複素数以外の数値でない場合はエラー。
+fn interval_pair(lhs: &Value, rhs: &Value) -> Result<Option<(Interval, Interval)>, EvalError> {
+    match (lhs, rhs) {
+        (Value::Interval(_), _) | (_, Value::Interval(_)) => {
+            match (lhs.to_interval(), rhs.to_interval()) {
+                (Some(a), Some(b)) => Ok(Some((a, b))),
+                _ => Err(EvalError::NotNumber),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// いずれかが量の場合は、両辺を量に変換して返す
+///
+/// いずれも量でない場合はNone。もう一方が実数でない場合はエラー。
+fn quantity_pair(lhs: &Value, rhs: &Value) -> Result<Option<(Quantity, Quantity)>, EvalError> {
+    match (lhs, rhs) {
+        (Value::Quantity(_), _) | (_, Value::Quantity(_)) => {
+            match (lhs.to_quantity(), rhs.to_quantity()) {
+                (Some(a), Some(b)) => Ok(Some((a, b))),
+                _ => Err(EvalError::NotReal),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// 量の演算結果を値に変換
+///
+/// 次元が異なる量の加減算など、結果がNoneの場合はエラー。無次元の量は浮動小数点数とする。
+fn quantity(q: Option<Quantity>) -> Result<Value, EvalError> {
+    match q {
+        Some(q) if q.dim.is_none() => Ok(Value::Float(q.value)),
+        Some(q) => Ok(Value::Quantity(q)),
+        None => Err(EvalError::IncompatibleUnits),
+    }
+}
+
+/// 単項演算opをリストの要素ごとに行う
+fn map(xs: Vec<Value>, op: fn(Value) -> Result<Value, EvalError>) -> Result<Value, EvalError> {
+    xs.into_iter()
+        .map(op)
+        .collect::<Result<_, _>>()
+        .map(Value::List)
+}
+
+/// 二項演算を行う
+///
+/// 両辺がi64の整数の場合はint_opで、いずれかが多倍長整数の場合はbig_opで、
+/// いずれかが10進小数の場合はdecimal_opで、いずれかが有理数の場合はratio_opで演算する。
+/// それ以外は浮動小数点数に変換してfloat_opで演算する。
+fn arith(
+    lhs: Value,
+    rhs: Value,
+    int_op: impl Fn(i64, i64) -> Result<i64, EvalError>,
+    big_op: impl Fn(BigInt, BigInt) -> Result<BigInt, EvalError>,
+    decimal_op: impl Fn(Decimal, Decimal) -> Result<Decimal, EvalError>,
+    ratio_op: impl Fn(BigRational, BigRational) -> Result<BigRational, EvalError>,
+    float_op: impl Fn(f64, f64) -> f64,
+) -> Result<Value, EvalError> {
+    if let (Value::Int(a), Value::Int(b)) = (&lhs, &rhs) {
+        return Ok(Value::Int(int_op(*a, *b)?));
+    }
+    if let (Some(a), Some(b)) = (lhs.to_big(), rhs.to_big()) {
+        return Ok(Value::Big(big_op(a, b)?));
+    }
+    if let (Some(a), Some(b)) = (lhs.to_decimal(), rhs.to_decimal()) {
+        return Ok(Value::Decimal(Box::new(decimal_op(a, b)?)));
+    }
+    if let (Some(a), Some(b)) = (lhs.to_ratio(), rhs.to_ratio()) {
+        return Ok(Value::Ratio(Box::new(ratio_op(a, b)?)));
+    }
+    Ok(Value::Float(float_op(lhs.to_f64()?, rhs.to_f64()?)))
+}
+
+/// 整数同士のビット演算を行う
+///
+/// いずれかが多倍長整数または分母が1の有理数の場合はbig_opで演算する。
+/// いずれかが整数でない場合はエラー。
+fn bitwise(
+    lhs: Value,
+    rhs: Value,
+    int_op: impl Fn(i64, i64) -> Result<i64, EvalError>,
+    big_op: impl Fn(BigInt, BigInt) -> Result<BigInt, EvalError>,
+) -> Result<Value, EvalError> {
+    match (lhs, rhs) {
+        (Value::Int(a), Value::Int(b)) => Ok(Value::Int(int_op(a, b)?)),
+        (a, b) => match (a.to_integer(), b.to_integer()) {
+            (Some(a), Some(b)) => Ok(Value::Big(big_op(a, b)?)),
+            _ => Err(EvalError::NotInteger),
+        },
+    }
+}
+
+/// シフト量をu32に変換
+fn shift_amount(n: i64) -> Result<u32, EvalError> {
+    u32::try_from(n).map_err(|_| EvalError::InvalidShift)
+}
+
+/// 多倍長整数のシフト量をu32に変換
+fn big_shift_amount(n: &BigInt) -> Result<u32, EvalError> {
+    n.to_u32()
+        .filter(|&n| u64::from(n) <= MAX_BIG_BITS)
+        .ok_or(EvalError::InvalidShift)
+}
+
+/// 実数のべき乗を計算
+///
+/// 有理数や10進小数の指数が整数でない場合は、浮動小数点数で計算する。
+/// 量以外の数値のべき乗を計算
+fn pow_number(a: Value, b: Value) -> Result<Value, EvalError> {
+    match complex_pair(&a, &b)? {
+        Some((z, w)) => Ok(Value::Complex(match b {
+            Value::Int(n) if i32::try_from(n).is_ok() => z.powi(n as i32),
+            _ => z.powc(w),
+        })),
+        None => match interval_pair(&a, &b)? {
+            Some((x, _)) => interval_pow(x, &b),
+            None => real_pow(a, b),
+        },
+    }
+}
+
+fn real_pow(base: Value, exp: Value) -> Result<Value, EvalError> {
+    match exp {
+        Value::Ratio(_) | Value::Decimal(_) if exp.to_integer().is_none() => {
+            Ok(Value::Float(base.to_f64()?.powf(exp.to_f64()?)))
+        }
+        exp => arith(base, exp, pow, big_pow, decimal_pow, ratio_pow, f64::powf),
+    }
+}
+
+/// 区間の整数乗を計算
+///
+/// 指数が整数でない場合はエラー。
+fn interval_pow(base: Interval, exp: &Value) -> Result<Value, EvalError> {
+    base.powi(integer_exponent(exp)?)
+        .map(Value::Interval)
+        .ok_or(EvalError::DivideByZero)
+}
+
+/// 指数をi32の整数に変換
+///
+/// 整数でない場合はエラー。
+fn integer_exponent(exp: &Value) -> Result<i32, EvalError> {
+    exp.to_integer()
+        .ok_or(EvalError::NotInteger)?
+        .to_i32()
+        .ok_or(EvalError::Overflow)
+}
+
+/// 整数のべき乗を計算
+fn pow(base: i64, exp: i64) -> Result<i64, EvalError> {
+    if exp < 0 {
+        return Err(EvalError::NegativeExponent);
+    }
+
+    match base {
+        // 指数が大きくてもオーバーフローしない底
+        0 | 1 => Ok(if exp == 0 { 1 } else { base }),
+        -1 => Ok(if exp % 2 == 0 { 1 } else { -1 }),
+        _ => {
+            let exp = u32::try_from(exp).map_err(|_| EvalError::Overflow)?;
+            base.checked_pow(exp).ok_or(EvalError::Overflow)
+        }
+    }
+}
+
+/// 多倍長整数のべき乗を計算
+///
+/// 結果のビット数がMAX_BIG_BITSを超える場合はエラー。
+fn big_pow(base: BigInt, exp: BigInt) -> Result<BigInt, EvalError> {
+    if exp.is_negative() {
+        return Err(EvalError::NegativeExponent);
+    }
+
+    // 指数が大きくてもオーバーフローしない底
+    if base.abs() <= BigInt::one() {
+        return Ok(if exp.is_zero() || (base.is_negative() && !exp.bit(0)) {
+            BigInt::one()
+        } else {
+            base
+        });
+    }
+
+    let exp = exp.to_u32().ok_or(EvalError::Overflow)?;
+    if (base.bits() - 1).saturating_mul(u64::from(exp)) > MAX_BIG_BITS {
+        return Err(EvalError::Overflow);
+    }
+    Ok(base.pow(exp))
+}
+
+/// 10進小数のべき乗を計算
+///
+/// 指数は0以上の整数に限る。結果の大きさがMAX_BIG_BITSを超える場合はエラー。
+fn decimal_pow(base: Decimal, exp: Decimal) -> Result<Decimal, EvalError> {
+    let exp = exp.to_integer().ok_or(EvalError::NotInteger)?;
+    if exp.is_negative() {
+        return Err(EvalError::NegativeExponent);
+    }
+    let exp = exp.to_u32().ok_or(EvalError::Overflow)?;
+    base.pow(exp, MAX_BIG_BITS).ok_or(EvalError::Overflow)
+}
+
+/// 有理数のべき乗を計算
+///
+/// 指数は整数に限る。0の負の指数乗はエラー。
+fn ratio_pow(base: BigRational, exp: BigRational) -> Result<BigRational, EvalError> {
+    if base.is_zero() && exp.is_negative() {
+        return Err(EvalError::DivideByZero);
+    }
+
+    let exp = exp.to_integer();
+    let numer = big_pow(base.numer().clone(), exp.abs())?;
+    let denom = big_pow(base.denom().clone(), exp.abs())?;
+    let r = BigRational::new(numer, denom);
+    Ok(if exp.is_negative() { r.recip() } else { r })
+}
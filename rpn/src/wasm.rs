@@ -0,0 +1,661 @@
+//! 式をWebAssemblyのモジュールに変換する
+//!
+//! 式の値を返す関数`eval`をエクスポートする。式の自由変数は、最初に現れた順に`eval`の引数とし、
+//! いずれもi64の整数とする。`pi`などの定数は、その値に置き換える。
+//!
+//! 整数はi64、浮動小数点数はf64、真偽値はi32で表す。
+//! 整数と浮動小数点数の演算では、評価器と同様に整数を浮動小数点数に変換する。
+//! オーバーフローや0による除算は、評価器ではエラーとなるため、トラップ（unreachable）とする。
+//!
+//! べき乗、階乗、シフト、関数呼び出し、リストなど、対応する命令がない式は変換できない。
+use crate::{env::Env, parser::Expr, value::Value};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+use wasm_encoder::{
+    BlockType, CodeSection, ExportKind, ExportSection, Function, FunctionSection, IndirectNameMap,
+    Instruction, InstructionSink, Module, NameMap, NameSection, TypeSection, ValType,
+};
+
+/// 変換エラーを表す型
+#[derive(Debug, PartialEq)]
+pub enum WasmError {
+    Unsupported(String), // 変換できない式
+    NotNumber,           // 算術演算や大小比較の被演算子が数値でない
+    NotInteger,          // ビット演算の被演算子が整数でない
+    NotBool,             // 論理演算や条件の被演算子が真偽値でない
+    TypeMismatch,        // if式の2つの式の型が異なる
+}
+
+impl Display for WasmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WasmError::Unsupported(e) => {
+                write!(f, "WasmError: cannot compile '{e}' to WebAssembly")
+            }
+            WasmError::NotNumber => write!(f, "WasmError: expected a number"),
+            WasmError::NotInteger => write!(f, "WasmError: expected an integer"),
+            WasmError::NotBool => write!(f, "WasmError: expected a boolean"),
+            WasmError::TypeMismatch => {
+                write!(f, "WasmError: both branches of if must have the same type")
+            }
+        }
+    }
+}
+
+impl Error for WasmError {}
+
+/// 式の値の型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Int,
+    Float,
+    Bool,
+}
+
+impl Type {
+    fn val_type(self) -> ValType {
+        match self {
+            Type::Int => ValType::I64,
+            Type::Float => ValType::F64,
+            Type::Bool => ValType::I32,
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "i64"),
+            Type::Float => write!(f, "f64"),
+            Type::Bool => write!(f, "i32"),
+        }
+    }
+}
+
+/// 変換したモジュール
+pub struct WasmModule {
+    pub bytes: Vec<u8>,      // モジュールのバイナリ
+    pub params: Vec<String>, // `eval`の引数の変数名
+    result: Type,
+}
+
+impl WasmModule {
+    /// `eval`のシグネチャを表す文字列を返す
+    ///
+    /// 例: `eval(x: i64, y: i64) -> f64`
+    pub fn signature(&self) -> String {
+        let params = self
+            .params
+            .iter()
+            .map(|p| format!("{p}: i64"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("eval({params}) -> {}", self.result)
+    }
+}
+
+/// 式をWebAssemblyのモジュールに変換する関数
+pub fn compile(e: &Expr) -> Result<WasmModule, WasmError> {
+    let mut params = Vec::new();
+    collect_vars(e, &mut params);
+    let result = type_of(e)?;
+
+    // 引数の後に、演算の途中の値を保持するi64を3つとf64を1つ置く
+    let scratch = params.len() as u32;
+    let mut func = Function::new([(3, ValType::I64), (1, ValType::F64)]);
+    let mut generator = Generator {
+        func: &mut func,
+        params: &params,
+        scratch,
+    };
+    generator.gen_expr(e)?;
+    func.instructions().end();
+
+    let mut types = TypeSection::new();
+    types
+        .ty()
+        .function(vec![ValType::I64; params.len()], [result.val_type()]);
+    let mut functions = FunctionSection::new();
+    functions.function(0);
+    let mut exports = ExportSection::new();
+    exports.export("eval", ExportKind::Func, 0);
+    let mut code = CodeSection::new();
+    code.function(&func);
+
+    // デバッグ用に、関数名と引数名を名前セクションに記録する
+    let mut names = NameSection::new();
+    let mut fn_names = NameMap::new();
+    fn_names.append(0, "eval");
+    names.functions(&fn_names);
+    let mut param_names = NameMap::new();
+    for (i, p) in params.iter().enumerate() {
+        param_names.append(i as u32, p);
+    }
+    let mut local_names = IndirectNameMap::new();
+    local_names.append(0, &param_names);
+    names.locals(&local_names);
+
+    let mut module = Module::new();
+    module
+        .section(&types)
+        .section(&functions)
+        .section(&exports)
+        .section(&code)
+        .section(&names);
+
+    Ok(WasmModule {
+        bytes: module.finish(),
+        params,
+        result,
+    })
+}
+
+/// 定数の値を返す
+fn constant(name: &str) -> Option<Value> {
+    Env::new().get(name)
+}
+
+/// 定数でない変数名を、最初に現れた順にvarsに追加する
+fn collect_vars(e: &Expr, vars: &mut Vec<String>) {
+    match e {
+        Expr::Var(name) if constant(name).is_none() && !vars.contains(name) => {
+            vars.push(name.clone())
+        }
+        _ => {
+            for c in e.children() {
+                collect_vars(c, vars);
+            }
+        }
+    }
+}
+
+/// 式の値の型を求める
+///
+/// 型が合わない場合や、変換できない式を含む場合はエラー。
+fn type_of(e: &Expr) -> Result<Type, WasmError> {
+    match e {
+        Expr::Num(_) => Ok(Type::Int),
+        Expr::Float(_) => Ok(Type::Float),
+        Expr::Bool(_) => Ok(Type::Bool),
+        Expr::Var(name) => match constant(name) {
+            None | Some(Value::Int(_)) => Ok(Type::Int),
+            Some(Value::Float(_)) => Ok(Type::Float),
+            // 複素数の定数`i`
+            Some(_) => Err(WasmError::Unsupported(e.to_string())),
+        },
+        Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) | Expr::Div(a, b) => {
+            unify(type_of(a)?, type_of(b)?)
+        }
+        Expr::Rem(a, b) => match unify(type_of(a)?, type_of(b)?)? {
+            Type::Float => Err(WasmError::Unsupported(e.to_string())),
+            t => Ok(t),
+        },
+        Expr::Neg(a) => unify(type_of(a)?, Type::Int),
+        Expr::BitAnd(a, b) | Expr::BitOr(a, b) | Expr::BitXor(a, b) => {
+            match (type_of(a)?, type_of(b)?) {
+                (Type::Int, Type::Int) => Ok(Type::Int),
+                _ => Err(WasmError::NotInteger),
+            }
+        }
+        Expr::BitNot(a) => match type_of(a)? {
+            Type::Int => Ok(Type::Int),
+            _ => Err(WasmError::NotInteger),
+        },
+        Expr::Eq(a, b) | Expr::Ne(a, b) => match (type_of(a)?, type_of(b)?) {
+            (Type::Bool, Type::Bool) => Ok(Type::Bool),
+            (ta, tb) => unify(ta, tb).map(|_| Type::Bool),
+        },
+        Expr::Lt(a, b) | Expr::Le(a, b) | Expr::Gt(a, b) | Expr::Ge(a, b) => {
+            unify(type_of(a)?, type_of(b)?).map(|_| Type::Bool)
+        }
+        Expr::And(a, b) | Expr::Or(a, b) => match (type_of(a)?, type_of(b)?) {
+            (Type::Bool, Type::Bool) => Ok(Type::Bool),
+            _ => Err(WasmError::NotBool),
+        },
+        Expr::Not(a) => match type_of(a)? {
+            Type::Bool => Ok(Type::Bool),
+            _ => Err(WasmError::NotBool),
+        },
+        Expr::If(cond, then, els) => {
+            if type_of(cond)? != Type::Bool {
+                return Err(WasmError::NotBool);
+            }
+            match (type_of(then)?, type_of(els)?) {
+                (Type::Bool, Type::Bool) => Ok(Type::Bool),
+                (Type::Bool, _) | (_, Type::Bool) => Err(WasmError::TypeMismatch),
+                (ta, tb) => unify(ta, tb),
+            }
+        }
+        _ => Err(WasmError::Unsupported(e.to_string())),
+    }
+}
+
+/// 2つの数値の型から、演算に用いる型を求める
+///
+/// どちらかが浮動小数点数の場合は浮動小数点数とする。
+fn unify(ta: Type, tb: Type) -> Result<Type, WasmError> {
+    match (ta, tb) {
+        (Type::Bool, _) | (_, Type::Bool) => Err(WasmError::NotNumber),
+        (Type::Int, Type::Int) => Ok(Type::Int),
+        _ => Ok(Type::Float),
+    }
+}
+
+/// 関数本体の命令の生成器
+struct Generator<'a> {
+    func: &'a mut Function,
+    params: &'a [String],
+    scratch: u32, // 途中の値を保持する局所変数の先頭の番号
+}
+
+impl Generator<'_> {
+    /// 関数本体に命令を追加するためのシンクを返す
+    fn sink(&mut self) -> InstructionSink<'_> {
+        self.func.instructions()
+    }
+
+    /// 式の値をスタックに積む命令を生成し、その型を返す
+    fn gen_expr(&mut self, e: &Expr) -> Result<Type, WasmError> {
+        let ty = type_of(e)?;
+        match e {
+            Expr::Num(n) => {
+                self.sink().i64_const(*n);
+            }
+            Expr::Float(x) => {
+                self.sink().f64_const((*x).into());
+            }
+            Expr::Bool(b) => {
+                self.sink().i32_const(i32::from(*b));
+            }
+            Expr::Var(name) => match constant(name) {
+                Some(Value::Int(n)) => {
+                    self.sink().i64_const(n);
+                }
+                Some(Value::Float(x)) => {
+                    self.sink().f64_const(x.into());
+                }
+                _ => {
+                    let i = self.params.iter().position(|p| p == name).unwrap();
+                    self.sink().local_get(i as u32);
+                }
+            },
+            Expr::Add(a, b) => self.gen_arith(
+                a,
+                b,
+                ty,
+                |g| g.gen_checked_add(),
+                |g| {
+                    g.sink().f64_add();
+                },
+            )?,
+            Expr::Sub(a, b) => self.gen_arith(
+                a,
+                b,
+                ty,
+                |g| g.gen_checked_sub(),
+                |g| {
+                    g.sink().f64_sub();
+                },
+            )?,
+            Expr::Mul(a, b) => self.gen_arith(
+                a,
+                b,
+                ty,
+                |g| g.gen_checked_mul(),
+                |g| {
+                    g.sink().f64_mul();
+                },
+            )?,
+            Expr::Div(a, b) => self.gen_arith(
+                a,
+                b,
+                ty,
+                // i64.div_sは、0による除算とi64::MIN / -1でトラップする
+                |g| {
+                    g.sink().i64_div_s();
+                },
+                |g| g.gen_checked_fdiv(),
+            )?,
+            Expr::Rem(a, b) => {
+                self.gen_expr(a)?;
+                self.gen_expr(b)?;
+                self.gen_checked_rem();
+            }
+            Expr::Neg(a) => {
+                if self.gen_expr(a)? == Type::Float {
+                    self.sink().f64_neg();
+                } else {
+                    // 0 - a として、オーバーフローを検出する
+                    let x = self.scratch;
+                    self.sink().local_set(x).i64_const(0).local_get(x);
+                    self.gen_checked_sub();
+                }
+            }
+            Expr::BitAnd(a, b) => self.gen_binary(a, b, Instruction::I64And)?,
+            Expr::BitOr(a, b) => self.gen_binary(a, b, Instruction::I64Or)?,
+            Expr::BitXor(a, b) => self.gen_binary(a, b, Instruction::I64Xor)?,
+            Expr::BitNot(a) => {
+                self.gen_expr(a)?;
+                self.sink().i64_const(-1).i64_xor();
+            }
+            Expr::Eq(a, b) => self.gen_compare(
+                a,
+                b,
+                Instruction::I64Eq,
+                Instruction::F64Eq,
+                Instruction::I32Eq,
+            )?,
+            Expr::Ne(a, b) => self.gen_compare(
+                a,
+                b,
+                Instruction::I64Ne,
+                Instruction::F64Ne,
+                Instruction::I32Ne,
+            )?,
+            Expr::Lt(a, b) => self.gen_compare(
+                a,
+                b,
+                Instruction::I64LtS,
+                Instruction::F64Lt,
+                Instruction::I32LtU,
+            )?,
+            Expr::Le(a, b) => self.gen_compare(
+                a,
+                b,
+                Instruction::I64LeS,
+                Instruction::F64Le,
+                Instruction::I32LeU,
+            )?,
+            Expr::Gt(a, b) => self.gen_compare(
+                a,
+                b,
+                Instruction::I64GtS,
+                Instruction::F64Gt,
+                Instruction::I32GtU,
+            )?,
+            Expr::Ge(a, b) => self.gen_compare(
+                a,
+                b,
+                Instruction::I64GeS,
+                Instruction::F64Ge,
+                Instruction::I32GeU,
+            )?,
+            // 論理演算は短絡評価する
+            Expr::And(a, b) => {
+                self.gen_expr(a)?;
+                self.sink().if_(BlockType::Result(ValType::I32));
+                self.gen_expr(b)?;
+                self.sink().else_().i32_const(0).end();
+            }
+            Expr::Or(a, b) => {
+                self.gen_expr(a)?;
+                self.sink()
+                    .if_(BlockType::Result(ValType::I32))
+                    .i32_const(1)
+                    .else_();
+                self.gen_expr(b)?;
+                self.sink().end();
+            }
+            Expr::Not(a) => {
+                self.gen_expr(a)?;
+                self.sink().i32_eqz();
+            }
+            Expr::If(cond, then, els) => {
+                self.gen_expr(cond)?;
+                self.sink().if_(BlockType::Result(ty.val_type()));
+                self.gen_operand(then, ty)?;
+                self.sink().else_();
+                self.gen_operand(els, ty)?;
+                self.sink().end();
+            }
+            _ => return Err(WasmError::Unsupported(e.to_string())),
+        }
+        Ok(ty)
+    }
+
+    /// 式の値を型tyに変換して積む命令を生成する
+    fn gen_operand(&mut self, e: &Expr, ty: Type) -> Result<(), WasmError> {
+        if self.gen_expr(e)? != ty {
+            // 型が異なるのは、整数を浮動小数点数として扱う場合のみ
+            self.sink().f64_convert_i64_s();
+        }
+        Ok(())
+    }
+
+    /// 算術演算の命令を生成する
+    ///
+    /// 型tyが整数の場合はint_opを、浮動小数点数の場合はfloat_opを生成する。
+    fn gen_arith(
+        &mut self,
+        a: &Expr,
+        b: &Expr,
+        ty: Type,
+        int_op: impl FnOnce(&mut Self),
+        float_op: impl FnOnce(&mut Self),
+    ) -> Result<(), WasmError> {
+        self.gen_operand(a, ty)?;
+        self.gen_operand(b, ty)?;
+        match ty {
+            Type::Float => float_op(self),
+            _ => int_op(self),
+        }
+        Ok(())
+    }
+
+    /// 整数の二項演算の命令を生成する
+    fn gen_binary(
+        &mut self,
+        a: &Expr,
+        b: &Expr,
+        op: Instruction<'static>,
+    ) -> Result<(), WasmError> {
+        self.gen_expr(a)?;
+        self.gen_expr(b)?;
+        self.func.instruction(&op);
+        Ok(())
+    }
+
+    /// 比較演算の命令を生成する
+    ///
+    /// 被演算子の型に応じて、int_op、float_op、bool_opのいずれかを生成する。
+    /// 真偽値の大小比較は型の検査でエラーとなるため、bool_opは等値比較でのみ用いる。
+    fn gen_compare(
+        &mut self,
+        a: &Expr,
+        b: &Expr,
+        int_op: Instruction<'static>,
+        float_op: Instruction<'static>,
+        bool_op: Instruction<'static>,
+    ) -> Result<(), WasmError> {
+        let ty = match (type_of(a)?, type_of(b)?) {
+            (Type::Bool, Type::Bool) => Type::Bool,
+            (ta, tb) => unify(ta, tb)?,
+        };
+        self.gen_operand(a, ty)?;
+        self.gen_operand(b, ty)?;
+        let op = match ty {
+            Type::Int => int_op,
+            Type::Float => float_op,
+            Type::Bool => bool_op,
+        };
+        self.func.instruction(&op);
+        Ok(())
+    }
+
+    /// スタックの上の2つの整数を局所変数a、bに移す命令を生成する
+    fn gen_take2(&mut self) -> (u32, u32) {
+        let (a, b) = (self.scratch, self.scratch + 1);
+        self.sink().local_set(b).local_set(a);
+        (a, b)
+    }
+
+    /// 条件が真の場合にトラップする命令を生成する
+    fn gen_trap_if(&mut self) {
+        self.sink().if_(BlockType::Empty).unreachable().end();
+    }
+
+    /// オーバーフローを検出する整数の加算の命令を生成する
+    ///
+    /// 結果の符号が両辺の符号と異なる場合にオーバーフローとする。
+    fn gen_checked_add(&mut self) {
+        let (a, b) = self.gen_take2();
+        let r = self.scratch + 2;
+        self.sink().local_get(a).local_get(b).i64_add().local_set(r);
+        self.sink()
+            .local_get(a)
+            .local_get(r)
+            .i64_xor()
+            .local_get(b)
+            .local_get(r)
+            .i64_xor()
+            .i64_and()
+            .i64_const(0)
+            .i64_lt_s();
+        self.gen_trap_if();
+        self.sink().local_get(r);
+    }
+
+    /// オーバーフローを検出する整数の減算の命令を生成する
+    ///
+    /// 両辺の符号が異なり、結果の符号が左辺と異なる場合にオーバーフローとする。
+    fn gen_checked_sub(&mut self) {
+        let (a, b) = self.gen_take2();
+        let r = self.scratch + 2;
+        self.sink().local_get(a).local_get(b).i64_sub().local_set(r);
+        self.sink()
+            .local_get(a)
+            .local_get(b)
+            .i64_xor()
+            .local_get(a)
+            .local_get(r)
+            .i64_xor()
+            .i64_and()
+            .i64_const(0)
+            .i64_lt_s();
+        self.gen_trap_if();
+        self.sink().local_get(r);
+    }
+
+    /// オーバーフローを検出する整数の乗算の命令を生成する
+    ///
+    /// `-1 * i64::MIN`の場合と、結果を左辺で割った値が右辺と異なる場合にオーバーフローとする。
+    fn gen_checked_mul(&mut self) {
+        let (a, b) = self.gen_take2();
+        let r = self.scratch + 2;
+        self.sink().local_get(a).local_get(b).i64_mul().local_set(r);
+        self.sink()
+            .local_get(a)
+            .i64_const(-1)
+            .i64_eq()
+            .local_get(b)
+            .i64_const(i64::MIN)
+            .i64_eq()
+            .i32_and();
+        self.gen_trap_if();
+        // 左辺が0の場合は除算しない
+        self.sink()
+            .local_get(a)
+            .i64_const(0)
+            .i64_ne()
+            .if_(BlockType::Empty);
+        self.sink()
+            .local_get(r)
+            .local_get(a)
+            .i64_div_s()
+            .local_get(b)
+            .i64_ne();
+        self.gen_trap_if();
+        self.sink().end().local_get(r);
+    }
+
+    /// オーバーフローを検出する整数の剰余の命令を生成する
+    ///
+    /// i64.rem_sは0による除算でトラップするが、`i64::MIN % -1`は0となるため、別に検出する。
+    fn gen_checked_rem(&mut self) {
+        let (a, b) = self.gen_take2();
+        self.sink()
+            .local_get(a)
+            .i64_const(i64::MIN)
+            .i64_eq()
+            .local_get(b)
+            .i64_const(-1)
+            .i64_eq()
+            .i32_and();
+        self.gen_trap_if();
+        self.sink().local_get(a).local_get(b).i64_rem_s();
+    }
+
+    /// 0による除算を検出する浮動小数点数の除算の命令を生成する
+    fn gen_checked_fdiv(&mut self) {
+        let b = self.scratch + 3;
+        self.sink().local_tee(b).f64_const(0.0.into()).f64_eq();
+        self.gen_trap_if();
+        self.sink().local_get(b).f64_div();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, Notation, Stmt, DEFAULT_MAX_DEPTH};
+
+    fn compile_str(c: &str) -> Result<WasmModule, WasmError> {
+        match parse(c, Notation::Infix, DEFAULT_MAX_DEPTH).unwrap() {
+            Stmt::Expr(e) => compile(&e),
+            s => panic!("not an expression: {s:?}"),
+        }
+    }
+
+    #[test]
+    fn test_compile() {
+        for c in [
+            "1 + 2 * 3",
+            "x * y - x / 2 % y",
+            "-x + (y & 255 | ~z xor 1)",
+            "x / 2.0 + pi * r",
+            "if x > 0 && !(y == 0) || z <= 1 then x else 0.5",
+            "x == y != (1.5 < x)",
+        ] {
+            let m = compile_str(c).unwrap();
+            wasmparser::validate(&m.bytes).unwrap_or_else(|e| panic!("{c}: {e}"));
+        }
+
+        // 自由変数は最初に現れた順に引数となり、定数は引数としない
+        let m = compile_str("y * x + pi * y + z").unwrap();
+        assert_eq!(m.params, ["y", "x", "z"]);
+        assert_eq!(m.signature(), "eval(y: i64, x: i64, z: i64) -> f64");
+        assert_eq!(
+            compile_str("1 < x").unwrap().signature(),
+            "eval(x: i64) -> i32"
+        );
+    }
+
+    #[test]
+    fn test_compile_error() {
+        assert!(matches!(
+            compile_str("x ^ 2"),
+            Err(WasmError::Unsupported(_))
+        ));
+        assert!(matches!(
+            compile_str("f(x) + 1"),
+            Err(WasmError::Unsupported(_))
+        ));
+        assert!(matches!(
+            compile_str("x % 1.5"),
+            Err(WasmError::Unsupported(_))
+        ));
+        assert!(matches!(
+            compile_str("x + 2 * i"),
+            Err(WasmError::Unsupported(_))
+        ));
+        assert_eq!(compile_str("x + true").err(), Some(WasmError::NotNumber));
+        assert_eq!(compile_str("1.5 & x").err(), Some(WasmError::NotInteger));
+        assert_eq!(compile_str("x && y").err(), Some(WasmError::NotBool));
+        assert_eq!(
+            compile_str("if x > 0 then true else 1").err(),
+            Some(WasmError::TypeMismatch)
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! zeroコマンド
+//!
+//! 正規表現による検索、電卓、JSONの整形、正規表現のデバッグ表示を、サブコマンドとして1つのコマンドにまとめる。
+//! `zero grep`は`zerogrep`と、`zero calc`は`rpn`と、`zero json`は`zerojson`と同じ引数をとり、
+//! 同じライブラリの実装を呼び出す。
+use clap::{Parser, Subcommand};
+use std::{ffi::OsString, process::ExitCode};
+
+/// Search with regular expressions, evaluate expressions, query JSON and debug regular expressions
+#[derive(Parser, Debug)]
+#[command(name = "zero", version)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// サブコマンド
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Search files for lines matching a regular expression (see `zero grep --help`)
+    #[command(disable_help_flag = true)]
+    Grep {
+        /// Arguments of zerogrep
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<OsString>,
+    },
+
+    /// Evaluate arithmetic expressions interactively (see `zero calc --help`)
+    #[command(disable_help_flag = true)]
+    Calc {
+        /// Arguments of rpn
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<OsString>,
+    },
+
+    /// Print the values selected from JSON by a jq-like filter (see `zero json --help`)
+    #[command(disable_help_flag = true)]
+    Json {
+        /// Arguments of zerojson
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<OsString>,
+    },
+
+    /// Print the AST and the compiled program of regular expressions
+    RegexDebug {
+        /// Regular expressions to print
+        #[arg(required = true)]
+        patterns: Vec<String>,
+    },
+}
+
+fn main() -> ExitCode {
+    match Args::parse().command {
+        Command::Grep { args } => regex::grep::main(command_line("zero grep", args)),
+        Command::Calc { args } => {
+            rpn::cli::main(command_line("zero calc", args));
+            ExitCode::SUCCESS
+        }
+        Command::Json { args } => json::cli::main(command_line("zero json", args)),
+        Command::RegexDebug { patterns } => {
+            for (i, pattern) in patterns.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                if let Err(e) = regex::print(pattern) {
+                    match e {
+                        regex::Error::Parse(e) => eprintln!("{}", e.diagnostic(pattern)),
+                        e => eprintln!("{e}"),
+                    }
+                    return ExitCode::FAILURE;
+                }
+            }
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+/// サブコマンドの引数argsの前に、コマンド名nameを付ける
+fn command_line(name: &str, args: Vec<OsString>) -> Vec<OsString> {
+    let mut line = vec![OsString::from(name)];
+    line.extend(args);
+    line
+}